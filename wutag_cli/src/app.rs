@@ -1,18 +1,35 @@
+use std::io::{IsTerminal, Write};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
-use crate::client::Client;
-use crate::config::Config;
-use crate::fmt;
+use crate::client::{Client, HandledResponse};
+use crate::config::{Config, ConfigError, ContentTagRule};
+use crate::exec;
+use crate::fmt::{self, TerminalBackground};
+use crate::mapping::{parse_json_records, parse_mapping, JsonRecord};
+use crate::offline::{default_registry_file, OfflineRegistry};
 use crate::opt::{
-    ClearObject, ClearOpts, Command, CpOpts, EditOpts, GetOpts, ListObject, ListOpts, Opts,
-    OutputFormat, RmOpts, SearchOpts, SetOpts,
+    split_tags, ApplyOpts, AuditLogOpts, CapacityOpts, CatOpts, ClearObject, ClearOpts, ColorsOpts,
+    Command, CountOpts, CpOpts, CreateTagOpts, DiffFsOpts, EditOpts, ExportGraphOpts,
+    ExportXattrsOpts, GetOpts, GraphFormat, LinkOpts, ListObject, ListOpts, MigrateNamespaceOpts,
+    MigratePathsOpts, Opts, OutputFormat, OverlapOpts, PageOpts, RandomOpts, RecentOpts,
+    RenamePrefixOpts, RmOpts, SameTagsOpts, SearchOpts, SetExactOpts, SetJsonOpts, SetOpts,
+    SortKey, TagMetaOpts, TagReportOpts, VerifyOpts,
 };
+use crate::report;
+use crate::schema::{CatEntry, RandomPick, Status, TagChange, TagComparison, TagListing};
 use crate::{Error, Result};
 use thiserror::Error as ThisError;
-use wutag_core::color::{self, parse_color, Color, DEFAULT_COLORS};
-use wutag_core::glob::Glob;
-use wutag_core::tag::Tag;
-use wutag_ipc::{default_socket, Response};
+use wutag_core::color::{self, palette_by_name, parse_color, Color, Colorize, DEFAULT_COLORS};
+use wutag_core::glob::{Glob, DEFAULT_MAX_DEPTH};
+use wutag_core::registry::EntryData;
+use wutag_core::tag::{
+    fold_name, normalize_name, search_files_with_tags, Tag, TagFilter, TagPattern,
+};
+use wutag_ipc::{
+    default_circuit_file, default_socket, AuditLogEntry, BatchSummary, InspectFilesResult, Page,
+    Response,
+};
 
 #[derive(Debug, ThisError)]
 pub enum AppError {
@@ -30,12 +47,51 @@ pub enum AppError {
     ListTags(String),
     #[error("failed to edit tag - {0}")]
     EditTag(String),
+    #[error("tag `{0}` doesn't exist")]
+    TagNotFound(String),
     #[error("failed to serialize output as yaml - {0}")]
     SerializeYamlOutput(serde_yaml::Error),
     #[error("failed to serialize output as json - {0}")]
     SerializeJsonOutput(serde_json::Error),
+    #[error("failed to render graph with `dot` - {0}")]
+    RenderGraph(std::io::Error),
+    #[error("failed to write output to `{0}` - {1}")]
+    WriteOutput(PathBuf, std::io::Error),
+    #[error("detected {0} problem(s) with the daemon or registry")]
+    StatusProblems(usize),
+    /// A predicate command like `tag-exists` evaluated to false. This is not reported as an
+    /// error, the program only exits with a non zero status.
+    #[error("predicate is false")]
+    PredicateFalse,
+    #[error("failed to read mapping `{0}` - {1}")]
+    ReadMapping(PathBuf, std::io::Error),
+    #[error("failed to apply {0} line(s) of the mapping")]
+    ApplyMapping(usize),
+    #[error("failed to read records - {0}")]
+    ReadRecords(std::io::Error),
+    #[error("failed to parse records, expected a JSON array - {0}")]
+    ParseRecords(serde_json::Error),
+    #[error("failed to apply {0} record(s)")]
+    ApplyRecords(usize),
+    #[error("no unused colors left in the palette for {0} tag(s), add more `colors` to the configuration")]
+    PaletteExhausted(usize),
+    #[error("refusing to {0} without confirmation, pass `--yes` to proceed")]
+    ConfirmationRequired(&'static str),
+    #[error("failed to read confirmation - {0}")]
+    ReadConfirmation(std::io::Error),
+    #[error("only `list`, `search` and `get` are supported with `--offline`")]
+    OfflineUnsupported,
+    #[error("failed to load registry - {0}")]
+    LoadRegistry(wutag_core::registry::RegistryError),
+    /// Some files of a batch operation failed. The failures and the summary are already printed
+    /// so this only makes the program exit with a non zero status.
+    #[error("failed to process {0} file(s)")]
+    BatchFailed(usize),
     #[error("failed to {action} - unexpected response from server {response:?}")]
-    UnexpectedResponse { action: String, response: Response },
+    UnexpectedResponse {
+        action: String,
+        response: Box<Response>,
+    },
 }
 
 pub struct App {
@@ -44,6 +100,12 @@ pub struct App {
     pub colors: Vec<Color>,
     pub pretty: bool,
     pub format: OutputFormat,
+    pub json_pretty: bool,
+    pub background: Option<TerminalBackground>,
+    pub content_rules: Vec<ContentTagRule>,
+    pub normalize_tag_names: bool,
+    pub follow_symlinks: bool,
+    pub quiet: bool,
     pub client: Client,
 }
 
@@ -65,13 +127,39 @@ impl App {
                 colors.push(color.map_err(AppError::ParseColor)?);
             }
             colors
+        } else if let Some(theme) = &config.theme {
+            palette_by_name(theme)
+                .ok_or_else(|| ConfigError::UnknownTheme(theme.clone()))?
+                .to_vec()
         } else {
             DEFAULT_COLORS.to_vec()
         };
 
-        let client = Client::new(default_socket());
+        let registry = match &opts.registry {
+            Some(registry) if registry.is_relative() => Some(base_dir.join(registry)),
+            registry => registry.clone(),
+        };
+        let offline = if opts.offline {
+            if !matches!(
+                opts.cmd,
+                Command::List(_) | Command::Search(_) | Command::Get(_)
+            ) {
+                return Err(AppError::OfflineUnsupported.into());
+            }
+            let path = default_registry_file().unwrap_or_default();
+            Some(OfflineRegistry::load(&path).map_err(AppError::LoadRegistry)?)
+        } else {
+            None
+        };
+        let client = Client::new(default_socket())
+            .with_circuit_file(Some(default_circuit_file()))
+            .with_registry(registry)
+            .with_offline(offline);
 
-        client.ping()?;
+        // `status` reports the state of the daemon itself
+        if !opts.offline && !matches!(opts.cmd, Command::Status) {
+            client.ping()?;
+        }
 
         Ok(App {
             base_dir,
@@ -83,37 +171,160 @@ impl App {
             colors,
             pretty: opts.pretty || config.pretty_output,
             format: opts.output_format,
+            json_pretty: opts.json_pretty,
+            background: config.terminal_background,
+            content_rules: config.content_rules,
+            normalize_tag_names: config.normalize_tag_names,
+            follow_symlinks: config.follow_symlinks.unwrap_or(true),
+            quiet: opts.quiet,
             client,
         })
     }
 
-    pub fn run_command(&mut self, cmd: Command) -> Result<()> {
-        if !self.pretty {
+    /// Decides whether the output should be colorized. `NO_COLOR` environment variable disables
+    /// colors and `CLICOLOR_FORCE` enables them regardless of the `pretty` setting. Colors are
+    /// also disabled when the standard output is not a terminal.
+    fn setup_colors(&self) {
+        let no_color = std::env::var_os("NO_COLOR").map(|v| !v.is_empty());
+        let force_color = std::env::var_os("CLICOLOR_FORCE").map(|v| !v.is_empty() && v != "0");
+        if no_color.unwrap_or_default() {
             color::control::SHOULD_COLORIZE.set_override(false);
+        } else if force_color.unwrap_or_default() {
+            color::control::SHOULD_COLORIZE.set_override(true);
+        } else if !self.pretty || !std::io::stdout().is_terminal() {
+            color::control::SHOULD_COLORIZE.set_override(false);
+        }
+    }
+
+    /// Reports the `summary` of a batch operation like `set` or `rm`. Errors of individual files
+    /// are printed first unless `quiet` is set, followed by a single `summary:` line on the
+    /// standard error or the serialized summary on the standard output with `--output-format`.
+    /// Fails if any of the files failed to be processed.
+    fn report_batch(&self, verb: &str, summary: BatchSummary) -> Result<()> {
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(&summary)?,
+            OutputFormat::Default | OutputFormat::Table => {
+                if !self.quiet {
+                    for error in &summary.errors {
+                        eprintln!("error: {error}");
+                    }
+                }
+                let already = if summary.already_tagged > 0 {
+                    format!(" already_tagged={}", summary.already_tagged)
+                } else {
+                    String::new()
+                };
+                eprintln!(
+                    "summary: {verb}={} failed={} skipped={}{already}",
+                    summary.succeeded, summary.failed, summary.skipped
+                );
+            }
+        }
+        if summary.failed > 0 {
+            return Err(AppError::BatchFailed(summary.failed).into());
+        }
+        Ok(())
+    }
+
+    /// Normalizes the tag `name` converting it to lowercase if `normalize_tag_names` is enabled.
+    fn tag_name<S: AsRef<str>>(&self, name: S) -> String {
+        if self.normalize_tag_names {
+            fold_name(name)
+        } else {
+            normalize_name(name)
         }
+    }
+
+    fn tag_names(&self, names: Vec<String>) -> Vec<String> {
+        names.into_iter().map(|name| self.tag_name(name)).collect()
+    }
+
+    /// Formats the `tags` separated by spaces.
+    fn joined_tags(&self, tags: &[Tag]) -> String {
+        tags.iter()
+            .map(|t| fmt::tag(t, self.background).to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Decides whether symlinks should be used themselves instead of their targets from the
+    /// `--follow` and `--no-follow` flags falling back to `follow_symlinks` of the configuration.
+    fn no_follow(&self, follow: bool, no_follow: bool) -> bool {
+        no_follow || !(follow || self.follow_symlinks)
+    }
+
+    /// Creates a tag with a random color from the configured colors.
+    fn new_tag<S: AsRef<str>>(&self, name: S) -> Tag {
+        Tag::random(self.tag_name(name), &self.colors)
+    }
+
+    pub fn run_command(&mut self, cmd: Command) -> Result<()> {
+        self.setup_colors();
         match cmd {
             Command::List(opts) => self.list(opts),
             Command::Set(opts) => self.set(opts),
+            Command::SetExact(opts) => self.set_exact(opts),
             Command::Get(opts) => self.get(opts),
             Command::Rm(opts) => self.rm(opts),
             Command::Clear(opts) => self.clear(opts),
             Command::Search(opts) => self.search(opts),
             Command::Cp(opts) => self.cp(opts),
             Command::Edit(opts) => self.edit(opts),
-            // This command should be handled in main
-            Command::PrintCompletions(_) => unreachable!(),
+            Command::CreateTag(opts) => self.create_tag(opts),
+            Command::TagMeta(opts) => self.tag_meta(opts),
+            Command::ExportXattrs(opts) => self.export_xattrs(opts),
+            Command::Capacity(opts) => self.capacity(opts),
+            Command::ExportGraph(opts) => self.export_graph(opts),
+            Command::TagReport(opts) => self.tag_report(opts),
+            Command::RecentTags => self.recent_tags(),
+            Command::Recent(opts) => self.recent(opts),
+            Command::Random(opts) => self.random(opts),
+            Command::AuditLog(opts) => self.audit_log(opts),
+            Command::DiffFs(opts) => self.diff_fs(opts),
+            Command::Verify(opts) => self.verify(opts),
+            Command::RenamePrefix(opts) => self.rename_prefix(opts),
+            Command::Cat(opts) => self.cat(opts),
+            Command::Apply(opts) => self.apply(opts),
+            Command::SetJson(opts) => self.set_json(opts),
+            Command::Overlap(opts) => self.overlap(opts),
+            Command::SameTags(opts) => self.same_tags(opts),
+            Command::TagExists { name } => self.tag_exists(name),
+            Command::Count(opts) => self.count(opts),
+            Command::Link(opts) => self.link(opts),
+            Command::ListRules => self.list_rules(),
+            Command::LogLevel { level } => self.client.set_log_level(level),
+            Command::MigrateNamespace(opts) => self.migrate_namespace(opts),
+            Command::MigratePaths(opts) => self.migrate_paths(opts),
+            Command::Subscribe => self.subscribe(),
+            Command::ListMounts => self.list_mounts(),
+            Command::Colors(opts) => self.colors(opts),
+            Command::Status => self.status(),
+            Command::Maintenance => self.maintenance(),
+            // These commands should be handled in main
+            Command::PrintCompletions(_)
+            | Command::Init(_)
+            | Command::AddContentRule(_)
+            | Command::Alias(_)
+            | Command::Schema { .. }
+            | Command::External(_) => {
+                unreachable!()
+            }
         }
     }
 
     fn print_serialized<T: serde::Serialize + std::fmt::Debug>(&self, it: T) -> Result<()> {
         let output = match self.format {
+            OutputFormat::Json if self.json_pretty => {
+                serde_json::to_string_pretty(&it).map_err(AppError::SerializeJsonOutput)?
+            }
             OutputFormat::Json => {
                 serde_json::to_string(&it).map_err(AppError::SerializeJsonOutput)?
             }
             OutputFormat::Yaml => {
                 serde_yaml::to_string(&it).map_err(AppError::SerializeYamlOutput)?
             }
-            OutputFormat::Default => format!("{it:?}"),
+            // there is no generic table layout so fall back to the default output
+            OutputFormat::Default | OutputFormat::Table => format!("{it:?}"),
         };
         println!("{output}");
         Ok(())
@@ -125,11 +336,28 @@ impl App {
 
     fn list(&self, opts: ListOpts) -> Result<()> {
         match opts.object {
-            ListObject::Files { with_tags } => {
-                let entries = self.client.list_files(with_tags)?;
+            ListObject::Files {
+                with_tags,
+                page,
+                exec,
+                exec_batch,
+            } => {
+                let table = self.format == OutputFormat::Table;
+                let entries = self
+                    .client
+                    .list_files_page(with_tags || table, ipc_page(page))?;
+                print_page_summary(page, entries.items.len(), entries.total);
+                let entries = entries.items;
+                let paths: Vec<_> = entries.iter().map(|(e, _)| e.path()).collect();
+                if let Some(command) = exec {
+                    return exec::exec_each(&command, &paths).map_err(Error::from);
+                }
+                if let Some(command) = exec_batch {
+                    return exec::exec_batch(&command, &paths).map_err(Error::from);
+                }
                 match self.format {
                     OutputFormat::Json | OutputFormat::Yaml => {
-                        let entries: std::collections::HashMap<_, _> = entries
+                        let entries: std::collections::BTreeMap<_, _> = entries
                             .into_iter()
                             .map(|(e, tags)| {
                                 (
@@ -140,26 +368,60 @@ impl App {
                             .collect();
                         self.print_serialized(entries)?;
                     }
+                    OutputFormat::Table => {
+                        let rows = entries.into_iter().map(|(entry, tags)| {
+                            [
+                                fmt::path(entry.path()).to_string(),
+                                self.joined_tags(&tags),
+                                tags.len().to_string(),
+                            ]
+                        });
+                        println!("{}", fmt::table(["Path", "Tags", "Tag Count"], rows));
+                    }
                     OutputFormat::Default => {
-                        for (entry, mut tags) in entries {
+                        for (entry, tags) in entries {
                             print!("{}", fmt::path(entry.path()));
-                            tags.sort_unstable();
-                            let tags = tags
-                                .into_iter()
-                                .map(|t| fmt::tag(&t).to_string())
-                                .collect::<Vec<_>>()
-                                .join(" ");
-
-                            println!(": {}", tags);
+                            println!(": {}", self.joined_tags(&tags));
                         }
                     }
                 }
             }
-            ListObject::Tags { with_files } => {
-                let tags = self.client.list_tags(with_files)?;
+            ListObject::Tags {
+                with_files,
+                verbose,
+                unused,
+                page,
+            } => {
+                let table = self.format == OutputFormat::Table;
+                // unused tags are told apart by having no files
+                let tags = self.client.list_tags_page(
+                    with_files || table || unused,
+                    ipc_page(page),
+                    unused,
+                )?;
+                print_page_summary(page, tags.items.len(), tags.total);
+                let tags = tags.items;
+                let mut meta = if verbose {
+                    self.client.tag_meta(None)?
+                } else {
+                    std::collections::BTreeMap::new()
+                };
                 match self.format {
+                    OutputFormat::Json | OutputFormat::Yaml if verbose => {
+                        let tags: std::collections::BTreeMap<_, _> = tags
+                            .into_iter()
+                            .map(|(t, e)| {
+                                let listing = TagListing {
+                                    files: e.into_iter().map(|e| e.into_path_buf()).collect(),
+                                    meta: meta.remove(t.name()).unwrap_or_default(),
+                                };
+                                (t.into_name(), listing)
+                            })
+                            .collect();
+                        self.print_serialized(tags)?;
+                    }
                     OutputFormat::Json | OutputFormat::Yaml => {
-                        let tags: std::collections::HashMap<_, _> = tags
+                        let tags: std::collections::BTreeMap<_, _> = tags
                             .into_iter()
                             .map(|(t, e)| {
                                 (
@@ -170,19 +432,57 @@ impl App {
                             .collect();
                         self.print_serialized(tags)?;
                     }
+                    OutputFormat::Table => {
+                        let rows = tags.into_iter().map(|(tag, entries)| {
+                            [
+                                fmt::tag(&tag, self.background).to_string(),
+                                color::color_to_hex(*tag.color()),
+                                entries.len().to_string(),
+                            ]
+                        });
+                        println!("{}", fmt::table(["Tag Name", "Color", "File Count"], rows));
+                    }
                     OutputFormat::Default => {
+                        let joined_meta = |tag: &Tag| {
+                            meta.get(tag.name())
+                                .map(|meta| {
+                                    meta.iter()
+                                        .map(|(key, value)| format!(" {key}={value}"))
+                                        .collect::<String>()
+                                })
+                                .unwrap_or_default()
+                        };
+                        let marker = |entries: &[EntryData]| {
+                            if unused && entries.is_empty() {
+                                "*"
+                            } else {
+                                ""
+                            }
+                        };
                         if with_files {
                             for (tag, entries) in tags {
-                                println!("{}:", fmt::tag(&tag));
+                                println!(
+                                    "{}{}:{}",
+                                    fmt::tag(&tag, self.background),
+                                    marker(&entries),
+                                    joined_meta(&tag)
+                                );
                                 for entry in entries {
                                     println!("\t{}", fmt::path(entry.path()));
                                 }
                             }
+                        } else if verbose {
+                            for (tag, entries) in tags {
+                                println!(
+                                    "{}{}{}",
+                                    fmt::tag(&tag, self.background),
+                                    marker(&entries),
+                                    joined_meta(&tag)
+                                );
+                            }
                         } else {
-                            let mut tags: Vec<_> = tags.into_iter().map(|(t, _)| t).collect();
-                            tags.sort_unstable();
-                            for tag in tags {
-                                print!("{} ", fmt::tag(&tag));
+                            for (tag, entries) in tags {
+                                print!("{}{} ", fmt::tag(&tag, self.background), marker(&entries));
                             }
                         }
                     }
@@ -193,33 +493,87 @@ impl App {
     }
 
     fn set(&mut self, opts: SetOpts) -> Result<()> {
-        let tags: Vec<_> = opts
-            .tags
+        let no_follow = self.no_follow(opts.follow, opts.no_follow);
+        let tags: Vec<_> = split_tags(opts.tags)
             .into_iter()
-            .map(|t| Tag::random(t, &self.colors))
+            .map(|t| self.new_tag(t))
             .collect();
+        let rules = if opts.auto_tag {
+            let mut rules = vec![];
+            for rule in &self.content_rules {
+                rules.push(rule.to_tag_rule(&self.colors)?);
+            }
+            Some(rules)
+        } else {
+            None
+        };
 
-        if opts.glob {
+        let summary = if opts.glob {
             let glob = self.glob(&opts.paths[0])?;
-            self.client
-                .tag_files_pattern(glob, tags)
-                .map_err(Error::from)
-                .map(|_| ())
+            self.client.tag_files_pattern(
+                glob,
+                tags,
+                rules,
+                opts.expires,
+                no_follow,
+                &self.colors,
+            )?
         } else {
-            self.client
-                .tag_files(opts.paths, tags)
-                .map_err(Error::from)
-                .map(|_| ())
+            self.client.tag_files(
+                opts.paths,
+                tags,
+                rules,
+                opts.expires,
+                no_follow,
+                &self.colors,
+            )?
+        };
+        self.report_batch("tagged", summary)
+    }
+
+    fn set_exact(&mut self, opts: SetExactOpts) -> Result<()> {
+        let tags: Vec<_> = split_tags(opts.tags)
+            .into_iter()
+            .map(|t| self.new_tag(t))
+            .collect();
+        let result = self.client.set_exact_tags(opts.paths, tags)?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                self.print_serialized(&result)?;
+                if result.summary.failed > 0 {
+                    return Err(AppError::BatchFailed(result.summary.failed).into());
+                }
+                Ok(())
+            }
+            OutputFormat::Default | OutputFormat::Table => {
+                if !self.quiet {
+                    for change in &result.changes {
+                        let added = change.added.iter().map(|name| format!("+{name}"));
+                        let removed = change.removed.iter().map(|name| format!("-{name}"));
+                        let changes: Vec<_> = added.chain(removed).collect();
+                        println!("{}: {}", fmt::path(&change.path), changes.join(" "));
+                    }
+                }
+                self.report_batch("changed", result.summary)
+            }
         }
     }
 
     fn get(&mut self, opts: GetOpts) -> Result<()> {
-        let entries = if opts.glob {
+        let no_follow = self.no_follow(opts.follow, opts.no_follow);
+        let InspectFilesResult {
+            entries, expiries, ..
+        } = if opts.glob {
             let glob = self.glob(&opts.paths[0])?;
-            self.client.inspect_files_pattern(glob)?
+            let result = self.client.inspect_files_pattern(glob, no_follow)?;
+            if result.match_count == 0 {
+                eprintln!("warning: pattern `{}` matched no files", opts.paths[0]);
+            }
+            result
         } else {
-            self.client.inspect_files(opts.paths)?
+            self.client.inspect_files(opts.paths, no_follow)?
         };
+        let now = chrono::Utc::now();
 
         match self.format {
             OutputFormat::Json | OutputFormat::Yaml => {
@@ -229,12 +583,16 @@ impl App {
                     .collect();
                 self.print_serialized(entries)?;
             }
-            OutputFormat::Default => {
+            OutputFormat::Default | OutputFormat::Table => {
                 for (entry, mut tags) in entries {
                     tags.sort_unstable();
                     print!("{}:", fmt::path(entry.path()));
+                    let entry_expiries = expiries.get(entry.path());
                     for tag in &tags {
-                        print!(" {}", fmt::tag(tag))
+                        print!(" {}", fmt::tag(tag, self.background));
+                        if let Some(expires_at) = entry_expiries.and_then(|e| e.get(tag.name())) {
+                            print!(" (expires in {})", fmt::remaining(*expires_at - now));
+                        }
                     }
                 }
             }
@@ -243,54 +601,474 @@ impl App {
     }
 
     fn rm(&mut self, opts: RmOpts) -> Result<()> {
-        let tags: Vec<_> = opts
-            .tags
+        let no_follow = self.no_follow(opts.follow, opts.no_follow);
+        let tags: Vec<_> = split_tags(opts.tags)
             .into_iter()
-            .map(|t| Tag::random(t, &self.colors))
+            .map(|t| self.new_tag(t))
             .collect();
 
-        if opts.glob {
+        let summary = if opts.glob {
             let glob = self.glob(&opts.paths[0])?;
             self.client
-                .untag_files_pattern(glob, tags)
-                .map_err(Error::from)
-                .map(|_| ())
+                .untag_files_pattern(glob, tags, no_follow, opts.force)?
         } else {
             self.client
-                .untag_files(opts.paths, tags)
-                .map_err(Error::from)
-                .map(|_| ())
-        }
+                .untag_files(opts.paths, tags, no_follow, opts.force)?
+        };
+        self.report_batch("untagged", summary)
     }
 
     fn clear(&mut self, opts: ClearOpts) -> Result<()> {
         match opts.object {
-            ClearObject::Files { paths, glob } => {
-                if glob {
+            ClearObject::Files { paths, glob, force } => {
+                let summary = if glob {
                     let glob = self.glob(&paths[0])?;
-                    self.client.clear_files_pattern(glob)?;
+                    self.client.clear_files_pattern(glob, force)?
                 } else {
-                    self.client.clear_files(paths)?;
+                    self.client.clear_files(paths, force)?
+                };
+                return self.report_batch("cleared", summary);
+            }
+            ClearObject::Tags {
+                all: true,
+                yes,
+                force,
+                ..
+            } => {
+                if !yes {
+                    let count = self.client.list_tags(false)?.len();
+                    let prompt = format!("Remove all {count} tag(s) from all files?");
+                    if !confirm(&prompt, "clear all tags")? {
+                        eprintln!("Aborted");
+                        return Ok(());
+                    }
                 }
+                self.client.clear_all_tags(force)?;
             }
-            ClearObject::Tags { names } => {
-                self.client.clear_tags(names)?;
+            ClearObject::Tags {
+                names, yes, force, ..
+            } => {
+                let names = self.tag_names(names);
+                let patterns: Vec<_> = names
+                    .iter()
+                    .map(TagPattern::new)
+                    .filter(|pattern| pattern.literal().is_none())
+                    .collect();
+                if !yes && !patterns.is_empty() {
+                    let count = self
+                        .client
+                        .list_tags_page(false, Page::default(), true)?
+                        .items
+                        .iter()
+                        .filter(|(tag, _)| patterns.iter().any(|p| p.matches(tag.name())))
+                        .count();
+                    let prompt =
+                        format!("Remove {count} tag(s) matching the patterns from all files?");
+                    if !confirm(&prompt, "clear tags matching patterns")? {
+                        eprintln!("Aborted");
+                        return Ok(());
+                    }
+                }
+                self.client.clear_tags(names, true, force)?;
             }
             ClearObject::Cache => self.clear_cache()?,
+            ClearObject::RecentTags => self.client.clear_recent_tags()?,
+        }
+        Ok(())
+    }
+
+    fn migrate_namespace(&self, opts: MigrateNamespaceOpts) -> Result<()> {
+        let glob = self.glob(&opts.pattern)?;
+        let moved = self.client.migrate_namespace(glob, opts.from, opts.to)?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(moved)?,
+            OutputFormat::Default | OutputFormat::Table => println!("migrated {moved} tag(s)"),
+        }
+        Ok(())
+    }
+
+    fn migrate_paths(&self, opts: MigratePathsOpts) -> Result<()> {
+        let root = if opts.root.is_absolute() {
+            opts.root
+        } else {
+            self.base_dir.join(opts.root)
+        };
+        let migration = self.client.absolutize_paths(root)?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(&migration)?,
+            OutputFormat::Default | OutputFormat::Table => {
+                for path in &migration.unresolved {
+                    println!("{}: not found", fmt::path(path));
+                }
+            }
+        }
+        if !self.quiet {
+            eprintln!(
+                "rewrote {} path(s), merged {} into existing entries, {} unresolved",
+                migration.rewritten,
+                migration.merged,
+                migration.unresolved.len()
+            );
+        }
+        Ok(())
+    }
+
+    fn colors(&self, opts: ColorsOpts) -> Result<()> {
+        let mut colors = self.client.list_colors()?;
+        let used: Vec<_> = colors.keys().cloned().collect();
+        if opts.conflicts {
+            colors.retain(|_, names| names.len() > 1);
+        }
+        if opts.fix {
+            return self.fix_color_conflicts(colors, &used);
+        }
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(colors)?,
+            OutputFormat::Table => {
+                let rows = colors.into_iter().map(|(hex, names)| {
+                    let color = match parse_color(&hex) {
+                        Ok(color) => format!("{} {hex}", fmt::swatch(color)),
+                        Err(_) => hex,
+                    };
+                    [color, names.join(" ")]
+                });
+                println!("{}", fmt::table(["Color", "Tags"], rows));
+            }
+            OutputFormat::Default => {
+                for (hex, names) in colors {
+                    if let Ok(color) = parse_color(&hex) {
+                        print!("{} ", fmt::swatch(color));
+                    }
+                    println!("{hex}: {}", names.join(" "));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Recolors all but the first tag of each group of `conflicts` with colors of the palette
+    /// that are not `used` by any tag.
+    fn fix_color_conflicts(
+        &self,
+        conflicts: std::collections::BTreeMap<String, Vec<String>>,
+        used: &[String],
+    ) -> Result<()> {
+        let mut seen = used.to_vec();
+        let mut free = self
+            .colors
+            .iter()
+            .map(|color| (color::color_to_hex(*color), *color))
+            .filter(|(hex, _)| {
+                if seen.contains(hex) {
+                    false
+                } else {
+                    seen.push(hex.clone());
+                    true
+                }
+            });
+
+        let conflicting: Vec<_> = conflicts
+            .into_iter()
+            .flat_map(|(hex, names)| {
+                names
+                    .into_iter()
+                    .skip(1)
+                    .map(move |name| (hex.clone(), name))
+            })
+            .collect();
+        for (i, (hex, name)) in conflicting.iter().enumerate() {
+            let (new_hex, color) = match free.next() {
+                Some(color) => color,
+                None => return Err(AppError::PaletteExhausted(conflicting.len() - i).into()),
+            };
+            self.client
+                .edit_tag(name.clone(), Some(color), None, None)?;
+            println!("{name}: {hex} -> {} {new_hex}", fmt::swatch(color));
+        }
+        Ok(())
+    }
+
+    fn list_mounts(&self) -> Result<()> {
+        let mounts = self.client.list_mounts()?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(mounts)?,
+            OutputFormat::Table => {
+                let rows = mounts.into_iter().map(|mount| {
+                    [
+                        fmt::path(&mount.path).to_string(),
+                        mount.registry_file.display().to_string(),
+                    ]
+                });
+                println!("{}", fmt::table(["Path", "Registry"], rows));
+            }
+            OutputFormat::Default => {
+                for mount in mounts {
+                    println!(
+                        "{}: {}",
+                        fmt::path(&mount.path),
+                        mount.registry_file.display()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Result<()> {
+        for change in self.client.subscribe()? {
+            let (path, added, removed) = match change? {
+                HandledResponse::TagChanged {
+                    path,
+                    added,
+                    removed,
+                } => (path, added, removed),
+                _ => continue,
+            };
+            match self.format {
+                OutputFormat::Json | OutputFormat::Yaml => {
+                    self.print_serialized(TagChange {
+                        path,
+                        added: added.into_iter().map(Tag::into_name).collect(),
+                        removed: removed.into_iter().map(Tag::into_name).collect(),
+                    })?;
+                }
+                OutputFormat::Default | OutputFormat::Table => {
+                    print!("{}:", fmt::path(&path));
+                    for tag in &added {
+                        print!(" +{}", fmt::tag(tag, self.background));
+                    }
+                    for tag in &removed {
+                        print!(" -{}", fmt::tag(tag, self.background));
+                    }
+                    println!();
+                }
+            }
         }
         Ok(())
     }
 
     fn search(&self, opts: SearchOpts) -> Result<()> {
-        let entries = self.client.search(opts.tags, opts.any)?;
+        if let Some(prefix) = &opts.prefix {
+            return self.search_prefix(self.tag_name(prefix), opts);
+        }
+        if let Some(name) = &opts.original_name {
+            let found = self.client.search_by_original_name(name.clone())?;
+            return self.print_found_entries(found, opts);
+        }
+        if let Some(fragment) = &opts.path_contains {
+            let found = self.client.search_by_path_fragment(fragment.clone())?;
+            return self.print_found_entries(found, opts);
+        }
+        if !opts.not_tag.is_empty()
+            || opts.path_prefix.is_some()
+            || opts.created_after.is_some()
+            || opts.created_before.is_some()
+        {
+            return self.search_filtered(opts);
+        }
+        if opts.scan {
+            return self.search_scan(opts);
+        }
+        let tags = self.tag_names(opts.tags);
+        let exec = opts.exec.is_some() || opts.exec_batch.is_some();
+        if opts.with_tags || (self.format == OutputFormat::Table && !exec) {
+            return self.search_with_tags(tags, opts.any, opts.with_tags);
+        }
+        let entries = self.client.search(tags.clone(), opts.any)?;
+        if let Some(command) = opts.exec {
+            let paths: Vec<_> = entries.iter().map(|(e, _)| e.path()).collect();
+            return exec::exec_each(&command, &paths).map_err(Error::from);
+        }
+        if let Some(command) = opts.exec_batch {
+            let paths: Vec<_> = entries.iter().map(|(e, _)| e.path()).collect();
+            return exec::exec_batch(&command, &paths).map_err(Error::from);
+        }
         match self.format {
             OutputFormat::Json | OutputFormat::Yaml => {
-                let entries: Vec<_> = entries.into_iter().map(|e| e.into_path_buf()).collect();
-                self.print_serialized(entries)?;
+                if opts.verbose {
+                    let entries: Vec<_> = entries
+                        .into_iter()
+                        .map(|(e, score)| (e.into_path_buf(), score))
+                        .collect();
+                    self.print_serialized(entries)?;
+                } else {
+                    let entries: Vec<_> = entries
+                        .into_iter()
+                        .map(|(e, _)| e.into_path_buf())
+                        .collect();
+                    self.print_serialized(entries)?;
+                }
+            }
+            // tables are printed by `search_with_tags`
+            OutputFormat::Default | OutputFormat::Table => {
+                for (entry, score) in entries {
+                    if opts.verbose {
+                        println!("{score:.2}\t{}", fmt::path(entry.path()));
+                    } else {
+                        println!("{}", fmt::path(entry.path()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints entries found by the `tags` along with their tags. Tables only show the matched
+    /// tags unless `all_tags` is set.
+    fn search_with_tags(&self, tags: Vec<String>, any: bool, all_tags: bool) -> Result<()> {
+        let found = self.client.search_with_tags(tags.clone(), any)?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let found: Vec<_> = found
+                    .into_iter()
+                    .map(|(entry, entry_tags)| {
+                        let names: Vec<_> = entry_tags.into_iter().map(Tag::into_name).collect();
+                        (entry.into_path_buf(), names)
+                    })
+                    .collect();
+                self.print_serialized(found)?;
+            }
+            OutputFormat::Table => {
+                let rows = found.into_iter().map(|(entry, mut entry_tags)| {
+                    if !all_tags {
+                        entry_tags.retain(|t| tags.iter().any(|name| name == t.name()));
+                    }
+                    [
+                        fmt::path(entry.path()).to_string(),
+                        self.joined_tags(&entry_tags),
+                    ]
+                });
+                let header = if all_tags { "Tags" } else { "Matched Tags" };
+                println!("{}", fmt::table(["Path", header], rows));
+            }
+            OutputFormat::Default => {
+                for (entry, entry_tags) in found {
+                    println!(
+                        "{}: {}",
+                        fmt::path(entry.path()),
+                        self.joined_tags(&entry_tags)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn search_filtered(&self, mut opts: SearchOpts) -> Result<()> {
+        let tags = self.tag_names(std::mem::take(&mut opts.tags));
+        let mut filter = if opts.any {
+            TagFilter::new().has_any_tags(tags)
+        } else {
+            TagFilter::new().has_all_tags(tags)
+        };
+        for tag in self.tag_names(std::mem::take(&mut opts.not_tag)) {
+            filter = filter.not_has_tag(tag);
+        }
+        if let Some(prefix) = &opts.path_prefix {
+            let prefix = if prefix.is_absolute() {
+                prefix.clone()
+            } else {
+                self.base_dir.join(prefix)
+            };
+            filter = filter.path_starts_with(prefix);
+        }
+        if let Some(time) = opts.created_after {
+            filter = filter.created_after(time);
+        }
+        if let Some(time) = opts.created_before {
+            filter = filter.created_before(time);
+        }
+        let found = self.client.search_filtered(filter.build())?;
+        self.print_found_entries(found, opts)
+    }
+
+    /// Prints paths of entries `found` by their name or path or runs the commands of `opts` on
+    /// them.
+    /// Searches files under the base directory by tags saved in their extended attributes.
+    fn search_scan(&self, mut opts: SearchOpts) -> Result<()> {
+        let tags = self.tag_names(std::mem::take(&mut opts.tags));
+        let max_depth = self.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        let found = search_files_with_tags(&self.base_dir, max_depth, &tags, opts.any)
+            .map_err(|e| AppError::Search(e.to_string()))?;
+        let found = found.into_iter().map(EntryData::new).collect();
+        self.print_found_entries(found, opts)
+    }
+
+    fn print_found_entries(&self, found: Vec<EntryData>, opts: SearchOpts) -> Result<()> {
+        let paths: Vec<_> = found.iter().map(|e| e.path()).collect();
+        if let Some(command) = opts.exec {
+            return exec::exec_each(&command, &paths).map_err(Error::from);
+        }
+        if let Some(command) = opts.exec_batch {
+            return exec::exec_batch(&command, &paths).map_err(Error::from);
+        }
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(paths)?,
+            OutputFormat::Table => {
+                let found = self.client.inspect_files(paths, false)?.entries;
+                let rows = found.into_iter().map(|(entry, mut tags)| {
+                    tags.sort_unstable();
+                    [fmt::path(entry.path()).to_string(), self.joined_tags(&tags)]
+                });
+                println!("{}", fmt::table(["Path", "Tags"], rows));
+            }
+            OutputFormat::Default => {
+                for path in paths {
+                    println!("{}", fmt::path(path));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn search_prefix(&self, prefix: String, opts: SearchOpts) -> Result<()> {
+        let found = self.client.search_by_tag_prefix(prefix)?;
+        if opts.exec.is_some() || opts.exec_batch.is_some() {
+            let paths: std::collections::BTreeSet<_> = found
+                .iter()
+                .flat_map(|(_, entries)| entries.iter().map(|e| e.path()))
+                .collect();
+            let paths: Vec<_> = paths.into_iter().collect();
+            return match (opts.exec, opts.exec_batch) {
+                (Some(command), _) => exec::exec_each(&command, &paths),
+                (_, Some(command)) => exec::exec_batch(&command, &paths),
+                _ => unreachable!(),
+            }
+            .map_err(Error::from);
+        }
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let found: std::collections::BTreeMap<_, _> = found
+                    .into_iter()
+                    .map(|(tag, entries)| {
+                        let paths: Vec<_> =
+                            entries.into_iter().map(|e| e.into_path_buf()).collect();
+                        (tag.into_name(), paths)
+                    })
+                    .collect();
+                self.print_serialized(found)?;
+            }
+            OutputFormat::Table => {
+                let mut matched: std::collections::BTreeMap<_, Vec<_>> = Default::default();
+                for (tag, entries) in found {
+                    for entry in entries {
+                        matched
+                            .entry(entry.into_path_buf())
+                            .or_default()
+                            .push(tag.clone());
+                    }
+                }
+                let rows = matched
+                    .into_iter()
+                    .map(|(path, tags)| [fmt::path(path).to_string(), self.joined_tags(&tags)]);
+                println!("{}", fmt::table(["Path", "Matched Tags"], rows));
             }
             OutputFormat::Default => {
-                for entry in entries {
-                    println!("{}", fmt::path(entry.path()));
+                for (tag, entries) in found {
+                    println!("{}", fmt::tag(&tag, self.background));
+                    for entry in entries {
+                        println!("\t{}", fmt::path(entry.path()));
+                    }
                 }
             }
         }
@@ -298,30 +1076,1064 @@ impl App {
     }
 
     fn cp(&mut self, opts: CpOpts) -> Result<()> {
-        if opts.glob {
+        let summary = if opts.glob {
             let glob = self.glob(&opts.paths[0])?;
+            self.client.copy_tags_pattern(opts.input_path, glob)?
+        } else {
+            self.client.copy_tags(opts.input_path, opts.paths)?
+        };
+        self.report_batch("copied", summary)
+    }
+
+    fn edit(&mut self, opts: EditOpts) -> Result<()> {
+        let color = opts
+            .color
+            .map(|color| parse_color(color).map_err(AppError::ParseColor))
+            .transpose()?;
+        let system = if opts.system {
+            Some(true)
+        } else if opts.no_system {
+            Some(false)
+        } else {
+            None
+        };
+
+        self.client
+            .edit_tag(self.tag_name(opts.tag), color, opts.weight, system)
+            .map(|_| ())
+    }
+
+    fn create_tag(&mut self, opts: CreateTagOpts) -> Result<()> {
+        let mut tag = self.new_tag(opts.name);
+        if let Some(color) = opts.color {
+            tag.set_color(&parse_color(color).map_err(AppError::ParseColor)?);
+        }
+        self.client
+            .create_tag(tag.name().to_string(), *tag.color(), opts.description)
+    }
+
+    fn tag_meta(&self, opts: TagMetaOpts) -> Result<()> {
+        let tag = self.tag_name(&opts.tag);
+        if opts.set.is_empty() && opts.unset.is_empty() {
+            let meta = self
+                .client
+                .tag_meta(Some(tag.clone()))?
+                .remove(&tag)
+                .unwrap_or_default();
+            match self.format {
+                OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(meta)?,
+                OutputFormat::Default | OutputFormat::Table => {
+                    for (key, value) in meta {
+                        println!("{key}: {value}");
+                    }
+                }
+            }
+            return Ok(());
+        }
+        for key in opts.unset {
+            self.client.set_tag_meta(tag.clone(), key, None)?;
+        }
+        for (key, value) in opts.set {
+            self.client.set_tag_meta(tag.clone(), key, Some(value))?;
+        }
+        Ok(())
+    }
+
+    fn capacity(&self, opts: CapacityOpts) -> Result<()> {
+        let capacity = self.client.xattr_capacity(&opts.path)?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(capacity)?,
+            OutputFormat::Default | OutputFormat::Table => {
+                let xattrs = capacity.capacity;
+                println!(
+                    "{}: {} tag(s), {} bytes used by {} attribute(s)",
+                    opts.path.display(),
+                    capacity.tags,
+                    xattrs.used,
+                    xattrs.attributes
+                );
+                println!(
+                    "about {} more tag(s) of {} bytes fit in {} bytes left",
+                    capacity.tags_left, capacity.tag_size, xattrs.free
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn export_xattrs(&self, opts: ExportXattrsOpts) -> Result<()> {
+        let files = if opts.glob {
+            let glob = self.glob(&opts.paths[0])?;
+            self.client.inspect_files_raw_pattern(glob)?
+        } else {
+            self.client.inspect_files_raw(opts.paths)?
+        };
+
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let files: std::collections::BTreeMap<_, std::collections::BTreeMap<_, _>> = files
+                    .into_iter()
+                    .map(|(path, xattrs)| (path, xattrs.into_iter().collect()))
+                    .collect();
+                self.print_serialized(files)?;
+            }
+            OutputFormat::Default | OutputFormat::Table => {
+                if opts.script {
+                    println!("#!/bin/bash\nset -e\n");
+                }
+                for (path, xattrs) in files {
+                    let path = exec::shell_quote(&path.to_string_lossy());
+                    for (key, value) in xattrs {
+                        let value = if opts.base64 {
+                            format!("0s{}", base64::encode(value))
+                        } else {
+                            value
+                        };
+                        println!(
+                            "setfattr -n {} -v {} {path}",
+                            exec::shell_quote(&key),
+                            exec::shell_quote(&value)
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn export_graph(&mut self, opts: ExportGraphOpts) -> Result<()> {
+        let dot = self.client.export_graph()?;
+        let graph = match opts.format {
+            GraphFormat::Dot => dot.into_bytes(),
+            GraphFormat::Svg => render_svg(&dot).map_err(AppError::RenderGraph)?,
+        };
+
+        if let Some(output) = opts.output {
+            std::fs::write(&output, graph).map_err(|e| AppError::WriteOutput(output, e))?;
+        } else {
+            println!("{}", String::from_utf8_lossy(&graph));
+        }
+        Ok(())
+    }
+
+    fn tag_report(&self, opts: TagReportOpts) -> Result<()> {
+        let tags = self.client.list_tags(true)?;
+        let meta = if opts.include_metadata {
+            Some(self.client.tag_meta(None)?)
+        } else {
+            None
+        };
+        let report = report::tag_report(tags, meta.as_ref());
+
+        if let Some(output) = opts.output {
+            std::fs::write(&output, report).map_err(|e| AppError::WriteOutput(output, e))?;
+        } else {
+            print!("{report}");
+        }
+        Ok(())
+    }
+
+    fn recent(&self, opts: RecentOpts) -> Result<()> {
+        let now = chrono::Utc::now();
+        let since = opts.since.map(|since| time_before(now, since));
+        let entries = self.client.recent_entries(since, opts.limit)?;
+        // entries saved before creation times were recorded have the Unix epoch as their time
+        let added = |entry: &EntryData| {
+            if entry.created_at().timestamp() == 0 {
+                "unknown".to_string()
+            } else {
+                format!("{} ago", fmt::remaining(now - entry.created_at()))
+            }
+        };
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(entries)?,
+            OutputFormat::Table => {
+                let rows = entries
+                    .iter()
+                    .map(|entry| [fmt::path(entry.path()).to_string(), added(entry)]);
+                println!("{}", fmt::table(["Path", "Added"], rows));
+            }
+            OutputFormat::Default => {
+                for entry in &entries {
+                    println!("{}\t{}", added(entry), fmt::path(entry.path()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn random(&self, opts: RandomOpts) -> Result<()> {
+        use rand::seq::SliceRandom;
+
+        let tags = self.tag_names(split_tags(opts.tags));
+        let paths: Vec<_> = if tags.is_empty() {
             self.client
-                .copy_tags_pattern(opts.input_path, glob)
-                .map_err(Error::from)
-                .map(|_| ())
+                .list_files(false)?
+                .into_iter()
+                .map(|(entry, _)| entry.into_path_buf())
+                .collect()
         } else {
             self.client
-                .copy_tags(opts.input_path, opts.paths)
-                .map_err(Error::from)
-                .map(|_| ())
+                .search(tags, opts.any)?
+                .into_iter()
+                .map(|(entry, _)| entry.into_path_buf())
+                .collect()
+        };
+        let picked: Vec<_> = paths
+            .choose_multiple(&mut rand::thread_rng(), opts.count.unwrap_or(1))
+            .collect();
+
+        if let Some(command) = opts.exec {
+            return exec::exec_batch(&command, &picked).map_err(Error::from);
         }
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let picked: Vec<_> = picked
+                    .into_iter()
+                    .map(|path| RandomPick { path: path.clone() })
+                    .collect();
+                self.print_serialized(picked)?;
+            }
+            OutputFormat::Default | OutputFormat::Table => {
+                for path in picked {
+                    println!("{}", fmt::path(path));
+                }
+            }
+        }
+        Ok(())
     }
 
-    fn edit(&mut self, opts: EditOpts) -> Result<()> {
-        let c = parse_color(&opts.color).map_err(AppError::ParseColor)?;
+    fn audit_log(&self, opts: AuditLogOpts) -> Result<()> {
+        let since = opts
+            .since
+            .map(|since| time_before(chrono::Utc::now(), since));
+        let entries = self.client.audit_log(since, opts.limit)?;
+        let time = |entry: &AuditLogEntry| {
+            entry
+                .timestamp
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+        let outcome = |entry: &AuditLogEntry| if entry.succeeded { "ok" } else { "failed" };
+        let paths = |entry: &AuditLogEntry| {
+            entry
+                .paths
+                .iter()
+                .map(|p| fmt::path(p).to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(entries)?,
+            OutputFormat::Table => {
+                let rows = entries.iter().map(|entry| {
+                    [
+                        time(entry),
+                        entry.user.clone(),
+                        entry.operation.clone(),
+                        outcome(entry).to_string(),
+                        entry.tags.join(","),
+                        paths(entry),
+                    ]
+                });
+                println!(
+                    "{}",
+                    fmt::table(
+                        ["Time", "User", "Operation", "Result", "Tags", "Paths"],
+                        rows
+                    )
+                );
+            }
+            OutputFormat::Default => {
+                for entry in &entries {
+                    println!(
+                        "{}\t{}\t{}\t{}\t{}\t{}",
+                        time(entry),
+                        entry.user,
+                        entry.operation,
+                        outcome(entry),
+                        entry.tags.join(","),
+                        paths(entry)
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn verify(&self, opts: VerifyOpts) -> Result<()> {
+        let report = self.client.verify_tags(opts.full)?;
+        let names = |tags: &[Tag]| tags.iter().map(Tag::name).collect::<Vec<_>>().join(",");
+        let rows = report
+            .stale
+            .iter()
+            .map(|path| (path, "missing", String::new(), String::new()))
+            .chain(
+                report
+                    .tag_mismatches
+                    .iter()
+                    .map(|(path, registry, xattrs)| {
+                        (path, "tag mismatch", names(registry), names(xattrs))
+                    }),
+            );
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(&report)?,
+            OutputFormat::Table => {
+                let rows = rows.map(|(path, kind, registry, xattrs)| {
+                    [
+                        fmt::path(path).to_string(),
+                        kind.to_string(),
+                        registry,
+                        xattrs,
+                    ]
+                });
+                println!(
+                    "{}",
+                    fmt::table(["Path", "Difference", "Registry", "Xattrs"], rows)
+                );
+            }
+            OutputFormat::Default => {
+                for (path, kind, registry, xattrs) in rows {
+                    if registry.is_empty() && xattrs.is_empty() {
+                        println!("{}: {kind}", fmt::path(path));
+                    } else {
+                        println!(
+                            "{}: {kind}, registry: {registry}, xattrs: {xattrs}",
+                            fmt::path(path)
+                        );
+                    }
+                }
+            }
+        }
+        for (path, e) in &report.errors {
+            eprintln!(
+                "{}",
+                format!("failed to read tags of {} - {e}", path.display()).yellow()
+            );
+        }
+        if !self.quiet {
+            eprintln!(
+                "checked {} file(s), skipped {} unchanged file(s)",
+                report.checked, report.skipped
+            );
+        }
+        if report.count_drift {
+            eprintln!(
+                "{}",
+                "counts of tags kept by the registry don't match its tags, restart the daemon to rebuild them"
+                    .yellow()
+            );
+        }
+        Ok(())
+    }
+
+    fn diff_fs(&self, opts: DiffFsOpts) -> Result<()> {
+        let diff =
+            self.client
+                .diff_with_filesystem(self.base_dir.clone(), self.max_depth, opts.fix)?;
+        let names = |tags: &[Tag]| tags.iter().map(Tag::name).collect::<Vec<_>>().join(",");
+        let rows = diff
+            .entries_in_registry_only
+            .iter()
+            .map(|path| (path, "registry only", String::new(), String::new()))
+            .chain(
+                diff.entries_in_xattrs_only
+                    .iter()
+                    .map(|path| (path, "xattrs only", String::new(), String::new())),
+            )
+            .chain(diff.tag_mismatches.iter().map(|(path, registry, xattrs)| {
+                (path, "tag mismatch", names(registry), names(xattrs))
+            }));
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(&diff)?,
+            OutputFormat::Table => {
+                let rows = rows.map(|(path, kind, registry, xattrs)| {
+                    [
+                        fmt::path(path).to_string(),
+                        kind.to_string(),
+                        registry,
+                        xattrs,
+                    ]
+                });
+                println!(
+                    "{}",
+                    fmt::table(["Path", "Difference", "Registry", "Xattrs"], rows)
+                );
+            }
+            OutputFormat::Default => {
+                for (path, kind, registry, xattrs) in rows {
+                    if registry.is_empty() && xattrs.is_empty() {
+                        println!("{}: {kind}", fmt::path(path));
+                    } else {
+                        println!(
+                            "{}: {kind}, registry: {registry}, xattrs: {xattrs}",
+                            fmt::path(path)
+                        );
+                    }
+                }
+            }
+        }
+        if opts.fix && !diff.is_empty() && !self.quiet {
+            eprintln!("updated the registry to match the files");
+        }
+        Ok(())
+    }
+
+    fn recent_tags(&self) -> Result<()> {
+        let tags = self.client.recent_tags()?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let tags: Vec<_> = tags.into_iter().map(Tag::into_name).collect();
+                self.print_serialized(tags)?;
+            }
+            OutputFormat::Default | OutputFormat::Table => {
+                for tag in tags {
+                    println!("{}", fmt::tag(&tag, self.background));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Tags files listed in a mapping file. Malformed lines and files that failed to be tagged
+    /// are reported and skipped.
+    fn apply(&self, opts: ApplyOpts) -> Result<()> {
+        let content = std::fs::read_to_string(&opts.mapping)
+            .map_err(|e| AppError::ReadMapping(opts.mapping.clone(), e))?;
+        let base_dir = match opts.mapping.parent() {
+            Some(parent) => self.base_dir.join(parent),
+            None => self.base_dir.clone(),
+        };
+        let (entries, malformed) = parse_mapping(&content, &base_dir);
+
+        for line in &malformed {
+            eprintln!(
+                "warning: skipping line {} of `{}` - {}",
+                line.line,
+                opts.mapping.display(),
+                line.reason
+            );
+        }
 
+        let mut failed = 0;
+        for entry in entries {
+            let tags: Vec<_> = entry.tags.iter().map(|t| self.new_tag(t)).collect();
+            if opts.dry_run {
+                let tags: Vec<_> = tags
+                    .iter()
+                    .map(|t| fmt::tag(t, self.background).to_string())
+                    .collect();
+                println!("{}: {}", fmt::path(&entry.path), tags.join(" "));
+                continue;
+            }
+            match self
+                .client
+                .tag_files([&entry.path], tags, None, None, false, &self.colors)
+            {
+                Ok(summary) if summary.failed == 0 => {}
+                Ok(summary) => {
+                    eprintln!("line {}: {}", entry.line, summary.errors.join(", "));
+                    failed += 1;
+                }
+                Err(e) => {
+                    eprintln!("line {}: {e}", entry.line);
+                    failed += 1;
+                }
+            }
+        }
+
+        if failed > 0 {
+            return Err(AppError::ApplyMapping(failed).into());
+        }
+        Ok(())
+    }
+
+    fn set_json(&self, opts: SetJsonOpts) -> Result<()> {
+        use std::io::Read;
+
+        let mut content = String::new();
+        match &opts.file {
+            Some(file) => {
+                std::fs::File::open(file).and_then(|mut f| f.read_to_string(&mut content))
+            }
+            None => std::io::stdin().read_to_string(&mut content),
+        }
+        .map_err(AppError::ReadRecords)?;
+        let records =
+            parse_json_records(&content, &self.base_dir).map_err(AppError::ParseRecords)?;
+
+        let mut failed = 0;
+        for (index, record) in records.into_iter().enumerate() {
+            let result = record
+                .and_then(|record| self.record_tags(&record).map(|tags| (record.path, tags)))
+                .and_then(|(path, tags)| {
+                    // colors of the records are kept as they are
+                    self.client
+                        .tag_files([path], tags, None, None, false, &[])
+                        .map_err(|e| e.to_string())
+                })
+                .and_then(|summary| {
+                    if summary.failed > 0 {
+                        Err(summary.errors.join(", "))
+                    } else {
+                        Ok(())
+                    }
+                });
+            if let Err(e) = result {
+                eprintln!("record {index}: {e}");
+                failed += 1;
+            }
+        }
+
+        if failed > 0 {
+            return Err(AppError::ApplyRecords(failed).into());
+        }
+        Ok(())
+    }
+
+    /// Creates tags of the `record` with colors parsed from the record.
+    fn record_tags(&self, record: &JsonRecord) -> std::result::Result<Vec<Tag>, String> {
+        let mut tags = vec![];
+        for json_tag in &record.tags {
+            let mut tag = self.new_tag(&json_tag.name);
+            if let Some(color) = &json_tag.color {
+                let color =
+                    parse_color(color).map_err(|e| format!("tag `{}` - {e}", json_tag.name))?;
+                tag.set_color(&color);
+            }
+            tag.set_description(json_tag.description.as_ref());
+            for (key, value) in &json_tag.metadata {
+                tag.set_metadata(key, value);
+            }
+            tags.push(tag);
+        }
+        Ok(tags)
+    }
+
+    fn count(&self, opts: CountOpts) -> Result<()> {
+        use std::collections::BTreeMap;
+
+        let names = self.tag_names(opts.tags);
+        let tags = self.client.list_tags(true)?;
+        let files = tagged_files(&tags, &names);
+        let missing: Vec<_> = names
+            .iter()
+            .filter(|name| !files.contains_key(name.as_str()))
+            .collect();
+
+        if opts.total {
+            let total = count_total(&names, &files, opts.any);
+            match self.format {
+                OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(total)?,
+                OutputFormat::Default | OutputFormat::Table => println!("{total}"),
+            }
+        } else {
+            let counts: BTreeMap<_, _> = names
+                .iter()
+                .map(|name| {
+                    let count = files
+                        .get(name.as_str())
+                        .map(|f| f.len())
+                        .unwrap_or_default();
+                    (name.as_str(), count)
+                })
+                .collect();
+            match self.format {
+                OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(&counts)?,
+                OutputFormat::Default | OutputFormat::Table => {
+                    for name in &names {
+                        println!("{name}: {}", counts[name.as_str()]);
+                    }
+                }
+            }
+        }
+
+        for name in &missing {
+            eprintln!("tag `{name}` doesn't exist");
+        }
+        if !missing.is_empty() {
+            return Err(AppError::PredicateFalse.into());
+        }
+        Ok(())
+    }
+
+    fn tag_exists(&self, name: String) -> Result<()> {
+        if self.client.tag_exists(self.tag_name(name))? {
+            Ok(())
+        } else {
+            Err(AppError::PredicateFalse.into())
+        }
+    }
+
+    fn link(&self, opts: LinkOpts) -> Result<()> {
         self.client
-            .edit_tag(opts.tag, c)
-            .map_err(Error::from)
-            .map(|_| ())
+            .add_implication_rule(self.tag_name(opts.tag), self.tag_name(opts.implies))
+    }
+
+    fn list_rules(&self) -> Result<()> {
+        let rules = self.client.list_implication_rules()?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(rules)?,
+            OutputFormat::Default | OutputFormat::Table => {
+                for rule in rules {
+                    println!("{} -> {}", rule.if_tag, rule.then_tag);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn overlap(&self, opts: OverlapOpts) -> Result<()> {
+        let overlap = self
+            .client
+            .tag_overlap(self.tag_name(&opts.a), self.tag_name(&opts.b))?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(overlap)?,
+            OutputFormat::Default | OutputFormat::Table => {
+                println!("both: {}", overlap.both.len());
+                println!("only {}: {}", opts.a, overlap.only_a.len());
+                println!("only {}: {}", opts.b, overlap.only_b.len());
+            }
+        }
+        Ok(())
+    }
+
+    fn same_tags(&self, opts: SameTagsOpts) -> Result<()> {
+        let no_follow = self.no_follow(opts.follow, opts.no_follow);
+        let tags = |path: &PathBuf| -> Result<Vec<Tag>> {
+            let found = self.client.inspect_files([path], no_follow)?;
+            let mut tags: Vec<_> = found
+                .entries
+                .into_iter()
+                .flat_map(|(_, tags)| tags)
+                .collect();
+            tags.sort_unstable();
+            tags.dedup();
+            Ok(tags)
+        };
+        let (a, b) = (tags(&opts.a)?, tags(&opts.b)?);
+        let (common, only_a): (Vec<_>, Vec<_>) = a.iter().partition(|tag| b.contains(tag));
+        let only_b: Vec<_> = b.iter().filter(|tag| !a.contains(tag)).collect();
+        let same = only_a.is_empty() && only_b.is_empty();
+
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let names = |tags: &[&Tag]| tags.iter().map(|t| t.name().to_string()).collect();
+                self.print_serialized(TagComparison {
+                    same,
+                    common: names(&common),
+                    only_a: names(&only_a),
+                    only_b: names(&only_b),
+                })?;
+            }
+            OutputFormat::Default | OutputFormat::Table => {
+                let joined = |tags: Vec<&Tag>| {
+                    self.joined_tags(&tags.into_iter().cloned().collect::<Vec<_>>())
+                };
+                println!("common: {}", joined(common));
+                if !same {
+                    println!("only {}: {}", fmt::path(&opts.a), joined(only_a));
+                    println!("only {}: {}", fmt::path(&opts.b), joined(only_b));
+                }
+            }
+        }
+        if same {
+            Ok(())
+        } else {
+            Err(AppError::PredicateFalse.into())
+        }
+    }
+
+    fn rename_prefix(&self, opts: RenamePrefixOpts) -> Result<()> {
+        let mapping = self
+            .client
+            .rename_tags_matching(opts.prefix, opts.replacement)?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let mapping: std::collections::BTreeMap<_, _> = mapping.into_iter().collect();
+                self.print_serialized(mapping)?;
+            }
+            OutputFormat::Default | OutputFormat::Table => {
+                for (from, to) in mapping {
+                    println!("{from} -> {to}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn cat(&self, opts: CatOpts) -> Result<()> {
+        let name = self.tag_name(&opts.tag);
+        let tags = self.client.list_tags(true)?;
+        let files = cat_entries(&tags, &name, opts.sort.unwrap_or(SortKey::Path))
+            .ok_or_else(|| AppError::TagNotFound(opts.tag.clone()))?;
+
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(files)?,
+            OutputFormat::Default | OutputFormat::Table => {
+                for file in files {
+                    println!(
+                        "{} {:>10} {}",
+                        fmt::path(format!("{:<60}", file.path.display())),
+                        file.size,
+                        file.mtime
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn maintenance(&self) -> Result<()> {
+        let summary = self.client.run_maintenance()?;
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(&summary)?,
+            OutputFormat::Default | OutputFormat::Table => {
+                println!(
+                    "evicted {} stale entries, removed {} expired tags",
+                    summary.evicted, summary.expired_tags
+                );
+                for backup in &summary.backups {
+                    println!("saved backup {}", fmt::path(backup));
+                }
+                for error in &summary.errors {
+                    eprintln!("{}", error.red());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> Result<()> {
+        let mut status = Status {
+            daemon_running: self.client.ping().is_ok(),
+            registry: None,
+            entries: None,
+            tags: None,
+            most_used_tags: vec![],
+            stale_entries: vec![],
+            watch_failures: None,
+            last_maintenance: None,
+            unsaved_added: None,
+            unsaved_removed: None,
+        };
+        if status.daemon_running {
+            let stats = self.client.get_stats()?;
+            status.registry = Some(stats.path);
+            status.entries = Some(stats.entries);
+            status.tags = Some(stats.tags);
+            status.most_used_tags = stats.most_used_tags;
+            status.watch_failures = Some(stats.watch_failures);
+            status.last_maintenance = stats.last_maintenance;
+            status.unsaved_added = stats.unsaved_added;
+            status.unsaved_removed = stats.unsaved_removed;
+            status.stale_entries = self
+                .client
+                .verify()?
+                .into_iter()
+                .map(|e| e.into_path_buf())
+                .collect();
+        }
+        let unwatched = status
+            .watch_failures
+            .as_ref()
+            .map(|f| f.unwatched)
+            .unwrap_or_default();
+        // the daemon saves the registry after every change so unsaved entries mean saving failed
+        let unsaved =
+            status.unsaved_added.unwrap_or_default() + status.unsaved_removed.unwrap_or_default();
+        let problems = usize::from(!status.daemon_running)
+            + status.stale_entries.len()
+            + unwatched
+            + usize::from(unsaved > 0);
+
+        match self.format {
+            OutputFormat::Json | OutputFormat::Yaml => self.print_serialized(&status)?,
+            OutputFormat::Default | OutputFormat::Table => {
+                if !status.daemon_running {
+                    println!("daemon: {}", "not running".red());
+                } else {
+                    println!("daemon: {}", "running".green());
+                    if let Some(registry) = &status.registry {
+                        println!("registry: {}", fmt::path(registry));
+                    }
+                    println!("entries: {}", status.entries.unwrap_or_default());
+                    println!("tags: {}", status.tags.unwrap_or_default());
+                    if !status.most_used_tags.is_empty() {
+                        let tags: Vec<_> = status
+                            .most_used_tags
+                            .iter()
+                            .map(|(tag, count)| format!("{tag} ({count})"))
+                            .collect();
+                        println!("most used tags: {}", tags.join(", "));
+                    }
+                    match (status.unsaved_added, status.unsaved_removed) {
+                        (Some(0), Some(0)) => println!("unsaved changes: {}", "none".green()),
+                        (Some(added), Some(removed)) => println!(
+                            "unsaved changes: {}",
+                            format!("{added} entries added, {removed} removed").yellow()
+                        ),
+                        _ => println!("unsaved changes: {}", "unknown".yellow()),
+                    }
+                    if status.stale_entries.is_empty() {
+                        println!("stale entries: {}", "none".green());
+                    } else {
+                        println!(
+                            "stale entries: {}",
+                            status.stale_entries.len().to_string().yellow()
+                        );
+                        for entry in &status.stale_entries {
+                            println!("\t{}", entry.display().to_string().yellow());
+                        }
+                    }
+                    match &status.watch_failures {
+                        Some(failures) if failures.unwatched > 0 => {
+                            println!(
+                                "unmonitored files: {}",
+                                failures.unwatched.to_string().red()
+                            );
+                            if failures.limit_reached {
+                                println!(
+                                    "\twatch limit reached, increase fs.inotify.max_user_watches"
+                                );
+                            } else if let Some(error) = &failures.last_error {
+                                println!("\t{error}");
+                            }
+                        }
+                        _ => println!("unmonitored files: {}", "none".green()),
+                    }
+                    match status.last_maintenance {
+                        Some(time) => println!("last maintenance: {}", time.to_rfc3339()),
+                        None => println!("last maintenance: never"),
+                    }
+                }
+            }
+        }
+
+        if problems > 0 {
+            return Err(AppError::StatusProblems(problems).into());
+        }
+        Ok(())
     }
 
     fn glob(&self, pattern: impl Into<String>) -> Result<Glob> {
         Glob::new(pattern.into(), Some(self.base_dir.clone()), self.max_depth).map_err(Error::Glob)
     }
 }
+
+fn ipc_page(page: PageOpts) -> Page {
+    Page {
+        offset: page.offset,
+        limit: page.limit,
+    }
+}
+
+/// Prints which part of a listing of `total` items was shown to stderr so that the output itself
+/// stays parseable.
+fn print_page_summary(page: PageOpts, shown: usize, total: usize) {
+    if !page.is_paged() {
+        return;
+    }
+    if shown == 0 {
+        eprintln!("showing 0 of {total}");
+    } else {
+        eprintln!(
+            "showing {}-{} of {total}",
+            page.offset + 1,
+            page.offset + shown
+        );
+    }
+}
+
+/// Returns the time `duration` before `now` or the earliest representable time if it would
+/// overflow.
+fn time_before(
+    now: chrono::DateTime<chrono::Utc>,
+    duration: Duration,
+) -> chrono::DateTime<chrono::Utc> {
+    chrono::Duration::from_std(duration)
+        .ok()
+        .and_then(|duration| now.checked_sub_signed(duration))
+        .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC)
+}
+
+/// Asks the user on the terminal whether to proceed with the `action`. Fails if stdin is not a
+/// terminal so that scripts have to confirm destructive actions explicitly.
+fn confirm(prompt: &str, action: &'static str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Err(AppError::ConfirmationRequired(action).into());
+    }
+    eprint!("{prompt} [y/N] ");
+    std::io::stderr()
+        .flush()
+        .map_err(AppError::ReadConfirmation)?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .map_err(AppError::ReadConfirmation)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Returns the paths of files tagged with each of the tags `names` that exist in `tags`.
+fn tagged_files<'a>(
+    tags: &'a std::collections::HashMap<Tag, Vec<EntryData>>,
+    names: &[String],
+) -> std::collections::BTreeMap<&'a str, std::collections::BTreeSet<&'a std::path::Path>> {
+    tags.iter()
+        .filter(|(tag, _)| names.iter().any(|name| name == tag.name()))
+        .map(|(tag, entries)| (tag.name(), entries.iter().map(|e| e.path()).collect()))
+        .collect()
+}
+
+/// Counts distinct files tagged with any of the tags `names` if `any` is set, otherwise files
+/// tagged with all of them. Tags missing from `files` have no files.
+fn count_total(
+    names: &[String],
+    files: &std::collections::BTreeMap<&str, std::collections::BTreeSet<&std::path::Path>>,
+    any: bool,
+) -> usize {
+    let mut sets = names
+        .iter()
+        .map(|name| files.get(name.as_str()).cloned().unwrap_or_default());
+    let first = sets.next().unwrap_or_default();
+    sets.fold(first, |acc, set| {
+        if any {
+            acc.union(&set).copied().collect()
+        } else {
+            acc.intersection(&set).copied().collect()
+        }
+    })
+    .len()
+}
+
+/// Returns files tagged with the tag `name` of the listed `tags` along with their size and
+/// modification time sorted by the `sort` key, or `None` if there is no such tag. Files whose
+/// metadata can't be read are skipped with a warning.
+fn cat_entries(
+    tags: &std::collections::HashMap<Tag, Vec<EntryData>>,
+    name: &str,
+    sort: SortKey,
+) -> Option<Vec<CatEntry>> {
+    let (_, entries) = tags.iter().find(|(tag, _)| tag.name() == name)?;
+
+    let mut files = vec![];
+    for entry in entries {
+        let metadata = match std::fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!(
+                    "warning: failed to read metadata of `{}` - {e}",
+                    entry.path().display()
+                );
+                continue;
+            }
+        };
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut entry_tags: Vec<_> = tags
+            .iter()
+            .filter(|(_, entries)| entries.contains(entry))
+            .map(|(tag, _)| tag.name().to_string())
+            .collect();
+        entry_tags.sort_unstable();
+        files.push(CatEntry {
+            path: entry.path().to_path_buf(),
+            size: metadata.len(),
+            mtime: chrono::DateTime::<chrono::Utc>::from(modified)
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            tags: entry_tags,
+            modified,
+        });
+    }
+
+    match sort {
+        SortKey::Path => files.sort_unstable_by(|a, b| a.path.cmp(&b.path)),
+        SortKey::Size => files.sort_by_key(|f| f.size),
+        SortKey::SizeDesc => files.sort_by_key(|f| std::cmp::Reverse(f.size)),
+        SortKey::Mtime => files.sort_by_key(|f| f.modified),
+        SortKey::MtimeDesc => files.sort_by_key(|f| std::cmp::Reverse(f.modified)),
+    }
+    Some(files)
+}
+
+/// Renders the `dot` graph as SVG by running the `dot` executable from Graphviz.
+fn render_svg(dot: &str) -> std::io::Result<Vec<u8>> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(dot.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "`dot` exited with {}",
+            output.status
+        )));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_files_of_tag_with_metadata() {
+        let dir = tempdir::TempDir::new("wutag-cat").unwrap();
+        let small = dir.path().join("small");
+        let large = dir.path().join("large");
+        let missing = dir.path().join("missing");
+        std::fs::write(&small, b"a").unwrap();
+        std::fs::write(&large, b"abc").unwrap();
+        let entry = |path: &PathBuf| EntryData::new(path);
+        let tags = std::collections::HashMap::from([
+            (
+                Tag::new("project", Color::Red),
+                vec![entry(&small), entry(&large), entry(&missing)],
+            ),
+            (Tag::new("big", Color::Blue), vec![entry(&large)]),
+        ]);
+
+        let files = cat_entries(&tags, "project", SortKey::SizeDesc).unwrap();
+        // files that no longer exist are skipped
+        let found: Vec<_> = files.iter().map(|f| (&f.path, f.size)).collect();
+        assert_eq!(found, vec![(&large, 3), (&small, 1)]);
+        assert_eq!(files[0].tags, vec!["big", "project"]);
+        assert!(files[0].mtime.ends_with('Z'));
+
+        let files = cat_entries(&tags, "project", SortKey::Path).unwrap();
+        assert_eq!(files[0].path, large);
+        assert!(cat_entries(&tags, "unknown", SortKey::Path).is_none());
+    }
+
+    #[test]
+    fn counts_files_of_tags() {
+        let entry = |path: &str| EntryData::new(path);
+        let tags = std::collections::HashMap::from([
+            (Tag::new("a", Color::Red), vec![entry("/1"), entry("/2")]),
+            (Tag::new("b", Color::Blue), vec![entry("/2"), entry("/3")]),
+            (Tag::new("c", Color::Green), vec![entry("/4")]),
+        ]);
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        let requested = names(&["a", "b", "missing"]);
+        let files = tagged_files(&tags, &requested);
+        assert_eq!(files.keys().copied().collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(files["b"].len(), 2);
+
+        let requested = names(&["a", "b"]);
+        assert_eq!(count_total(&requested, &files, true), 3);
+        assert_eq!(count_total(&requested, &files, false), 1);
+
+        // a missing tag has no files in common with the others
+        let requested = names(&["a", "missing"]);
+        assert_eq!(count_total(&requested, &files, true), 2);
+        assert_eq!(count_total(&requested, &files, false), 0);
+    }
+}
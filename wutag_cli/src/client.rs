@@ -1,13 +1,22 @@
 #![allow(dead_code)]
+use crate::offline::OfflineRegistry;
 use crate::Result;
+use chrono::{DateTime, Utc};
 use wutag_core::color::Color;
 use wutag_core::glob::Glob;
-use wutag_core::registry::EntryData;
-use wutag_core::tag::Tag;
-use wutag_ipc::{IpcClient, Request, Response};
+use wutag_core::registry::{
+    EntryData, PathMigration, RegistryFsDiff, RegistryMount, TagOverlap, VerifyReport,
+};
+use wutag_core::tag::{ImplicationRule, Tag, TagFilter, TagRule};
+use wutag_ipc::{
+    AuditLogEntry, BatchSummary, CircuitBreakerClient, ErrorCode, InspectFilesResult,
+    MaintenanceSummary, Page, Paged, RawXattrs, RegistryStats, Request, Response, ResponseError,
+    SetExactTagsResult, TagCapacity,
+};
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error as ThisError;
 
 #[derive(Debug, ThisError)]
@@ -16,6 +25,8 @@ pub enum ClientError {
     TagFiles(String),
     #[error("failed to untag files - {0}")]
     UntagFiles(String),
+    #[error("failed to set exact tags of files - {0}")]
+    SetExactTags(String),
     #[error("failed to edit tag - {0}")]
     EditTag(String),
     #[error("failed to copy tags - {0}")]
@@ -36,28 +47,139 @@ pub enum ClientError {
     Ping(String),
     #[error("failed to clear cache - {0}")]
     ClearCache(String),
+    #[error("failed to export graph - {0}")]
+    ExportGraph(String),
+    #[error("failed to list recent tags - {0}")]
+    RecentTags(String),
+    #[error("failed to clear recent tags - {0}")]
+    ClearRecentTags(String),
+    #[error("failed to rename tags - {0}")]
+    RenameTags(String),
+    #[error("failed to get registry stats - {0}")]
+    Stats(String),
+    #[error("failed to run maintenance - {0}")]
+    RunMaintenance(String),
+    #[error("failed to verify registry - {0}")]
+    Verify(String),
+    #[error("failed to verify tags of files - {0}")]
+    VerifyTags(String),
+    #[error("failed to compute tag overlap - {0}")]
+    TagOverlap(String),
+    #[error("failed to check if tag exists - {0}")]
+    TagExists(String),
+    #[error("failed to add implication rule - {0}")]
+    AddImplicationRule(String),
+    #[error("failed to list implication rules - {0}")]
+    ListImplicationRules(String),
+    #[error("failed to migrate namespace - {0}")]
+    MigrateNamespace(String),
+    #[error("failed to subscribe to tag changes - {0}")]
+    Subscribe(String),
+    #[error("failed to list colors - {0}")]
+    ListColors(String),
+    #[error("failed to list mounts - {0}")]
+    ListMounts(String),
+    #[error("failed to list recent entries - {0}")]
+    RecentEntries(String),
+    #[error("failed to set tag metadata - {0}")]
+    SetTagMeta(String),
+    #[error("failed to get tag metadata - {0}")]
+    GetTagMeta(String),
+    #[error("failed to create tag - {0}")]
+    CreateTag(String),
+    #[error("failed to migrate paths - {0}")]
+    AbsolutizePaths(String),
+    #[error("failed to inspect extended attributes - {0}")]
+    InspectFilesRaw(String),
+    #[error("failed to estimate capacity of extended attributes - {0}")]
+    XattrCapacity(String),
+    #[error("failed to get audit log - {0}")]
+    GetAuditLog(String),
+    #[error("failed to compare registry with the filesystem - {0}")]
+    DiffWithFilesystem(String),
+    #[error("files belong to registries of different mounts ({}), handle them separately", format_paths(.0))]
+    MixedMounts(Vec<PathBuf>),
+    #[error("failed to set log level - {0}")]
+    SetLogLevel(String),
+    /// The pattern of a request matched no files. Not a failure, reported as a warning.
+    #[error("pattern `{0}` matched no files")]
+    NoMatches(String),
+    /// Error of the daemon with a code, used to choose the exit status.
+    #[error("{0}")]
+    Response(#[from] ResponseError),
     #[error("unexpected response {0:?}")]
     UnexpectedResponse(HandledResponse),
 }
 
 #[derive(Debug)]
 pub enum HandledResponse {
-    TagFiles,
-    UntagFiles,
+    TagFiles(BatchSummary),
+    UntagFiles(BatchSummary),
+    SetExactTags(SetExactTagsResult),
     EditTag,
-    CopyTags,
-    ClearFiles,
+    CopyTags(BatchSummary),
+    ClearFiles(BatchSummary),
     ClearTags,
-    ListTags(HashMap<Tag, Vec<EntryData>>),
-    ListFiles(Vec<(EntryData, Vec<Tag>)>),
-    InspectFiles(Vec<(EntryData, Vec<Tag>)>),
+    ListTags(Paged<(Tag, Vec<EntryData>)>),
+    ListFiles(Paged<(EntryData, Vec<Tag>)>),
+    InspectFiles(InspectFilesResult),
+    InspectFilesRaw(RawXattrs),
+    XattrCapacity(TagCapacity),
     Search(Vec<EntryData>),
+    SearchWeighted(Vec<(EntryData, f32)>),
+    SearchWithTags(Vec<(EntryData, Vec<Tag>)>),
+    SearchByTagPrefix(Vec<(Tag, Vec<EntryData>)>),
+    SearchByOriginalName(Vec<EntryData>),
+    SearchByPathFragment(Vec<EntryData>),
+    SearchFiltered(Vec<EntryData>),
     Ping,
     ClearCache,
+    ExportGraph(String),
+    RecentTags(Vec<Tag>),
+    ClearRecentTags,
+    RenameTagsMatching(Vec<(String, String)>),
+    Stats(Box<RegistryStats>),
+    RunMaintenance(MaintenanceSummary),
+    Verify(Vec<EntryData>),
+    VerifyTags(Box<VerifyReport>),
+    TagOverlap(TagOverlap),
+    TagExists(bool),
+    AddImplicationRule,
+    ListImplicationRules(Vec<ImplicationRule>),
+    SetLogLevel,
+    MigrateNamespace(usize),
+    Subscribe,
+    ListMounts(Vec<RegistryMount>),
+    ListColors(BTreeMap<String, Vec<String>>),
+    RecentEntries(Vec<EntryData>),
+    SetTagMeta,
+    GetTagMeta(BTreeMap<String, BTreeMap<String, String>>),
+    CreateTag,
+    AbsolutizePaths(PathMigration),
+    GetAuditLog(Vec<AuditLogEntry>),
+    DiffWithFilesystem(RegistryFsDiff),
+    TagChanged {
+        path: PathBuf,
+        added: Vec<Tag>,
+        removed: Vec<Tag>,
+    },
 }
 
 pub struct Client {
-    client: IpcClient,
+    client: CircuitBreakerClient,
+    /// Root directory of the mount whose registry is used for all requests.
+    registry: Option<PathBuf>,
+    /// Registry answering read requests in place of the daemon with `--offline`.
+    offline: Option<OfflineRegistry>,
+}
+
+/// Formats the `paths` as a list of quoted paths separated with commas.
+fn format_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| format!("`{}`", path.display()))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 fn map_response(response: Response) -> Result<HandledResponse> {
@@ -68,19 +190,22 @@ fn map_response(response: Response) -> Result<HandledResponse> {
     match response {
         Response::TagFiles(inner) => inner
             .to_result(|e| ClientError::TagFiles(format_multiple_errors(e)).into())
-            .map(|_| HandledResponse::TagFiles),
+            .map(HandledResponse::TagFiles),
         Response::UntagFiles(inner) => inner
             .to_result(|e| ClientError::UntagFiles(format_multiple_errors(e)).into())
-            .map(|_| HandledResponse::UntagFiles),
+            .map(HandledResponse::UntagFiles),
+        Response::SetExactTags(inner) => inner
+            .to_result(|e| ClientError::SetExactTags(format_multiple_errors(e)).into())
+            .map(HandledResponse::SetExactTags),
         Response::EditTag(inner) => inner
             .to_result(|e| ClientError::EditTag(e).into())
             .map(|_| HandledResponse::EditTag),
         Response::CopyTags(inner) => inner
             .to_result(|e| ClientError::CopyTags(format_multiple_errors(e)).into())
-            .map(|_| HandledResponse::CopyTags),
+            .map(HandledResponse::CopyTags),
         Response::ClearFiles(inner) => inner
             .to_result(|e| ClientError::ClearFiles(format_multiple_errors(e)).into())
-            .map(|_| HandledResponse::ClearFiles),
+            .map(HandledResponse::ClearFiles),
         Response::ClearTags(inner) => inner
             .to_result(|e| ClientError::ClearTags(format_multiple_errors(e)).into())
             .map(|_| HandledResponse::ClearTags),
@@ -96,79 +221,267 @@ fn map_response(response: Response) -> Result<HandledResponse> {
         Response::Search(inner) => inner
             .to_result(|e| ClientError::Search(e).into())
             .map(HandledResponse::Search),
+        Response::SearchByTagPrefix(inner) => inner
+            .to_result(|e| ClientError::Search(e).into())
+            .map(HandledResponse::SearchByTagPrefix),
+        Response::SearchByOriginalName(inner) => inner
+            .to_result(|e| ClientError::Search(e).into())
+            .map(HandledResponse::SearchByOriginalName),
+        Response::SearchByPathFragment(inner) => inner
+            .to_result(|e| ClientError::Search(e).into())
+            .map(HandledResponse::SearchByPathFragment),
+        Response::SearchFiltered(inner) => inner
+            .to_result(|e| ClientError::Search(e).into())
+            .map(HandledResponse::SearchFiltered),
+        Response::SearchWeighted(inner) => inner
+            .to_result(|e| ClientError::Search(e).into())
+            .map(HandledResponse::SearchWeighted),
+        Response::SearchWithTags(inner) => inner
+            .to_result(|e| ClientError::Search(e).into())
+            .map(HandledResponse::SearchWithTags),
         Response::Ping(inner) => inner
             .to_result(|e| ClientError::Ping(e).into())
             .map(|_| HandledResponse::Ping),
         Response::ClearCache(inner) => inner
             .to_result(|e| ClientError::ClearCache(e).into())
             .map(|_| HandledResponse::ClearCache),
+        Response::ExportGraph(inner) => inner
+            .to_result(|e| ClientError::ExportGraph(e).into())
+            .map(HandledResponse::ExportGraph),
+        Response::RecentTags(inner) => inner
+            .to_result(|e| ClientError::RecentTags(e).into())
+            .map(HandledResponse::RecentTags),
+        Response::ClearRecentTags(inner) => inner
+            .to_result(|e| ClientError::ClearRecentTags(e).into())
+            .map(|_| HandledResponse::ClearRecentTags),
+        Response::RenameTagsMatching(inner) => inner
+            .to_result(|e| ClientError::RenameTags(format_multiple_errors(e)).into())
+            .map(HandledResponse::RenameTagsMatching),
+        Response::Stats(inner) => inner
+            .to_result(|e| ClientError::Stats(e).into())
+            .map(|stats| HandledResponse::Stats(Box::new(stats))),
+        Response::RunMaintenance(inner) => inner
+            .to_result(|e| ClientError::RunMaintenance(e).into())
+            .map(HandledResponse::RunMaintenance),
+        Response::Verify(inner) => inner
+            .to_result(|e| ClientError::Verify(e).into())
+            .map(HandledResponse::Verify),
+        Response::VerifyTags(inner) => inner
+            .to_result(|e| ClientError::VerifyTags(e).into())
+            .map(|report| HandledResponse::VerifyTags(Box::new(report))),
+        Response::TagOverlap(inner) => inner
+            .to_result(|e| ClientError::TagOverlap(e).into())
+            .map(HandledResponse::TagOverlap),
+        Response::TagExists(inner) => inner
+            .to_result(|e| ClientError::TagExists(e).into())
+            .map(HandledResponse::TagExists),
+        Response::NoMatches(pattern) => Err(ClientError::NoMatches(pattern).into()),
+        Response::SetLogLevel(inner) => inner
+            .to_result(|e| ClientError::SetLogLevel(e).into())
+            .map(|_| HandledResponse::SetLogLevel),
+        Response::MigrateNamespace(inner) => inner
+            .to_result(|e| ClientError::MigrateNamespace(format_multiple_errors(e)).into())
+            .map(HandledResponse::MigrateNamespace),
+        Response::ListMounts(inner) => inner
+            .to_result(|e| ClientError::ListMounts(e).into())
+            .map(HandledResponse::ListMounts),
+        Response::UnknownMount(path) => Err(ClientError::Response(
+            ResponseError::new(
+                ErrorCode::MountNotFound,
+                format!("no registry is mounted at `{}`", path.display()),
+            )
+            .with_details(serde_json::json!({ "registry": path })),
+        )
+        .into()),
+        Response::MixedMounts(paths) => Err(ClientError::MixedMounts(paths).into()),
+        Response::ListColors(inner) => inner
+            .to_result(|e| ClientError::ListColors(e).into())
+            .map(HandledResponse::ListColors),
+        Response::RecentEntries(inner) => inner
+            .to_result(|e| ClientError::RecentEntries(e).into())
+            .map(HandledResponse::RecentEntries),
+        Response::SetTagMeta(inner) => inner
+            .to_result(|e| ClientError::SetTagMeta(e).into())
+            .map(|_| HandledResponse::SetTagMeta),
+        Response::GetTagMeta(inner) => inner
+            .to_result(|e| ClientError::GetTagMeta(e).into())
+            .map(HandledResponse::GetTagMeta),
+        Response::CreateTag(inner) => inner
+            .to_result(|e| ClientError::CreateTag(e).into())
+            .map(|_| HandledResponse::CreateTag),
+        Response::AbsolutizePaths(inner) => inner
+            .to_result(|e| ClientError::AbsolutizePaths(e).into())
+            .map(HandledResponse::AbsolutizePaths),
+        Response::InspectFilesRaw(inner) => inner
+            .to_result(|e| ClientError::InspectFilesRaw(e).into())
+            .map(HandledResponse::InspectFilesRaw),
+        Response::XattrCapacity(inner) => inner
+            .to_result(|e| ClientError::XattrCapacity(e).into())
+            .map(HandledResponse::XattrCapacity),
+        Response::GetAuditLog(inner) => inner
+            .to_result(|e| ClientError::GetAuditLog(e).into())
+            .map(HandledResponse::GetAuditLog),
+        Response::DiffWithFilesystem(inner) => inner
+            .to_result(|e| ClientError::DiffWithFilesystem(e).into())
+            .map(HandledResponse::DiffWithFilesystem),
+        Response::Subscribe(inner) => inner
+            .to_result(|e| ClientError::Subscribe(e).into())
+            .map(|_| HandledResponse::Subscribe),
+        Response::TagChanged {
+            path,
+            added,
+            removed,
+        } => Ok(HandledResponse::TagChanged {
+            path,
+            added,
+            removed,
+        }),
+        Response::AddImplicationRule(inner) => inner
+            .to_result(|e| ClientError::AddImplicationRule(e).into())
+            .map(|_| HandledResponse::AddImplicationRule),
+        Response::ListImplicationRules(inner) => inner
+            .to_result(|e| ClientError::ListImplicationRules(e).into())
+            .map(HandledResponse::ListImplicationRules),
     }
 }
 
 impl Client {
     pub fn new(socket: impl Into<String>) -> Self {
         Self {
-            client: IpcClient::new(socket),
+            client: CircuitBreakerClient::new(socket),
+            registry: None,
+            offline: None,
         }
     }
 
-    fn tag_files_impl(&self, request: Request) -> Result<()> {
+    /// Shares the state of the circuit breaker with other invocations through the file at
+    /// `path`, so that the circuit opens after consecutive invocations failed to connect.
+    pub fn with_circuit_file(mut self, path: Option<PathBuf>) -> Self {
+        self.client = self.client.with_state_file(path);
+        self
+    }
+
+    /// Answers requests from the `offline` registry instead of sending them to the daemon.
+    pub fn with_offline(mut self, offline: Option<OfflineRegistry>) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Scopes all requests to the registry of the mount with the root directory at `registry`.
+    pub fn with_registry(mut self, registry: Option<PathBuf>) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    fn request(&self, request: Request) -> wutag_ipc::Result<Response> {
+        if let Some(offline) = &self.offline {
+            return offline.request(request);
+        }
+        match &self.registry {
+            Some(registry) => self.client.request(Request::Scoped {
+                registry: registry.clone(),
+                request: Box::new(request),
+            }),
+            None => self.client.request(request),
+        }
+    }
+
+    fn tag_files_impl(&self, request: Request) -> Result<BatchSummary> {
         debug_assert!(matches!(
             request,
             Request::TagFiles { .. } | Request::TagFilesPattern { .. }
         ));
-        self.client
-            .request(request)
+        self.request(request)
             .map_err(|e| ClientError::TagFiles(e.to_string()).into())
             .and_then(map_response)
-            .map(|_| ())
+            .and_then(|r| {
+                if let HandledResponse::TagFiles(summary) = r {
+                    Ok(summary)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
     }
 
+    /// Tags `files` with `tags`. If `rules` are provided the files are also tagged with tags of
+    /// the matching rules. With `no_follow` symlinks are tagged themselves instead of their
+    /// targets. Tags that don't exist yet get the least used colors of the `palette` unless it's
+    /// empty.
     pub fn tag_files<P: AsRef<Path>>(
         &self,
         files: impl IntoIterator<Item = P>,
         tags: impl IntoIterator<Item = Tag>,
-    ) -> Result<()> {
+        rules: Option<Vec<TagRule>>,
+        expires_in: Option<Duration>,
+        no_follow: bool,
+        palette: &[Color],
+    ) -> Result<BatchSummary> {
         self.tag_files_impl(Request::TagFiles {
             files: files
                 .into_iter()
                 .map(|p| p.as_ref().to_path_buf())
                 .collect(),
             tags: tags.into_iter().collect(),
+            auto_tag: rules.is_some(),
+            rules: rules.unwrap_or_default(),
+            expires_in,
+            no_follow,
+            palette: palette.to_vec(),
         })
     }
 
-    pub fn tag_files_pattern(&self, glob: Glob, tags: impl IntoIterator<Item = Tag>) -> Result<()> {
+    pub fn tag_files_pattern(
+        &self,
+        glob: Glob,
+        tags: impl IntoIterator<Item = Tag>,
+        rules: Option<Vec<TagRule>>,
+        expires_in: Option<Duration>,
+        no_follow: bool,
+        palette: &[Color],
+    ) -> Result<BatchSummary> {
         self.tag_files_impl(Request::TagFilesPattern {
             glob,
-
             tags: tags.into_iter().collect(),
+            auto_tag: rules.is_some(),
+            rules: rules.unwrap_or_default(),
+            expires_in,
+            no_follow,
+            palette: palette.to_vec(),
         })
     }
 
-    fn untag_files_impl(&self, request: Request) -> Result<()> {
+    fn untag_files_impl(&self, request: Request) -> Result<BatchSummary> {
         debug_assert!(matches!(
             request,
             Request::UntagFiles { .. } | Request::UntagFilesPattern { .. }
         ));
-        self.client
-            .request(request)
+        self.request(request)
             .map_err(|e| ClientError::UntagFiles(e.to_string()).into())
             .and_then(map_response)
-            .map(|_| ())
+            .and_then(|r| {
+                if let HandledResponse::UntagFiles(summary) = r {
+                    Ok(summary)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
     }
 
     pub fn untag_files<P: AsRef<Path>>(
         &self,
         files: impl IntoIterator<Item = P>,
         tags: impl IntoIterator<Item = Tag>,
-    ) -> Result<()> {
+        no_follow: bool,
+        force: bool,
+    ) -> Result<BatchSummary> {
         self.untag_files_impl(Request::UntagFiles {
             files: files
                 .into_iter()
                 .map(|p| p.as_ref().to_path_buf())
                 .collect(),
             tags: tags.into_iter().collect(),
+            no_follow,
+            force,
         })
     }
 
@@ -176,110 +489,199 @@ impl Client {
         &self,
         glob: Glob,
         tags: impl IntoIterator<Item = Tag>,
-    ) -> Result<()> {
+        no_follow: bool,
+        force: bool,
+    ) -> Result<BatchSummary> {
         self.untag_files_impl(Request::UntagFilesPattern {
             glob,
             tags: tags.into_iter().collect(),
+            no_follow,
+            force,
         })
     }
 
-    pub fn edit_tag(&self, tag: String, color: Color) -> Result<()> {
-        self.client
-            .request(Request::EditTag { tag, color })
-            .map_err(|e| ClientError::EditTag(e.to_string()).into())
-            .and_then(map_response)
-            .map(|_| ())
+    /// Makes `tags` the only tags of the `files` returning the tags added to and removed from
+    /// each of them.
+    pub fn set_exact_tags<P: AsRef<Path>>(
+        &self,
+        files: impl IntoIterator<Item = P>,
+        tags: impl IntoIterator<Item = Tag>,
+    ) -> Result<SetExactTagsResult> {
+        self.request(Request::SetExactTags {
+            files: files
+                .into_iter()
+                .map(|p| p.as_ref().to_path_buf())
+                .collect(),
+            tags: tags.into_iter().collect(),
+        })
+        .map_err(|e| ClientError::SetExactTags(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::SetExactTags(result) = r {
+                Ok(result)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
     }
 
-    fn copy_tags_impl(&self, request: Request) -> Result<()> {
+    pub fn edit_tag(
+        &self,
+        tag: String,
+        color: Option<Color>,
+        weight: Option<f32>,
+        system: Option<bool>,
+    ) -> Result<()> {
+        self.request(Request::EditTag {
+            tag,
+            color,
+            weight,
+            system,
+        })
+        .map_err(|e| ClientError::EditTag(e.to_string()).into())
+        .and_then(map_response)
+        .map(|_| ())
+    }
+
+    fn copy_tags_impl(&self, request: Request) -> Result<BatchSummary> {
         debug_assert!(matches!(
             request,
             Request::CopyTags { .. } | Request::CopyTagsPattern { .. }
         ));
-        self.client
-            .request(request)
+        self.request(request)
             .map_err(|e| ClientError::CopyTags(e.to_string()).into())
             .and_then(map_response)
-            .map(|_| ())
+            .and_then(|r| {
+                if let HandledResponse::CopyTags(summary) = r {
+                    Ok(summary)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
     }
 
     pub fn copy_tags<P1: AsRef<Path>, P2: AsRef<Path>>(
         &self,
         source: P1,
         target: impl IntoIterator<Item = P2>,
-    ) -> Result<()> {
-        self.client
-            .request(Request::CopyTags {
-                source: source.as_ref().to_path_buf(),
-                target: target
-                    .into_iter()
-                    .map(|p| p.as_ref().to_path_buf())
-                    .collect(),
-            })
-            .map_err(|e| ClientError::CopyTags(e.to_string()).into())
-            .and_then(map_response)
-            .map(|_| ())
+    ) -> Result<BatchSummary> {
+        self.copy_tags_impl(Request::CopyTags {
+            source: source.as_ref().to_path_buf(),
+            target: target
+                .into_iter()
+                .map(|p| p.as_ref().to_path_buf())
+                .collect(),
+        })
     }
 
-    pub fn copy_tags_pattern(&self, source: impl AsRef<Path>, glob: Glob) -> Result<()> {
+    pub fn copy_tags_pattern(&self, source: impl AsRef<Path>, glob: Glob) -> Result<BatchSummary> {
         self.copy_tags_impl(Request::CopyTagsPattern {
             glob,
             source: source.as_ref().to_path_buf(),
         })
     }
 
-    fn clear_files_impl(&self, request: Request) -> Result<()> {
+    fn clear_files_impl(&self, request: Request) -> Result<BatchSummary> {
         debug_assert!(matches!(
             request,
             Request::ClearFiles { .. } | Request::ClearFilesPattern { .. }
         ));
-        self.client
-            .request(request)
+        self.request(request)
             .map_err(|e| ClientError::ClearFiles(e.to_string()).into())
             .and_then(map_response)
-            .map(|_| ())
+            .and_then(|r| {
+                if let HandledResponse::ClearFiles(summary) = r {
+                    Ok(summary)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
     }
 
-    pub fn clear_files<P: AsRef<Path>>(&self, files: impl IntoIterator<Item = P>) -> Result<()> {
+    pub fn clear_files<P: AsRef<Path>>(
+        &self,
+        files: impl IntoIterator<Item = P>,
+        force: bool,
+    ) -> Result<BatchSummary> {
         self.clear_files_impl(Request::ClearFiles {
             files: files
                 .into_iter()
                 .map(|p| p.as_ref().to_path_buf())
                 .collect(),
+            force,
         })
     }
 
-    pub fn clear_files_pattern(&self, glob: Glob) -> Result<()> {
-        self.clear_files_impl(Request::ClearFilesPattern { glob })
+    pub fn clear_files_pattern(&self, glob: Glob, force: bool) -> Result<BatchSummary> {
+        self.clear_files_impl(Request::ClearFilesPattern { glob, force })
     }
 
-    pub fn clear_tags<T: AsRef<str>>(&self, tags: impl IntoIterator<Item = T>) -> Result<()> {
-        self.client
-            .request(Request::ClearTags {
-                tags: tags.into_iter().map(|t| t.as_ref().to_string()).collect(),
-            })
+    /// Removes the `tags` from all files. With `patterns` names with wildcards clear every tag
+    /// matching them.
+    pub fn clear_tags<T: AsRef<str>>(
+        &self,
+        tags: impl IntoIterator<Item = T>,
+        patterns: bool,
+        force: bool,
+    ) -> Result<()> {
+        self.request(Request::ClearTags {
+            tags: tags.into_iter().map(|t| t.as_ref().to_string()).collect(),
+            patterns,
+            force,
+        })
+        .map_err(|e| ClientError::ClearTags(e.to_string()).into())
+        .and_then(map_response)
+        .map(|_| ())
+    }
+
+    pub fn clear_all_tags(&self, force: bool) -> Result<()> {
+        self.request(Request::ClearAllTags { force })
             .map_err(|e| ClientError::ClearTags(e.to_string()).into())
             .and_then(map_response)
             .map(|_| ())
     }
 
     pub fn list_tags(&self, with_files: bool) -> Result<HashMap<Tag, Vec<EntryData>>> {
-        self.client
-            .request(Request::ListTags { with_files })
-            .map_err(|e| ClientError::ListTags(e.to_string()).into())
-            .and_then(map_response)
-            .and_then(|r| {
-                if let HandledResponse::ListTags(tags) = r {
-                    Ok(tags)
-                } else {
-                    Err(ClientError::UnexpectedResponse(r).into())
-                }
-            })
+        self.list_tags_page(with_files, Page::default(), false)
+            .map(|tags| tags.items.into_iter().collect())
+    }
+
+    /// Lists tags sorted by name within the `page`. Tags created with
+    /// [create_tag](Client::create_tag) that no file has are included with `include_unused`.
+    pub fn list_tags_page(
+        &self,
+        with_files: bool,
+        page: Page,
+        include_unused: bool,
+    ) -> Result<Paged<(Tag, Vec<EntryData>)>> {
+        self.request(Request::ListTags {
+            with_files,
+            page,
+            include_unused,
+        })
+        .map_err(|e| ClientError::ListTags(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::ListTags(tags) = r {
+                Ok(tags)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
     }
 
     pub fn list_files(&self, with_tags: bool) -> Result<Vec<(EntryData, Vec<Tag>)>> {
-        self.client
-            .request(Request::ListFiles { with_tags })
+        self.list_files_page(with_tags, Page::default())
+            .map(|files| files.items)
+    }
+
+    /// Lists files sorted by path within the `page`.
+    pub fn list_files_page(
+        &self,
+        with_tags: bool,
+        page: Page,
+    ) -> Result<Paged<(EntryData, Vec<Tag>)>> {
+        self.request(Request::ListFiles { with_tags, page })
             .map_err(|e| ClientError::ListFiles(e.to_string()).into())
             .and_then(map_response)
             .and_then(|r| {
@@ -291,13 +693,12 @@ impl Client {
             })
     }
 
-    fn inspect_files_impl(&self, request: Request) -> Result<Vec<(EntryData, Vec<Tag>)>> {
+    fn inspect_files_impl(&self, request: Request) -> Result<InspectFilesResult> {
         debug_assert!(matches!(
             request,
-            Request::InspectFiles { files: _ } | Request::InspectFilesPattern { .. }
+            Request::InspectFiles { .. } | Request::InspectFilesPattern { .. }
         ));
-        self.client
-            .request(request)
+        self.request(request)
             .map_err(|e| ClientError::InspectFiles(e.to_string()).into())
             .and_then(map_response)
             .and_then(|r| {
@@ -309,56 +710,595 @@ impl Client {
             })
     }
 
+    /// Returns tags of `files`. With `no_follow` tags of symlinks themselves are returned instead
+    /// of the tags of their targets.
     pub fn inspect_files<P: AsRef<Path>>(
         &self,
         files: impl IntoIterator<Item = P>,
-    ) -> Result<Vec<(EntryData, Vec<Tag>)>> {
+        no_follow: bool,
+    ) -> Result<InspectFilesResult> {
         self.inspect_files_impl(Request::InspectFiles {
             files: files
                 .into_iter()
                 .map(|p| p.as_ref().to_path_buf())
                 .collect(),
+            no_follow,
         })
     }
 
-    pub fn inspect_files_pattern(&self, glob: Glob) -> Result<Vec<(EntryData, Vec<Tag>)>> {
-        self.inspect_files_impl(Request::InspectFilesPattern { glob })
+    pub fn inspect_files_pattern(&self, glob: Glob, no_follow: bool) -> Result<InspectFilesResult> {
+        self.inspect_files_impl(Request::InspectFilesPattern { glob, no_follow })
     }
 
-    pub fn search<S: Into<String>>(
+    fn inspect_files_raw_impl(&self, request: Request) -> Result<RawXattrs> {
+        debug_assert!(matches!(
+            request,
+            Request::InspectFilesRaw { .. } | Request::InspectFilesRawPattern { .. }
+        ));
+        self.request(request)
+            .map_err(|e| ClientError::InspectFilesRaw(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::InspectFilesRaw(files) = r {
+                    Ok(files)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Returns keys and values of extended attributes of the `files` in the wutag namespace as
+    /// they are saved.
+    pub fn inspect_files_raw<P: AsRef<Path>>(
         &self,
-        tags: impl IntoIterator<Item = S>,
-        any: bool,
-    ) -> Result<Vec<EntryData>> {
-        self.client
-            .request(Request::Search {
-                tags: tags.into_iter().map(S::into).collect(),
-                any,
+        files: impl IntoIterator<Item = P>,
+    ) -> Result<RawXattrs> {
+        self.inspect_files_raw_impl(Request::InspectFilesRaw {
+            files: files
+                .into_iter()
+                .map(|p| p.as_ref().to_path_buf())
+                .collect(),
+        })
+    }
+
+    pub fn inspect_files_raw_pattern(&self, glob: Glob) -> Result<RawXattrs> {
+        self.inspect_files_raw_impl(Request::InspectFilesRawPattern { glob })
+    }
+
+    /// Estimates how many more tags the `file` can hold.
+    pub fn xattr_capacity(&self, file: impl AsRef<Path>) -> Result<TagCapacity> {
+        self.request(Request::XattrCapacity {
+            file: file.as_ref().to_path_buf(),
+        })
+        .map_err(|e| ClientError::XattrCapacity(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::XattrCapacity(capacity) = r {
+                Ok(capacity)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    /// Finds entries whose files were named `name` when they were first tagged.
+    pub fn search_by_original_name(&self, name: impl Into<String>) -> Result<Vec<EntryData>> {
+        self.request(Request::SearchByOriginalName { name: name.into() })
+            .map_err(|e| ClientError::Search(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::SearchByOriginalName(found) = r {
+                    Ok(found)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
             })
+    }
+
+    /// Finds entries whose paths contain `fragment` ignoring case.
+    pub fn search_by_path_fragment(&self, fragment: impl Into<String>) -> Result<Vec<EntryData>> {
+        self.request(Request::SearchByPathFragment {
+            fragment: fragment.into(),
+        })
+        .map_err(|e| ClientError::Search(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::SearchByPathFragment(found) = r {
+                Ok(found)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    /// Finds entries matching all conditions of the `filter` sorted by their paths.
+    pub fn search_filtered(&self, filter: TagFilter) -> Result<Vec<EntryData>> {
+        self.request(Request::SearchFiltered { filter })
             .map_err(|e| ClientError::Search(e.to_string()).into())
             .and_then(map_response)
             .and_then(|r| {
-                if let HandledResponse::Search(files) = r {
-                    Ok(files)
+                if let HandledResponse::SearchFiltered(found) = r {
+                    Ok(found)
                 } else {
                     Err(ClientError::UnexpectedResponse(r).into())
                 }
             })
     }
 
+    /// Searches for entries with the `tags` returning them along with their scores, sorted by
+    /// the score in descending order.
+    pub fn search<S: Into<String>>(
+        &self,
+        tags: impl IntoIterator<Item = S>,
+        any: bool,
+    ) -> Result<Vec<(EntryData, f32)>> {
+        self.request(Request::SearchWeighted {
+            tags: tags.into_iter().map(S::into).collect(),
+            any,
+        })
+        .map_err(|e| ClientError::Search(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::SearchWeighted(files) = r {
+                Ok(files)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    /// Searches for entries with tags starting with the `prefix` grouped by the matching tag.
+    pub fn search_by_tag_prefix(
+        &self,
+        prefix: impl Into<String>,
+    ) -> Result<Vec<(Tag, Vec<EntryData>)>> {
+        self.request(Request::SearchByTagPrefix {
+            prefix: prefix.into(),
+        })
+        .map_err(|e| ClientError::Search(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::SearchByTagPrefix(found) = r {
+                Ok(found)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    /// Searches like [search](Client::search) returning all tags of the found entries instead
+    /// of their scores.
+    pub fn search_with_tags<S: Into<String>>(
+        &self,
+        tags: impl IntoIterator<Item = S>,
+        any: bool,
+    ) -> Result<Vec<(EntryData, Vec<Tag>)>> {
+        self.request(Request::SearchWithTags {
+            tags: tags.into_iter().map(S::into).collect(),
+            any,
+        })
+        .map_err(|e| ClientError::Search(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::SearchWithTags(files) = r {
+                Ok(files)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
     pub fn ping(&self) -> Result<()> {
-        self.client
-            .request(Request::Ping)
+        self.request(Request::Ping)
             .map_err(|e| ClientError::Ping(e.to_string()).into())
             .and_then(map_response)
             .map(|_| ())
     }
 
     pub fn clear_cache(&self) -> Result<()> {
-        self.client
-            .request(Request::ClearCache)
+        self.request(Request::ClearCache)
             .map_err(|e| ClientError::ClearCache(e.to_string()).into())
             .and_then(map_response)
             .map(|_| ())
     }
+
+    pub fn export_graph(&self) -> Result<String> {
+        self.request(Request::ExportGraph)
+            .map_err(|e| ClientError::ExportGraph(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::ExportGraph(dot) = r {
+                    Ok(dot)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    pub fn recent_tags(&self) -> Result<Vec<Tag>> {
+        self.request(Request::RecentTags)
+            .map_err(|e| ClientError::RecentTags(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::RecentTags(tags) = r {
+                    Ok(tags)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    pub fn clear_recent_tags(&self) -> Result<()> {
+        self.request(Request::ClearRecentTags)
+            .map_err(|e| ClientError::ClearRecentTags(e.to_string()).into())
+            .and_then(map_response)
+            .map(|_| ())
+    }
+
+    /// Renames all tags starting with `prefix` replacing it with `replacement`. Returns the
+    /// applied mapping of old names to new names.
+    pub fn rename_tags_matching(
+        &self,
+        prefix: impl Into<String>,
+        replacement: impl Into<String>,
+    ) -> Result<Vec<(String, String)>> {
+        self.request(Request::RenameTagsMatching {
+            from_pattern: prefix.into(),
+            replacement: replacement.into(),
+        })
+        .map_err(|e| ClientError::RenameTags(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::RenameTagsMatching(mapping) = r {
+                Ok(mapping)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    pub fn get_stats(&self) -> Result<RegistryStats> {
+        self.request(Request::Stats)
+            .map_err(|e| ClientError::Stats(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::Stats(stats) = r {
+                    Ok(*stats)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Makes the daemon run its scheduled maintenance right away.
+    pub fn run_maintenance(&self) -> Result<MaintenanceSummary> {
+        self.request(Request::RunMaintenance)
+            .map_err(|e| ClientError::RunMaintenance(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::RunMaintenance(summary) = r {
+                    Ok(summary)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Returns entries of the registry that no longer exist on the filesystem.
+    pub fn verify(&self) -> Result<Vec<EntryData>> {
+        self.request(Request::Verify)
+            .map_err(|e| ClientError::Verify(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::Verify(entries) = r {
+                    Ok(entries)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Compares tags of entries with tags saved in their files. Unless `full` is set files that
+    /// didn't change since their tags were last verified are skipped.
+    pub fn verify_tags(&self, full: bool) -> Result<VerifyReport> {
+        self.request(Request::VerifyTags { full })
+            .map_err(|e| ClientError::VerifyTags(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::VerifyTags(report) = r {
+                    Ok(*report)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Returns entries tagged with both `a` and `b` and entries tagged with only one of them.
+    pub fn tag_overlap(&self, a: impl Into<String>, b: impl Into<String>) -> Result<TagOverlap> {
+        self.request(Request::TagOverlap {
+            a: a.into(),
+            b: b.into(),
+        })
+        .map_err(|e| ClientError::TagOverlap(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::TagOverlap(overlap) = r {
+                Ok(overlap)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    pub fn tag_exists(&self, name: impl Into<String>) -> Result<bool> {
+        self.request(Request::TagExists { name: name.into() })
+            .map_err(|e| ClientError::TagExists(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::TagExists(exists) = r {
+                    Ok(exists)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    pub fn add_implication_rule(
+        &self,
+        if_tag: impl Into<String>,
+        then_tag: impl Into<String>,
+    ) -> Result<()> {
+        self.request(Request::AddImplicationRule {
+            if_tag: if_tag.into(),
+            then_tag: then_tag.into(),
+        })
+        .map_err(|e| ClientError::AddImplicationRule(e.to_string()).into())
+        .and_then(map_response)
+        .map(|_| ())
+    }
+
+    pub fn list_implication_rules(&self) -> Result<Vec<ImplicationRule>> {
+        self.request(Request::ListImplicationRules)
+            .map_err(|e| ClientError::ListImplicationRules(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::ListImplicationRules(rules) = r {
+                    Ok(rules)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    pub fn set_log_level(&self, level: impl Into<String>) -> Result<()> {
+        self.request(Request::SetLogLevel {
+            level: level.into(),
+        })
+        .map_err(|e| ClientError::SetLogLevel(e.to_string()).into())
+        .and_then(map_response)
+        .map(|_| ())
+    }
+
+    /// Moves tags of files matched by the `glob` from the xattr namespace `from`, if set, to `to`
+    /// and rewrites values saved by older versions returning the number of migrated tags.
+    pub fn migrate_namespace(
+        &self,
+        glob: Glob,
+        from: Option<String>,
+        to: impl Into<String>,
+    ) -> Result<usize> {
+        self.request(Request::MigrateNamespace {
+            glob,
+            from,
+            to: to.into(),
+        })
+        .map_err(|e| ClientError::MigrateNamespace(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::MigrateNamespace(moved) = r {
+                Ok(moved)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    /// Subscribes to changes of tags returning an iterator over `HandledResponse::TagChanged`
+    /// responses that ends when the daemon closes the connection.
+    pub fn subscribe(&self) -> Result<impl Iterator<Item = Result<HandledResponse>>> {
+        let mut subscription = self
+            .client
+            .subscribe::<_, Response>(Request::Subscribe)
+            .map_err(|e| ClientError::Subscribe(e.to_string()))?;
+        match subscription.next() {
+            Some(response) => {
+                response
+                    .map_err(|e| ClientError::Subscribe(e.to_string()).into())
+                    .and_then(map_response)?;
+            }
+            None => return Err(ClientError::Subscribe("connection closed".into()).into()),
+        }
+        Ok(subscription.map(|response| {
+            response
+                .map_err(|e| ClientError::Subscribe(e.to_string()).into())
+                .and_then(map_response)
+        }))
+    }
+
+    pub fn list_mounts(&self) -> Result<Vec<RegistryMount>> {
+        self.request(Request::ListMounts)
+            .map_err(|e| ClientError::ListMounts(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::ListMounts(mounts) = r {
+                    Ok(mounts)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Lists colors of tags as hex strings along with sorted names of tags using them.
+    pub fn list_colors(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        self.request(Request::ListColors)
+            .map_err(|e| ClientError::ListColors(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::ListColors(colors) = r {
+                    Ok(colors)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Lists entries added at or after `since`, most recently added first, at most `limit` of
+    /// them.
+    pub fn recent_entries(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: Option<usize>,
+    ) -> Result<Vec<EntryData>> {
+        self.request(Request::RecentEntries { since, limit })
+            .map_err(|e| ClientError::RecentEntries(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::RecentEntries(entries) = r {
+                    Ok(entries)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Returns entries of the audit log of the daemon logged at or after `since`, oldest first,
+    /// at most `limit` of the most recent ones.
+    pub fn audit_log(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: Option<usize>,
+    ) -> Result<Vec<AuditLogEntry>> {
+        self.request(Request::GetAuditLog { since, limit })
+            .map_err(|e| ClientError::GetAuditLog(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::GetAuditLog(entries) = r {
+                    Ok(entries)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Compares the registry with tags saved in the files, untracked files are searched for under
+    /// `base_dir`. With `fix` the registry is updated to match the files. Returns the
+    /// inconsistencies found before fixing them.
+    pub fn diff_with_filesystem(
+        &self,
+        base_dir: PathBuf,
+        max_depth: Option<usize>,
+        fix: bool,
+    ) -> Result<RegistryFsDiff> {
+        self.request(Request::DiffWithFilesystem {
+            base_dir,
+            max_depth,
+            fix,
+        })
+        .map_err(|e| ClientError::DiffWithFilesystem(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::DiffWithFilesystem(diff) = r {
+                Ok(diff)
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    /// Defines a tag with the `name` and `color` without tagging any file.
+    pub fn create_tag(
+        &self,
+        name: String,
+        color: Color,
+        description: Option<String>,
+    ) -> Result<()> {
+        self.request(Request::CreateTag {
+            name,
+            color,
+            description,
+        })
+        .map_err(|e| ClientError::CreateTag(e.to_string()).into())
+        .and_then(map_response)
+        .and_then(|r| {
+            if let HandledResponse::CreateTag = r {
+                Ok(())
+            } else {
+                Err(ClientError::UnexpectedResponse(r).into())
+            }
+        })
+    }
+
+    /// Makes relative paths of entries absolute by joining them with the absolute `root`.
+    pub fn absolutize_paths(&self, root: PathBuf) -> Result<PathMigration> {
+        self.request(Request::AbsolutizePaths { root })
+            .map_err(|e| ClientError::AbsolutizePaths(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::AbsolutizePaths(migration) = r {
+                    Ok(migration)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Sets the metadata `key` of the `tag` to `value` or removes it if `value` is `None`.
+    pub fn set_tag_meta(&self, tag: String, key: String, value: Option<String>) -> Result<()> {
+        self.request(Request::SetTagMeta { tag, key, value })
+            .map_err(|e| ClientError::SetTagMeta(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::SetTagMeta = r {
+                    Ok(())
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+
+    /// Returns metadata of the `tag` or of all tags with metadata if `tag` is `None` by tag
+    /// name.
+    pub fn tag_meta(
+        &self,
+        tag: Option<String>,
+    ) -> Result<BTreeMap<String, BTreeMap<String, String>>> {
+        self.request(Request::GetTagMeta { tag })
+            .map_err(|e| ClientError::GetTagMeta(e.to_string()).into())
+            .and_then(map_response)
+            .and_then(|r| {
+                if let HandledResponse::GetTagMeta(meta) = r {
+                    Ok(meta)
+                } else {
+                    Err(ClientError::UnexpectedResponse(r).into())
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn reports_unknown_mounts_with_code() {
+        let result = map_response(Response::UnknownMount(PathBuf::from("/mnt/usb")));
+        match result {
+            Err(Error::Client(ClientError::Response(e))) => {
+                assert_eq!(e.code, ErrorCode::MountNotFound);
+                assert_eq!(e.details.unwrap()["registry"], "/mnt/usb");
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
 }
@@ -1,9 +1,14 @@
+use crate::fmt::TerminalBackground;
 use crate::{Error, Result};
 
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 use thiserror::Error as ThisError;
+use wutag_core::color::{palette_by_name, parse_color, Color, DEFAULT_COLORS, THEMES};
+use wutag_core::tag::{Tag, TagRule};
 
 #[derive(Debug, ThisError)]
 pub enum ConfigError {
@@ -13,30 +18,283 @@ pub enum ConfigError {
     Deserialize(serde_yaml::Error),
     #[error("failed to determine user config directory")]
     FindUserDir,
+    #[error("invalid color `{value}` in configuration at line {line} - {source}")]
+    InvalidColor {
+        value: String,
+        line: usize,
+        source: wutag_core::Error,
+    },
+    #[error("unknown theme `{0}`, available themes are {}", THEMES.join(", "))]
+    UnknownTheme(String),
+    #[error("invalid content rule pattern `{0}` - {1}")]
+    InvalidPattern(String, regex::Error),
+    #[error("failed to serialize configuration - {0}")]
+    Serialize(serde_yaml::Error),
+    #[error("failed to save configuration - {0}")]
+    Save(io::Error),
+}
+
+/// Rule automatically tagging files when tagging with `set --auto-tag`. Files are tagged with
+/// `tags` if their MIME type starts with `mime` and their content matches `pattern`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ContentTagRule {
+    pub mime: Option<String>,
+    pub pattern: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl ContentTagRule {
+    /// Converts this rule to a [TagRule](TagRule) choosing colors of new tags from `colors`.
+    pub fn to_tag_rule(&self, colors: &[Color]) -> Result<TagRule> {
+        let content_pattern = self
+            .pattern
+            .as_ref()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| ConfigError::InvalidPattern(pattern.clone(), e))
+            })
+            .transpose()?;
+        Ok(TagRule {
+            mime_prefix: self.mime.clone(),
+            content_pattern,
+            tags: self.tags.iter().map(|t| Tag::random(t, colors)).collect(),
+        })
+    }
 }
 
 const CONFIG_FILE: &str = "wutag.yml";
+/// Name of the configuration of a project created by `wutag init`.
+pub const PROJECT_CONFIG_FILE: &str = ".wutag.yml";
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     pub max_depth: Option<usize>,
     pub colors: Option<Vec<String>>,
+    /// Name of a built-in palette used for new tags when `colors` are not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
     #[serde(default)]
     pub pretty_output: bool,
+    pub terminal_background: Option<TerminalBackground>,
+    #[serde(default)]
+    pub content_rules: Vec<ContentTagRule>,
+    /// Convert tag names to lowercase so that for example `Work` and `work` are the same tag.
+    /// Tag names are always normalized to Unicode NFC form.
+    #[serde(default)]
+    pub normalize_tag_names: bool,
+    /// Whether `set`, `rm` and `get` work with tags of files symlinks point to rather than of the
+    /// symlinks themselves, `true` if not set.
+    pub follow_symlinks: Option<bool>,
+    /// Commands run in place of subcommands with the alias names, for example `rust` aliased to
+    /// `set --glob "**/*.rs" -- rust` makes `wutag rust` tag all Rust files.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+}
+
+/// Returns the number of the line in the YAML `data` with the color at `index` of the top level
+/// `colors` sequence or 0 if it can't be found.
+fn color_line(data: &str, index: usize) -> usize {
+    let mut lines = data
+        .lines()
+        .enumerate()
+        .skip_while(|(_, line)| !line.starts_with("colors:"));
+    let Some((start, colors)) = lines.next() else {
+        return 0;
+    };
+    // a flow sequence like `colors: ['#ff0000', blue]` is on a single line
+    if !colors["colors:".len()..].trim().is_empty() {
+        return start + 1;
+    }
+    lines
+        .take_while(|(_, line)| line.trim().is_empty() || line.starts_with([' ', '-', '#']))
+        .filter(|(_, line)| line.trim_start().starts_with('-'))
+        .nth(index)
+        .map(|(idx, _)| idx + 1)
+        .unwrap_or_default()
 }
 
 impl Config {
     /// Loads Config from provided `path` by appending [CONFIG_FILE](CONFIG_FILE) name to it and
     /// reading the file.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref().join(CONFIG_FILE);
-        serde_yaml::from_slice(&fs::read(path).map_err(ConfigError::Load)?)
-            .map_err(ConfigError::Deserialize)
+        Self::load_file(path.as_ref().join(CONFIG_FILE))
+    }
+
+    /// Loads Config from the file at provided `path`.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_slice(&fs::read(path).map_err(ConfigError::Load)?)
+    }
+
+    /// Loads Config from the file at provided `path` that is about to be modified. A missing file
+    /// yields the default config so that it can be created, other errors are returned so that a
+    /// file that can't be read or parsed is never overwritten.
+    pub fn load_for_update<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match fs::read(path) {
+            Ok(data) => Self::from_slice(&data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Load(e).into()),
+        }
+    }
+
+    /// Deserializes Config from YAML `data` verifying that all colors are valid.
+    fn from_slice(data: &[u8]) -> Result<Self> {
+        let config: Self = serde_yaml::from_slice(data).map_err(ConfigError::Deserialize)?;
+
+        for (index, color) in config.colors.iter().flatten().enumerate() {
+            if let Err(source) = parse_color(color) {
+                let line = color_line(&String::from_utf8_lossy(data), index);
+                return Err(ConfigError::InvalidColor {
+                    value: color.clone(),
+                    line,
+                    source,
+                }
+                .into());
+            }
+        }
+        if let Some(theme) = &config.theme {
+            if palette_by_name(theme).is_none() {
+                return Err(ConfigError::UnknownTheme(theme.clone()).into());
+            }
+        }
+        for rule in &config.content_rules {
+            rule.to_tag_rule(DEFAULT_COLORS)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Saves this config to the file at provided `path`.
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let serialized = serde_yaml::to_string(self).map_err(ConfigError::Serialize)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(ConfigError::Save)?;
+        }
+        fs::write(path, serialized)
+            .map_err(ConfigError::Save)
             .map_err(Error::from)
     }
 
-    /// Loads config file from config directory of user executing the program
+    /// Returns the path of the configuration of the project containing the current directory if
+    /// there is one, otherwise the path of the config file in the config directory of user
+    /// executing the program.
+    pub fn default_path() -> Result<PathBuf> {
+        if let Some(path) = std::env::current_dir()
+            .ok()
+            .and_then(|dir| find_project_config(&dir))
+        {
+            return Ok(path);
+        }
+        Ok(dirs::config_dir()
+            .ok_or(ConfigError::FindUserDir)?
+            .join(CONFIG_FILE))
+    }
+
+    /// Loads the config file of the project containing the current directory or from config
+    /// directory of user executing the program
     pub fn load_default_location() -> Result<Self> {
-        Self::load(dirs::config_dir().ok_or(ConfigError::FindUserDir)?)
+        match std::env::current_dir()
+            .ok()
+            .and_then(|dir| find_project_config(&dir))
+        {
+            Some(path) => Self::load_file(path),
+            None => Self::load(dirs::config_dir().ok_or(ConfigError::FindUserDir)?),
+        }
+    }
+}
+
+/// Finds the closest [PROJECT_CONFIG_FILE](PROJECT_CONFIG_FILE) in `dir` or its parents.
+fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_on_invalid_colors() {
+        let config = b"---\nmax_depth: 5\ncolors:\n- '#ff0000'\n- notacolor\n";
+        match Config::from_slice(config) {
+            Err(Error::Config(ConfigError::InvalidColor { value, line, .. })) => {
+                assert_eq!(value, "notacolor");
+                assert_eq!(line, 5);
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+
+        // the line of a color is looked up only within the colors
+        let config = b"---\nmax_depth: 5\ncolors:\n  # primary\n  - '#ff0000'\n  - 5\n";
+        match Config::from_slice(config) {
+            Err(Error::Config(ConfigError::InvalidColor { value, line, .. })) => {
+                assert_eq!(value, "5");
+                assert_eq!(line, 6);
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+        let config = b"---\nmax_depth: 5\ncolors: ['#ff0000', 5]\n";
+        match Config::from_slice(config) {
+            Err(Error::Config(ConfigError::InvalidColor { line, .. })) => assert_eq!(line, 3),
+            result => panic!("unexpected result {result:?}"),
+        }
+
+        let config = b"---\ncolors:\n- '#ff0000'\n- 'hsl(120, 100%, 50%)'\n";
+        assert_eq!(Config::from_slice(config).unwrap().colors.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn finds_project_config_in_parents() {
+        let dir = tempdir::TempDir::new("wutag-project-config").unwrap();
+        let nested = dir.path().join("src").join("bin");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_config(&nested), None);
+
+        let config = dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(&config, "---\nmax_depth: 5\n").unwrap();
+        assert_eq!(find_project_config(&nested), Some(config.clone()));
+        assert_eq!(find_project_config(dir.path()), Some(config));
+    }
+
+    #[test]
+    fn errors_on_unknown_themes() {
+        let config = Config::from_slice(b"---\ntheme: pastel\n").unwrap();
+        assert_eq!(config.theme.as_deref(), Some("pastel"));
+        assert!(matches!(
+            Config::from_slice(b"---\ntheme: neon\n"),
+            Err(Error::Config(ConfigError::UnknownTheme(theme))) if theme == "neon"
+        ));
+    }
+
+    #[test]
+    fn errors_on_invalid_content_rules() {
+        let config = b"---\ncontent_rules:\n- pattern: '(unclosed'\n  tags: [todo]\n";
+        assert!(matches!(
+            Config::from_slice(config),
+            Err(Error::Config(ConfigError::InvalidPattern(pattern, _))) if pattern == "(unclosed"
+        ));
+
+        let config = b"---\ncontent_rules:\n- mime: image/\n  tags: [image]\n";
+        let config = Config::from_slice(config).unwrap();
+        let rule = config.content_rules[0].to_tag_rule(DEFAULT_COLORS).unwrap();
+        assert_eq!(rule.mime_prefix.as_deref(), Some("image/"));
+        assert_eq!(rule.tags[0].name(), "image");
+    }
+
+    #[test]
+    fn loads_config_for_update() {
+        let dir = tempdir::TempDir::new("wutag-config").unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+        assert!(Config::load_for_update(&path).unwrap().aliases.is_empty());
+
+        fs::write(&path, "---\ncolors: notalist\n").unwrap();
+        assert!(matches!(
+            Config::load_for_update(&path),
+            Err(Error::Config(ConfigError::Deserialize(_)))
+        ));
+        assert!(matches!(
+            Config::load_for_update(dir.path()),
+            Err(Error::Config(ConfigError::Load(_)))
+        ));
     }
 }
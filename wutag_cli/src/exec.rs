@@ -0,0 +1,208 @@
+//! Running external commands on entries
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum ExecError {
+    #[error("invalid command `{0}` - {1}")]
+    InvalidCommand(String, &'static str),
+    #[error("failed to run `{0}` - {1}")]
+    Spawn(String, io::Error),
+}
+
+/// Placeholder replaced with a single path.
+const PLACEHOLDER: &str = "{}";
+/// Placeholder replaced with all paths.
+const BATCH_PLACEHOLDER: &str = "{+}";
+
+/// Splits the `command` into arguments on whitespace. Whitespace can be preserved by surrounding
+/// it with single or double quotes or escaping it with a backslash.
+pub fn split_command(command: &str) -> Result<Vec<String>, ExecError> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+
+    while let Some(ch) = chars.next() {
+        match (ch, quote) {
+            ('\\', q) if q != Some('\'') => {
+                let escaped = chars.next().ok_or_else(|| {
+                    ExecError::InvalidCommand(command.to_string(), "trailing backslash")
+                })?;
+                current.push(escaped);
+                in_arg = true;
+            }
+            ('"' | '\'', None) => {
+                quote = Some(ch);
+                in_arg = true;
+            }
+            (ch, Some(q)) if ch == q => quote = None,
+            (ch, None) if ch.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (ch, _) => {
+                current.push(ch);
+                in_arg = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(ExecError::InvalidCommand(
+            command.to_string(),
+            "unterminated quote",
+        ));
+    }
+    if in_arg {
+        args.push(current);
+    }
+    if args.is_empty() {
+        return Err(ExecError::InvalidCommand(
+            command.to_string(),
+            "empty command",
+        ));
+    }
+    Ok(args)
+}
+
+fn run(command: &str, args: Vec<OsString>) -> Result<(), ExecError> {
+    let (program, args) = args.split_first().expect("non empty command");
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| ExecError::Spawn(command.to_string(), e))?;
+    if !status.success() {
+        eprintln!("`{}` exited with {status}", program.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Replaces every `{}` in the `arg` with the `path`. Paths are passed on as they are, even when
+/// they aren't valid UTF-8.
+fn replace_placeholder(arg: &str, path: &OsStr) -> OsString {
+    let mut replaced = OsString::new();
+    for (i, part) in arg.split(PLACEHOLDER).enumerate() {
+        if i > 0 {
+            replaced.push(path);
+        }
+        replaced.push(part);
+    }
+    replaced
+}
+
+/// Runs the `command` once for each of the `paths` replacing every `{}` in the arguments with the
+/// path. If the command contains no placeholder the path is appended as the last argument.
+pub fn exec_each<P: AsRef<Path>>(command: &str, paths: &[P]) -> Result<(), ExecError> {
+    let args = split_command(command)?;
+    let has_placeholder = args.iter().any(|arg| arg.contains(PLACEHOLDER));
+
+    for path in paths {
+        let path = path.as_ref().as_os_str();
+        let mut args: Vec<_> = args
+            .iter()
+            .map(|arg| replace_placeholder(arg, path))
+            .collect();
+        if !has_placeholder {
+            args.push(path.to_os_string());
+        }
+        run(command, args)?;
+    }
+    Ok(())
+}
+
+/// Runs the `command` once with all of the `paths`. An argument equal to `{}` or `{+}` is replaced
+/// with the paths, otherwise the paths are appended at the end.
+pub fn exec_batch<P: AsRef<Path>>(command: &str, paths: &[P]) -> Result<(), ExecError> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let paths: Vec<_> = paths
+        .iter()
+        .map(|p| p.as_ref().as_os_str().to_os_string())
+        .collect();
+    let mut args = vec![];
+    let mut replaced = false;
+    for arg in split_command(command)? {
+        if arg == PLACEHOLDER || arg == BATCH_PLACEHOLDER {
+            args.extend(paths.iter().cloned());
+            replaced = true;
+        } else {
+            args.push(arg.into());
+        }
+    }
+    if !replaced {
+        args.extend(paths);
+    }
+    run(command, args)
+}
+
+/// Quotes the `arg` with single quotes so that a POSIX shell passes it to a command unchanged.
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_commands() {
+        assert_eq!(split_command("code {}").unwrap(), vec!["code", "{}"]);
+        assert_eq!(
+            split_command("  echo 'a b'   \"c d\" e\\ f ").unwrap(),
+            vec!["echo", "a b", "c d", "e f"]
+        );
+        assert_eq!(split_command("echo ''").unwrap(), vec!["echo", ""]);
+        assert_eq!(
+            split_command(r#"echo "say \"hi\"""#).unwrap(),
+            vec!["echo", "say \"hi\""]
+        );
+        assert!(split_command("echo 'a").is_err());
+        assert!(split_command("   ").is_err());
+    }
+
+    #[test]
+    fn quotes_shell_arguments() {
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        for arg in ["it's", "$HOME `id` \"", "a\\b", "'"] {
+            let command = format!("echo {}", shell_quote(arg));
+            assert_eq!(split_command(&command).unwrap(), vec!["echo", arg]);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_commands_on_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempdir::TempDir::new("wutag-exec").unwrap();
+        let paths = [
+            dir.path().join("with space"),
+            dir.path().join(OsStr::from_bytes(b"invalid-\xff-utf8")),
+        ];
+        for path in &paths {
+            std::fs::write(path, b"").unwrap();
+        }
+
+        exec_each("cp {} {}.copy", &paths).unwrap();
+        for path in &paths {
+            let mut copy = path.clone().into_os_string();
+            copy.push(".copy");
+            assert!(Path::new(&copy).exists());
+        }
+
+        exec_batch("rm --", &paths).unwrap();
+        for path in &paths {
+            assert!(!path.exists());
+        }
+    }
+}
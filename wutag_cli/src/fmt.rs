@@ -1,16 +1,116 @@
-use wutag_core::color::{ColoredString, Colorize};
+use wutag_core::color::{color_to_rgb, Color, ColoredString, Colorize};
 use wutag_core::tag::Tag;
 
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 
+/// Color of the background of the terminal the output is printed to. Used to avoid printing
+/// tags with colors that would be barely visible.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminalBackground {
+    Light,
+    Dark,
+}
+
+impl TerminalBackground {
+    /// Returns a color readable on this background if `color` has poor contrast against it,
+    /// otherwise returns `color` unchanged.
+    pub fn readable_color(self, color: Color) -> Color {
+        let luminance = luminance(color);
+        match self {
+            TerminalBackground::Light if luminance > 0.7 => Color::Black,
+            TerminalBackground::Dark if luminance < 0.05 => Color::BrightWhite,
+            _ => color,
+        }
+    }
+}
+
+/// Calculates relative luminance of the `color` in range `0.0..=1.0`.
+fn luminance(color: Color) -> f32 {
+    let (r, g, b) = color_to_rgb(color);
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.
+}
+
 pub fn path<P: AsRef<Path>>(path: P) -> ColoredString {
     path.as_ref().display().to_string().bold().blue()
 }
 
-pub fn tag(tag: &Tag) -> ColoredString {
+pub fn tag(tag: &Tag, background: Option<TerminalBackground>) -> ColoredString {
+    let color = match background {
+        Some(background) => background.readable_color(*tag.color()),
+        None => *tag.color(),
+    };
     if tag.name().chars().any(|c| c.is_ascii_whitespace()) {
-        format!("\"{}\"", tag.name()).color(*tag.color()).bold()
+        format!("\"{}\"", tag.name()).color(color).bold()
     } else {
-        tag.name().color(*tag.color()).bold()
+        tag.name().color(color).bold()
+    }
+}
+
+/// A block of the `color` used to show it next to its hex code.
+pub fn swatch(color: Color) -> ColoredString {
+    "██".color(color)
+}
+
+/// Renders the `rows` as an aligned table with a `header`. Cells may contain colored text.
+pub fn table<const N: usize>(
+    header: [&str; N],
+    rows: impl IntoIterator<Item = [String; N]>,
+) -> String {
+    let mut builder = tabled::builder::Builder::default();
+    builder.push_record(header);
+    for row in rows {
+        builder.push_record(row);
+    }
+    builder.build().to_string()
+}
+
+/// Formats the `remaining` time using its two largest units like `6d 23h`.
+pub fn remaining(remaining: chrono::Duration) -> String {
+    let secs = remaining.num_seconds().max(0);
+    let units = [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m"), (1, "s")];
+    let parts: Vec<_> = units
+        .iter()
+        .scan(secs, |left, (unit, suffix)| {
+            let value = *left / unit;
+            *left %= unit;
+            Some((value, suffix))
+        })
+        .skip_while(|(value, _)| *value == 0)
+        .take(2)
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, suffix)| format!("{value}{suffix}"))
+        .collect();
+    if parts.is_empty() {
+        "0s".into()
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_table_columns() {
+        let red = "\x1b[31mRed\x1b[0m".to_string();
+        let rows = [
+            ["work".into(), red.clone(), "3".into()],
+            ["project".into(), "Blue".into(), "12".into()],
+        ];
+        let table = table(["Tag Name", "Color", "File Count"], rows);
+        assert!(table.contains(&red));
+
+        // escape sequences of colored cells don't count towards the width of the columns
+        let lines: Vec<_> = table
+            .lines()
+            .map(|line| line.replace("\x1b[31m", "").replace("\x1b[0m", ""))
+            .collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+        assert_eq!(lines[1], "| Tag Name | Color | File Count |");
+        assert_eq!(lines[3], "| work     | Red   | 3          |");
     }
 }
@@ -0,0 +1,114 @@
+//! Initialization of per project configuration files
+use crate::config::PROJECT_CONFIG_FILE;
+use crate::opt::{InitOpts, InitTheme};
+use crate::{Error, Result};
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use thiserror::Error as ThisError;
+use wutag_core::color::{color_to_hex, DEFAULT_COLORS};
+
+#[derive(Debug, ThisError)]
+pub enum InitError {
+    #[error("directory `{0}` doesn't exist")]
+    DirectoryNotFound(PathBuf),
+    #[error("failed to write `{0}` - {1}")]
+    Write(PathBuf, io::Error),
+}
+
+pub const PROJECT_MANIFEST_FILE: &str = ".wutag-manifest.toml";
+
+/// Colorblind friendly palette by Okabe and Ito.
+const ACCESSIBLE_COLORS: &[&str] = &[
+    "#e69f00", "#56b4e9", "#009e73", "#f0e442", "#0072b2", "#d55e00", "#cc79a7",
+];
+
+const MANIFEST_STUB: &str = r#"# Rules used to automatically tag files of this project.
+#
+# [[rule]]
+# pattern = "**/*.rs"
+# tags = ["rust", "code"]
+#
+# [[rule]]
+# pattern = "docs/**/*.md"
+# tags = ["docs"]
+"#;
+
+/// Returns the content of a project configuration with colors of the `theme`. Only settings read
+/// by [Config](crate::config::Config) are included.
+fn project_config(theme: InitTheme) -> String {
+    let mut config = String::from("---\nmax_depth: 5\npretty_output: true\n");
+    match theme {
+        InitTheme::Default => {
+            config.push_str("# colors:\n");
+            for color in DEFAULT_COLORS {
+                let _ = writeln!(config, "# - '{}'", color_to_hex(*color));
+            }
+        }
+        InitTheme::Accessible => {
+            config.push_str("colors:\n");
+            for color in ACCESSIBLE_COLORS {
+                let _ = writeln!(config, "- '{color}'");
+            }
+        }
+        InitTheme::Custom => {
+            config.push_str("colors:\n# - '#rrggbb'\n");
+        }
+    }
+    config
+}
+
+/// Writes `content` to `path` unless the file already exists in which case a warning is printed.
+fn write_new(path: &Path, content: &str) -> Result<()> {
+    if path.exists() {
+        eprintln!("warning: `{}` already exists, skipping", path.display());
+        return Ok(());
+    }
+    fs::write(path, content)
+        .map_err(|e| InitError::Write(path.to_path_buf(), e))
+        .map_err(Error::from)?;
+    println!("created `{}`", path.display());
+    Ok(())
+}
+
+pub fn init(opts: &InitOpts) -> Result<()> {
+    if !opts.directory.is_dir() {
+        return Err(InitError::DirectoryNotFound(opts.directory.clone()).into());
+    }
+    let config = project_config(opts.colors);
+
+    write_new(&opts.directory.join(PROJECT_CONFIG_FILE), &config)?;
+    write_new(&opts.directory.join(PROJECT_MANIFEST_FILE), MANIFEST_STUB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn creates_loadable_project_config() {
+        let dir = tempdir::TempDir::new("wutag-init").unwrap();
+        let opts = InitOpts {
+            directory: dir.path().to_path_buf(),
+            colors: InitTheme::Accessible,
+        };
+        init(&opts).unwrap();
+
+        let config = Config::load_file(dir.path().join(PROJECT_CONFIG_FILE)).unwrap();
+        assert_eq!(config.max_depth, Some(5));
+        assert!(config.pretty_output);
+        assert_eq!(config.colors.unwrap().len(), ACCESSIBLE_COLORS.len());
+        assert!(dir.path().join(PROJECT_MANIFEST_FILE).exists());
+
+        for theme in [InitTheme::Default, InitTheme::Custom] {
+            let path = dir.path().join(format!("{theme:?}.yml"));
+            fs::write(&path, project_config(theme)).unwrap();
+            let config = Config::load_file(path).unwrap();
+            assert_eq!(config.max_depth, Some(5));
+            // colors are only listed in comments
+            assert!(config.colors.is_none());
+        }
+    }
+}
@@ -1,16 +1,27 @@
 mod app;
 mod client;
 mod config;
+mod exec;
 mod fmt;
+mod init;
+mod mapping;
+mod offline;
 mod opt;
+mod report;
+mod schema;
 
 use clap::{CommandFactory, Parser};
 
 use app::App;
-use config::Config;
-use opt::{Command, CompletionsOpts, Opts, Shell, APP_NAME};
+use config::{Config, ContentTagRule};
+use opt::{AddContentRuleOpts, AliasOpts, Command, CompletionsOpts, Opts, Shell, APP_NAME};
+use schema::SchemaCommand;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::io;
+use std::path::Path;
 use thiserror::Error as ThisError;
+use wutag_ipc::ErrorCode;
 
 #[derive(Debug, ThisError)]
 pub enum Error {
@@ -20,16 +31,55 @@ pub enum Error {
     Client(#[from] client::ClientError),
     #[error(transparent)]
     App(#[from] app::AppError),
+    #[error(transparent)]
+    Init(#[from] init::InitError),
+    #[error(transparent)]
+    Exec(#[from] exec::ExecError),
     #[error("failed to glob pattern - {0}")]
     Glob(wutag_core::Error),
     #[error("invalid shell - {0}")]
     InvalidShell(String),
     #[error("invalid output format - {0}")]
     InvalidOutputFormat(String),
+    #[error("invalid graph format - {0}")]
+    InvalidGraphFormat(String),
+    #[error("invalid sort key - {0}")]
+    InvalidSortKey(String),
+    #[error("no schema for command - {0}")]
+    InvalidSchemaCommand(String),
+    #[error("failed to serialize schema - {0}")]
+    SerializeSchema(serde_json::Error),
+    #[error("alias `{0}` would shadow a subcommand")]
+    AliasShadowsCommand(String),
+    #[error("no alias named `{0}`")]
+    AliasNotFound(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl From<wutag_ipc::ResponseError> for Error {
+    fn from(e: wutag_ipc::ResponseError) -> Self {
+        Error::Client(e.into())
+    }
+}
+
+/// Exit status of a failed command or of a check that doesn't hold.
+const EXIT_FAILURE: i32 = 1;
+/// Exit status of a command whose pattern matched no files.
+const EXIT_NO_MATCHES: i32 = 2;
+/// Exit status of a command given a tag, entry or mount that doesn't exist.
+const EXIT_NOT_FOUND: i32 = 3;
+
+/// Exit status of a command that failed with an error of the daemon with the `code`.
+fn exit_status(code: ErrorCode) -> i32 {
+    match code {
+        ErrorCode::TagNotFound | ErrorCode::EntryNotFound | ErrorCode::MountNotFound => {
+            EXIT_NOT_FOUND
+        }
+        _ => EXIT_FAILURE,
+    }
+}
+
 fn print_completions(opts: &CompletionsOpts) -> Result<()> {
     use clap_complete::{
         generate,
@@ -48,9 +98,114 @@ fn print_completions(opts: &CompletionsOpts) -> Result<()> {
     Ok(())
 }
 
+fn print_schema(command: SchemaCommand) -> Result<()> {
+    let schema = schema::command_schema(command);
+    let schema = serde_json::to_string_pretty(&schema).map_err(Error::SerializeSchema)?;
+    println!("{schema}");
+    Ok(())
+}
+
+/// Adds a content rule to the config file at `path` or the default location.
+fn add_content_rule(opts: &AddContentRuleOpts, path: Option<&Path>) -> Result<()> {
+    let rule = ContentTagRule {
+        mime: opts.mime.clone(),
+        pattern: opts.pattern.clone(),
+        tags: opt::split_tags(&opts.tags),
+    };
+    rule.to_tag_rule(wutag_core::color::DEFAULT_COLORS)?;
+
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => Config::default_path()?,
+    };
+    let mut config = Config::load_for_update(&path)?;
+    config.content_rules.push(rule);
+    config.save_file(path)
+}
+
+/// Adds, removes or lists aliases of the configuration at `path` or the default location.
+fn alias(opts: &AliasOpts, path: Option<&Path>) -> Result<()> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => Config::default_path()?,
+    };
+    let mut config = Config::load_for_update(&path)?;
+    if opts.list {
+        for (name, command) in &config.aliases {
+            println!("{name}\t{command}");
+        }
+        return Ok(());
+    }
+    if let Some(name) = &opts.remove {
+        if config.aliases.remove(name).is_none() {
+            return Err(Error::AliasNotFound(name.clone()));
+        }
+    } else if let (Some(name), Some(command)) = (&opts.name, &opts.command) {
+        if name.starts_with('-') || Opts::command().find_subcommand(name).is_some() {
+            return Err(Error::AliasShadowsCommand(name.clone()));
+        }
+        exec::split_command(command)?;
+        config.aliases.insert(name.clone(), command.clone());
+    }
+    config.save_file(path)
+}
+
+/// Replaces the name of the alias, the first of the `args` of an external subcommand, with the
+/// arguments of the aliased command. Returns `None` if no alias of the `aliases` has the name.
+fn expand_alias(
+    args: &[OsString],
+    aliases: &BTreeMap<String, String>,
+) -> Result<Option<Vec<OsString>>> {
+    let Some(command) = args
+        .first()
+        .and_then(|name| name.to_str())
+        .and_then(|name| aliases.get(name))
+    else {
+        return Ok(None);
+    };
+    let mut expanded: Vec<_> = exec::split_command(command)?
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+    expanded.extend_from_slice(&args[1..]);
+    Ok(Some(expanded))
+}
+
+/// Parses the command line arguments replacing an alias in place of the subcommand with the
+/// aliased command. Aliases are read from the configuration passed with `--config`, at
+/// `WUTAG_CONFIG` or at the default location.
+fn parse_opts() -> Opts {
+    let mut opts = Opts::parse();
+    let Command::External(args) = &opts.cmd else {
+        return opts;
+    };
+    let config = match &opts.config {
+        Some(path) => Config::load_file(path),
+        None => Config::load_default_location(),
+    };
+    let aliases = config.map(|config| config.aliases).unwrap_or_default();
+    let expanded = match expand_alias(args, &aliases) {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            eprintln!("Execution failed, reason: {}", e);
+            std::process::exit(EXIT_FAILURE);
+        }
+    };
+    // aliases of other aliases aren't expanded
+    match expanded.map(|args| Command::parse_from(std::iter::once(APP_NAME.into()).chain(args))) {
+        Some(Command::External(_)) | None => Opts::command()
+            .error(
+                clap::error::ErrorKind::InvalidSubcommand,
+                format!("unrecognized subcommand '{}'", args[0].to_string_lossy()),
+            )
+            .exit(),
+        Some(cmd) => opts.cmd = cmd,
+    }
+    opts
+}
+
 fn main() {
-    let config = Config::load_default_location().unwrap_or_default();
-    let opts = Opts::parse();
+    let opts = parse_opts();
 
     if let Command::PrintCompletions(opts) = &opts.cmd {
         if let Err(e) = print_completions(opts) {
@@ -61,7 +216,148 @@ fn main() {
         }
     }
 
-    if let Err(e) = App::run(opts, config) {
-        eprintln!("Execution failed, reason: {}", e);
+    if let Command::Schema { command } = &opts.cmd {
+        if let Err(e) = print_schema(*command) {
+            eprintln!("Execution failed, reason: {}", e);
+            std::process::exit(1);
+        } else {
+            std::process::exit(0);
+        }
+    }
+
+    if let Command::Alias(alias_opts) = &opts.cmd {
+        if let Err(e) = alias(alias_opts, opts.config.as_deref()) {
+            eprintln!("Execution failed, reason: {}", e);
+            std::process::exit(1);
+        } else {
+            std::process::exit(0);
+        }
+    }
+
+    if let Command::AddContentRule(rule_opts) = &opts.cmd {
+        if let Err(e) = add_content_rule(rule_opts, opts.config.as_deref()) {
+            eprintln!("Execution failed, reason: {}", e);
+            std::process::exit(1);
+        } else {
+            std::process::exit(0);
+        }
+    }
+
+    if let Command::Init(opts) = &opts.cmd {
+        if let Err(e) = init::init(opts) {
+            eprintln!("Execution failed, reason: {}", e);
+            std::process::exit(1);
+        } else {
+            std::process::exit(0);
+        }
+    }
+
+    let config = match &opts.config {
+        Some(path) => match Config::load_file(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Execution failed, reason: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => match Config::load_default_location() {
+            Ok(config) => config,
+            Err(e @ Error::Config(config::ConfigError::InvalidColor { .. })) => {
+                eprintln!("Execution failed, reason: {}", e);
+                std::process::exit(1);
+            }
+            Err(_) => Config::default(),
+        },
+    };
+
+    match App::run(opts, config) {
+        Ok(()) => {}
+        Err(Error::App(app::AppError::PredicateFalse | app::AppError::BatchFailed(_))) => {
+            std::process::exit(EXIT_FAILURE)
+        }
+        Err(e @ Error::Client(client::ClientError::NoMatches(_))) => {
+            eprintln!("warning: {e}");
+            std::process::exit(EXIT_NO_MATCHES);
+        }
+        Err(Error::Client(client::ClientError::Response(e))) => {
+            eprintln!("Execution failed, reason: {e}");
+            let suggested = e
+                .details
+                .as_ref()
+                .and_then(|details| details.get("suggested_tags"))
+                .and_then(|tags| tags.as_array())
+                .filter(|tags| !tags.is_empty());
+            if let Some(tags) = suggested {
+                let tags: Vec<_> = tags.iter().filter_map(|tag| tag.as_str()).collect();
+                eprintln!("Did you mean: {}", tags.join(", "));
+            }
+            std::process::exit(exit_status(e.code));
+        }
+        Err(e) => {
+            eprintln!("Execution failed, reason: {}", e);
+            std::process::exit(EXIT_FAILURE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_aliases() {
+        let aliases = BTreeMap::from([(
+            "rust".to_string(),
+            r#"set --glob "**/*.rs" --tags rust"#.to_string(),
+        )]);
+        let external = |args: &[&str]| match Opts::try_parse_from(args).unwrap().cmd {
+            Command::External(args) => args,
+            _ => panic!("not an external subcommand"),
+        };
+        let args = |args: &[&str]| -> Vec<OsString> { args.iter().map(OsString::from).collect() };
+
+        assert_eq!(
+            expand_alias(&external(&["wutag", "rust", "code"]), &aliases).unwrap(),
+            Some(args(&[
+                "set", "--glob", "**/*.rs", "--tags", "rust", "code"
+            ]))
+        );
+        // global options before the alias are parsed as usual
+        let opts = Opts::try_parse_from(["wutag", "--config", "a.yml", "-qojson", "rust"]).unwrap();
+        assert!(opts.quiet);
+        assert_eq!(opts.config, Some("a.yml".into()));
+        assert_eq!(
+            expand_alias(&external(&["wutag", "-q", "rust"]), &aliases).unwrap(),
+            Some(args(&["set", "--glob", "**/*.rs", "--tags", "rust"]))
+        );
+        assert_eq!(
+            expand_alias(&external(&["wutag", "unknown"]), &aliases).unwrap(),
+            None
+        );
+        assert!(matches!(
+            Opts::try_parse_from(["wutag", "list", "tags"]).unwrap().cmd,
+            Command::List(_)
+        ));
+
+        let expanded = expand_alias(&external(&["wutag", "rust", "code"]), &aliases)
+            .unwrap()
+            .unwrap();
+        let cmd = Command::try_parse_from(std::iter::once(APP_NAME.into()).chain(expanded));
+        assert!(matches!(cmd.unwrap(), Command::Set(_)));
+    }
+
+    #[test]
+    fn exits_with_distinct_status_when_not_found() {
+        for code in [
+            ErrorCode::TagNotFound,
+            ErrorCode::EntryNotFound,
+            ErrorCode::MountNotFound,
+        ] {
+            assert_eq!(exit_status(code), EXIT_NOT_FOUND);
+        }
+        assert_eq!(exit_status(ErrorCode::Filesystem), EXIT_FAILURE);
+        // scripts globbing files tell apart a missing tag from a pattern matching nothing
+        assert_ne!(EXIT_NOT_FOUND, EXIT_NO_MATCHES);
+        assert_ne!(EXIT_NOT_FOUND, EXIT_FAILURE);
     }
 }
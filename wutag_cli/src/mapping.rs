@@ -0,0 +1,180 @@
+//! Parsing of mappings of paths to tags used to tag files in bulk
+use crate::opt::split_tags;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A file and tags it should be tagged with.
+#[derive(Debug, PartialEq)]
+pub struct MappingEntry {
+    /// Line of the mapping this entry was read from, starting at 1.
+    pub line: usize,
+    pub path: PathBuf,
+    pub tags: Vec<String>,
+}
+
+/// A line of the mapping that couldn't be parsed.
+#[derive(Debug, PartialEq)]
+pub struct MalformedLine {
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+/// Parses a mapping where each line contains a path and a comma separated list of tags
+/// separated by a tab like `notes.txt<TAB>work,urgent`. Relative paths are resolved against
+/// `base_dir`. Empty lines and lines starting with `#` are skipped, malformed lines are returned
+/// separately so that the rest of the mapping can still be applied.
+pub fn parse_mapping(content: &str, base_dir: &Path) -> (Vec<MappingEntry>, Vec<MalformedLine>) {
+    let mut entries = vec![];
+    let mut malformed = vec![];
+
+    for (i, line) in content.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut error = |reason| {
+            malformed.push(MalformedLine {
+                line: line_number,
+                reason,
+            })
+        };
+
+        let Some((path, tags)) = line.split_once('\t') else {
+            error("missing tab separating the path from tags");
+            continue;
+        };
+        if path.is_empty() {
+            error("empty path");
+            continue;
+        }
+        let tags = split_tags([tags.trim()]);
+        if tags.is_empty() {
+            error("no tags");
+            continue;
+        }
+
+        entries.push(MappingEntry {
+            line: line_number,
+            path: base_dir.join(path),
+            tags,
+        });
+    }
+
+    (entries, malformed)
+}
+
+/// A tag of a [JsonRecord](JsonRecord).
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JsonTag {
+    pub name: String,
+    /// Color of the tag, a random color is used if it is not specified.
+    pub color: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+/// A file and tags it should be tagged with read from a JSON batch.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JsonRecord {
+    pub path: PathBuf,
+    pub tags: Vec<JsonTag>,
+}
+
+/// Parses a JSON array of records like `[{"path": "notes.txt", "tags": [{"name": "work",
+/// "color": "#f00"}]}]`. Relative paths are resolved against `base_dir`. Each record is parsed
+/// and validated separately so that a single invalid record doesn't prevent applying the rest,
+/// the result of every record is returned in order of the array.
+pub fn parse_json_records(
+    content: &str,
+    base_dir: &Path,
+) -> serde_json::Result<Vec<Result<JsonRecord, String>>> {
+    let values: Vec<serde_json::Value> = serde_json::from_str(content)?;
+    Ok(values
+        .into_iter()
+        .map(|value| {
+            let mut record: JsonRecord =
+                serde_json::from_value(value).map_err(|e| e.to_string())?;
+            if record.path.as_os_str().is_empty() {
+                return Err("empty path".into());
+            }
+            if record.tags.is_empty() {
+                return Err("no tags".into());
+            }
+            if record.tags.iter().any(|tag| tag.name.is_empty()) {
+                return Err("empty tag name".into());
+            }
+            record.path = base_dir.join(record.path);
+            Ok(record)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mapping_and_reports_malformed_lines() {
+        let mapping = "# exported tags\n\
+                       /tmp/a.txt\twork,urgent\n\
+                       \n\
+                       b.txt\tnotes\r\n\
+                       missing-tab work\n\
+                       \twork\n\
+                       c.txt\t ,\n";
+
+        let (entries, malformed) = parse_mapping(mapping, Path::new("/home/user"));
+
+        assert_eq!(
+            entries,
+            vec![
+                MappingEntry {
+                    line: 2,
+                    path: "/tmp/a.txt".into(),
+                    tags: vec!["work".into(), "urgent".into()],
+                },
+                MappingEntry {
+                    line: 4,
+                    path: "/home/user/b.txt".into(),
+                    tags: vec!["notes".into()],
+                },
+            ]
+        );
+        assert_eq!(
+            malformed.iter().map(|m| m.line).collect::<Vec<_>>(),
+            vec![5, 6, 7]
+        );
+    }
+
+    #[test]
+    fn parses_json_records_and_reports_invalid_ones() {
+        let json = r##"[
+            {"path": "a.txt", "tags": [{"name": "work", "color": "#f00", "description": "job"}]},
+            {"path": "/tmp/b.txt", "tags": []},
+            {"path": "c.txt"},
+            {"path": "d.txt", "tags": [{"name": "", "color": "#f00"}]},
+            {"path": "e.txt", "tags": [{"name": "notes", "metadata": {"source": "import"}}]}
+        ]"##;
+
+        let records = parse_json_records(json, Path::new("/home/user")).unwrap();
+
+        assert_eq!(records.len(), 5);
+        let first = records[0].as_ref().unwrap();
+        assert_eq!(first.path, Path::new("/home/user/a.txt"));
+        assert_eq!(first.tags[0].color.as_deref(), Some("#f00"));
+        assert_eq!(first.tags[0].description.as_deref(), Some("job"));
+        assert_eq!(records[1], Err("no tags".into()));
+        assert!(records[2].is_err());
+        assert_eq!(records[3], Err("empty tag name".into()));
+        let last = records[4].as_ref().unwrap();
+        assert_eq!(last.tags[0].metadata["source"], "import");
+
+        assert!(parse_json_records("{}", Path::new("/")).is_err());
+    }
+}
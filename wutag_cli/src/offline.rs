@@ -0,0 +1,231 @@
+//! Answers read requests straight from the registry file when running with `--offline`.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use wutag_core::registry::{EntryData, EntryId, RegistryError, TagRegistry};
+use wutag_core::tag::{list_tags, Tag};
+use wutag_ipc::{InspectFilesResult, IpcError, Paged, PayloadResult, Request, Response};
+
+/// Returns the path of the registry file used by the daemon by default.
+pub fn default_registry_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("wutag.db"))
+}
+
+/// Registry loaded from its file without the daemon. It is never locked nor saved so it can be
+/// read while the daemon is running, only requests that read the registry are answered.
+pub struct OfflineRegistry {
+    registry: TagRegistry,
+}
+
+impl OfflineRegistry {
+    pub fn load(path: &Path) -> Result<Self, RegistryError> {
+        TagRegistry::load(path).map(|registry| Self { registry })
+    }
+
+    /// Answers the `request` the same way the daemon would, failing for requests that would
+    /// modify the registry or files.
+    pub fn request(&self, request: Request) -> wutag_ipc::Result<Response> {
+        let registry = &self.registry;
+        let response = match request {
+            Request::ListTags {
+                with_files,
+                page,
+                include_unused,
+            } => {
+                let (items, total) =
+                    registry.list_tags_page(page.offset, page.limit, with_files, include_unused);
+                Response::ListTags(PayloadResult::Ok(Paged { items, total }))
+            }
+            Request::ListFiles { with_tags, page } => {
+                let (items, total) = registry.list_entries_page(page.offset, page.limit, with_tags);
+                Response::ListFiles(PayloadResult::Ok(Paged { items, total }))
+            }
+            Request::GetTagMeta { tag: None } => Response::GetTagMeta(PayloadResult::Ok(
+                registry
+                    .list_tag_meta()
+                    .map(|(name, meta)| (name.clone(), meta.clone()))
+                    .collect(),
+            )),
+            Request::InspectFiles { files, no_follow } => self.inspect_files(files, no_follow),
+            Request::InspectFilesPattern { glob, no_follow } => match glob.glob_paths() {
+                Ok(files) if files.is_empty() => {
+                    Response::InspectFiles(PayloadResult::Ok(InspectFilesResult {
+                        entries: vec![],
+                        match_count: 0,
+                        expiries: HashMap::new(),
+                    }))
+                }
+                Ok(files) => self.inspect_files(files, no_follow),
+                Err(e) => Response::InspectFiles(PayloadResult::Error(e.to_string())),
+            },
+            Request::SearchWeighted { tags, any } if !tags.is_empty() => {
+                let found = self
+                    .search(&tags, any)
+                    .into_iter()
+                    .map(|(_, entry, score)| (entry.clone(), score))
+                    .collect();
+                Response::SearchWeighted(PayloadResult::Ok(found))
+            }
+            Request::SearchWithTags { tags, any } if !tags.is_empty() => {
+                let found = self
+                    .search(&tags, any)
+                    .into_iter()
+                    .map(|(id, entry, _)| {
+                        let mut entry_tags: Vec<_> = registry
+                            .list_entry_tags(id)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .cloned()
+                            .collect();
+                        entry_tags.sort_unstable();
+                        (entry.clone(), entry_tags)
+                    })
+                    .collect();
+                Response::SearchWithTags(PayloadResult::Ok(found))
+            }
+            Request::SearchByTagPrefix { prefix } if !prefix.is_empty() => {
+                let found = registry
+                    .list_tags_with_prefix(&prefix)
+                    .into_iter()
+                    .map(|tag| {
+                        let mut entries: Vec<_> = registry
+                            .list_entries_with_any_tags([tag.name()])
+                            .into_iter()
+                            .filter_map(|id| registry.get_entry(id).cloned())
+                            .collect();
+                        entries.sort_unstable_by(|a, b| a.path().cmp(b.path()));
+                        (tag.clone(), entries)
+                    })
+                    .collect();
+                Response::SearchByTagPrefix(PayloadResult::Ok(found))
+            }
+            Request::SearchByOriginalName { name } if !name.is_empty() => {
+                let found = registry
+                    .find_entries_by_original_name(&name)
+                    .into_iter()
+                    .map(|(_, entry)| entry.clone())
+                    .collect();
+                Response::SearchByOriginalName(PayloadResult::Ok(found))
+            }
+            Request::SearchByPathFragment { fragment } if !fragment.is_empty() => {
+                let found = registry
+                    .search_entries_by_path_fragment(&fragment)
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                Response::SearchByPathFragment(PayloadResult::Ok(found))
+            }
+            Request::SearchFiltered { filter } => {
+                let found = filter
+                    .apply(registry)
+                    .into_iter()
+                    .filter_map(|id| registry.get_entry(id).cloned())
+                    .collect();
+                Response::SearchFiltered(PayloadResult::Ok(found))
+            }
+            request => {
+                return Err(IpcError::Other(format!(
+                    "request `{request:?}` is not available in offline mode"
+                )))
+            }
+        };
+        Ok(response)
+    }
+
+    /// Returns entries with the `tags` along with their scores sorted by the score in
+    /// descending order.
+    fn search(&self, tags: &[String], any: bool) -> Vec<(EntryId, &EntryData, f32)> {
+        let registry = &self.registry;
+        let entries = if any {
+            registry.list_entries_with_any_tags(tags)
+        } else {
+            registry.list_entries_with_all_tags(tags)
+        };
+        let mut found: Vec<_> = entries
+            .into_iter()
+            .filter_map(|id| {
+                registry
+                    .get_entry(id)
+                    .map(|entry| (id, entry, registry.entry_score(id, tags)))
+            })
+            .collect();
+        found.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+        found
+    }
+
+    fn inspect_files(&self, files: Vec<PathBuf>, no_follow: bool) -> Response {
+        if files.is_empty() {
+            return Response::InspectFiles(PayloadResult::Error("no files to inspect".into()));
+        }
+        let registry = &self.registry;
+        let match_count = files.len();
+        let mut entries = vec![];
+        let mut expiries: HashMap<PathBuf, BTreeMap<String, _>> = HashMap::new();
+        for file in files {
+            if no_follow {
+                // the registry doesn't tell tags of symlinks apart from tags of their targets
+                if let Ok(tags) = list_tags(&file, false) {
+                    if !tags.is_empty() {
+                        let entry = registry
+                            .find_entry(&file)
+                            .and_then(|id| registry.get_entry(id).cloned())
+                            .unwrap_or_else(|| EntryData::new(&file).with_no_follow(true));
+                        entries.push((entry, tags));
+                    }
+                }
+            } else if let Some(id) = registry.find_entry(&file) {
+                let tags: Vec<Tag> = registry
+                    .list_entry_tags(id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                let entry = registry.get_entry(id).unwrap().clone();
+                if let Some(entry_expiries) = registry.entry_expiries(id) {
+                    expiries.insert(entry.path().to_path_buf(), entry_expiries.clone());
+                }
+                entries.push((entry, tags));
+            }
+        }
+        Response::InspectFiles(PayloadResult::Ok(InspectFilesResult {
+            entries,
+            match_count,
+            expiries,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use wutag_core::color::Color;
+
+    #[test]
+    fn answers_read_requests_only() {
+        let dir = TempDir::new("wutag-offline").unwrap();
+        let path = dir.path().join("wutag.db");
+        let file = dir.path().join("file");
+        let mut registry = TagRegistry::new(path.clone());
+        let (id, _) = registry.add_or_update_entry(EntryData::new(&file));
+        registry.tag_entry(&Tag::new("offline", Color::Red), id);
+        registry.save().unwrap();
+
+        let offline = OfflineRegistry::load(&path).unwrap();
+        let Response::SearchWithTags(PayloadResult::Ok(found)) = offline
+            .request(Request::SearchWithTags {
+                tags: vec!["offline".into()],
+                any: false,
+            })
+            .unwrap()
+        else {
+            panic!("unexpected response");
+        };
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0.path(), file);
+        assert_eq!(found[0].1[0].name(), "offline");
+
+        assert!(offline
+            .request(Request::ClearAllTags { force: false })
+            .is_err());
+    }
+}
@@ -1,7 +1,11 @@
 //! Options used by the main executable
-use std::{path::PathBuf, str::FromStr};
+use std::{ffi::OsString, path::PathBuf, str::FromStr, time::Duration};
 
-use clap::Parser;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use clap::{Parser, ValueEnum};
+use wutag_core::tag::WEIGHT_RANGE;
+
+use crate::schema::SchemaCommand;
 
 pub const APP_NAME: &str = "wutag";
 pub const APP_VERSION: &str = "0.5.0";
@@ -31,8 +35,28 @@ pub struct Opts {
     pub pretty: bool,
     #[arg(short, long)]
     #[clap(default_value = "default")]
-    /// Change the output format to `json` or `yaml`
+    /// Change the output format to `json`, `yaml` or `table`
     pub output_format: OutputFormat,
+    #[arg(long)]
+    /// Pretty-print the output with `--output-format json` instead of printing compact JSON on
+    /// a single line.
+    pub json_pretty: bool,
+    #[arg(long)]
+    /// Use the registry of the daemon mounted at this directory, as listed by `list-mounts`,
+    /// instead of selecting the registry by paths of the files.
+    pub registry: Option<PathBuf>,
+    #[arg(long, conflicts_with = "registry")]
+    /// Read the registry file directly instead of asking the daemon, which doesn't have to be
+    /// running. The registry is never modified so only `list`, `search` and `get` are supported.
+    pub offline: bool,
+    #[arg(long, short)]
+    /// Only print a single `summary:` line after batch operations like `set`, `rm`, `cp` and
+    /// `clear files` instead of reporting every failed file.
+    pub quiet: bool,
+    #[arg(long, env = "WUTAG_CONFIG")]
+    /// Path to the configuration file to use instead of `wutag.yml` in the user configuration
+    /// directory.
+    pub config: Option<PathBuf>,
     #[clap(subcommand)]
     pub cmd: Command,
 }
@@ -42,6 +66,7 @@ pub struct Opts {
 pub enum OutputFormat {
     Yaml,
     Json,
+    Table,
     Default,
 }
 
@@ -51,6 +76,7 @@ impl FromStr for OutputFormat {
         match &s.to_lowercase()[..] {
             "yaml" => Ok(OutputFormat::Yaml),
             "json" => Ok(OutputFormat::Json),
+            "table" => Ok(OutputFormat::Table),
             "default" => Ok(OutputFormat::Default),
             _ => Err(crate::Error::InvalidOutputFormat(s.to_string())),
         }
@@ -63,14 +89,48 @@ pub enum ListObject {
         #[arg(long, short = 'f')]
         /// Should all entries tagged with a tag be shown
         with_files: bool,
+        #[arg(long, short)]
+        /// Also show metadata of the tags set with `tag-meta`
+        verbose: bool,
+        #[arg(long, short)]
+        /// Also list tags created with `create-tag` that no file has yet, they are marked with `*`
+        unused: bool,
+        #[clap(flatten)]
+        page: PageOpts,
     },
     Files {
         #[arg(long, short = 't')]
         /// Should the tags of the entry be display.
         with_tags: bool,
+        #[clap(flatten)]
+        page: PageOpts,
+        #[arg(long, short = 'x', conflicts_with = "exec_batch")]
+        /// Run the command for each listed file replacing `{}` with its path, like `search
+        /// --exec`.
+        exec: Option<String>,
+        #[arg(long, short = 'X')]
+        /// Run the command once with paths of all listed files, like `search --exec-batch`.
+        exec_batch: Option<String>,
     },
 }
 
+#[derive(Parser, Clone, Copy, Debug, Default)]
+pub struct PageOpts {
+    #[arg(long, default_value_t = 0)]
+    /// Skip this many items of the listing sorted by name
+    pub offset: usize,
+    #[arg(long)]
+    /// List at most this many items
+    pub limit: Option<usize>,
+}
+
+impl PageOpts {
+    /// Whether only a part of the listing was requested.
+    pub fn is_paged(&self) -> bool {
+        self.offset > 0 || self.limit.is_some()
+    }
+}
+
 #[derive(Parser)]
 pub struct ListOpts {
     #[clap(subcommand)]
@@ -85,10 +145,24 @@ pub struct SetOpts {
     #[arg(short, long)]
     /// Treat the first path as a glob pattern
     pub glob: bool,
-    #[clap(required = true)]
+    #[arg(short, long)]
+    /// Also tag the entries with tags of matching `content_rules` from the configuration
+    pub auto_tag: bool,
+    #[clap(required_unless_present = "auto_tag")]
     #[arg(short, long, action = clap::ArgAction::Append, num_args = 0..)]
-    /// List of tags to tag the entries with
+    /// List of tags to tag the entries with. Tags can also be separated with commas like
+    /// `-t work,urgent`, use `\,` to include a literal comma in a tag name.
     pub tags: Vec<String>,
+    #[arg(short, long, value_parser = parse_duration)]
+    /// Remove the tags after the specified time like `30m`, `12h`, `7d` or `1w2d`. Expired tags
+    /// are only removed while the daemon is running.
+    pub expires: Option<Duration>,
+    #[arg(long, conflicts_with = "follow")]
+    /// Tag symlinks themselves instead of the files they point to
+    pub no_follow: bool,
+    #[arg(long)]
+    /// Tag the files symlinks point to even if `follow_symlinks` is disabled in the configuration
+    pub follow: bool,
 }
 
 #[derive(Parser)]
@@ -98,6 +172,34 @@ pub struct GetOpts {
     #[arg(short, long)]
     /// Treat the first path as a glob pattern
     pub glob: bool,
+    #[arg(long, conflicts_with = "follow")]
+    /// Print tags of symlinks themselves instead of the files they point to
+    pub no_follow: bool,
+    #[arg(long)]
+    /// Print tags of the files symlinks point to even if `follow_symlinks` is disabled in the
+    /// configuration
+    pub follow: bool,
+}
+
+#[derive(Parser)]
+pub struct CapacityOpts {
+    /// The file of which to estimate the capacity
+    pub path: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct ExportXattrsOpts {
+    /// A list of files to export extended attributes of
+    pub paths: Vec<String>,
+    #[arg(short, long)]
+    /// Treat the first path as a glob pattern
+    pub glob: bool,
+    #[arg(long)]
+    /// Print the values encoded with base64 in the `0s` notation of `setfattr`
+    pub base64: bool,
+    #[arg(long)]
+    /// Print a complete bash script that stops at the first failing command
+    pub script: bool,
 }
 
 #[derive(Parser)]
@@ -109,15 +211,39 @@ pub struct RmOpts {
     pub glob: bool,
     #[clap(required = true)]
     #[arg(short, long, action = clap::ArgAction::Append, num_args = 0..)]
+    /// List of tags to remove from the entries. Tags can also be separated with commas like
+    /// `-t work,urgent`, use `\,` to include a literal comma in a tag name.
     pub tags: Vec<String>,
+    #[arg(long, conflicts_with = "follow")]
+    /// Remove tags from symlinks themselves instead of the files they point to
+    pub no_follow: bool,
+    #[arg(long)]
+    /// Remove tags from the files symlinks point to even if `follow_symlinks` is disabled in the
+    /// configuration
+    pub follow: bool,
+    #[arg(long)]
+    /// Remove system tags as well, they are skipped otherwise
+    pub force: bool,
 }
 
 #[derive(Parser)]
 pub enum ClearObject {
     /// Remove tags completely
     Tags {
-        /// The names of the tags to clear from all entries
+        #[clap(required_unless_present = "all")]
+        /// The names of the tags to clear from all entries. Names can contain wildcards like
+        /// `tmp-*` matching any number of characters or `?` matching a single one, use `\*` to
+        /// match a literal `*`.
         names: Vec<String>,
+        #[arg(long, conflicts_with = "names")]
+        /// Clear every tag from all entries, removing the tags from the files as well
+        all: bool,
+        #[arg(long, short)]
+        /// Don't ask for confirmation when clearing all tags or tags matching wildcards
+        yes: bool,
+        #[arg(long)]
+        /// Clear system tags as well, they are skipped otherwise
+        force: bool,
     },
     /// Remove all tags from specified files
     Files {
@@ -126,8 +252,13 @@ pub enum ClearObject {
         #[arg(short, long)]
         /// Treat the first path as a glob pattern
         glob: bool,
+        #[arg(long)]
+        /// Remove system tags as well, they are kept otherwise
+        force: bool,
     },
     Cache,
+    /// Clear the history of recently applied tags
+    RecentTags,
 }
 
 #[derive(Parser)]
@@ -137,13 +268,115 @@ pub struct ClearOpts {
     pub object: ClearObject,
 }
 
+/// Options of `search` that can't be combined with filtering by `--not-tag`, `--path-prefix`,
+/// `--created-after` or `--created-before`.
+const FILTER_CONFLICTS: [&str; 5] = [
+    "verbose",
+    "prefix",
+    "original_name",
+    "path_contains",
+    "with_tags",
+];
+
 #[derive(Parser)]
 pub struct SearchOpts {
-    #[clap(required = true)]
+    #[clap(required_unless_present_any = [
+        "prefix",
+        "original_name",
+        "path_contains",
+        "not_tag",
+        "path_prefix",
+        "created_after",
+        "created_before"
+    ])]
     pub tags: Vec<String>,
+    #[arg(long, conflicts_with_all = ["tags", "any", "verbose"])]
+    /// Search for files with any tag starting with the prefix like `project/` instead of the
+    /// tags. Found files are grouped by the matching tag.
+    pub prefix: Option<String>,
+    #[arg(long, conflicts_with_all = ["tags", "any", "verbose", "prefix"])]
+    /// Search for tagged files that were named `name` when they were first tagged, even if they
+    /// were renamed since.
+    pub original_name: Option<String>,
+    #[arg(long, conflicts_with_all = ["tags", "any", "verbose", "prefix", "original_name"])]
+    /// Search for tagged files whose paths contain the substring ignoring case.
+    pub path_contains: Option<String>,
+    #[arg(long, conflicts_with_all = FILTER_CONFLICTS)]
+    /// Only find files not tagged with this tag, can be passed multiple times
+    pub not_tag: Vec<String>,
+    #[arg(long, conflicts_with_all = FILTER_CONFLICTS)]
+    /// Only find files under this directory
+    pub path_prefix: Option<PathBuf>,
+    #[arg(long, value_parser = parse_date, conflicts_with_all = FILTER_CONFLICTS)]
+    /// Only find files first tagged after this date like `2024-01-31` or RFC 3339 time
+    pub created_after: Option<DateTime<Utc>>,
+    #[arg(long, value_parser = parse_date, conflicts_with_all = FILTER_CONFLICTS)]
+    /// Only find files first tagged before this date like `2024-01-31` or RFC 3339 time
+    pub created_before: Option<DateTime<Utc>>,
     #[arg(long, short)]
     /// If set to 'true' all entries containing any of provided tags will be returned
     pub any: bool,
+    #[arg(long, short)]
+    /// Print the score of each entry, that is the sum of weights of matched tags. Entries are
+    /// always sorted by the score in descending order.
+    pub verbose: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "verbose",
+            "prefix",
+            "original_name",
+            "path_contains",
+            "exec",
+            "exec_batch"
+        ]
+    )]
+    /// Print all tags of each found entry next to its path.
+    pub with_tags: bool,
+    #[arg(long, conflicts_with_all = FILTER_CONFLICTS)]
+    /// Search files under the base directory up to `--max-depth` levels deep by the tags saved in
+    /// their extended attributes instead of the registry. This also finds tagged files that the
+    /// daemon doesn't track.
+    pub scan: bool,
+    #[arg(long, short = 'x', conflicts_with = "exec_batch")]
+    /// Run the command for each found entry replacing `{}` with the path of the entry, for
+    /// example `--exec 'code {}'`. The command is not run through a shell.
+    pub exec: Option<String>,
+    #[arg(long, short = 'X')]
+    /// Run the command once with paths of all found entries in place of `{}` or `{+}`, or at the
+    /// end of the command if it contains no placeholder.
+    pub exec_batch: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct MigrateNamespaceOpts {
+    #[arg(long)]
+    /// The xattr namespace the tags are currently saved in, like `user.oldwutag`. Without it only
+    /// values of tags saved by older versions in the current namespace are rewritten
+    pub from: Option<String>,
+    #[arg(long, default_value = wutag_core::WUTAG_NAMESPACE)]
+    /// The xattr namespace to move the tags to
+    pub to: String,
+    /// A glob pattern like `'**/*'` matching the files to migrate
+    pub pattern: String,
+}
+
+#[derive(Parser)]
+pub struct MigratePathsOpts {
+    /// The directory relative paths of the registry are resolved against, usually the directory
+    /// the old version of wutag was run from
+    pub root: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct SetExactOpts {
+    #[clap(required = true)]
+    /// A list of files whose tags to set
+    pub paths: Vec<String>,
+    #[clap(last = true)]
+    /// The only tags the files should have after the command, like `-- photos 2024`. Other tags
+    /// are removed from the files and no tags at all clears them.
+    pub tags: Vec<String>,
 }
 
 #[derive(Parser)]
@@ -159,14 +392,319 @@ pub struct CpOpts {
 }
 
 #[derive(Parser)]
+#[clap(group(clap::ArgGroup::new("edit").required(true).multiple(true).args(["color", "weight"])))]
 pub struct EditOpts {
     /// The tag to edit
     pub tag: String,
     #[arg(long, short)]
     /// Set the color of the tag to the specified color. Accepted values are hex colors like
     /// `0x000000` or `#1F1F1F` or just plain `ff000a`. The colors are case insensitive meaning
-    /// `1f1f1f` is equivalent to `1F1F1F`.
-    pub color: String,
+    /// `1f1f1f` is equivalent to `1F1F1F`. Colors can also be specified in HSL notation like
+    /// `hsl(120, 100%, 50%)` or `hsla(120, 100%, 50%, 0.5)`, the alpha channel is ignored.
+    pub color: Option<String>,
+    #[arg(long, short, value_parser = parse_weight)]
+    /// Set the weight of the tag used to rank search results. Accepted values are in range
+    /// 0.0-10.0, the default weight of a tag is 1.0.
+    pub weight: Option<f32>,
+    #[arg(long, conflicts_with = "no_system")]
+    /// Mark the tag as a system tag, system tags are only removed from files with `--force`
+    pub system: bool,
+    #[arg(long)]
+    /// Unmark the tag as a system tag
+    pub no_system: bool,
+}
+
+#[derive(Parser)]
+pub struct CreateTagOpts {
+    /// The name of the tag
+    pub name: String,
+    #[arg(long, short)]
+    /// The color of the tag, accepts the same values as `edit --color`. A color of the configured
+    /// palette is picked if not specified.
+    pub color: Option<String>,
+    #[arg(long, short)]
+    /// A description of the tag stored in its metadata
+    pub description: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct TagMetaOpts {
+    /// The tag of which metadata will be shown or modified
+    pub tag: String,
+    #[arg(long, short, value_parser = parse_key_value)]
+    /// Set metadata of the tag like `icon=folder`, can be used multiple times
+    pub set: Vec<(String, String)>,
+    #[arg(long, short)]
+    /// Remove metadata of the tag with the key, can be used multiple times
+    pub unset: Vec<String>,
+}
+
+/// Parses a duration made of numbers followed by a unit, one of `s`, `m`, `h`, `d` or `w`,
+/// like `1d12h`.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            c => {
+                return Err(format!(
+                    "invalid unit `{c}`, valid units are s, m, h, d and w"
+                ))
+            }
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| format!("missing number before unit `{c}`"))?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or("duration is too long")?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(format!("missing unit after `{number}`"));
+    }
+    if total == 0 {
+        return Err("duration must be greater than zero".into());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Parses a date like `2024-01-31`, taken as midnight UTC, or an RFC 3339 time.
+fn parse_date(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_time(NaiveTime::MIN).and_utc());
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("expected a date like `2024-01-31` or an RFC 3339 time - {e}"))
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected `key=value`, got `{s}`")),
+    }
+}
+
+fn parse_weight(s: &str) -> Result<f32, String> {
+    let weight: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if WEIGHT_RANGE.contains(&weight) {
+        Ok(weight)
+    } else {
+        Err(format!(
+            "weight must be in range {}-{}",
+            WEIGHT_RANGE.start(),
+            WEIGHT_RANGE.end()
+        ))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortKey {
+    Path,
+    Size,
+    SizeDesc,
+    Mtime,
+    MtimeDesc,
+}
+
+impl FromStr for SortKey {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "path" => Ok(SortKey::Path),
+            "size" => Ok(SortKey::Size),
+            "size-desc" => Ok(SortKey::SizeDesc),
+            "mtime" => Ok(SortKey::Mtime),
+            "mtime-desc" => Ok(SortKey::MtimeDesc),
+            _ => Err(crate::Error::InvalidSortKey(s.to_string())),
+        }
+    }
+}
+
+#[derive(Parser)]
+pub struct CatOpts {
+    /// The tag of which files will be shown
+    pub tag: String,
+    #[arg(long, short)]
+    /// Sort the files by the specified key. Available keys are: path, size, size-desc, mtime,
+    /// mtime-desc
+    pub sort: Option<SortKey>,
+}
+
+#[derive(Parser)]
+#[clap(group(clap::ArgGroup::new("criteria").required(true).multiple(true).args(["mime", "pattern"])))]
+pub struct AddContentRuleOpts {
+    #[arg(long, short)]
+    /// Prefix of the MIME type of matching files like `image/` or `application/pdf`
+    pub mime: Option<String>,
+    #[arg(long, short)]
+    /// Regular expression matched against the first 4KB of the content of files
+    pub pattern: Option<String>,
+    #[clap(required = true)]
+    #[arg(short, long, action = clap::ArgAction::Append, num_args = 0..)]
+    /// List of tags applied to matching files
+    pub tags: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct AliasOpts {
+    #[clap(required_unless_present_any = ["list", "remove"])]
+    /// Name of the alias used in place of a subcommand
+    pub name: Option<String>,
+    #[clap(required_unless_present_any = ["list", "remove"])]
+    /// Arguments the alias expands to, for example `'set --glob "**/*.rs" -- rust'`
+    pub command: Option<String>,
+    #[arg(long, short, conflicts_with_all = ["name", "remove"])]
+    /// List defined aliases
+    pub list: bool,
+    #[arg(long, short, conflicts_with = "name")]
+    /// Remove the alias with this name
+    pub remove: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ApplyOpts {
+    /// Path to the mapping, each line contains a path and a comma separated list of tags
+    /// separated by a tab. Relative paths are resolved against the directory of the mapping.
+    pub mapping: PathBuf,
+    #[arg(long, short = 'n')]
+    /// Only print the tags that would be applied without tagging any files
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct SetJsonOpts {
+    /// Path to the JSON file with records to apply, records are read from stdin if not
+    /// specified. Relative paths of records are resolved against the working directory.
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct RecentOpts {
+    #[arg(short, long, value_parser = parse_duration)]
+    /// Only show files tagged for the first time within the specified time like `30m`, `1h` or
+    /// `2d`
+    pub since: Option<Duration>,
+    #[arg(short, long)]
+    /// Show at most this many files
+    pub limit: Option<usize>,
+}
+
+#[derive(Parser)]
+pub struct RandomOpts {
+    #[arg(short, long)]
+    /// Pick this many files, defaults to 1
+    pub count: Option<usize>,
+    #[arg(short, long, action = clap::ArgAction::Append, num_args = 1..)]
+    /// Only pick from files tagged with these tags, all tagged files are picked from otherwise.
+    /// Tags can also be separated with commas like `-t photos,2024`.
+    pub tags: Vec<String>,
+    #[arg(long, short)]
+    /// Pick from files tagged with any of the tags instead of all of them
+    pub any: bool,
+    #[arg(long, short = 'x')]
+    /// Run the command once with paths of the picked files in place of `{}` or `{+}`, or at the
+    /// end of the command if it contains no placeholder.
+    pub exec: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct AuditLogOpts {
+    #[arg(short, long, value_parser = parse_duration)]
+    /// Only show operations made within the specified time like `30m`, `1h` or `2d`
+    pub since: Option<Duration>,
+    #[arg(short, long)]
+    /// Show at most this many of the most recent operations
+    pub limit: Option<usize>,
+}
+
+#[derive(Parser)]
+pub struct DiffFsOpts {
+    #[arg(long)]
+    /// Update the registry to match tags saved in the files
+    pub fix: bool,
+}
+
+#[derive(Parser)]
+pub struct VerifyOpts {
+    #[arg(long)]
+    /// Read tags of all files, including files that didn't change since they were last verified
+    pub full: bool,
+}
+
+#[derive(Parser)]
+pub struct CountOpts {
+    #[clap(required = true)]
+    /// The tags to count files of
+    pub tags: Vec<String>,
+    #[arg(long, short)]
+    /// Print the number of files that have all of the tags instead of counts of each tag
+    pub total: bool,
+    #[arg(long, short, requires = "total")]
+    /// Count files that have any of the tags when used with `--total`
+    pub any: bool,
+}
+
+#[derive(Parser)]
+pub struct LinkOpts {
+    /// The tag implying the other tag
+    pub tag: String,
+    #[arg(long)]
+    /// The tag added to files tagged with `tag`
+    pub implies: String,
+}
+
+#[derive(Parser)]
+pub struct ColorsOpts {
+    #[arg(long)]
+    /// Only show colors used by more than one tag
+    pub conflicts: bool,
+    #[arg(long, requires = "conflicts")]
+    /// Give all but the first tag of each conflicting group a color from the palette that no
+    /// tag uses yet
+    pub fix: bool,
+}
+
+#[derive(Parser)]
+pub struct OverlapOpts {
+    /// The first tag
+    pub a: String,
+    /// The second tag
+    pub b: String,
+}
+
+#[derive(Parser)]
+pub struct SameTagsOpts {
+    /// The first file
+    pub a: PathBuf,
+    /// The second file
+    pub b: PathBuf,
+    #[arg(long, conflicts_with = "follow")]
+    /// Compare tags of symlinks themselves rather than of the files they point to
+    pub no_follow: bool,
+    #[arg(long)]
+    /// Compare tags of the files symlinks point to even if `follow_symlinks` is disabled in the
+    /// configuration
+    pub follow: bool,
+}
+
+#[derive(Parser)]
+pub struct RenamePrefixOpts {
+    /// The prefix of tags to rename
+    pub prefix: String,
+    /// The prefix that will replace the old one
+    pub replacement: String,
 }
 
 #[derive(Parser, Clone, Copy)]
@@ -200,12 +738,75 @@ pub struct CompletionsOpts {
     pub shell: Shell,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphFormat {
+    Dot,
+    Svg,
+}
+
+impl FromStr for GraphFormat {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "dot" => Ok(GraphFormat::Dot),
+            "svg" => Ok(GraphFormat::Svg),
+            _ => Err(crate::Error::InvalidGraphFormat(s.to_string())),
+        }
+    }
+}
+
+#[derive(Parser)]
+pub struct ExportGraphOpts {
+    #[arg(long, short)]
+    /// Path to the file to which the graph will be written, otherwise the graph is printed to
+    /// stdout.
+    pub output: Option<PathBuf>,
+    #[arg(long, short)]
+    #[clap(default_value = "dot")]
+    /// Format of the graph. Available formats are: `dot`, `svg`. Rendering `svg` requires the
+    /// `dot` executable from Graphviz.
+    pub format: GraphFormat,
+}
+
+#[derive(Parser)]
+pub struct TagReportOpts {
+    #[arg(long, short)]
+    /// Path to the file to which the report will be written, otherwise the report is printed to
+    /// stdout.
+    pub output: Option<PathBuf>,
+    #[arg(long)]
+    /// Include descriptions and metadata of tags in the report.
+    pub include_metadata: bool,
+}
+
+#[derive(Parser)]
+pub struct InitOpts {
+    /// The directory of the project in which the configuration will be created
+    pub directory: PathBuf,
+    #[arg(long, short, value_enum, default_value_t = InitTheme::Default)]
+    /// The palette used to prepopulate colors of the configuration
+    pub colors: InitTheme,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InitTheme {
+    /// Lists the default colors commented out
+    Default,
+    /// Uses a colorblind friendly palette
+    Accessible,
+    /// Leaves the colors to be filled in
+    Custom,
+}
+
 #[derive(Parser)]
 pub enum Command {
     /// Lists all available tags or files.
     List(ListOpts),
     /// Tags the files that match the given pattern with specified tags.
     Set(SetOpts),
+    /// Makes the given tags the only tags of the files, adding the missing ones and removing all
+    /// others. Unlike `set` running it again changes nothing.
+    SetExact(SetExactOpts),
     /// Retrieve tags of files
     Get(GetOpts),
     /// Removes the specified tags of the files that match the provided pattern.
@@ -218,6 +819,198 @@ pub enum Command {
     Cp(CpOpts),
     /// Edits a tag.
     Edit(EditOpts),
+    /// Creates a tag without tagging any file so that its color and description are kept until
+    /// it is used.
+    CreateTag(CreateTagOpts),
+    /// Shows or modifies metadata of a tag like an icon or priority. The metadata describes the
+    /// tag itself and is only stored by the daemon, not in tagged files.
+    TagMeta(TagMetaOpts),
     /// Prints completions for the specified shell to stdout.
     PrintCompletions(CompletionsOpts),
+    /// Creates a default `.wutag.yml` configuration in the specified project directory.
+    Init(InitOpts),
+    /// Prints tags of files as `setfattr` commands that recreate them, for example on another
+    /// machine without wutag.
+    ExportXattrs(ExportXattrsOpts),
+    /// Estimates how many more tags fit on a file before reaching the limit of the filesystem
+    /// for extended attributes, ext4 for example fits all attributes of a file in one block.
+    Capacity(CapacityOpts),
+    /// Exports a graph of tags and files tagged with them in Graphviz DOT format.
+    ExportGraph(ExportGraphOpts),
+    /// Generates a Markdown report listing files of every tag, for example to document a project
+    /// in its README or wiki.
+    TagReport(TagReportOpts),
+    /// Lists recently applied tags, most recent first.
+    RecentTags,
+    /// Lists recently tagged files, most recent first. Files are listed by the time they were
+    /// first tagged.
+    Recent(RecentOpts),
+    /// Prints randomly picked tagged files, for example to pick a file to review.
+    Random(RandomOpts),
+    /// Lists operations modifying tags recorded by the daemon along with the user and time they
+    /// were made, oldest first.
+    AuditLog(AuditLogOpts),
+    /// Lists differences between the registry and tags saved in extended attributes of files.
+    /// Files missing from the registry are searched for in the base directory.
+    DiffFs(DiffFsOpts),
+    /// Checks that tags saved in files match the registry. Only files changed since they were
+    /// last verified are read unless `--full` is passed.
+    Verify(VerifyOpts),
+    /// Renames all tags starting with a prefix by replacing it. Tags that would be renamed to an
+    /// already existing tag are merged into it.
+    RenamePrefix(RenamePrefixOpts),
+    /// Shows all files tagged with a tag along with their size and modification time.
+    Cat(CatOpts),
+    /// Tags files according to a mapping file with lines like `path<TAB>tag1,tag2`.
+    Apply(ApplyOpts),
+    /// Tags files according to a JSON array of records like
+    /// `[{"path": "a.txt", "tags": [{"name": "x", "color": "#f00"}]}]`. Tags can also have a
+    /// `description` and string `metadata`.
+    SetJson(SetJsonOpts),
+    /// Prints the number of files tagged with each of the tags. Exits with status 1 if any of the
+    /// tags doesn't exist.
+    Count(CountOpts),
+    /// Checks whether a tag exists. Prints nothing and exits with status 0 if the tag exists or
+    /// with status 1 otherwise.
+    TagExists {
+        /// Name of the tag
+        name: String,
+    },
+    /// Shows how many files are tagged with both of the tags and with only one of them.
+    Overlap(OverlapOpts),
+    /// Compares tags of two files printing the tags they share and the ones only one of them has.
+    /// Files missing from the registry have no tags. Exits with status 1 if the tags differ.
+    SameTags(SameTagsOpts),
+    /// Adds a rule to the configuration automatically tagging files by their content when
+    /// tagging with `set --auto-tag`.
+    AddContentRule(AddContentRuleOpts),
+    /// Saves an alias to the configuration making `wutag <name>` run the aliased command.
+    Alias(AliasOpts),
+    /// Adds a rule that automatically tags files tagged with a tag with another tag, for example
+    /// `wutag link rust --implies code`.
+    Link(LinkOpts),
+    /// Lists tag implication rules added with `link`.
+    ListRules,
+    /// Changes the level of messages logged by the running daemon without restarting it.
+    LogLevel {
+        /// The new level, one of `off`, `error`, `warn`, `info`, `debug` or `trace`
+        level: String,
+    },
+    /// Lists colors used by tags along with the tags using each of them.
+    Colors(ColorsOpts),
+    /// Lists directories whose files are tracked in separate registries by the daemon.
+    ListMounts,
+    /// Prints changes of tags of files as they happen until interrupted.
+    Subscribe,
+    /// Moves tags saved under another xattr namespace, for example by an older version, to the
+    /// current one and rewrites values of tags saved in an older format.
+    MigrateNamespace(MigrateNamespaceOpts),
+    /// Makes relative paths of files saved in the registry by an older version absolute by
+    /// resolving them against a root directory.
+    MigratePaths(MigratePathsOpts),
+    /// Shows the state of the daemon and the registry. Exits with status 1 if any problem is
+    /// detected.
+    Status,
+    /// Makes the daemon remove stale entries and back up the registries right away instead of
+    /// waiting for the `maintenance_cron` schedule.
+    Maintenance,
+    /// Prints the JSON schema of the output of a command with `--output-format json`.
+    Schema {
+        /// The command, one of `list-files`, `list-tags`, `get`, `search`, `recent`, `recent-tags`,
+        /// `rename-prefix`, `cat`, `count`, `overlap`, `list-rules`, `colors`, `list-mounts`,
+        /// `subscribe`, `migrate-namespace`, `migrate-paths`, `status`, `tag-meta`, `audit-log`,
+        /// `capacity`, `verify`, `maintenance`, `same-tags` or one of the batch operations `set`,
+        /// `rm`, `cp` and `clear-files`
+        command: SchemaCommand,
+    },
+    /// An alias saved with `alias` followed by its arguments, replaced with the aliased command
+    /// before running.
+    #[command(external_subcommand)]
+    External(Vec<OsString>),
+}
+
+/// Splits each of the `tags` on unescaped commas. A comma preceded by a backslash is kept as a
+/// part of the tag name. Empty tag names are skipped.
+pub fn split_tags<S: AsRef<str>>(tags: impl IntoIterator<Item = S>) -> Vec<String> {
+    let mut split = vec![];
+    for tag in tags {
+        let mut current = String::new();
+        let mut chars = tag.as_ref().chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&',') => {
+                    current.push(',');
+                    chars.next();
+                }
+                ',' => split.push(std::mem::take(&mut current)),
+                c => current.push(c),
+            }
+        }
+        split.push(current);
+    }
+    split.retain(|tag| !tag.is_empty());
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_tags_on_commas() {
+        assert_eq!(
+            split_tags(["work,urgent,2024", "notes"]),
+            vec!["work", "urgent", "2024", "notes"]
+        );
+        assert_eq!(split_tags([r"work\,stuff,a\b"]), vec!["work,stuff", r"a\b"]);
+        assert_eq!(split_tags(["a,,b,", ""]), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(
+            parse_duration("7d"),
+            Ok(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(
+            parse_duration("1w2d"),
+            Ok(Duration::from_secs(9 * 24 * 60 * 60))
+        );
+        assert!(parse_duration("7").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("0s").is_err());
+    }
+
+    #[test]
+    fn parses_dates() {
+        let midnight = parse_date("2024-01-31").unwrap();
+        assert_eq!(midnight.to_rfc3339(), "2024-01-31T00:00:00+00:00");
+        let time = parse_date("2024-01-31T12:00:00+02:00").unwrap();
+        assert_eq!(time - midnight, chrono::Duration::hours(10));
+        assert!(parse_date("31.01.2024").is_err());
+    }
+
+    #[test]
+    fn parses_clearing_all_tags() {
+        let parse = |args: &[&str]| {
+            Opts::try_parse_from(["wutag", "clear", "tags"].iter().chain(args)).map(|opts| {
+                match opts.cmd {
+                    Command::Clear(ClearOpts {
+                        object:
+                            ClearObject::Tags {
+                                names, all, yes, ..
+                            },
+                    }) => (names, all, yes),
+                    _ => unreachable!(),
+                }
+            })
+        };
+        assert_eq!(parse(&["--all", "--yes"]).unwrap(), (vec![], true, true));
+        assert_eq!(parse(&["--all"]).unwrap(), (vec![], true, false));
+        // either the names or `--all` are required but not both
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["tmp", "--all"]).is_err());
+    }
 }
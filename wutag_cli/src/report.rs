@@ -0,0 +1,120 @@
+//! Markdown report of tags and their files printed by `tag-report`.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use wutag_core::color::color_to_hex;
+use wutag_core::registry::EntryData;
+use wutag_core::tag::Tag;
+
+/// Metadata of tags by their names.
+pub type TagMeta = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Renders a Markdown document with a table of contents followed by a section listing the files
+/// of every tag. Descriptions and metadata of tags are included when `meta` is set.
+pub fn tag_report(tags: HashMap<Tag, Vec<EntryData>>, meta: Option<&TagMeta>) -> String {
+    let mut tags: Vec<_> = tags.into_iter().collect();
+    tags.sort_unstable_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+
+    let mut report = String::from("# Tags\n\n");
+    for (tag, _) in &tags {
+        let _ = writeln!(report, "- [{}](#{})", escape(tag.name()), anchor(tag));
+    }
+
+    for (tag, mut entries) in tags {
+        let _ = write!(
+            report,
+            "\n<a id=\"{}\"></a>\n\n## <span style=\"color: {}\">{}</span>\n\n",
+            anchor(&tag),
+            color_to_hex(*tag.color()),
+            escape(tag.name())
+        );
+        if let Some(meta) = meta {
+            if let Some(description) = tag.description() {
+                let _ = writeln!(report, "{}\n", escape(description));
+            }
+            if let Some(tag_meta) = meta.get(tag.name()).filter(|m| !m.is_empty()) {
+                report.push_str("| Key | Value |\n| --- | --- |\n");
+                for (key, value) in tag_meta {
+                    let _ = writeln!(report, "| {} | {} |", escape(key), escape(value));
+                }
+                report.push('\n');
+            }
+        }
+        if entries.is_empty() {
+            report.push_str("_No files._\n");
+        }
+        entries.sort_unstable_by(|a, b| a.path().cmp(b.path()));
+        for entry in entries {
+            let _ = writeln!(report, "- `{}`", entry.path().display());
+        }
+    }
+    report
+}
+
+/// Id of the section of the `tag` linked from the table of contents.
+fn anchor(tag: &Tag) -> String {
+    let slug: String = tag
+        .name()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("tag-{slug}")
+}
+
+/// Escapes characters that Markdown or HTML would otherwise interpret.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '\\' | '`' | '*' | '_' | '[' | ']' | '#' | '|' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wutag_core::color::Color;
+
+    #[test]
+    fn lists_files_by_tag() {
+        let mut docs = Tag::new("docs", Color::Red);
+        docs.set_description(Some("user *guides*"));
+        let tags = HashMap::from([
+            (docs, vec![EntryData::new("/b.md"), EntryData::new("/a.md")]),
+            (Tag::new("empty_tag", Color::Blue), vec![]),
+        ]);
+        let meta = TagMeta::from([(
+            "docs".to_string(),
+            BTreeMap::from([("icon".to_string(), "book".to_string())]),
+        )]);
+
+        let report = tag_report(tags.clone(), None);
+        assert!(
+            report.starts_with("# Tags\n\n- [docs](#tag-docs)\n- [empty\\_tag](#tag-empty-tag)\n")
+        );
+        assert!(report.contains(&format!(
+            "## <span style=\"color: {}\">docs</span>\n\n- `/a.md`\n- `/b.md`\n",
+            color_to_hex(Color::Red)
+        )));
+        assert!(report.contains("_No files._"));
+        assert!(!report.contains("guides"));
+
+        let report = tag_report(tags, Some(&meta));
+        assert!(report
+            .contains("user \\*guides\\*\n\n| Key | Value |\n| --- | --- |\n| icon | book |\n"));
+    }
+}
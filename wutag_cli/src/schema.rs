@@ -0,0 +1,226 @@
+//! Shapes of the machine readable output of commands and their JSON schemas.
+use chrono::{DateTime, Utc};
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::SystemTime;
+use wutag_core::registry::{
+    EntryData, PathMigration, RegistryFsDiff, RegistryMount, TagOverlap, VerifyReport,
+};
+use wutag_core::tag::{ImplicationRule, Tag};
+use wutag_ipc::{
+    AuditLogEntry, BatchSummary, MaintenanceSummary, SetExactTagsResult, TagCapacity, WatchFailures,
+};
+
+/// File printed by `cat`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CatEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub mtime: String,
+    pub tags: Vec<String>,
+    #[serde(skip)]
+    pub modified: SystemTime,
+}
+
+/// State of the daemon and the registry printed by `status`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Status {
+    pub daemon_running: bool,
+    pub registry: Option<PathBuf>,
+    pub entries: Option<usize>,
+    pub tags: Option<usize>,
+    pub most_used_tags: Vec<(String, usize)>,
+    pub stale_entries: Vec<PathBuf>,
+    pub watch_failures: Option<WatchFailures>,
+    pub last_maintenance: Option<DateTime<Utc>>,
+    /// Entries added to the registry since it was last saved.
+    pub unsaved_added: Option<usize>,
+    /// Entries removed from the registry since it was last saved.
+    pub unsaved_removed: Option<usize>,
+}
+
+/// Comparison of tags of two files printed by `same-tags`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TagComparison {
+    pub same: bool,
+    pub common: Vec<String>,
+    pub only_a: Vec<String>,
+    pub only_b: Vec<String>,
+}
+
+/// File picked by `random`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RandomPick {
+    pub path: PathBuf,
+}
+
+/// Change of tags of a file printed by `subscribe`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TagChange {
+    pub path: PathBuf,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Tag printed by `list tags --verbose`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TagListing {
+    /// Files tagged with the tag, only listed with `--with-files`.
+    pub files: Vec<PathBuf>,
+    pub meta: BTreeMap<String, String>,
+}
+
+/// Output of `list tags`, paths of files by tag name or tags along with their metadata with
+/// `--verbose`.
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum ListTagsOutput {
+    Files(HashMap<String, Vec<PathBuf>>),
+    Verbose(HashMap<String, TagListing>),
+}
+
+/// Output of `search`. Paths are printed with their scores with `--verbose`, grouped by the
+/// matching tag with `--prefix` and along with their tags with `--with-tags`.
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum SearchOutput {
+    Paths(Vec<PathBuf>),
+    Scored(Vec<(PathBuf, f64)>),
+    ByTag(BTreeMap<String, Vec<PathBuf>>),
+    WithTags(Vec<(PathBuf, Vec<String>)>),
+}
+
+/// Output of `count`, a number of files per tag or a single number with `--total`.
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum CountOutput {
+    PerTag(BTreeMap<String, usize>),
+    Total(usize),
+}
+
+/// Commands with a machine readable output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SchemaCommand {
+    ListFiles,
+    ListTags,
+    Get,
+    Search,
+    Recent,
+    RecentTags,
+    RenamePrefix,
+    Cat,
+    Count,
+    Overlap,
+    ListRules,
+    Colors,
+    ListMounts,
+    Subscribe,
+    MigrateNamespace,
+    Status,
+    TagMeta,
+    AuditLog,
+    DiffFs,
+    Random,
+    SetExact,
+    Batch,
+    Capacity,
+    Verify,
+    Maintenance,
+    SameTags,
+    MigratePaths,
+}
+
+impl FromStr for SchemaCommand {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "list-files" => Ok(SchemaCommand::ListFiles),
+            "list-tags" => Ok(SchemaCommand::ListTags),
+            "get" => Ok(SchemaCommand::Get),
+            "search" => Ok(SchemaCommand::Search),
+            "recent" => Ok(SchemaCommand::Recent),
+            "recent-tags" => Ok(SchemaCommand::RecentTags),
+            "rename-prefix" => Ok(SchemaCommand::RenamePrefix),
+            "cat" => Ok(SchemaCommand::Cat),
+            "count" => Ok(SchemaCommand::Count),
+            "overlap" => Ok(SchemaCommand::Overlap),
+            "list-rules" => Ok(SchemaCommand::ListRules),
+            "colors" => Ok(SchemaCommand::Colors),
+            "list-mounts" => Ok(SchemaCommand::ListMounts),
+            "subscribe" => Ok(SchemaCommand::Subscribe),
+            "migrate-namespace" => Ok(SchemaCommand::MigrateNamespace),
+            "status" => Ok(SchemaCommand::Status),
+            "tag-meta" => Ok(SchemaCommand::TagMeta),
+            "audit-log" => Ok(SchemaCommand::AuditLog),
+            "diff-fs" => Ok(SchemaCommand::DiffFs),
+            "random" => Ok(SchemaCommand::Random),
+            "set-exact" => Ok(SchemaCommand::SetExact),
+            "capacity" => Ok(SchemaCommand::Capacity),
+            "verify" => Ok(SchemaCommand::Verify),
+            "maintenance" => Ok(SchemaCommand::Maintenance),
+            "same-tags" => Ok(SchemaCommand::SameTags),
+            "migrate-paths" => Ok(SchemaCommand::MigratePaths),
+            "batch" => Ok(SchemaCommand::Batch),
+            _ => Err(crate::Error::InvalidSchemaCommand(s.to_string())),
+        }
+    }
+}
+
+/// Returns the JSON schema of the output of the `command` with `--output-format json`.
+pub fn command_schema(command: SchemaCommand) -> RootSchema {
+    match command {
+        SchemaCommand::ListFiles => schema_for!(HashMap<PathBuf, Vec<String>>),
+        SchemaCommand::ListTags => schema_for!(ListTagsOutput),
+        SchemaCommand::Get => schema_for!(HashMap<PathBuf, Vec<Tag>>),
+        SchemaCommand::Search => schema_for!(SearchOutput),
+        SchemaCommand::Recent => schema_for!(Vec<EntryData>),
+        SchemaCommand::RecentTags => schema_for!(Vec<String>),
+        SchemaCommand::RenamePrefix => schema_for!(BTreeMap<String, String>),
+        SchemaCommand::Cat => schema_for!(Vec<CatEntry>),
+        SchemaCommand::Count => schema_for!(CountOutput),
+        SchemaCommand::Overlap => schema_for!(TagOverlap),
+        SchemaCommand::ListRules => schema_for!(Vec<ImplicationRule>),
+        SchemaCommand::Colors => schema_for!(BTreeMap<String, Vec<String>>),
+        SchemaCommand::ListMounts => schema_for!(Vec<RegistryMount>),
+        SchemaCommand::Subscribe => schema_for!(TagChange),
+        SchemaCommand::MigrateNamespace => schema_for!(usize),
+        SchemaCommand::Status => schema_for!(Status),
+        SchemaCommand::TagMeta => schema_for!(BTreeMap<String, String>),
+        SchemaCommand::AuditLog => schema_for!(Vec<AuditLogEntry>),
+        SchemaCommand::DiffFs => schema_for!(RegistryFsDiff),
+        SchemaCommand::Random => schema_for!(Vec<RandomPick>),
+        SchemaCommand::SetExact => schema_for!(SetExactTagsResult),
+        SchemaCommand::Batch => schema_for!(BatchSummary),
+        SchemaCommand::Capacity => schema_for!(TagCapacity),
+        SchemaCommand::Verify => schema_for!(VerifyReport),
+        SchemaCommand::Maintenance => schema_for!(MaintenanceSummary),
+        SchemaCommand::SameTags => schema_for!(TagComparison),
+        SchemaCommand::MigratePaths => schema_for!(PathMigration),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_tags_with_colors() {
+        let schema = serde_json::to_value(command_schema(SchemaCommand::Get)).unwrap();
+        let tag = &schema["definitions"]["Tag"];
+        assert_eq!(tag["properties"]["name"]["type"], "string");
+        assert_eq!(tag["properties"]["color"]["$ref"], "#/definitions/Color");
+        assert!(schema["definitions"]["Color"].is_object());
+
+        assert_eq!(
+            "list-files".parse::<SchemaCommand>().unwrap(),
+            SchemaCommand::ListFiles
+        );
+        assert!("set".parse::<SchemaCommand>().is_err());
+    }
+}
@@ -20,6 +20,98 @@ pub const DEFAULT_COLORS: &[Color] = &[
     BrightCyan,
 ];
 
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    TrueColor { r, g, b }
+}
+
+/// Accent colors of the Solarized palette.
+pub const SOLARIZED_COLORS: &[Color] = &[
+    rgb(0xb5, 0x89, 0x00),
+    rgb(0xcb, 0x4b, 0x16),
+    rgb(0xdc, 0x32, 0x2f),
+    rgb(0xd3, 0x36, 0x82),
+    rgb(0x6c, 0x71, 0xc4),
+    rgb(0x26, 0x8b, 0xd2),
+    rgb(0x2a, 0xa1, 0x98),
+    rgb(0x85, 0x99, 0x00),
+];
+
+/// Soft light colors readable on dark terminal backgrounds.
+pub const PASTEL_COLORS: &[Color] = &[
+    rgb(0xff, 0xb3, 0xba),
+    rgb(0xff, 0xdf, 0xba),
+    rgb(0xff, 0xff, 0xba),
+    rgb(0xba, 0xff, 0xc9),
+    rgb(0xba, 0xe1, 0xff),
+    rgb(0xe0, 0xbb, 0xe4),
+    rgb(0xfe, 0xc8, 0xd8),
+    rgb(0xd4, 0xf0, 0xf0),
+];
+
+/// Frost and aurora colors of the Nord palette.
+pub const NORD_COLORS: &[Color] = &[
+    rgb(0xbf, 0x61, 0x6a),
+    rgb(0xd0, 0x87, 0x70),
+    rgb(0xeb, 0xcb, 0x8b),
+    rgb(0xa3, 0xbe, 0x8c),
+    rgb(0xb4, 0x8e, 0xad),
+    rgb(0x8f, 0xbc, 0xbb),
+    rgb(0x88, 0xc0, 0xd0),
+    rgb(0x81, 0xa1, 0xc1),
+    rgb(0x5e, 0x81, 0xac),
+];
+
+/// Bright colors of the Gruvbox dark palette.
+pub const GRUVBOX_COLORS: &[Color] = &[
+    rgb(0xfb, 0x49, 0x34),
+    rgb(0xb8, 0xbb, 0x26),
+    rgb(0xfa, 0xbd, 0x2f),
+    rgb(0x83, 0xa5, 0x98),
+    rgb(0xd3, 0x86, 0x9b),
+    rgb(0x8e, 0xc0, 0x7c),
+    rgb(0xfe, 0x80, 0x19),
+];
+
+/// Names of the built-in palettes accepted by [palette_by_name](palette_by_name).
+pub const THEMES: &[&str] = &["default", "solarized", "pastel", "nord", "gruvbox"];
+
+/// Returns the built-in palette called `name`, `default` being [DEFAULT_COLORS](DEFAULT_COLORS).
+pub fn palette_by_name(name: &str) -> Option<&'static [Color]> {
+    match &name.to_lowercase()[..] {
+        "default" => Some(DEFAULT_COLORS),
+        "solarized" => Some(SOLARIZED_COLORS),
+        "pastel" => Some(PASTEL_COLORS),
+        "nord" => Some(NORD_COLORS),
+        "gruvbox" => Some(GRUVBOX_COLORS),
+        _ => None,
+    }
+}
+
+// Mirror of `Color` describing its serialized form in JSON schemas.
+/// Color of a tag, either one of the named colors or an RGB color.
+#[derive(schemars::JsonSchema)]
+#[schemars(rename = "Color")]
+#[allow(dead_code)]
+pub(crate) enum ColorSchema {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    TrueColor { r: u8, g: u8, b: u8 },
+}
+
 /// Parses a [Color](colored::Color) from a foreground color string
 pub fn color_from_fg_str(s: &str) -> Option<Color> {
     match s {
@@ -56,6 +148,37 @@ pub fn color_from_fg_str(s: &str) -> Option<Color> {
     }
 }
 
+/// Returns the RGB components of the `color`. Named colors are approximated with the values used
+/// by xterm.
+pub fn color_to_rgb(color: Color) -> (u8, u8, u8) {
+    match color {
+        Black => (0, 0, 0),
+        Red => (205, 0, 0),
+        Green => (0, 205, 0),
+        Yellow => (205, 205, 0),
+        Blue => (0, 0, 238),
+        Magenta => (205, 0, 205),
+        Cyan => (0, 205, 205),
+        White => (229, 229, 229),
+        BrightBlack => (127, 127, 127),
+        BrightRed => (255, 0, 0),
+        BrightGreen => (0, 255, 0),
+        BrightYellow => (255, 255, 0),
+        BrightBlue => (92, 92, 255),
+        BrightMagenta => (255, 0, 255),
+        BrightCyan => (0, 255, 255),
+        BrightWhite => (255, 255, 255),
+        TrueColor { r, g, b } => (r, g, b),
+    }
+}
+
+/// Formats the `color` as a hex string like `#1f1f1f` that can be parsed back with
+/// [parse_color](parse_color).
+pub fn color_to_hex(color: Color) -> String {
+    let (r, g, b) = color_to_rgb(color);
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
 const fn hex_val(ch: u8) -> u8 {
     match ch {
         b'0'..=b'9' => ch - 48,
@@ -83,11 +206,78 @@ fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
     ))
 }
 
+/// Converts a color in HSL notation to RGB. `h` is the hue in degrees, `s` and `l` are the
+/// saturation and lightness in range `0.0..=1.0`.
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let c = (1. - (2. * l - 1.).abs()) * s;
+    let h = h / 60.;
+    let x = c * (1. - (h % 2. - 1.).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let m = l - c / 2.;
+    let channel = |v: f32| ((v + m) * 255.).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+/// Parses colors like `hsl(120, 100%, 50%)` or `hsla(120, 100%, 50%, 0.5)`. The alpha channel is
+/// ignored as terminal colors don't support transparency. Returns `None` if the string is not in
+/// HSL notation.
+fn parse_hsl(color: &str) -> Option<Result<Color>> {
+    let lower = color.to_lowercase();
+    let (args, has_alpha) = if let Some(args) = lower.strip_prefix("hsla(") {
+        (args, true)
+    } else if let Some(args) = lower.strip_prefix("hsl(") {
+        (args, false)
+    } else {
+        return None;
+    };
+    let invalid = || Error::InvalidColor(color.to_string());
+
+    let parse = || {
+        let mut args = args.strip_suffix(')')?.split(',').map(str::trim);
+        let h: f32 = args.next()?.trim_end_matches("deg").parse().ok()?;
+        let s: f32 = args.next()?.trim_end_matches('%').parse().ok()?;
+        let l: f32 = args.next()?.trim_end_matches('%').parse().ok()?;
+        if has_alpha {
+            let _alpha: f32 = args.next()?.trim_end_matches('%').parse().ok()?;
+            log::warn!("alpha channel of color `{color}` is not supported and will be ignored");
+        }
+        if args.next().is_some()
+            || !(0. ..=360.).contains(&h)
+            || !(0. ..=100.).contains(&s)
+            || !(0. ..=100.).contains(&l)
+        {
+            return None;
+        }
+        let (r, g, b) = hsl_to_rgb(h % 360., s / 100., l / 100.);
+        Some(Color::TrueColor { r, g, b })
+    };
+
+    Some(parse().ok_or_else(invalid))
+}
+
 /// Parses a [Color](colored::Color) from a String. If the provided string starts with
 /// `0x` or `#` or without any prefix the color will be treated as hex color notation so any colors like `0x1f1f1f` or
-/// `#ABBA12` or `121212` are valid.
+/// `#ABBA12` or `121212` are valid. Colors in HSL notation like `hsl(120, 100%, 50%)` or
+/// `hsla(120, 100%, 50%, 1.0)` are also accepted.
 pub fn parse_color<S: AsRef<str>>(color: S) -> Result<Color> {
     let color = color.as_ref();
+    if let Some(color) = parse_hsl(color) {
+        return color;
+    }
+    // shorthand notation like `#f00`
+    if let Some(short) = color
+        .strip_prefix('#')
+        .filter(|c| c.len() == 3 && c.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return parse_color(short.chars().flat_map(|c| [c, c]).collect::<String>());
+    }
     macro_rules! if_6 {
         ($c:ident) => {
             if $c.len() == 6 {
@@ -117,9 +307,17 @@ pub fn parse_color<S: AsRef<str>>(color: S) -> Result<Color> {
 
 #[cfg(test)]
 mod tests {
-    use super::parse_color;
+    use super::{color_to_hex, palette_by_name, parse_color, THEMES};
     use colored::Color::*;
     #[test]
+    fn resolves_themes() {
+        for theme in THEMES {
+            assert!(!palette_by_name(theme).unwrap().is_empty(), "{theme}");
+        }
+        assert_eq!(palette_by_name("Solarized"), palette_by_name("solarized"));
+        assert!(palette_by_name("unknown").is_none());
+    }
+    #[test]
     fn parses_colors() {
         assert_eq!(
             parse_color("0xffffff").unwrap(),
@@ -145,10 +343,68 @@ mod tests {
                 b: 15
             }
         );
+        assert_eq!(
+            parse_color("#f0A").unwrap(),
+            TrueColor {
+                r: 255,
+                g: 0,
+                b: 170
+            }
+        );
+    }
+    #[test]
+    fn parses_hsl_colors() {
+        let rgb = |r, g, b| TrueColor { r, g, b };
+        assert_eq!(parse_color("hsl(0,100%,50%)").unwrap(), rgb(255, 0, 0));
+        assert_eq!(parse_color("hsl(120,100%,50%)").unwrap(), rgb(0, 255, 0));
+        assert_eq!(parse_color("hsl(240, 100%, 50%)").unwrap(), rgb(0, 0, 255));
+        assert_eq!(parse_color("HSL(60, 100%, 50%)").unwrap(), rgb(255, 255, 0));
+        assert_eq!(parse_color("hsl(360, 100%, 50%)").unwrap(), rgb(255, 0, 0));
+        assert_eq!(parse_color("hsl(0, 0%, 100%)").unwrap(), rgb(255, 255, 255));
+        assert_eq!(parse_color("hsl(200, 50%, 0%)").unwrap(), rgb(0, 0, 0));
+        assert_eq!(parse_color("hsl(0, 0%, 50%)").unwrap(), rgb(128, 128, 128));
+        assert_eq!(parse_color("hsl(90, 0%, 25%)").unwrap(), rgb(64, 64, 64));
+        assert_eq!(
+            parse_color("hsla(120, 100%, 50%, 0.5)").unwrap(),
+            rgb(0, 255, 0)
+        );
+    }
+    #[test]
+    fn errors_on_invalid_hsl_colors() {
+        assert!(parse_color("hsl(120, 100%)").is_err());
+        assert!(parse_color("hsl(120, 100%, 50%").is_err());
+        assert!(parse_color("hsl(361, 100%, 50%)").is_err());
+        assert!(parse_color("hsl(120, 101%, 50%)").is_err());
+        assert!(parse_color("hsl(120, 100%, 50%, 1)").is_err());
+        assert!(parse_color("hsla(120, 100%, 50%)").is_err());
+        assert!(parse_color("hsl(a, 100%, 50%)").is_err());
+    }
+    #[test]
+    fn formats_colors_as_hex() {
+        assert_eq!(color_to_hex(Black), "#000000");
+        assert_eq!(color_to_hex(BrightBlue), "#5c5cff");
+        assert_eq!(
+            color_to_hex(TrueColor {
+                r: 15,
+                g: 240,
+                b: 15
+            }),
+            "#0ff00f"
+        );
+        assert_eq!(
+            parse_color(color_to_hex(Cyan)).unwrap(),
+            TrueColor {
+                r: 0,
+                g: 205,
+                b: 205
+            }
+        );
     }
     #[test]
     fn errors_on_invalid_colors() {
         assert!(parse_color("0ff00").is_err());
+        assert!(parse_color("f00").is_err());
+        assert!(parse_color("#fg0").is_err());
         assert!(parse_color("0x12345").is_err());
         assert!(parse_color("#53241").is_err());
         assert!(parse_color("1234567").is_err());
@@ -6,7 +6,7 @@ use std::path::{Path, PathBuf};
 /// Default max depth passed to [GlobWalker](globwalker::GlobWalker)
 pub const DEFAULT_MAX_DEPTH: usize = 2;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Glob {
     pub pattern: String,
     pub base_dir: PathBuf,
@@ -14,11 +14,17 @@ pub struct Glob {
 }
 
 impl Glob {
+    /// Creates a new glob with the `pattern` matched relative to `base_dir` or the current
+    /// working directory. Absolute patterns are rejected as they would silently ignore the base
+    /// directory.
     pub fn new(
         pattern: String,
         base_dir: Option<PathBuf>,
         max_depth: Option<usize>,
     ) -> Result<Self> {
+        if pattern.starts_with('/') {
+            return Err(Error::AbsoluteGlobPattern(pattern));
+        }
         let base_dir = base_dir
             .or_else(|| std::env::current_dir().ok())
             .ok_or(Error::GetCurrentWorkingDir)?;
@@ -32,6 +38,93 @@ impl Glob {
     pub fn glob_paths(&self) -> Result<Vec<PathBuf>> {
         paths(&self.pattern, &self.base_dir, Some(self.max_depth))
     }
+
+    /// Checks whether the pattern contains an alternation like `*.{rs,toml}` matching any of the
+    /// comma separated alternatives.
+    pub fn has_alternation(&self) -> bool {
+        find_alternation(&self.pattern).is_some()
+    }
+
+    /// Expands all alternations of the pattern, including nested ones, returning a glob for each
+    /// of the alternatives. A glob without alternations expands to itself. Globs are matched with
+    /// alternations as they are so this is only needed to inspect the alternatives separately.
+    pub fn expand_alternations(&self) -> Vec<Glob> {
+        expand(&self.pattern)
+            .into_iter()
+            .map(|pattern| Glob {
+                pattern,
+                base_dir: self.base_dir.clone(),
+                max_depth: self.max_depth,
+            })
+            .collect()
+    }
+}
+
+/// Returns the byte range of the first alternation of the `pattern` including the braces.
+/// Escaped braces and braces inside of character classes like `[{]` are skipped.
+fn find_alternation(pattern: &str) -> Option<(usize, usize)> {
+    let mut start = None;
+    let mut depth = 0;
+    let mut in_class = false;
+    let mut chars = pattern.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            '{' if !in_class => {
+                if depth == 0 {
+                    start = Some(i);
+                }
+                depth += 1;
+            }
+            '}' if !in_class && depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return start.map(|start| (start, i + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the contents of an alternation on commas that are not nested in another alternation.
+fn split_alternatives(alternation: &str) -> Vec<&str> {
+    let mut alternatives = vec![];
+    let mut depth = 0;
+    let mut last = 0;
+    let mut chars = alternation.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&alternation[last..i]);
+                last = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&alternation[last..]);
+    alternatives
+}
+
+fn expand(pattern: &str) -> Vec<String> {
+    let Some((start, end)) = find_alternation(pattern) else {
+        return vec![pattern.to_string()];
+    };
+    let (prefix, suffix) = (&pattern[..start], &pattern[end..]);
+    split_alternatives(&pattern[start + 1..end - 1])
+        .into_iter()
+        .flat_map(|alternative| expand(&format!("{prefix}{alternative}{suffix}")))
+        .collect()
 }
 
 /// Returns a GlobWalker instance with base path set to `base_path` and pattern to `pattern`. If
@@ -62,3 +155,76 @@ where
         .map(|entry| entry.into_path())
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn errors_on_absolute_pattern() {
+        assert!(matches!(
+            Glob::new("/home/**".into(), None, None),
+            Err(Error::AbsoluteGlobPattern(pattern)) if pattern == "/home/**"
+        ));
+        assert!(Glob::new("**/*.rs".into(), None, None).is_ok());
+    }
+
+    #[test]
+    fn matches_no_paths() {
+        let dir = TempDir::new("wutag-glob").unwrap();
+        std::fs::write(dir.path().join("file.txt"), b"").unwrap();
+
+        let glob = Glob::new("*.rs".into(), Some(dir.path().to_path_buf()), None).unwrap();
+        assert!(glob.glob_paths().unwrap().is_empty());
+
+        let glob = Glob::new("*.txt".into(), Some(dir.path().to_path_buf()), None).unwrap();
+        assert_eq!(
+            glob.glob_paths().unwrap(),
+            vec![dir.path().join("file.txt")]
+        );
+    }
+
+    #[test]
+    fn matches_alternations() {
+        let dir = TempDir::new("wutag-glob").unwrap();
+        for name in ["lib.rs", "Cargo.toml", "README.md", "notes.txt"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let glob = Glob::new(
+            "*.{rs,toml,md}".into(),
+            Some(dir.path().to_path_buf()),
+            None,
+        )
+        .unwrap();
+        let mut paths = glob.glob_paths().unwrap();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().join("Cargo.toml"),
+                dir.path().join("README.md"),
+                dir.path().join("lib.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_alternations() {
+        let glob = Glob::new("src/*.{r{s,lib},md}".into(), None, None).unwrap();
+        assert!(glob.has_alternation());
+        let patterns: Vec<_> = glob
+            .expand_alternations()
+            .into_iter()
+            .map(|glob| glob.pattern)
+            .collect();
+        assert_eq!(patterns, vec!["src/*.rs", "src/*.rlib", "src/*.md"]);
+
+        for pattern in ["*.rs", r"\{a,b\}", "[{]a,b}"] {
+            let glob = Glob::new(pattern.into(), None, None).unwrap();
+            assert!(!glob.has_alternation(), "{pattern}");
+            assert_eq!(glob.expand_alternations()[0].pattern, pattern);
+        }
+    }
+}
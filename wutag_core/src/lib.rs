@@ -4,7 +4,7 @@ pub mod registry;
 pub mod tag;
 pub mod xattr;
 
-use std::{ffi, io, string};
+use std::{ffi, io, path::PathBuf, string};
 use thiserror::Error;
 
 /// Prefix used to identify extra attributes added by wutag on files
@@ -39,8 +39,14 @@ pub enum Error {
     TagListFull(io::Error),
     #[error("failed to glob pattern - {0}")]
     Glob(#[from] globwalk::GlobError),
+    #[error("glob pattern `{0}` is absolute, patterns are matched relative to the base directory")]
+    AbsoluteGlobPattern(String),
     #[error("failed to determine current working directory")]
     GetCurrentWorkingDir,
+    #[error("extended attributes are not supported on the filesystem at {}. Ensure you're on ext4, btrfs, or another xattr-supporting filesystem.", .0.display())]
+    XattrUnsupported(PathBuf),
+    #[error("extended attributes are not supported - {0}")]
+    XattrNotSupported(io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -52,6 +58,10 @@ impl From<io::Error> for Error {
             _ => match err.raw_os_error() {
                 Some(61) => Error::TagNotFound("".to_string()),
                 Some(28) => Error::TagListFull(err),
+                #[cfg(unix)]
+                Some(code) if code == libc::ENOTSUP || code == libc::EOPNOTSUPP => {
+                    Error::XattrNotSupported(err)
+                }
                 _ => Error::Other(err.to_string()),
             },
         }
@@ -1,13 +1,26 @@
 #![allow(dead_code)]
+mod sharded;
+#[cfg(feature = "trigram-index")]
+mod trigram;
 
-use crate::tag::Tag;
+pub use sharded::ShardedTagRegistry;
 
+use crate::color::color_to_hex;
+use crate::glob::Glob;
+use crate::tag::{change_time, list_tags, normalize_name, Tag, DEFAULT_COLOR};
+
+use chrono::{DateTime, Utc};
 use colored::Color;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
 use std::fs;
-use std::io;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -20,26 +33,94 @@ pub enum RegistryError {
     SaveRegistry(io::Error),
     #[error("Failed to serialize registry - {0}")]
     SerializeRegistry(serde_cbor::Error),
+    #[error("Failed to save backup of registry - {0}")]
+    SaveBackup(io::Error),
+    #[error("Failed to export graph - {0}")]
+    ExportGraph(io::Error),
+    #[error("Failed to lock registry - {0}")]
+    LockRegistry(io::Error),
+    #[error("Registry is locked by another process, lockfile `{}`", .0.display())]
+    RegistryLocked(PathBuf),
+    #[error("Failed to compare registry with the filesystem - {0}")]
+    DiffFilesystem(crate::Error),
 }
 
 type Result<T> = std::result::Result<T, RegistryError>;
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct EntryData {
     path: PathBuf,
+    /// Time at which the entry was added to the registry. Entries saved by older versions have
+    /// the Unix epoch as their creation time.
+    #[serde(default)]
+    created_at: DateTime<Utc>,
+    /// Whether the entry is a symlink tagged itself rather than its target.
+    #[serde(default)]
+    no_follow: bool,
+    /// File name of the entry when it was added to the registry, kept when the file is renamed.
+    #[serde(default)]
+    original_name: Option<OsString>,
+    /// Time at which the entry was last added or updated, used to evict least recently used
+    /// entries. Entries saved by older versions have the Unix epoch as their access time.
+    #[serde(default)]
+    last_accessed: DateTime<Utc>,
+    /// Change time of the file and a hash of the tags of the entry when the tags were last found
+    /// to match the tags of the file, lets [verify_entries](TagRegistry::verify_entries) skip
+    /// files that didn't change since.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    verified: Option<(SystemTime, u64)>,
+}
+
+impl PartialEq for EntryData {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
 }
 
+impl Eq for EntryData {}
+
 impl EntryData {
     pub fn new<P: AsRef<Path>>(path: P) -> Self {
         Self {
             path: path.as_ref().to_path_buf(),
+            created_at: Utc::now(),
+            no_follow: false,
+            original_name: path.as_ref().file_name().map(OsStr::to_os_string),
+            last_accessed: Utc::now(),
+            verified: None,
         }
     }
 
+    /// Marks the entry as a symlink whose tags are saved on the link itself.
+    pub fn with_no_follow(mut self, no_follow: bool) -> Self {
+        self.no_follow = no_follow;
+        self
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
 
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn last_accessed(&self) -> DateTime<Utc> {
+        self.last_accessed
+    }
+
+    /// Returns `true` if tags of the entry are saved on the symlink instead of its target.
+    pub fn no_follow(&self) -> bool {
+        self.no_follow
+    }
+
+    /// Returns the file name of the entry when it was first added to the registry. Entries saved
+    /// by older versions have no original name.
+    pub fn original_name(&self) -> Option<&OsStr> {
+        self.original_name.as_deref()
+    }
+
     pub fn into_path_buf(self) -> PathBuf {
         self.path
     }
@@ -47,11 +128,236 @@ impl EntryData {
 
 pub type EntryId = usize;
 
+/// Storage of the registry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryBackend {
+    /// The whole registry is stored in a single file, see [TagRegistry](TagRegistry).
+    #[default]
+    Single,
+    /// Entries are split into `shard_count` files, see
+    /// [ShardedTagRegistry](ShardedTagRegistry).
+    Sharded { shard_count: usize },
+}
+
+/// Generates an id for a new entry.
+fn new_entry_id() -> EntryId {
+    let timestamp = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    if timestamp < 0 {
+        timestamp.unsigned_abs() as usize
+    } else {
+        timestamp as usize
+    }
+}
+
+/// Breakdown of entries tagged with two tags `a` and `b`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct TagOverlap {
+    /// Entries tagged with both tags.
+    pub both: Vec<EntryData>,
+    /// Entries tagged only with tag `a`.
+    pub only_a: Vec<EntryData>,
+    /// Entries tagged only with tag `b`.
+    pub only_b: Vec<EntryData>,
+}
+
+/// Inconsistencies between the registry and tags saved in extended attributes of files.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+pub struct RegistryFsDiff {
+    /// Entries of the registry whose files don't exist or have no tags.
+    pub entries_in_registry_only: Vec<PathBuf>,
+    /// Files with tags that are missing from the registry.
+    pub entries_in_xattrs_only: Vec<PathBuf>,
+    /// Entries whose tags differ from tags of their files as `(path, registry_tags, xattr_tags)`.
+    pub tag_mismatches: Vec<(PathBuf, Vec<Tag>, Vec<Tag>)>,
+}
+
+/// Outcome of [verify_entries](TagRegistry::verify_entries).
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct VerifyReport {
+    /// Entries whose files no longer exist.
+    pub stale: Vec<PathBuf>,
+    /// Entries whose tags differ from tags of their files as `(path, registry_tags, xattr_tags)`.
+    pub tag_mismatches: Vec<(PathBuf, Vec<Tag>, Vec<Tag>)>,
+    /// Number of files whose tags were read.
+    pub checked: usize,
+    /// Number of files skipped as they didn't change since their tags were last verified.
+    pub skipped: usize,
+    /// Whether the counts of tags of entries or of entries of tags kept by the registry drifted
+    /// from its tags.
+    #[serde(default)]
+    pub count_drift: bool,
+    /// Files whose tags couldn't be read as `(path, error)`.
+    #[serde(default)]
+    pub errors: Vec<(PathBuf, String)>,
+    /// Changed verification state of entries, stored by
+    /// [record_verified](TagRegistry::record_verified).
+    #[serde(skip)]
+    #[schemars(skip)]
+    verified: Vec<(EntryId, Option<(SystemTime, u64)>)>,
+}
+
+impl VerifyReport {
+    /// Whether [record_verified](TagRegistry::record_verified) has anything to store.
+    pub fn has_verified_changes(&self) -> bool {
+        !self.verified.is_empty()
+    }
+}
+
+/// Outcome of [absolutize_paths](TagRegistry::absolutize_paths).
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct PathMigration {
+    /// Number of relative paths of entries replaced with absolute ones.
+    pub rewritten: usize,
+    /// Number of those entries merged into an entry that already had the absolute path.
+    pub merged: usize,
+    /// Relative paths left unchanged as no file exists at them under the root.
+    pub unresolved: Vec<PathBuf>,
+}
+
+/// Tags of entries changed while [recording changes](TagRegistry::record_changes) by the paths
+/// of the entries before and after the changes.
+#[derive(Clone, Debug, Default)]
+pub struct RecordedChanges {
+    pub before: HashMap<PathBuf, Vec<Tag>>,
+    pub after: HashMap<PathBuf, Vec<Tag>>,
+}
+
+/// Numbers of entries added to and removed from a registry since it was last saved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnsavedEntries {
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl RegistryFsDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entries_in_registry_only.is_empty()
+            && self.entries_in_xattrs_only.is_empty()
+            && self.tag_mismatches.is_empty()
+    }
+}
+
+/// Time after which acquiring a [RegistryLock](RegistryLock) fails.
+pub const REGISTRY_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Guard holding an exclusive lock on the lockfile of a registry. The lock is released when the
+/// guard is dropped.
+#[derive(Debug)]
+pub struct RegistryLock {
+    // The lock is held as long as the file descriptor of the lockfile is open.
+    _lock: fd_lock::RwLock<fs::File>,
+    path: PathBuf,
+}
+
+impl RegistryLock {
+    /// Returns the path of the lockfile.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Runs `f` holding a lock on the `{path}.io-lock` file, exclusive if `exclusive` is set and
+/// shared otherwise. The lock only serializes single reads and writes of the registry file
+/// between processes, unlike the lock from [lock](TagRegistry::lock) held through a whole
+/// [update](TagRegistry::update).
+/// The lockfile is only created by writers, so reading a registry that was never written
+/// with the lock, for example on a read-only filesystem, leaves the directory untouched.
+fn with_io_lock<T>(path: &Path, exclusive: bool, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".io-lock");
+    let file = match fs::OpenOptions::new()
+        .create(exclusive)
+        .truncate(false)
+        .read(!exclusive)
+        .write(exclusive)
+        .open(lock_path)
+    {
+        Ok(file) => file,
+        Err(e) if !exclusive && e.kind() == io::ErrorKind::NotFound => return f(),
+        Err(e) => return Err(RegistryError::LockRegistry(e)),
+    };
+    let mut lock = fd_lock::RwLock::new(file);
+    if exclusive {
+        let _guard = lock.write().map_err(RegistryError::LockRegistry)?;
+        f()
+    } else {
+        let _guard = lock.read().map_err(RegistryError::LockRegistry)?;
+        f()
+    }
+}
+
+/// Directory whose files are tracked in a separate registry.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct RegistryMount {
+    /// Root directory of the files tracked in the registry.
+    pub path: PathBuf,
+    /// File in which the registry is saved.
+    pub registry_file: PathBuf,
+}
+
+impl RegistryMount {
+    /// Checks whether the `path` is inside of the root directory of this mount.
+    pub fn contains<P: AsRef<Path>>(&self, path: P) -> bool {
+        path.as_ref().starts_with(&self.path)
+    }
+}
+
+/// Order in which entries are evicted from a registry with more entries than allowed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvictionPolicy {
+    /// Evict entries that were added or updated the longest time ago first.
+    #[default]
+    Lru,
+    /// Evict entries with the fewest tags first, least recently used first among them.
+    LeastTagged,
+    /// Evict entries that were first added to the registry the longest time ago first.
+    Oldest,
+}
+
+/// Maximum number of names kept in the history of recently applied tags.
+pub const MAX_RECENT_TAGS: usize = 50;
+
+/// Key of the metadata of a tag holding its description set when the tag is created.
+pub const DESCRIPTION_META_KEY: &str = "description";
+
 #[derive(Default, Deserialize, Serialize)]
 pub struct TagRegistry {
     tags: HashMap<Tag, BTreeSet<EntryId>>,
     entries: HashMap<EntryId, EntryData>,
     path: PathBuf,
+    #[serde(default)]
+    recent_tags: VecDeque<String>,
+    /// Times at which tags of entries expire by tag name.
+    #[serde(default)]
+    expiries: HashMap<EntryId, BTreeMap<String, DateTime<Utc>>>,
+    /// Metadata about tags like an icon or priority by tag name. It is only stored in the
+    /// registry and not in the extended attributes of tagged files.
+    #[serde(default)]
+    tag_meta: HashMap<String, BTreeMap<String, String>>,
+    /// Tags created ahead of tagging any entry with them by name. They are kept along with their
+    /// color while no entry has them.
+    #[serde(default)]
+    defined_tags: BTreeMap<String, Tag>,
+    /// Number of tags of each entry kept in sync with `tags`, rebuilt when the registry is
+    /// loaded.
+    #[serde(skip)]
+    entry_tag_counts: HashMap<EntryId, usize>,
+    /// Number of entries of each tag kept in sync with `tags`, rebuilt when the registry is
+    /// loaded.
+    #[serde(skip)]
+    tag_counts: HashMap<Tag, usize>,
+    /// Paths and tags of entries before their first change since
+    /// [record_changes](TagRegistry::record_changes) was called, `None` while changes aren't
+    /// recorded.
+    #[serde(skip)]
+    changes: Option<HashMap<EntryId, (PathBuf, Vec<Tag>)>>,
+    /// Trigrams of entry paths used to search for path fragments, rebuilt when the registry is
+    /// loaded.
+    #[cfg(feature = "trigram-index")]
+    #[serde(skip)]
+    path_index: trigram::TrigramIndex,
 }
 
 impl TagRegistry {
@@ -66,34 +372,225 @@ impl TagRegistry {
     /// Loads a registry from the specified `path`.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
+        with_io_lock(path, false, || Self::load_unlocked(path))
+    }
+
+    fn load_unlocked(path: &Path) -> Result<Self> {
         let data = fs::read(path).map_err(RegistryError::LoadRegistry)?;
 
-        serde_cbor::from_slice(&data).map_err(RegistryError::DeserializeRegistry)
+        let mut registry: Self =
+            serde_cbor::from_slice(&data).map_err(RegistryError::DeserializeRegistry)?;
+        registry.entry_tag_counts = registry.count_entry_tags();
+        registry.tag_counts = registry.count_tag_entries();
+        #[cfg(feature = "trigram-index")]
+        for (id, entry) in &registry.entries {
+            registry.path_index.insert(*id, &entry.path);
+        }
+        Ok(registry)
+    }
+
+    /// Returns the path to which this registry is saved.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Acquires an exclusive lock on the registry at `path` by locking the `{path}.lock` file.
+    /// Fails with [RegistryLocked](RegistryError::RegistryLocked) if the lock is not acquired
+    /// within [REGISTRY_LOCK_TIMEOUT](REGISTRY_LOCK_TIMEOUT).
+    pub fn lock(path: &Path) -> Result<RegistryLock> {
+        Self::lock_timeout(path, REGISTRY_LOCK_TIMEOUT)
+    }
+
+    /// Same as [lock](TagRegistry::lock) but with a custom `timeout`.
+    pub fn lock_timeout(path: &Path, timeout: Duration) -> Result<RegistryLock> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(RegistryError::LockRegistry)?;
+        let mut lock = fd_lock::RwLock::new(file);
+
+        let start = Instant::now();
+        loop {
+            match lock.try_write() {
+                Ok(guard) => {
+                    // Dropping the guard would unlock the file right away, instead the lock is
+                    // released once the lockfile is closed when `RegistryLock` is dropped.
+                    std::mem::forget(guard);
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if start.elapsed() >= timeout {
+                        return Err(RegistryError::RegistryLocked(lock_path));
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => return Err(RegistryError::LockRegistry(e)),
+            }
+        }
+
+        Ok(RegistryLock {
+            _lock: lock,
+            path: lock_path,
+        })
     }
 
     /// Saves the registry serialized to the path from which it was loaded.
     pub fn save(&self) -> Result<()> {
+        with_io_lock(&self.path, true, || self.save_unlocked())
+    }
+
+    fn save_unlocked(&self) -> Result<()> {
         let serialized = serde_cbor::to_vec(&self).map_err(RegistryError::SerializeRegistry)?;
         fs::write(&self.path, &serialized).map_err(RegistryError::SaveRegistry)
     }
 
+    /// Saves a copy of this registry next to its file as `<file>.<name>.1` after renaming older
+    /// copies to the next number, keeping at most `keep` copies. Returns the path of the copy.
+    pub fn save_backup(&self, name: &str, keep: usize) -> Result<PathBuf> {
+        let backup = |n: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{name}.{n}"));
+            PathBuf::from(path)
+        };
+        let keep = keep.max(1);
+        match fs::remove_file(backup(keep)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(RegistryError::SaveBackup(e))
+            }
+            _ => {}
+        }
+        for n in (1..keep).rev() {
+            let older = backup(n);
+            if older.exists() {
+                fs::rename(&older, backup(n + 1)).map_err(RegistryError::SaveBackup)?;
+            }
+        }
+        let serialized = serde_cbor::to_vec(&self).map_err(RegistryError::SerializeRegistry)?;
+        fs::write(backup(1), serialized).map_err(RegistryError::SaveBackup)?;
+        Ok(backup(1))
+    }
+
+    /// Loads the registry at `path`, modifies it with `f` and saves it without letting other
+    /// processes read or write the registry file in between, so that concurrent updates are not
+    /// lost. The registry is locked with [lock](TagRegistry::lock) until it is saved. A missing
+    /// registry file is updated as an empty registry.
+    pub fn update<P: AsRef<Path>, T>(path: P, f: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        let path = path.as_ref();
+        let _lock = Self::lock(path)?;
+        with_io_lock(path, true, || {
+            let mut registry = match Self::load_unlocked(path) {
+                Err(RegistryError::LoadRegistry(e)) if e.kind() == io::ErrorKind::NotFound => {
+                    Self::new(path)
+                }
+                registry => registry?,
+            };
+            let result = f(&mut registry);
+            registry.save_unlocked()?;
+            Ok(result)
+        })
+    }
+
+    /// Starts recording changes of tags of entries so that they can be compared without taking
+    /// a snapshot of the whole registry, see [take_changes](TagRegistry::take_changes).
+    pub fn record_changes(&mut self) {
+        self.changes = Some(HashMap::new());
+    }
+
+    /// Stops recording changes returning tags of the entries that changed since
+    /// [record_changes](TagRegistry::record_changes) was called.
+    pub fn take_changes(&mut self) -> RecordedChanges {
+        let Some(changes) = self.changes.take() else {
+            return RecordedChanges::default();
+        };
+        let mut recorded = RecordedChanges::default();
+        for (id, (path, tags)) in changes {
+            if let Some(entry) = self.entries.get(&id) {
+                let current = self
+                    .list_entry_tags(id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                recorded.after.insert(entry.path.clone(), current);
+            }
+            recorded.before.insert(path, tags);
+        }
+        recorded
+    }
+
+    /// Returns ids of the entries that changed since [record_changes](TagRegistry::record_changes)
+    /// was called.
+    pub fn changed_entries(&self) -> HashSet<EntryId> {
+        self.changes
+            .as_ref()
+            .map(|changes| changes.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Saves the path and tags of the `entry` unless it already changed while recording changes.
+    fn record_change(&mut self, entry: EntryId) {
+        match &self.changes {
+            Some(changes) if !changes.contains_key(&entry) => {}
+            _ => return,
+        }
+        let Some(data) = self.entries.get(&entry) else {
+            return;
+        };
+        let tags = self
+            .list_entry_tags(entry)
+            .unwrap_or_default()
+            .into_iter()
+            .cloned()
+            .collect();
+        let path = data.path.clone();
+        if let Some(changes) = &mut self.changes {
+            changes.insert(entry, (path, tags));
+        }
+    }
+
     /// Clears this tag registry by removing all entries and tags.
     pub fn clear(&mut self) {
+        if self.changes.is_some() {
+            let ids: Vec<_> = self.entries.keys().copied().collect();
+            ids.into_iter().for_each(|id| self.record_change(id));
+        }
         self.tags.clear();
         self.entries.clear();
+        self.expiries.clear();
+        self.tag_meta.clear();
+        self.defined_tags.clear();
+        self.entry_tag_counts.clear();
+        self.tag_counts.clear();
+        #[cfg(feature = "trigram-index")]
+        self.path_index.clear();
     }
 
     /// Removes the tag from this registry returing a set of entries that were
     /// completely removed.
     pub fn clear_tag(&mut self, tag: &Tag) -> Option<Vec<EntryData>> {
+        let ids: Vec<_> = self.tags.get(tag).into_iter().flatten().copied().collect();
+        ids.into_iter().for_each(|id| self.record_change(id));
         let removed = self.tags.remove(tag);
+        self.tag_counts.remove(tag);
+        self.tag_meta.remove(tag.name());
+        self.defined_tags.remove(tag.name());
         let mut final_removed = None;
         if let Some(removed) = removed {
+            for entry in &removed {
+                self.remove_tag_expiry(tag.name(), *entry);
+                self.decrement_tag_count(*entry);
+            }
             final_removed = Some(
                 removed
                     .into_iter()
                     .filter_map(|entry| {
-                        if self.list_entry_tags(entry).is_none() {
+                        if self.tag_count_for_entry(entry) == 0 {
                             self.remove_entry(entry)
                         } else {
                             None
@@ -106,51 +603,177 @@ impl TagRegistry {
     }
 
     /// Updates the entry or adds it if it is not present.
-    pub fn add_or_update_entry(&mut self, entry: EntryData) -> (EntryId, bool) {
+    pub fn add_or_update_entry(&mut self, mut entry: EntryData) -> (EntryId, bool) {
         let pos = self
             .list_entries_and_ids()
             .find(|(_, e)| **e == entry)
             .map(|(idx, _)| *idx);
 
+        entry.last_accessed = Utc::now();
         let res = if let Some(pos) = pos {
             let e = self.entries.get_mut(&pos).expect("entry");
+            entry.created_at = e.created_at;
+            if e.original_name.is_some() {
+                entry.original_name = e.original_name.take();
+            }
             *e = entry;
             (pos, false)
         } else {
-            let timestamp = chrono::Utc::now().timestamp_nanos();
-            let timestamp = if timestamp < 0 {
-                timestamp.unsigned_abs() as usize
-            } else {
-                timestamp as usize
-            };
-            self.entries.insert(timestamp, entry);
-            (timestamp, true)
+            let id = new_entry_id();
+            entry.created_at = Utc::now();
+            self.entries.insert(id, entry);
+            self.index_path(id);
+            (id, true)
         };
 
         res
     }
 
-    fn mut_tag_entries(&mut self, tag: &Tag) -> &mut BTreeSet<EntryId> {
-        let exists = self.tags.iter().any(|(t, _)| t == tag);
+    /// Returns ids of the entries that have to be evicted for the registry to have at most
+    /// `max_entries` entries, in the order given by the `policy`. Entries in `keep` are never
+    /// evicted so the registry may still have more entries if there are too many of them.
+    pub fn entries_to_evict(
+        &self,
+        max_entries: usize,
+        policy: EvictionPolicy,
+        keep: &HashSet<EntryId>,
+    ) -> Vec<EntryId> {
+        let excess = self.entries.len().saturating_sub(max_entries);
+        if excess == 0 {
+            return vec![];
+        }
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(id, _)| !keep.contains(id))
+            .collect();
+        entries.sort_unstable_by(|(a_id, a), (b_id, b)| {
+            let order = match policy {
+                EvictionPolicy::Lru => a.last_accessed.cmp(&b.last_accessed),
+                EvictionPolicy::LeastTagged => self
+                    .tag_count_for_entry(**a_id)
+                    .cmp(&self.tag_count_for_entry(**b_id))
+                    .then(a.last_accessed.cmp(&b.last_accessed)),
+                EvictionPolicy::Oldest => a.created_at.cmp(&b.created_at),
+            };
+            order.then_with(|| a.path.cmp(&b.path))
+        });
+        entries
+            .into_iter()
+            .take(excess)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Changes the path of the entry with `id` to `path` after its file was renamed keeping the
+    /// rest of its data like the original name. Returns `false` if there is no such entry.
+    pub fn update_entry_path<P: AsRef<Path>>(&mut self, id: EntryId, path: P) -> bool {
+        self.record_change(id);
+        self.unindex_path(id);
+        let updated = match self.entries.get_mut(&id) {
+            Some(entry) => {
+                entry.path = path.as_ref().to_path_buf();
+                true
+            }
+            None => false,
+        };
+        self.index_path(id);
+        updated
+    }
+
+    /// Inserts the `entry` with the given `id` replacing the entry previously stored under it.
+    pub(crate) fn insert_entry(&mut self, id: EntryId, entry: EntryData) {
+        self.record_change(id);
+        self.unindex_path(id);
+        self.entries.insert(id, entry);
+        self.index_path(id);
+    }
 
-        if !exists {
-            self.tags.insert(tag.clone(), BTreeSet::new());
+    /// Adds the path of the entry with `id` to the path index.
+    #[cfg(feature = "trigram-index")]
+    fn index_path(&mut self, id: EntryId) {
+        if let Some(entry) = self.entries.get(&id) {
+            self.path_index.insert(id, &entry.path);
         }
+    }
+
+    #[cfg(not(feature = "trigram-index"))]
+    fn index_path(&mut self, _id: EntryId) {}
+
+    /// Removes the path of the entry with `id` from the path index.
+    #[cfg(feature = "trigram-index")]
+    fn unindex_path(&mut self, id: EntryId) {
+        if let Some(entry) = self.entries.get(&id) {
+            self.path_index.remove(id, &entry.path);
+        }
+    }
+
+    #[cfg(not(feature = "trigram-index"))]
+    fn unindex_path(&mut self, _id: EntryId) {}
 
-        self.tags.get_mut(tag).unwrap()
+    fn mut_tag_entries(&mut self, tag: &Tag) -> &mut BTreeSet<EntryId> {
+        self.tags.entry(tag.clone()).or_default()
     }
 
     /// Adds the `tag` to an entry with `entry` id. Returns the id if the entry was already tagged
     /// or `None` if the tag was added.
     pub fn tag_entry(&mut self, tag: &Tag, entry: EntryId) -> Option<EntryId> {
+        self.record_change(entry);
         let entries = self.mut_tag_entries(tag);
         if !entries.insert(entry) {
             Some(entry)
         } else {
+            *self.entry_tag_counts.entry(entry).or_default() += 1;
+            *self.tag_counts.entry(tag.clone()).or_default() += 1;
             None
         }
     }
 
+    /// Adds tags to entries like [tag_entry](TagRegistry::tag_entry) called for every item,
+    /// but groups the `items` by tag so that every tag is looked up only once. Meant for
+    /// importing or reindexing a large number of tags. Returns the number of tags that were
+    /// added to entries that didn't have them yet.
+    pub fn bulk_tag(&mut self, items: impl Iterator<Item = (EntryId, Tag)>) -> usize {
+        let mut by_tag: HashMap<Tag, Vec<EntryId>> = HashMap::new();
+        for (entry, tag) in items {
+            self.record_change(entry);
+            by_tag.entry(tag).or_default().push(entry);
+        }
+
+        let mut added = 0;
+        for (tag, ids) in by_tag {
+            let entries = self.tags.entry(tag.clone()).or_default();
+            let mut tag_added = 0;
+            for id in ids {
+                if entries.insert(id) {
+                    *self.entry_tag_counts.entry(id).or_default() += 1;
+                    tag_added += 1;
+                }
+            }
+            *self.tag_counts.entry(tag).or_default() += tag_added;
+            added += tag_added;
+        }
+        added
+    }
+
+    fn decrement_tag_count(&mut self, entry: EntryId) {
+        if let Some(count) = self.entry_tag_counts.get_mut(&entry) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.entry_tag_counts.remove(&entry);
+            }
+        }
+    }
+
+    fn decrement_entry_count(&mut self, tag: &Tag) {
+        if let Some(count) = self.tag_counts.get_mut(tag) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.tag_counts.remove(tag);
+            }
+        }
+    }
+
     fn clean_tag_if_no_entries(&mut self, tag: &Tag) {
         let remove = if let Some(entries) = self.tags.get(tag) {
             entries.is_empty()
@@ -166,17 +789,44 @@ impl TagRegistry {
     /// Removes the `tag` from an entry with `entry` id. Returns the entry data if it has no tags
     /// left or `None` otherwise.
     pub fn untag_entry(&mut self, tag: &Tag, entry: EntryId) -> Option<EntryData> {
+        self.record_change(entry);
         let entries = self.mut_tag_entries(tag);
 
-        let _ = entries.remove(&entry);
+        if entries.remove(&entry) {
+            self.decrement_tag_count(entry);
+            self.decrement_entry_count(tag);
+        }
         self.clean_tag_if_no_entries(tag);
-        if self.list_entry_tags(entry).is_none() {
-            return self.entries.remove(&entry);
+        self.remove_tag_expiry(tag.name(), entry);
+        if self.tag_count_for_entry(entry) == 0 {
+            return self.remove_entry(entry);
         }
 
         None
     }
 
+    /// Synchronizes tags of the `entry` with `tags`, for example read from the file of the entry.
+    /// Tags not present in `tags` are removed from the entry and missing ones are added. Returns
+    /// the entry data if it has no tags left or `None` otherwise.
+    pub fn sync_entry_tags(&mut self, entry: EntryId, tags: &[Tag]) -> Option<EntryData> {
+        let current: Vec<Tag> = self
+            .list_entry_tags(entry)
+            .unwrap_or_default()
+            .into_iter()
+            .cloned()
+            .collect();
+
+        for tag in tags.iter().filter(|tag| !current.contains(tag)) {
+            self.tag_entry(tag, entry);
+        }
+
+        let mut removed = None;
+        for tag in current.iter().filter(|tag| !tags.contains(tag)) {
+            removed = self.untag_entry(tag, entry).or(removed);
+        }
+        removed
+    }
+
     /// Removes the tag with the `tag_name` from the `entry` returning the entry if it has no tags
     /// left or `None` otherwise.
     pub fn untag_by_name(&mut self, tag_name: &str, entry: EntryId) -> Option<EntryData> {
@@ -185,23 +835,93 @@ impl TagRegistry {
     }
 
     pub fn remove_entry(&mut self, entry: EntryId) -> Option<EntryData> {
+        self.record_change(entry);
+        self.expiries.remove(&entry);
+        self.unindex_path(entry);
         self.entries.remove(&entry)
     }
 
+    /// Sets the time at which the `tag` of the `entry` expires. Passing `None` makes the tag
+    /// permanent.
+    pub fn set_tag_expiry(&mut self, tag: &Tag, entry: EntryId, expires_at: Option<DateTime<Utc>>) {
+        match expires_at {
+            Some(expires_at) => {
+                self.expiries
+                    .entry(entry)
+                    .or_default()
+                    .insert(tag.name().to_string(), expires_at);
+            }
+            None => self.remove_tag_expiry(tag.name(), entry),
+        }
+    }
+
+    fn remove_tag_expiry(&mut self, tag_name: &str, entry: EntryId) {
+        if let Some(expiries) = self.expiries.get_mut(&entry) {
+            expiries.remove(tag_name);
+            if expiries.is_empty() {
+                self.expiries.remove(&entry);
+            }
+        }
+    }
+
+    /// Returns the time at which the tag with `tag_name` of the `entry` expires if it has one.
+    pub fn tag_expiry(&self, tag_name: &str, entry: EntryId) -> Option<DateTime<Utc>> {
+        self.expiries.get(&entry)?.get(tag_name).copied()
+    }
+
+    /// Returns expiry times of tags of the `entry` by tag name if any of its tags expire.
+    pub fn entry_expiries(&self, entry: EntryId) -> Option<&BTreeMap<String, DateTime<Utc>>> {
+        self.expiries.get(&entry)
+    }
+
+    /// Removes tags that expired by `now` from their entries. Returns the removed tags along with
+    /// data of their entries, so that they can also be removed from the files.
+    pub fn prune_expired_tags(&mut self, now: DateTime<Utc>) -> Vec<(EntryData, Tag)> {
+        self.list_expired_tags(now)
+            .into_iter()
+            .filter_map(|(id, tag)| {
+                let entry = self.get_entry(id)?.clone();
+                self.untag_entry(&tag, id);
+                Some((entry, tag))
+            })
+            .collect()
+    }
+
+    /// Lists tags of entries that expired at or before `now`.
+    pub fn list_expired_tags(&self, now: DateTime<Utc>) -> Vec<(EntryId, Tag)> {
+        self.expiries
+            .iter()
+            .flat_map(|(entry, expiries)| {
+                expiries
+                    .iter()
+                    .filter(|(_, expires_at)| **expires_at <= now)
+                    .filter_map(|(name, _)| self.get_tag(name).map(|tag| (*entry, tag.clone())))
+            })
+            .collect()
+    }
+
     /// Clears all tags of the `entry`.
     pub fn clear_entry(&mut self, entry: EntryId) {
+        self.record_change(entry);
+        let mut untagged = vec![];
         let mut to_remove = vec![];
         self.tags.iter_mut().for_each(|(tag, entries)| {
-            entries.remove(&entry);
+            if entries.remove(&entry) {
+                untagged.push(tag.to_owned());
+            }
             if entries.is_empty() {
                 to_remove.push(tag.to_owned());
             }
         });
 
+        for tag in untagged {
+            self.decrement_entry_count(&tag);
+        }
         for tag in to_remove {
             self.tags.remove(&tag);
         }
 
+        self.entry_tag_counts.remove(&entry);
         self.remove_entry(entry);
     }
 
@@ -213,6 +933,59 @@ impl TagRegistry {
             .map(|(idx, _)| *idx)
     }
 
+    /// Returns the number of tags of the `entry` in constant time.
+    pub fn tag_count_for_entry(&self, entry: EntryId) -> usize {
+        self.entry_tag_counts
+            .get(&entry)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of entries tagged with the `tag` in constant time.
+    pub fn entry_count_for_tag(&self, tag: &Tag) -> usize {
+        self.tag_counts.get(tag).copied().unwrap_or_default()
+    }
+
+    /// Returns up to `n` tags with the most entries along with the number of their entries,
+    /// sorted by the number of entries in descending order and then by name.
+    pub fn list_most_used_tags(&self, n: usize) -> Vec<(&Tag, usize)> {
+        let mut tags: Vec<_> = self
+            .tag_counts
+            .iter()
+            .map(|(tag, count)| (tag, *count))
+            .collect();
+        tags.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        tags.truncate(n);
+        tags
+    }
+
+    /// Counts tags of each entry by going through entries of all tags.
+    fn count_entry_tags(&self) -> HashMap<EntryId, usize> {
+        let mut counts: HashMap<EntryId, usize> = HashMap::new();
+        for entries in self.tags.values() {
+            for entry in entries {
+                *counts.entry(*entry).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// Counts entries of each tag by going through entries of all tags.
+    fn count_tag_entries(&self) -> HashMap<Tag, usize> {
+        self.tags
+            .iter()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|(tag, entries)| (tag.clone(), entries.len()))
+            .collect()
+    }
+
+    /// Checks whether the counts of tags of entries and of entries of tags match the tags stored
+    /// in the registry.
+    pub fn tag_counts_consistent(&self) -> bool {
+        self.entry_tag_counts == self.count_entry_tags()
+            && self.tag_counts == self.count_tag_entries()
+    }
+
     /// Lists tags of the `entry` if such entry exists.
     pub fn list_entry_tags(&self, entry: EntryId) -> Option<Vec<&Tag>> {
         let tags = self
@@ -251,6 +1024,30 @@ impl TagRegistry {
         }
     }
 
+    /// Lists tags with names starting with `prefix` sorted by name.
+    pub fn list_tags_with_prefix(&self, prefix: &str) -> Vec<&Tag> {
+        let mut tags: Vec<_> = self
+            .tags
+            .keys()
+            .filter(|tag| tag.name().starts_with(prefix))
+            .collect();
+        tags.sort_unstable();
+        tags
+    }
+
+    /// Returns entries that have at least one tag with a name starting with `prefix`.
+    pub fn find_entries_with_tag_prefix(&self, prefix: &str) -> Vec<(EntryId, &EntryData)> {
+        let ids: BTreeSet<EntryId> = self
+            .tags
+            .iter()
+            .filter(|(tag, _)| tag.name().starts_with(prefix))
+            .flat_map(|(_, entries)| entries.iter().copied())
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| self.entries.get(&id).map(|entry| (id, entry)))
+            .collect()
+    }
+
     /// Returns entries that have any tag of the `tags`.
     pub fn list_entries_with_any_tags<T, S>(&self, tags: T) -> Vec<EntryId>
     where
@@ -297,6 +1094,28 @@ impl TagRegistry {
         entries.into_iter().copied().collect()
     }
 
+    /// Returns the overlap of entries tagged with tags named `a` and `b`. Returns `None` if any
+    /// of the tags doesn't exist.
+    pub fn tag_overlap<S: AsRef<str>>(&self, a: S, b: S) -> Option<TagOverlap> {
+        let entries_of = |name: &str| {
+            self.tags
+                .iter()
+                .find(|(t, _)| t.name() == name)
+                .map(|(_, e)| e)
+        };
+        let a = entries_of(a.as_ref())?;
+        let b = entries_of(b.as_ref())?;
+        let collect = |ids: &mut dyn Iterator<Item = &EntryId>| {
+            ids.filter_map(|id| self.entries.get(id).cloned()).collect()
+        };
+
+        Some(TagOverlap {
+            both: collect(&mut a.intersection(b)),
+            only_a: collect(&mut a.difference(b)),
+            only_b: collect(&mut b.difference(a)),
+        })
+    }
+
     /// Lists ids of all entries present in the registry.
     pub fn list_entries_ids(&self) -> impl Iterator<Item = &EntryId> {
         self.entries.keys()
@@ -307,6 +1126,23 @@ impl TagRegistry {
         self.entries.values()
     }
 
+    /// Returns entries added at or after `since`, most recently added first, at most `limit` of
+    /// them.
+    pub fn list_recent_entries(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: Option<usize>,
+    ) -> Vec<&EntryData> {
+        let mut entries: Vec<_> = self
+            .entries
+            .values()
+            .filter(|entry| since.map_or(true, |since| entry.created_at >= since))
+            .collect();
+        entries.sort_unstable_by_key(|entry| std::cmp::Reverse(entry.created_at));
+        entries.truncate(limit.unwrap_or(usize::MAX));
+        entries
+    }
+
     /// Lists ids and data of all entries present in the registry.
     pub fn list_entries_and_ids(&self) -> impl Iterator<Item = (&EntryId, &EntryData)> {
         self.entries.iter()
@@ -336,40 +1172,702 @@ impl TagRegistry {
         self.tags.keys()
     }
 
-    /// Lists tags and their entries
-    pub fn list_tags_and_entries(&self) -> impl Iterator<Item = (Tag, Vec<EntryData>)> + '_ {
-        self.tags.clone().into_iter().map(|(tag, entries)| {
-            let entries: Vec<_> = entries
+    /// Lists entries sorted by path skipping the first `offset` of them and returning at most
+    /// `limit`, along with their tags if `with_tags` is set. Returns the listed entries and the
+    /// total number of entries.
+    pub fn list_entries_page(
+        &self,
+        offset: usize,
+        limit: Option<usize>,
+        with_tags: bool,
+    ) -> (Vec<(EntryData, Vec<Tag>)>, usize) {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by(|(_, a), (_, b)| a.path().cmp(b.path()));
+        let total = entries.len();
+        let page = entries
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(id, entry)| {
+                let tags = if with_tags {
+                    let mut tags: Vec<_> = self
+                        .list_entry_tags(*id)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .cloned()
+                        .collect();
+                    tags.sort_unstable();
+                    tags
+                } else {
+                    vec![]
+                };
+                (entry.clone(), tags)
+            })
+            .collect();
+        (page, total)
+    }
+
+    /// Lists tags sorted by name skipping the first `offset` of them and returning at most
+    /// `limit`, along with their entries if `with_entries` is set. Tags defined with
+    /// [define_tag](TagRegistry::define_tag) that no entry has are included with
+    /// `include_unused`. Returns the listed tags and the total number of tags.
+    pub fn list_tags_page(
+        &self,
+        offset: usize,
+        limit: Option<usize>,
+        with_entries: bool,
+        include_unused: bool,
+    ) -> (Vec<(Tag, Vec<EntryData>)>, usize) {
+        let no_entries = BTreeSet::new();
+        let mut tags: Vec<_> = self.tags.iter().collect();
+        if include_unused {
+            tags.extend(self.list_unused_tags().map(|tag| (tag, &no_entries)));
+        }
+        tags.sort_by_key(|(tag, _)| *tag);
+        let total = tags.len();
+        let page = tags
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(tag, entries)| {
+                let entries = if with_entries {
+                    let mut entries: Vec<_> = entries
+                        .iter()
+                        .filter_map(|id| self.get_entry(*id).cloned())
+                        .collect();
+                    entries.sort_by(|a, b| a.path().cmp(b.path()));
+                    entries
+                } else {
+                    vec![]
+                };
+                (tag.clone(), entries)
+            })
+            .collect();
+        (page, total)
+    }
+
+    /// Lists entries whose file was originally named `name` when it was added to the registry.
+    pub fn find_entries_by_original_name<S: AsRef<OsStr>>(
+        &self,
+        name: S,
+    ) -> Vec<(EntryId, &EntryData)> {
+        let name = name.as_ref();
+        let mut found: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.original_name() == Some(name))
+            .map(|(id, entry)| (*id, entry))
+            .collect();
+        found.sort_unstable_by(|a, b| a.1.path.cmp(&b.1.path));
+        found
+    }
+
+    /// Lists entries whose paths contain the `fragment` ignoring case, sorted by path. With the
+    /// `trigram-index` feature only entries containing all trigrams of the fragment are checked.
+    pub fn search_entries_by_path_fragment(&self, fragment: &str) -> Vec<&EntryData> {
+        let fragment = fragment.to_lowercase();
+        let matches = |entry: &&EntryData| {
+            entry
+                .path
+                .to_string_lossy()
+                .to_lowercase()
+                .contains(&fragment)
+        };
+        #[cfg(feature = "trigram-index")]
+        let candidates = self.path_index.candidates(&fragment);
+        #[cfg(not(feature = "trigram-index"))]
+        let candidates: Option<BTreeSet<EntryId>> = None;
+        let mut found: Vec<_> = match candidates {
+            Some(ids) => ids
                 .into_iter()
-                .filter_map(|e| self.get_entry(e).cloned())
-                .collect();
-            (tag, entries)
-        })
+                .filter_map(|id| self.entries.get(&id))
+                .filter(matches)
+                .collect(),
+            None => self.entries.values().filter(matches).collect(),
+        };
+        found.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        found
     }
 
-    /// Returns data of the entry with `id` if such entry exists.
-    pub fn get_entry(&self, id: EntryId) -> Option<&EntryData> {
-        self.entries.get(&id)
+    /// Compares entries of this registry with the file it is saved to and counts the entries
+    /// added and removed since it was last saved. All entries are unsaved if there is no file.
+    pub fn unsaved_entries(&self) -> Result<UnsavedEntries> {
+        let saved = match Self::load(&self.path) {
+            Ok(saved) => saved.entries,
+            Err(RegistryError::LoadRegistry(e)) if e.kind() == io::ErrorKind::NotFound => {
+                HashMap::new()
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(UnsavedEntries {
+            added: self
+                .entries
+                .keys()
+                .filter(|id| !saved.contains_key(id))
+                .count(),
+            removed: saved
+                .keys()
+                .filter(|id| !self.entries.contains_key(id))
+                .count(),
+        })
     }
 
-    /// Returns the tag with the name `tag` if it exists.
-    pub fn get_tag<T: AsRef<str>>(&self, tag: T) -> Option<&Tag> {
-        self.tags.keys().find(|t| t.name() == tag.as_ref())
+    /// Lists entries whose paths no longer exist on the filesystem.
+    pub fn list_stale_entries(&self) -> impl Iterator<Item = (&EntryId, &EntryData)> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| fs::symlink_metadata(&entry.path).is_err())
     }
 
-    /// Updates the color of the `tag`. Returns `true` if the tag was found and updated and `false`
-    /// otherwise.
-    pub fn update_tag_color<T: AsRef<str>>(&mut self, tag: T, color: Color) -> bool {
-        if let Some(mut t) = self.tags.keys().find(|t| t.name() == tag.as_ref()).cloned() {
-            let data = self.tags.remove(&t).expect("removed tag");
-            t.set_color(&color);
-            self.tags.insert(t, data);
-            true
-        } else {
-            false
+    /// Removes entries whose paths no longer exist on the filesystem along with their tags.
+    /// Returns the removed entries so that callers can stop watching them.
+    pub fn evict_stale_entries(&mut self) -> Vec<EntryData> {
+        let stale: Vec<_> = self.list_stale_entries().map(|(id, _)| *id).collect();
+        let mut evicted = Vec::with_capacity(stale.len());
+        for id in stale {
+            if let Some(entry) = self.entries.get(&id).cloned() {
+                self.clear_entry(id);
+                evicted.push(entry);
+            }
         }
+        evicted.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        evicted
     }
-}
+
+    /// Makes relative paths of entries, for example saved by old versions of wutag, absolute by
+    /// joining them with the absolute `root`. Entries whose files don't exist under the `root` are
+    /// left unchanged and reported, entries whose absolute path is already in the registry are
+    /// merged into the existing entry. Merged entries keep the earlier creation time, the later
+    /// access time and the original name of the existing entry if it has one, their verification
+    /// is reset as their tags might have changed.
+    pub fn absolutize_paths(&mut self, root: &Path) -> PathMigration {
+        let mut migration = PathMigration::default();
+        let mut relative: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.path.is_relative())
+            .map(|(id, entry)| (*id, entry.path.clone()))
+            .collect();
+        relative.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+        for (id, path) in relative {
+            let absolute = root.join(&path);
+            if fs::symlink_metadata(&absolute).is_err() {
+                migration.unresolved.push(path);
+                continue;
+            }
+            match self.find_entry(&absolute) {
+                Some(existing) => {
+                    let tags: Vec<_> = self
+                        .list_entry_tags(id)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .cloned()
+                        .collect();
+                    for tag in &tags {
+                        self.tag_entry(tag, existing);
+                    }
+                    if let Some(expiries) = self.expiries.remove(&id) {
+                        let existing = self.expiries.entry(existing).or_default();
+                        for (name, time) in expiries {
+                            existing.entry(name).or_insert(time);
+                        }
+                    }
+                    if let Some(merged) = self.entries.get(&id).cloned() {
+                        if let Some(entry) = self.entries.get_mut(&existing) {
+                            entry.created_at = entry.created_at.min(merged.created_at);
+                            entry.last_accessed = entry.last_accessed.max(merged.last_accessed);
+                            if entry.original_name.is_none() {
+                                entry.original_name = merged.original_name;
+                            }
+                            entry.verified = None;
+                        }
+                    }
+                    self.clear_entry(id);
+                    migration.merged += 1;
+                }
+                None => {
+                    self.update_entry_path(id, absolute);
+                }
+            }
+            migration.rewritten += 1;
+        }
+        migration
+    }
+
+    /// Compares tags of entries with tags saved in extended attributes of their files. Unless
+    /// `full` is set only files changed since their tags last matched are read, using the status
+    /// change time as setting extended attributes doesn't update the modification time. The
+    /// registry is left unchanged, the result of the verification is stored with
+    /// [record_verified](TagRegistry::record_verified).
+    pub fn verify_entries(&self, full: bool) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        let ids: Vec<_> = self.entries.keys().copied().collect();
+        for id in ids {
+            let mut registry_tags: Vec<_> = self
+                .list_entry_tags(id)
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect();
+            registry_tags.sort_unstable();
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            registry_tags
+                .iter()
+                .for_each(|tag| tag.name().hash(&mut hasher));
+            let tags_hash = hasher.finish();
+
+            let Some(entry) = self.entries.get(&id) else {
+                continue;
+            };
+            let follow = !entry.no_follow;
+            let metadata = if follow {
+                fs::metadata(&entry.path)
+            } else {
+                fs::symlink_metadata(&entry.path)
+            };
+            let Ok(changed) = metadata
+                .map_err(crate::Error::from)
+                .and_then(|m| change_time(&m))
+            else {
+                report.stale.push(entry.path.clone());
+                continue;
+            };
+            if !full && entry.verified == Some((changed, tags_hash)) {
+                report.skipped += 1;
+                continue;
+            }
+
+            report.checked += 1;
+            let mut xattr_tags = match list_tags(&entry.path, follow) {
+                Ok(tags) => tags,
+                Err(e) => {
+                    report.errors.push((entry.path.clone(), e.to_string()));
+                    continue;
+                }
+            };
+            xattr_tags.sort_unstable();
+            let verified = if registry_tags == xattr_tags {
+                Some((changed, tags_hash))
+            } else {
+                report
+                    .tag_mismatches
+                    .push((entry.path.clone(), registry_tags, xattr_tags));
+                None
+            };
+            if entry.verified != verified {
+                report.verified.push((id, verified));
+            }
+        }
+        report.stale.sort_unstable();
+        report.errors.sort_unstable();
+        report.tag_mismatches.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        report.count_drift = !self.tag_counts_consistent();
+        report
+    }
+
+    /// Stores the verification state of entries collected by
+    /// [verify_entries](TagRegistry::verify_entries) so that files that didn't change are skipped
+    /// next time. Returns whether any entry was updated.
+    pub fn record_verified(&mut self, report: &mut VerifyReport) -> bool {
+        let mut updated = false;
+        for (id, verified) in report.verified.drain(..) {
+            if let Some(entry) = self.entries.get_mut(&id) {
+                entry.verified = verified;
+                updated = true;
+            }
+        }
+        updated
+    }
+
+    /// Compares the registry with tags saved in extended attributes of the files. Every entry is
+    /// checked while files with tags missing from the registry are only searched for under
+    /// `base_dir` up to `max_depth`.
+    pub fn diff_with_filesystem<P: AsRef<Path>>(
+        &self,
+        base_dir: P,
+        max_depth: Option<usize>,
+    ) -> Result<RegistryFsDiff> {
+        let mut diff = RegistryFsDiff::default();
+        for (id, entry) in self.list_entries_and_ids() {
+            let path = entry.path();
+            let mut xattr_tags = match list_tags(path, !entry.no_follow()) {
+                Ok(tags) => tags,
+                Err(_) if fs::symlink_metadata(path).is_err() => vec![],
+                Err(e) => return Err(RegistryError::DiffFilesystem(e)),
+            };
+            if xattr_tags.is_empty() {
+                diff.entries_in_registry_only.push(path.to_path_buf());
+                continue;
+            }
+            let mut registry_tags: Vec<_> = self
+                .list_entry_tags(*id)
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect();
+            registry_tags.sort_unstable();
+            xattr_tags.sort_unstable();
+            if registry_tags != xattr_tags {
+                diff.tag_mismatches
+                    .push((path.to_path_buf(), registry_tags, xattr_tags));
+            }
+        }
+
+        let glob = Glob::new(
+            "**/*".into(),
+            Some(base_dir.as_ref().to_path_buf()),
+            max_depth,
+        )
+        .map_err(RegistryError::DiffFilesystem)?;
+        for path in glob.glob_paths().map_err(RegistryError::DiffFilesystem)? {
+            if self.find_entry(&path).is_some() {
+                continue;
+            }
+            match list_tags(&path, true) {
+                Ok(tags) if !tags.is_empty() => diff.entries_in_xattrs_only.push(path),
+                Ok(_) => {}
+                Err(e) => log::debug!("failed to list tags of `{}` - {e}", path.display()),
+            }
+        }
+
+        diff.entries_in_registry_only.sort_unstable();
+        diff.entries_in_xattrs_only.sort_unstable();
+        diff.tag_mismatches.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Ok(diff)
+    }
+
+    /// Lists tags and their entries
+    pub fn list_tags_and_entries(&self) -> impl Iterator<Item = (Tag, Vec<EntryData>)> + '_ {
+        self.tags.clone().into_iter().map(|(tag, entries)| {
+            let entries: Vec<_> = entries
+                .into_iter()
+                .filter_map(|e| self.get_entry(e).cloned())
+                .collect();
+            (tag, entries)
+        })
+    }
+
+    /// Returns data of the entry with `id` if such entry exists.
+    pub fn get_entry(&self, id: EntryId) -> Option<&EntryData> {
+        self.entries.get(&id)
+    }
+
+    /// Returns the tag with the name `tag` if it exists.
+    pub fn get_tag<T: AsRef<str>>(&self, tag: T) -> Option<&Tag> {
+        self.tags.keys().find(|t| t.name() == tag.as_ref())
+    }
+
+    /// Returns the tag with the name `tag` if it exists or was defined with
+    /// [define_tag](TagRegistry::define_tag) even if no entry has it.
+    pub fn get_tag_or_defined<T: AsRef<str>>(&self, tag: T) -> Option<&Tag> {
+        self.get_tag(&tag)
+            .or_else(|| self.defined_tags.get(tag.as_ref()))
+    }
+
+    /// Defines the `tag` without tagging any entry so that it is kept along with its color until
+    /// it is used. Returns `false` if a tag with the same name already exists.
+    pub fn define_tag(&mut self, tag: Tag) -> bool {
+        if self.get_tag_or_defined(tag.name()).is_some() {
+            return false;
+        }
+        self.defined_tags.insert(tag.name().to_string(), tag);
+        true
+    }
+
+    /// Lists tags defined with [define_tag](TagRegistry::define_tag) that no entry has.
+    pub fn list_unused_tags(&self) -> impl Iterator<Item = &Tag> {
+        self.defined_tags
+            .values()
+            .filter(|tag| !self.tags.contains_key(*tag))
+    }
+
+    /// Updates the color of the `tag`. Returns `true` if the tag was found and updated and `false`
+    /// otherwise.
+    pub fn update_tag_color<T: AsRef<str>>(&mut self, tag: T, color: Color) -> bool {
+        let defined = match self.defined_tags.get_mut(tag.as_ref()) {
+            Some(t) => {
+                t.set_color(&color);
+                true
+            }
+            None => false,
+        };
+        if let Some(mut t) = self.tags.keys().find(|t| t.name() == tag.as_ref()).cloned() {
+            let data = self.tags.remove(&t).expect("removed tag");
+            t.set_color(&color);
+            self.tags.insert(t, data);
+            true
+        } else {
+            defined
+        }
+    }
+
+    /// Returns the color of the `palette` used by the fewest tags of the registry, ties are
+    /// broken by the order of the `palette`. Falls back to the default color if the `palette` is
+    /// empty.
+    pub fn next_color(&self, palette: &[Color]) -> Color {
+        self.next_colors(palette).next().unwrap_or(DEFAULT_COLOR)
+    }
+
+    /// Returns an endless iterator of colors for new tags. Every color is the one of the
+    /// `palette` used by the fewest tags counting the colors returned before it, so colors of
+    /// several tags created at once are spread across the `palette` too.
+    pub fn next_colors<'a>(&self, palette: &'a [Color]) -> impl Iterator<Item = Color> + 'a {
+        let mut usage: Vec<usize> = palette
+            .iter()
+            .map(|color| self.tags.keys().filter(|t| t.color() == color).count())
+            .collect();
+        std::iter::from_fn(move || {
+            let (i, count) = usage
+                .iter_mut()
+                .enumerate()
+                .min_by_key(|(i, count)| (**count, *i))?;
+            *count += 1;
+            Some(palette[i])
+        })
+    }
+
+    /// Updates the weight of the `tag`. Returns `true` if the tag was found and updated and
+    /// `false` otherwise.
+    pub fn update_tag_weight<T: AsRef<str>>(&mut self, tag: T, weight: f32) -> bool {
+        let defined = match self.defined_tags.get_mut(tag.as_ref()) {
+            Some(t) => {
+                t.set_weight(weight);
+                true
+            }
+            None => false,
+        };
+        if let Some(mut t) = self.tags.keys().find(|t| t.name() == tag.as_ref()).cloned() {
+            let data = self.tags.remove(&t).expect("removed tag");
+            t.set_weight(weight);
+            self.tags.insert(t, data);
+            true
+        } else {
+            defined
+        }
+    }
+
+    /// Marks the `tag` as a system tag or unmarks it. Returns `true` if the tag was found and
+    /// updated and `false` otherwise.
+    pub fn update_tag_system<T: AsRef<str>>(&mut self, tag: T, system: bool) -> bool {
+        let defined = match self.defined_tags.get_mut(tag.as_ref()) {
+            Some(t) => {
+                t.set_system(system);
+                true
+            }
+            None => false,
+        };
+        if let Some(mut t) = self.tags.keys().find(|t| t.name() == tag.as_ref()).cloned() {
+            let data = self.tags.remove(&t).expect("removed tag");
+            t.set_system(system);
+            self.tags.insert(t, data);
+            true
+        } else {
+            defined
+        }
+    }
+
+    /// Renames tags according to `mapping` of old names to new names. All tags are removed
+    /// before being reinserted so the new name of one tag can be the old name of another. If a
+    /// tag with the new name already exists the entries of both tags are merged and the
+    /// existing tag is kept.
+    pub fn rename_tags<S: AsRef<str>>(&mut self, mapping: &[(S, S)]) {
+        let mut renamed = vec![];
+        for (from, to) in mapping {
+            if let Some(tag) = self.get_tag(from).cloned() {
+                let ids: Vec<_> = self.tags.get(&tag).into_iter().flatten().copied().collect();
+                ids.into_iter().for_each(|id| self.record_change(id));
+                let entries = self.tags.remove(&tag).unwrap_or_default();
+                self.tag_counts.remove(&tag);
+                renamed.push((tag, to, entries));
+            }
+        }
+
+        for (mut tag, to, entries) in renamed {
+            tag.set_name(to.as_ref());
+            match self.tags.get_mut(&tag) {
+                Some(existing) => {
+                    for entry in entries {
+                        // entries tagged with both tags now have one tag less
+                        if !existing.insert(entry) {
+                            if let Some(count) = self.entry_tag_counts.get_mut(&entry) {
+                                *count = count.saturating_sub(1);
+                            }
+                        }
+                    }
+                }
+                None => {
+                    self.tags.insert(tag.clone(), entries);
+                }
+            }
+            let count = self.tags.get(&tag).map(BTreeSet::len).unwrap_or_default();
+            self.tag_counts.insert(tag, count);
+        }
+
+        for (from, to) in mapping {
+            if let Some(mut tag) = self.defined_tags.remove(from.as_ref()) {
+                tag.set_name(to.as_ref());
+                self.defined_tags
+                    .entry(tag.name().to_string())
+                    .or_insert(tag);
+            }
+        }
+
+        let moved: Vec<_> = mapping
+            .iter()
+            .filter_map(|(from, to)| {
+                self.tag_meta
+                    .remove(from.as_ref())
+                    .map(|meta| (normalize_name(to), meta))
+            })
+            .collect();
+        for (to, meta) in moved {
+            let existing = self.tag_meta.entry(to).or_default();
+            for (key, value) in meta {
+                existing.entry(key).or_insert(value);
+            }
+        }
+
+        for expiries in self.expiries.values_mut() {
+            let moved: Vec<_> = mapping
+                .iter()
+                .filter_map(|(from, to)| {
+                    expiries
+                        .remove(from.as_ref())
+                        .map(|expires_at| (normalize_name(to), expires_at))
+                })
+                .collect();
+            expiries.extend(moved);
+        }
+    }
+
+    /// Returns the score of the `entry` that is the sum of weights of `tags` the entry is
+    /// tagged with.
+    pub fn entry_score<S: AsRef<str>>(&self, entry: EntryId, tags: &[S]) -> f32 {
+        self.tags
+            .iter()
+            .filter(|(tag, entries)| {
+                entries.contains(&entry) && tags.iter().any(|t| t.as_ref() == tag.name())
+            })
+            .map(|(tag, _)| tag.weight())
+            .sum()
+    }
+
+    /// Records the `tag` as the most recently applied one. Previous occurrences of the tag are
+    /// removed so that the history contains unique names and at most
+    /// [MAX_RECENT_TAGS](MAX_RECENT_TAGS) of them.
+    pub fn record_recent_tag(&mut self, tag: &Tag) {
+        self.recent_tags.retain(|name| name != tag.name());
+        self.recent_tags.push_front(tag.name().to_string());
+        self.recent_tags.truncate(MAX_RECENT_TAGS);
+    }
+
+    /// Lists names of recently applied tags, most recent first.
+    pub fn list_recent_tags(&self) -> impl Iterator<Item = &String> {
+        self.recent_tags.iter()
+    }
+
+    /// Clears the history of recently applied tags.
+    pub fn clear_recent_tags(&mut self) {
+        self.recent_tags.clear();
+    }
+
+    /// Sets the metadata `key` of the tag with `tag_name` to `value` or removes it if `value` is
+    /// `None`. Returns `false` if the tag doesn't exist.
+    pub fn set_tag_meta(&mut self, tag_name: &str, key: &str, value: Option<String>) -> bool {
+        if self.get_tag_or_defined(tag_name).is_none() {
+            return false;
+        }
+        match value {
+            Some(value) => {
+                self.tag_meta
+                    .entry(tag_name.to_string())
+                    .or_default()
+                    .insert(key.to_string(), value);
+            }
+            None => {
+                if let Some(meta) = self.tag_meta.get_mut(tag_name) {
+                    meta.remove(key);
+                    if meta.is_empty() {
+                        self.tag_meta.remove(tag_name);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns the metadata of the tag with `tag_name` if it has any.
+    pub fn tag_meta(&self, tag_name: &str) -> Option<&BTreeMap<String, String>> {
+        self.tag_meta.get(tag_name)
+    }
+
+    /// Lists metadata of all tags that have any by tag name.
+    pub fn list_tag_meta(&self) -> impl Iterator<Item = (&String, &BTreeMap<String, String>)> {
+        self.tag_meta.iter()
+    }
+
+    /// Writes a Graphviz DOT graph of this registry to the `writer`. Tags are nodes filled with
+    /// their color and connected with edges to the entries tagged with them. Entries are grouped
+    /// in clusters by their parent directory.
+    pub fn export_dot<W: Write>(&self, mut writer: W) -> Result<()> {
+        fn escape(s: &str) -> String {
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+
+        let mut tags: Vec<_> = self.tags.iter().collect();
+        tags.sort_unstable_by_key(|(tag, _)| *tag);
+
+        let mut clusters: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+        for entry in self.entries.values() {
+            let dir = entry.path.parent().unwrap_or_else(|| Path::new(""));
+            clusters.entry(dir).or_default().push(&entry.path);
+        }
+
+        let mut dot = String::from("digraph wutag {\n    node [style=filled];\n");
+        for (tag, _) in &tags {
+            let name = escape(tag.name());
+            let _ = writeln!(
+                dot,
+                "    \"tag:{name}\" [label=\"{name}\", fillcolor=\"{}\"];",
+                color_to_hex(*tag.color())
+            );
+        }
+        for (i, (dir, mut paths)) in clusters.into_iter().enumerate() {
+            paths.sort_unstable();
+            let _ = writeln!(dot, "    subgraph cluster_{i} {{");
+            let _ = writeln!(dot, "        label=\"{}\";", escape(&dir.to_string_lossy()));
+            let _ = writeln!(dot, "        node [shape=box, style=solid];");
+            for path in paths {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_else(|| path.to_string_lossy());
+                let _ = writeln!(
+                    dot,
+                    "        \"file:{}\" [label=\"{}\"];",
+                    escape(&path.to_string_lossy()),
+                    escape(&name)
+                );
+            }
+            let _ = writeln!(dot, "    }}");
+        }
+        for (tag, entries) in tags {
+            for entry in entries.iter().filter_map(|id| self.entries.get(id)) {
+                let _ = writeln!(
+                    dot,
+                    "    \"tag:{}\" -> \"file:{}\";",
+                    escape(tag.name()),
+                    escape(&entry.path.to_string_lossy())
+                );
+            }
+        }
+        dot.push_str("}\n");
+
+        writer
+            .write_all(dot.as_bytes())
+            .map_err(RegistryError::ExportGraph)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -476,6 +1974,34 @@ mod tests {
         assert!(registry.tags.is_empty());
     }
 
+    #[test]
+    fn syncs_entry_tags() {
+        let mut registry = TagRegistry::default();
+
+        let tag1 = Tag::new("src", Black);
+        let tag2 = Tag::new("code", Red);
+        let tag3 = Tag::new("rust", Blue);
+
+        let entry = EntryData::new("/tmp");
+        let (id, _) = registry.add_or_update_entry(entry.clone());
+        registry.tag_entry(&tag1, id);
+        registry.tag_entry(&tag2, id);
+
+        assert_eq!(
+            registry.sync_entry_tags(id, &[tag2.clone(), tag3.clone()]),
+            None
+        );
+        let tags = registry.list_entry_tags(id).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert!(tags.contains(&&tag2));
+        assert!(tags.contains(&&tag3));
+        assert!(registry.get_tag("src").is_none());
+
+        assert_eq!(registry.sync_entry_tags(id, &[]), Some(entry));
+        assert_eq!(registry.list_entries().count(), 0);
+        assert!(registry.tags.is_empty());
+    }
+
     #[test]
     fn lists_entry_tags() {
         let mut registry = TagRegistry::default();
@@ -495,6 +2021,49 @@ mod tests {
         assert!(tags.contains(&&tag2));
     }
 
+    #[test]
+    fn lists_pages_sorted() {
+        let mut registry = TagRegistry::default();
+        let tags = [
+            Tag::new("c", Black),
+            Tag::new("a", Red),
+            Tag::new("b", Blue),
+        ];
+        for (path, tag) in [
+            ("/tmp/3", &tags[0]),
+            ("/tmp/1", &tags[1]),
+            ("/tmp/2", &tags[2]),
+        ] {
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            registry.tag_entry(tag, id);
+        }
+
+        let (entries, total) = registry.list_entries_page(1, Some(5), true);
+        assert_eq!(total, 3);
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(e, tags)| (e.into_path_buf(), tags))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (PathBuf::from("/tmp/2"), vec![tags[2].clone()]),
+                (PathBuf::from("/tmp/3"), vec![tags[0].clone()])
+            ]
+        );
+
+        let (page, total) = registry.list_tags_page(0, Some(2), false, false);
+        assert_eq!(total, 3);
+        assert_eq!(
+            page,
+            vec![(tags[1].clone(), vec![]), (tags[2].clone(), vec![])]
+        );
+        let (page, _) = registry.list_tags_page(2, None, true, false);
+        assert_eq!(page[0].0, tags[0]);
+        assert_eq!(page[0].1[0].path(), Path::new("/tmp/3"));
+        assert!(registry.list_tags_page(3, None, true, false).0.is_empty());
+    }
+
     #[test]
     fn lists_entries_with_tags() {
         let mut registry = TagRegistry::default();
@@ -554,6 +2123,67 @@ mod tests {
         assert!(entries.contains(&id4));
     }
 
+    #[test]
+    fn exports_dot_graph() {
+        let mut registry = TagRegistry::default();
+
+        let tag1 = Tag::new("src", Black);
+        let tag2 = Tag::new("code", Red);
+
+        let (id1, _) = registry.add_or_update_entry(EntryData::new("/tmp/1"));
+        let (id2, _) = registry.add_or_update_entry(EntryData::new("/home/2"));
+        registry.tag_entry(&tag1, id1);
+        registry.tag_entry(&tag2, id1);
+        registry.tag_entry(&tag2, id2);
+
+        let mut out = vec![];
+        registry.export_dot(&mut out).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+
+        assert!(dot.starts_with("digraph wutag {"));
+        assert!(dot.contains(r##""tag:src" [label="src", fillcolor="#000000"];"##));
+        assert!(dot.contains(r##""tag:code" [label="code", fillcolor="#cd0000"];"##));
+        assert!(dot.contains(r#"label="/home";"#));
+        assert!(dot.contains(r#"label="/tmp";"#));
+        assert!(dot.contains(r#""file:/tmp/1" [label="1"];"#));
+        assert!(dot.contains(r#""tag:src" -> "file:/tmp/1";"#));
+        assert!(dot.contains(r#""tag:code" -> "file:/tmp/1";"#));
+        assert!(dot.contains(r#""tag:code" -> "file:/home/2";"#));
+        assert!(!dot.contains(r#""tag:src" -> "file:/home/2";"#));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn counts_unsaved_entries() {
+        let dir = tempdir::TempDir::new("wutag-registry-unsaved").unwrap();
+        let mut registry = TagRegistry::new(dir.path().join("wutag.registry"));
+        let (first, _) = registry.add_or_update_entry(EntryData::new("/tmp/first"));
+        assert_eq!(
+            registry.unsaved_entries().unwrap(),
+            UnsavedEntries {
+                added: 1,
+                removed: 0
+            }
+        );
+
+        registry.save().unwrap();
+        assert_eq!(
+            registry.unsaved_entries().unwrap(),
+            UnsavedEntries::default()
+        );
+
+        registry.remove_entry(first);
+        registry.add_or_update_entry(EntryData::new("/tmp/second"));
+        registry.add_or_update_entry(EntryData::new("/tmp/third"));
+        assert_eq!(
+            registry.unsaved_entries().unwrap(),
+            UnsavedEntries {
+                added: 2,
+                removed: 1
+            }
+        );
+    }
+
     #[test]
     fn saves_and_loads() {
         let tmp_dir = tempdir::TempDir::new("registry-test").unwrap();
@@ -579,5 +2209,784 @@ mod tests {
             registry.list_entries_with_any_tags(vec![tag.name()]),
             vec![id]
         );
+        assert_eq!(registry.tag_count_for_entry(id), 1);
+    }
+
+    #[test]
+    fn records_recent_tags() {
+        let mut registry = TagRegistry::default();
+        for i in 0..MAX_RECENT_TAGS + 5 {
+            registry.record_recent_tag(&Tag::new(format!("tag{i}"), Black));
+        }
+        registry.record_recent_tag(&Tag::new("tag10", Black));
+
+        let recent: Vec<_> = registry.list_recent_tags().collect();
+        assert_eq!(recent.len(), MAX_RECENT_TAGS);
+        assert_eq!(recent[0], "tag10");
+        assert_eq!(recent[1], &format!("tag{}", MAX_RECENT_TAGS + 4));
+        assert_eq!(recent.iter().filter(|name| **name == "tag10").count(), 1);
+        assert!(!recent.contains(&&"tag4".to_string()));
+
+        registry.clear_recent_tags();
+        assert_eq!(registry.list_recent_tags().count(), 0);
+    }
+
+    #[test]
+    fn stores_tag_meta() {
+        let mut registry = TagRegistry::default();
+        let (id, _) = registry.add_or_update_entry(EntryData::new("/tmp"));
+        registry.tag_entry(&Tag::new("work", Red), id);
+
+        assert!(registry.set_tag_meta("work", "icon", Some("briefcase".into())));
+        assert!(registry.set_tag_meta("work", "priority", Some("high".into())));
+        assert!(!registry.set_tag_meta("missing", "icon", Some("x".into())));
+        assert!(registry.set_tag_meta("work", "priority", None));
+        assert_eq!(
+            registry.tag_meta("work"),
+            Some(&BTreeMap::from([("icon".into(), "briefcase".into())]))
+        );
+
+        registry.rename_tags(&[("work", "job")]);
+        assert!(registry.tag_meta("work").is_none());
+        assert_eq!(registry.tag_meta("job").unwrap()["icon"], "briefcase");
+
+        let job = registry.get_tag("job").unwrap().clone();
+        registry.clear_tag(&job);
+        assert_eq!(registry.list_tag_meta().count(), 0);
+    }
+
+    #[test]
+    fn keeps_defined_tags_while_unused() {
+        let mut registry = TagRegistry::default();
+        assert!(registry.define_tag(Tag::new("work", Blue)));
+        assert!(!registry.define_tag(Tag::new("work", Red)));
+        assert!(registry.get_tag("work").is_none());
+        assert_eq!(registry.get_tag_or_defined("work").unwrap().color(), &Blue);
+        assert!(registry.set_tag_meta("work", "description", Some("job".into())));
+        let (page, total) = registry.list_tags_page(0, None, true, true);
+        assert_eq!((page, total), (vec![(Tag::new("work", Blue), vec![])], 1));
+
+        let work = Tag::new("work", Blue);
+        let (id, _) = registry.add_or_update_entry(EntryData::new("/tmp/work"));
+        registry.tag_entry(&work, id);
+        assert_eq!(registry.list_unused_tags().count(), 0);
+        assert!(!registry.define_tag(Tag::new("work", Red)));
+        registry.untag_entry(&work, id);
+        assert!(registry.get_tag("work").is_none());
+        assert_eq!(registry.list_unused_tags().collect::<Vec<_>>(), vec![&work]);
+
+        assert!(registry.update_tag_color("work", Green));
+        assert!(registry.update_tag_weight("work", 2.0));
+        assert!(registry.update_tag_system("work", true));
+        let defined = registry.get_tag_or_defined("work").unwrap();
+        assert_eq!((defined.weight(), defined.is_system()), (2.0, true));
+        registry.rename_tags(&[("work", "job")]);
+        assert_eq!(registry.get_tag_or_defined("job").unwrap().color(), &Green);
+        assert!(registry.get_tag_or_defined("work").is_none());
+        registry.clear_tag(&Tag::new("job", Green));
+        assert_eq!(registry.list_unused_tags().count(), 0);
+        assert_eq!(registry.list_tag_meta().count(), 0);
+    }
+
+    #[test]
+    fn computes_tag_overlap() {
+        let mut registry = TagRegistry::default();
+        let work = Tag::new("work", Red);
+        let urgent = Tag::new("urgent", Black);
+        let (both, _) = registry.add_or_update_entry(EntryData::new("/tmp/both"));
+        let (only_work, _) = registry.add_or_update_entry(EntryData::new("/tmp/work"));
+        let (only_urgent, _) = registry.add_or_update_entry(EntryData::new("/tmp/urgent"));
+        registry.tag_entry(&work, both);
+        registry.tag_entry(&urgent, both);
+        registry.tag_entry(&work, only_work);
+        registry.tag_entry(&urgent, only_urgent);
+
+        let overlap = registry.tag_overlap("work", "urgent").unwrap();
+        assert_eq!(overlap.both, vec![EntryData::new("/tmp/both")]);
+        assert_eq!(overlap.only_a, vec![EntryData::new("/tmp/work")]);
+        assert_eq!(overlap.only_b, vec![EntryData::new("/tmp/urgent")]);
+
+        assert!(registry.tag_overlap("work", "missing").is_none());
+    }
+
+    #[test]
+    fn lists_recent_entries() {
+        let mut registry = TagRegistry::default();
+        let now = Utc::now();
+        for (path, age) in [("/tmp/old", 120), ("/tmp/new", 5), ("/tmp/newer", 1)] {
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            registry.entries.get_mut(&id).unwrap().created_at =
+                now - chrono::Duration::minutes(age);
+        }
+        let paths = |entries: Vec<&EntryData>| -> Vec<_> {
+            entries
+                .into_iter()
+                .map(|e| e.path().to_path_buf())
+                .collect()
+        };
+
+        assert_eq!(
+            paths(registry.list_recent_entries(None, None)),
+            vec![
+                PathBuf::from("/tmp/newer"),
+                PathBuf::from("/tmp/new"),
+                PathBuf::from("/tmp/old")
+            ]
+        );
+        let since = Some(now - chrono::Duration::hours(1));
+        assert_eq!(
+            paths(registry.list_recent_entries(since, None)),
+            vec![PathBuf::from("/tmp/newer"), PathBuf::from("/tmp/new")]
+        );
+        assert_eq!(
+            paths(registry.list_recent_entries(since, Some(1))),
+            vec![PathBuf::from("/tmp/newer")]
+        );
+
+        // updating an entry keeps the time at which it was added
+        registry.add_or_update_entry(EntryData::new("/tmp/old"));
+        assert_eq!(
+            paths(registry.list_recent_entries(since, None)),
+            vec![PathBuf::from("/tmp/newer"), PathBuf::from("/tmp/new")]
+        );
+    }
+
+    #[test]
+    fn counts_tags_of_entries() {
+        let mut registry = TagRegistry::default();
+        let (fst, _) = registry.add_or_update_entry(EntryData::new("/tmp/1"));
+        let (snd, _) = registry.add_or_update_entry(EntryData::new("/tmp/2"));
+        let work = Tag::new("work", Red);
+        let urgent = Tag::new("urgent", Black);
+        let notes = Tag::new("notes", Blue);
+        registry.tag_entry(&work, fst);
+        registry.tag_entry(&work, fst);
+        registry.tag_entry(&urgent, fst);
+        registry.tag_entry(&notes, fst);
+        registry.tag_entry(&work, snd);
+        registry.tag_entry(&urgent, snd);
+
+        assert_eq!(registry.tag_count_for_entry(fst), 3);
+        assert_eq!(registry.tag_count_for_entry(snd), 2);
+        assert_eq!(registry.entry_count_for_tag(&work), 2);
+        assert_eq!(registry.entry_count_for_tag(&notes), 1);
+        assert_eq!(
+            registry.list_most_used_tags(2),
+            vec![(&urgent, 2), (&work, 2)]
+        );
+
+        registry.untag_entry(&notes, fst);
+        registry.rename_tags(&[("urgent", "work")]);
+        assert_eq!(registry.tag_count_for_entry(fst), 1);
+        assert_eq!(registry.entry_count_for_tag(&notes), 0);
+        assert_eq!(registry.entry_count_for_tag(&urgent), 0);
+        assert_eq!(registry.entry_count_for_tag(&work), 2);
+        assert!(registry.tag_counts_consistent());
+
+        let (third, _) = registry.add_or_update_entry(EntryData::new("/tmp/3"));
+        registry.bulk_tag([(third, work.clone()), (third, notes.clone())].into_iter());
+        registry.clear_entry(third);
+        assert_eq!(registry.entry_count_for_tag(&work), 2);
+        assert!(registry.tag_counts_consistent());
+
+        registry.tag_counts.insert(notes.clone(), 1);
+        assert!(!registry.tag_counts_consistent());
+        assert!(registry.verify_entries(false).count_drift);
+        registry.tag_counts.remove(&notes);
+
+        registry.clear_tag(&work);
+        assert_eq!(registry.tag_count_for_entry(fst), 0);
+        assert_eq!(registry.tag_count_for_entry(snd), 0);
+        assert!(registry.get_entry(fst).is_none());
+        assert!(registry.tag_counts_consistent());
+    }
+
+    #[test]
+    fn scores_entries_by_tag_weights() {
+        let mut registry = TagRegistry::default();
+        let (id, _) = registry.add_or_update_entry(EntryData::new("/tmp"));
+        let urgent = Tag::new("urgent", Red);
+        let notes = Tag::new("notes", Black);
+        registry.tag_entry(&urgent, id);
+        registry.tag_entry(&notes, id);
+
+        assert_eq!(registry.entry_score(id, &["urgent", "notes"]), 2.0);
+        assert!(registry.update_tag_weight("urgent", 5.0));
+        assert!(!registry.update_tag_weight("missing", 5.0));
+        assert_eq!(registry.get_tag("urgent").unwrap().weight(), 5.0);
+        assert_eq!(registry.entry_score(id, &["urgent", "notes"]), 6.0);
+        assert_eq!(registry.entry_score(id, &["urgent", "missing"]), 5.0);
+
+        registry.update_tag_weight("notes", 100.0);
+        assert_eq!(registry.get_tag("notes").unwrap().weight(), 10.0);
+    }
+
+    #[test]
+    fn renames_and_merges_tags() {
+        let mut registry = TagRegistry::default();
+        let (fst, _) = registry.add_or_update_entry(EntryData::new("/tmp"));
+        let (snd, _) = registry.add_or_update_entry(EntryData::new("/tmp/123"));
+        registry.tag_entry(&Tag::new("old-a", Red), fst);
+        registry.tag_entry(&Tag::new("old-b", Blue), snd);
+        registry.tag_entry(&Tag::new("new-b", Black), fst);
+
+        registry.rename_tags(&[("old-a", "new-a"), ("old-b", "new-b")]);
+
+        assert!(registry.get_tag("old-a").is_none());
+        assert!(registry.get_tag("old-b").is_none());
+        assert_eq!(registry.get_tag("new-a").unwrap().color(), &Red);
+        assert_eq!(registry.get_tag("new-b").unwrap().color(), &Black);
+        assert_eq!(registry.list_entries_with_all_tags(["new-a"]), vec![fst]);
+        let mut merged = registry.list_entries_with_all_tags(["new-b"]);
+        merged.sort_unstable();
+        let mut expected = vec![fst, snd];
+        expected.sort_unstable();
+        assert_eq!(merged, expected);
+
+        registry.rename_tags(&[("new-a", "new-b"), ("new-b", "new-c")]);
+        assert_eq!(registry.list_entries_with_all_tags(["new-b"]), vec![fst]);
+        assert_eq!(registry.list_entries_with_all_tags(["new-c"]).len(), 2);
+    }
+
+    #[test]
+    fn records_changes_of_touched_entries() {
+        let mut registry = TagRegistry::default();
+        let work = Tag::new("work", Red);
+        let home = Tag::new("home", Blue);
+        let (a, _) = registry.add_or_update_entry(EntryData::new("/tmp/a"));
+        let (b, _) = registry.add_or_update_entry(EntryData::new("/tmp/b"));
+        let (c, _) = registry.add_or_update_entry(EntryData::new("/tmp/c"));
+        registry.tag_entry(&work, a);
+        registry.tag_entry(&work, b);
+        registry.tag_entry(&home, c);
+
+        // changes are only recorded while requested
+        assert!(registry.take_changes().before.is_empty());
+
+        registry.record_changes();
+        registry.tag_entry(&home, a);
+        registry.untag_entry(&work, b);
+        registry.rename_tags(&[("home", "house")]);
+        let changes = registry.take_changes();
+
+        assert_eq!(
+            changes.before.keys().collect::<BTreeSet<_>>(),
+            BTreeSet::from([&PathBuf::from("/tmp/a"), &"/tmp/b".into(), &"/tmp/c".into()])
+        );
+        assert_eq!(changes.before[Path::new("/tmp/a")], vec![work.clone()]);
+        assert_eq!(changes.before[Path::new("/tmp/b")], vec![work.clone()]);
+        assert_eq!(changes.before[Path::new("/tmp/c")], vec![home.clone()]);
+        assert_eq!(changes.after.len(), 2);
+        assert_eq!(changes.after[Path::new("/tmp/a")].len(), 2);
+        assert_eq!(
+            changes.after[Path::new("/tmp/c")],
+            vec![Tag::new("house", Blue)]
+        );
+        assert!(registry.changes.is_none());
+    }
+
+    #[test]
+    fn locks_registry() {
+        let dir = tempdir::TempDir::new("wutag-registry").unwrap();
+        let path = dir.path().join("wutag.db");
+
+        let lock = TagRegistry::lock(&path).unwrap();
+        assert_eq!(lock.path(), dir.path().join("wutag.db.lock"));
+        assert!(matches!(
+            TagRegistry::lock_timeout(&path, Duration::from_millis(100)),
+            Err(RegistryError::RegistryLocked(_))
+        ));
+
+        // updates wait for the lock and release it once the registry is saved
+        let update = {
+            let path = path.clone();
+            std::thread::spawn(move || TagRegistry::update(path, |r| r.recent_tags.len()))
+        };
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!update.is_finished());
+        drop(lock);
+        assert_eq!(update.join().unwrap().unwrap(), 0);
+        assert!(TagRegistry::lock_timeout(&path, Duration::from_millis(100)).is_ok());
+    }
+
+    #[test]
+    fn bulk_tags_entries() {
+        let tags: Vec<_> = (0..20)
+            .map(|i| Tag::new(format!("tag-{i}"), DEFAULT_COLORS[i % DEFAULT_COLORS.len()]))
+            .collect();
+        let mut bulk = TagRegistry::default();
+        let mut single = TagRegistry::default();
+        let mut items = vec![];
+        for i in 0..5000 {
+            let (id, entry) = (i, EntryData::new(format!("/tmp/bulk/{i}")));
+            bulk.insert_entry(id, entry.clone());
+            single.insert_entry(id, entry);
+            for tag in tags.iter().skip(i % 7).step_by(3) {
+                items.push((id, tag.clone()));
+            }
+        }
+        // already tagged entries are skipped
+        let (first, _) = items[0].clone();
+        bulk.tag_entry(&items[0].1, first);
+        items.push(items[0].clone());
+
+        let added = bulk.bulk_tag(items.clone().into_iter());
+        assert_eq!(added, items.len() - 2);
+        for (id, tag) in &items {
+            single.tag_entry(tag, *id);
+        }
+
+        for tag in &tags {
+            let mut expected = single.list_entries_with_all_tags([tag.name()]);
+            let mut got = bulk.list_entries_with_all_tags([tag.name()]);
+            expected.sort_unstable();
+            got.sort_unstable();
+            assert_eq!(got, expected);
+        }
+        assert_eq!(bulk.count_entry_tags(), bulk.entry_tag_counts);
+        assert_eq!(bulk.entry_tag_counts, single.entry_tag_counts);
+    }
+
+    #[test]
+    fn selects_entries_to_evict() {
+        let mut registry = TagRegistry::default();
+        let now = Utc::now();
+        let tag = Tag::new("evict", Red);
+        let other = Tag::new("other", Red);
+        let mut ids = vec![];
+        // (path, minutes since creation, minutes since last access, tag count)
+        for (path, created, accessed, tags) in [
+            ("/tmp/a", 30, 1, 1),
+            ("/tmp/b", 20, 10, 2),
+            ("/tmp/c", 10, 5, 2),
+        ] {
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            let entry = registry.entries.get_mut(&id).unwrap();
+            entry.created_at = now - chrono::Duration::minutes(created);
+            entry.last_accessed = now - chrono::Duration::minutes(accessed);
+            for tag in [&tag, &other].into_iter().take(tags) {
+                registry.tag_entry(tag, id);
+            }
+            ids.push(id);
+        }
+
+        let none = HashSet::new();
+        assert!(registry
+            .entries_to_evict(3, EvictionPolicy::Lru, &none)
+            .is_empty());
+        assert_eq!(
+            registry.entries_to_evict(1, EvictionPolicy::Lru, &none),
+            vec![ids[1], ids[2]]
+        );
+        assert_eq!(
+            registry.entries_to_evict(2, EvictionPolicy::LeastTagged, &none),
+            vec![ids[0]]
+        );
+        assert_eq!(
+            registry.entries_to_evict(1, EvictionPolicy::Oldest, &none),
+            vec![ids[0], ids[1]]
+        );
+
+        // updating an entry makes it the most recently used one
+        registry.add_or_update_entry(EntryData::new("/tmp/b"));
+        assert_eq!(
+            registry.entries_to_evict(2, EvictionPolicy::Lru, &none),
+            vec![ids[2]]
+        );
+
+        let keep = HashSet::from([ids[2]]);
+        assert_eq!(
+            registry.entries_to_evict(1, EvictionPolicy::Lru, &keep),
+            vec![ids[0], ids[1]]
+        );
+        assert_eq!(
+            registry.entries_to_evict(0, EvictionPolicy::Lru, &keep),
+            vec![ids[0], ids[1]]
+        );
+    }
+
+    #[test]
+    fn serializes_concurrent_updates() {
+        let dir = tempdir::TempDir::new("wutag-registry").unwrap();
+        let path = dir.path().join("wutag.db");
+        let tag = Tag::new("concurrent", Red);
+
+        let threads: Vec<_> = (0..2)
+            .map(|n| {
+                let path = path.clone();
+                let tag = tag.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        TagRegistry::update(&path, |registry| {
+                            let entry = EntryData::new(format!("/tmp/{n}/{i}"));
+                            let (id, _) = registry.add_or_update_entry(entry);
+                            registry.tag_entry(&tag, id);
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let registry = TagRegistry::load(&path).unwrap();
+        assert_eq!(registry.list_entries().count(), 50);
+        assert_eq!(
+            registry.list_entries_with_all_tags(["concurrent"]).len(),
+            50
+        );
+    }
+
+    #[test]
+    fn creates_io_lock_only_on_writes() {
+        let dir = tempdir::TempDir::new("wutag-registry-io-lock").unwrap();
+        let path = dir.path().join("wutag.db");
+        let lock_path = dir.path().join("wutag.db.io-lock");
+        let registry = TagRegistry::new(&path);
+        registry.save().unwrap();
+        fs::remove_file(&lock_path).unwrap();
+
+        let registry = TagRegistry::load(&path).unwrap();
+        assert!(!lock_path.exists());
+        registry.save().unwrap();
+        assert!(lock_path.exists());
+        TagRegistry::load(&path).unwrap();
+    }
+
+    #[test]
+    fn diffs_registry_with_filesystem() {
+        let dir = tempdir::TempDir::new("wutag-registry-diff").unwrap();
+        let mut registry = TagRegistry::new(dir.path().join("wutag.db"));
+        let code = Tag::new("code", Red);
+        let rust = Tag::new("rust", Red);
+        let file = |name: &str, tags: &[&Tag]| {
+            let path = dir.path().join(name);
+            fs::write(&path, b"").unwrap();
+            for tag in tags {
+                tag.save_to(&path, true).unwrap();
+            }
+            path
+        };
+        let consistent = file("consistent", &[&code]);
+        let mismatched = file("mismatched", &[&code, &rust]);
+        let untracked = file("untracked", &[&rust]);
+        let untagged = file("untagged", &[]);
+        let missing = dir.path().join("missing");
+        for path in [&consistent, &mismatched, &untagged, &missing] {
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            registry.tag_entry(&code, id);
+        }
+
+        let diff = registry.diff_with_filesystem(dir.path(), None).unwrap();
+        assert_eq!(diff.entries_in_registry_only, vec![missing, untagged]);
+        assert_eq!(diff.entries_in_xattrs_only, vec![untracked]);
+        assert_eq!(
+            diff.tag_mismatches,
+            vec![(mismatched, vec![code.clone()], vec![code, rust])]
+        );
+    }
+
+    #[test]
+    fn verifies_changed_files_only() {
+        let dir = tempdir::TempDir::new("wutag-registry-verify").unwrap();
+        let mut registry = TagRegistry::new(dir.path().join("wutag.db"));
+        let code = Tag::new("code", Red);
+        let rust = Tag::new("rust", Red);
+        let consistent = dir.path().join("consistent");
+        let changed = dir.path().join("changed");
+        let missing = dir.path().join("missing");
+        for path in [&consistent, &changed] {
+            fs::write(path, b"").unwrap();
+            code.save_to(path, true).unwrap();
+        }
+        for path in [&consistent, &changed, &missing] {
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            registry.tag_entry(&code, id);
+        }
+
+        let mut report = registry.verify_entries(false);
+        assert_eq!(report.stale, vec![missing.clone()]);
+        assert!(report.tag_mismatches.is_empty());
+        assert!(report.errors.is_empty());
+        assert_eq!((report.checked, report.skipped), (2, 0));
+        assert!(registry.record_verified(&mut report));
+        // nothing changed so there is nothing to store
+        let mut report = registry.verify_entries(false);
+        assert_eq!((report.checked, report.skipped), (0, 2));
+        assert!(!report.has_verified_changes());
+        assert!(!registry.record_verified(&mut report));
+
+        rust.save_to(&changed, true).unwrap();
+        let mut report = registry.verify_entries(false);
+        assert_eq!(report.stale, vec![missing]);
+        assert_eq!(
+            report.tag_mismatches,
+            vec![(
+                changed.clone(),
+                vec![code.clone()],
+                vec![code, rust.clone()]
+            )]
+        );
+        assert_eq!((report.checked, report.skipped), (1, 1));
+        registry.record_verified(&mut report);
+
+        let id = registry.find_entry(&changed).unwrap();
+        registry.tag_entry(&rust, id);
+        let mut report = registry.verify_entries(false);
+        assert!(report.tag_mismatches.is_empty());
+        assert_eq!((report.checked, report.skipped), (1, 1));
+        registry.record_verified(&mut report);
+
+        let report = registry.verify_entries(true);
+        assert_eq!((report.checked, report.skipped), (2, 0));
+    }
+
+    #[test]
+    fn searches_entries_by_path_fragment() {
+        let mut registry = TagRegistry::default();
+        let (report, _) = registry.add_or_update_entry(EntryData::new("/home/user/Reports/q1.pdf"));
+        registry.add_or_update_entry(EntryData::new("/home/user/notes.txt"));
+        registry.add_or_update_entry(EntryData::new("/srv/report"));
+        let paths = |registry: &TagRegistry, fragment| {
+            registry
+                .search_entries_by_path_fragment(fragment)
+                .into_iter()
+                .map(|entry| entry.path().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            paths(&registry, "REPORT"),
+            vec!["/home/user/Reports/q1.pdf", "/srv/report"]
+        );
+        assert_eq!(paths(&registry, "s/"), vec!["/home/user/Reports/q1.pdf"]);
+        assert!(paths(&registry, "missing").is_empty());
+
+        registry.update_entry_path(report, "/home/user/archive/q1.pdf");
+        assert_eq!(paths(&registry, "report"), vec!["/srv/report"]);
+        assert_eq!(
+            paths(&registry, "archive"),
+            vec!["/home/user/archive/q1.pdf"]
+        );
+
+        registry.remove_entry(report);
+        assert!(paths(&registry, "archive").is_empty());
+        assert_eq!(paths(&registry, "").len(), 2);
+    }
+
+    #[test]
+    fn expires_tags() {
+        let mut registry = TagRegistry::default();
+        let (id, _) = registry.add_or_update_entry(EntryData::new("/tmp/report.pdf"));
+        let review = Tag::new("review", Red);
+        let keep = Tag::new("keep", Red);
+        registry.tag_entry(&review, id);
+        registry.tag_entry(&keep, id);
+
+        let now = Utc::now();
+        registry.set_tag_expiry(&review, id, Some(now + chrono::Duration::days(7)));
+        assert!(registry.list_expired_tags(now).is_empty());
+        assert_eq!(
+            registry.list_expired_tags(now + chrono::Duration::days(8)),
+            vec![(id, review.clone())]
+        );
+
+        registry.rename_tags(&[("review", "reviewed")]);
+        assert_eq!(registry.tag_expiry("review", id), None);
+        assert!(registry.tag_expiry("reviewed", id).is_some());
+
+        let reviewed = registry.get_tag("reviewed").unwrap().clone();
+        registry.untag_entry(&reviewed, id);
+        assert_eq!(registry.entry_expiries(id), None);
+
+        registry.set_tag_expiry(&keep, id, Some(now));
+        registry.set_tag_expiry(&keep, id, None);
+        assert_eq!(registry.entry_expiries(id), None);
+
+        registry.set_tag_expiry(&keep, id, Some(now));
+        let pruned = registry.prune_expired_tags(now);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].0.path(), Path::new("/tmp/report.pdf"));
+        assert_eq!(pruned[0].1, keep);
+        assert!(registry.get_entry(id).is_none());
+    }
+
+    #[test]
+    fn rotates_backups() {
+        let dir = tempdir::TempDir::new("wutag-registry-backup").unwrap();
+        let mut registry = TagRegistry::new(dir.path().join("wutag.db"));
+        for n in 0..4 {
+            registry.add_or_update_entry(EntryData::new(format!("/tmp/{n}")));
+            let backup = registry.save_backup("nightly", 3).unwrap();
+            assert_eq!(backup, dir.path().join("wutag.db.nightly.1"));
+        }
+        let entries = |n: usize| {
+            TagRegistry::load(dir.path().join(format!("wutag.db.nightly.{n}")))
+                .unwrap()
+                .list_entries()
+                .count()
+        };
+        assert_eq!((entries(1), entries(2), entries(3)), (4, 3, 2));
+        assert!(!dir.path().join("wutag.db.nightly.4").exists());
+    }
+
+    #[test]
+    fn absolutizes_relative_paths() {
+        let dir = tempdir::TempDir::new("wutag-registry-absolutize").unwrap();
+        for name in ["moved", "merged"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let mut registry = TagRegistry::default();
+        let mut add = |path: PathBuf, tag: &str| {
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            registry.tag_entry(&Tag::new(tag, Red), id);
+        };
+        add("moved".into(), "a");
+        add("merged".into(), "b");
+        add(dir.path().join("merged"), "c");
+        add("missing".into(), "d");
+        let created_at = registry
+            .get_entry(registry.find_entry("merged").unwrap())
+            .unwrap()
+            .created_at();
+
+        let migration = registry.absolutize_paths(dir.path());
+        assert_eq!((migration.rewritten, migration.merged), (2, 1));
+        assert_eq!(migration.unresolved, vec![PathBuf::from("missing")]);
+        assert!(registry.find_entry(dir.path().join("moved")).is_some());
+        assert!(registry.find_entry("merged").is_none());
+        let merged = registry.find_entry(dir.path().join("merged")).unwrap();
+        let mut tags = registry.list_entry_tags(merged).unwrap();
+        tags.sort_unstable();
+        assert_eq!(tags, vec![&Tag::new("b", Red), &Tag::new("c", Red)]);
+        assert_eq!(registry.get_entry(merged).unwrap().created_at(), created_at);
+        assert_eq!(registry.list_entries().count(), 3);
+    }
+
+    #[test]
+    fn finds_entries_by_tag_prefix() {
+        let mut registry = TagRegistry::default();
+        let mut add = |path: &str, tags: &[&str]| {
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            for tag in tags {
+                registry.tag_entry(&Tag::new(*tag, Red), id);
+            }
+            id
+        };
+        let frontend = add("/src/app.ts", &["project/frontend", "lang/ts"]);
+        let backend = add("/src/main.rs", &["project/backend", "lang/rust"]);
+        let both = add("/Makefile", &["project/frontend", "project/devops"]);
+        add("/notes.txt", &["projects", "personal"]);
+
+        let names = |tags: Vec<&Tag>| {
+            tags.into_iter()
+                .map(|t| t.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(registry.list_tags_with_prefix("project/")),
+            vec!["project/backend", "project/devops", "project/frontend"]
+        );
+        assert_eq!(
+            names(registry.list_tags_with_prefix("lang/")),
+            vec!["lang/rust", "lang/ts"]
+        );
+        assert!(registry.list_tags_with_prefix("work/").is_empty());
+
+        let mut found: Vec<_> = registry
+            .find_entries_with_tag_prefix("project/")
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        found.sort_unstable();
+        let mut expected = vec![frontend, backend, both];
+        expected.sort_unstable();
+        assert_eq!(found, expected);
+
+        let found = registry.find_entries_with_tag_prefix("project/devops");
+        assert_eq!(found, vec![(both, &EntryData::new("/Makefile"))]);
+        assert_eq!(registry.find_entries_with_tag_prefix("project").len(), 4);
+    }
+
+    #[test]
+    fn spreads_colors_of_new_tags_across_palette() {
+        let palette = [Red, Green, Blue];
+        let mut registry = TagRegistry::default();
+        assert_eq!(registry.next_color(&palette), Red);
+        assert_eq!(registry.next_color(&[]), DEFAULT_COLOR);
+
+        let (id, _) = registry.add_or_update_entry(EntryData::new("/tmp"));
+        for (name, color) in [("a", Red), ("b", Red), ("c", Blue), ("d", Yellow)] {
+            registry.tag_entry(&Tag::new(name, color), id);
+        }
+        assert_eq!(registry.next_color(&palette), Green);
+        assert_eq!(
+            registry.next_colors(&palette).take(4).collect::<Vec<_>>(),
+            vec![Green, Green, Blue, Red]
+        );
+
+        for (name, color) in registry.next_colors(&palette).take(6).enumerate() {
+            registry.tag_entry(&Tag::new(format!("new{name}"), color), id);
+        }
+        let used = |color| registry.list_tags().filter(|t| *t.color() == color).count();
+        assert_eq!((used(Red), used(Green), used(Blue)), (3, 3, 3));
+    }
+
+    #[test]
+    fn keeps_original_name_of_renamed_entries() {
+        let mut registry = TagRegistry::default();
+        let (id, _) = registry.add_or_update_entry(EntryData::new("/notes/draft.txt"));
+        assert!(registry.update_entry_path(id, "/notes/final.txt"));
+        assert!(!registry.update_entry_path(id + 1, "/notes/other.txt"));
+
+        // tagging the file again under its new name doesn't reset the original name
+        let (same, created) = registry.add_or_update_entry(EntryData::new("/notes/final.txt"));
+        assert_eq!((same, created), (id, false));
+        let entry = registry.get_entry(id).unwrap();
+        assert_eq!(entry.path(), Path::new("/notes/final.txt"));
+        assert_eq!(entry.original_name(), Some(OsStr::new("draft.txt")));
+
+        registry.add_or_update_entry(EntryData::new("/other/draft.txt"));
+        let found: Vec<_> = registry
+            .find_entries_by_original_name("draft.txt")
+            .into_iter()
+            .map(|(_, e)| e.path().to_path_buf())
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("/notes/final.txt"),
+                PathBuf::from("/other/draft.txt")
+            ]
+        );
+        assert!(registry
+            .find_entries_by_original_name("final.txt")
+            .is_empty());
+    }
+
+    #[test]
+    fn evicts_entries_of_missing_files() {
+        let dir = tempdir::TempDir::new("wutag-evict").unwrap();
+        let kept = dir.path().join("kept");
+        let missing = dir.path().join("missing");
+        fs::write(&kept, b"").unwrap();
+        let tag = Tag::new("evicted", Red);
+        let other = Tag::new("other", Blue);
+        let mut registry = TagRegistry::default();
+        let (kept_id, _) = registry.add_or_update_entry(EntryData::new(&kept));
+        let (missing_id, _) = registry.add_or_update_entry(EntryData::new(&missing));
+        registry.tag_entry(&tag, kept_id);
+        registry.tag_entry(&tag, missing_id);
+        registry.tag_entry(&other, missing_id);
+
+        let evicted = registry.evict_stale_entries();
+        assert_eq!(evicted, vec![EntryData::new(&missing)]);
+        assert!(registry.get_entry(missing_id).is_none());
+        assert_eq!(registry.list_entry_tags(kept_id), Some(vec![&tag]));
+        assert!(registry.get_tag("other").is_none());
+        assert!(registry.tag_counts_consistent());
+        assert!(registry.evict_stale_entries().is_empty());
     }
 }
@@ -0,0 +1,316 @@
+//! Registry split into multiple files for very large installations
+use super::{new_entry_id, EntryData, EntryId, RegistryError, Result, TagRegistry};
+use crate::tag::Tag;
+
+use chrono::Utc;
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Registry that splits entries into shards by `entry_id % shard_count`. Each shard is a
+/// [TagRegistry](TagRegistry) stored in a separate file `{path}.shard.{n}` containing the
+/// entries of the shard along with their tags. Shards are loaded lazily on first access and only
+/// modified shards are saved.
+///
+/// Operations on a single entry only touch the shard of the entry while operations like
+/// [find_entry](ShardedTagRegistry::find_entry) or
+/// [list_entries](ShardedTagRegistry::list_entries) load all shards. The shard count has to stay
+/// the same for the whole lifetime of the registry.
+pub struct ShardedTagRegistry {
+    path: PathBuf,
+    shards: Vec<OnceLock<TagRegistry>>,
+    dirty: Vec<bool>,
+}
+
+impl ShardedTagRegistry {
+    /// Creates a new empty registry with `shard_count` shards stored at `path` without loading
+    /// any existing shards.
+    pub fn new<P: AsRef<Path>>(path: P, shard_count: usize) -> Self {
+        let registry = Self::load(path, shard_count);
+        for (n, shard) in registry.shards.iter().enumerate() {
+            let _ = shard.set(TagRegistry::new(registry.shard_path(n)));
+        }
+        registry
+    }
+
+    /// Opens a registry with `shard_count` shards stored at `path`. Shards are loaded on first
+    /// access, shards without a file are empty.
+    pub fn load<P: AsRef<Path>>(path: P, shard_count: usize) -> Self {
+        let shard_count = shard_count.max(1);
+        Self {
+            path: path.as_ref().to_path_buf(),
+            shards: (0..shard_count).map(|_| OnceLock::new()).collect(),
+            dirty: vec![false; shard_count],
+        }
+    }
+
+    /// Returns the base path of the shards of this registry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the number of shards that are currently loaded.
+    pub fn loaded_shards(&self) -> usize {
+        self.shards.iter().filter(|s| s.get().is_some()).count()
+    }
+
+    /// Returns the path of the file of the `n`th shard.
+    pub fn shard_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(format!(".shard.{n}"));
+        PathBuf::from(path)
+    }
+
+    fn shard_of(&self, entry: EntryId) -> usize {
+        entry % self.shards.len()
+    }
+
+    fn shard(&self, n: usize) -> Result<&TagRegistry> {
+        if let Some(shard) = self.shards[n].get() {
+            return Ok(shard);
+        }
+        let path = self.shard_path(n);
+        let shard = match TagRegistry::load(&path) {
+            Ok(shard) => shard,
+            Err(RegistryError::LoadRegistry(e)) if e.kind() == io::ErrorKind::NotFound => {
+                TagRegistry::new(path)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(self.shards[n].get_or_init(|| shard))
+    }
+
+    fn shard_mut(&mut self, n: usize) -> Result<&mut TagRegistry> {
+        self.shard(n)?;
+        self.dirty[n] = true;
+        Ok(self.shards[n].get_mut().expect("loaded shard"))
+    }
+
+    fn all_shards(&self) -> Result<Vec<&TagRegistry>> {
+        (0..self.shards.len()).map(|n| self.shard(n)).collect()
+    }
+
+    /// Saves all shards modified since they were loaded or last saved.
+    pub fn save(&mut self) -> Result<()> {
+        for (n, dirty) in self.dirty.iter_mut().enumerate() {
+            if let (true, Some(shard)) = (*dirty, self.shards[n].get()) {
+                shard.save()?;
+                *dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the entry or adds it if it is not present.
+    pub fn add_or_update_entry(&mut self, mut entry: EntryData) -> Result<(EntryId, bool)> {
+        entry.last_accessed = Utc::now();
+        if let Some(id) = self.find_entry(entry.path())? {
+            if let Some(existing) = self.get_entry(id)? {
+                entry.created_at = existing.created_at;
+                if existing.original_name.is_some() {
+                    entry.original_name = existing.original_name.clone();
+                }
+            }
+            let n = self.shard_of(id);
+            self.shard_mut(n)?.insert_entry(id, entry);
+            return Ok((id, false));
+        }
+        entry.created_at = Utc::now();
+        let id = new_entry_id();
+        let n = self.shard_of(id);
+        self.shard_mut(n)?.insert_entry(id, entry);
+        Ok((id, true))
+    }
+
+    /// Adds the `tag` to an entry with `entry` id. Returns the id if the entry was already tagged
+    /// or `None` if the tag was added.
+    pub fn tag_entry(&mut self, tag: &Tag, entry: EntryId) -> Result<Option<EntryId>> {
+        let n = self.shard_of(entry);
+        Ok(self.shard_mut(n)?.tag_entry(tag, entry))
+    }
+
+    /// Removes the `tag` from an entry with `entry` id. Returns the entry data if it has no tags
+    /// left or `None` otherwise.
+    pub fn untag_entry(&mut self, tag: &Tag, entry: EntryId) -> Result<Option<EntryData>> {
+        let n = self.shard_of(entry);
+        Ok(self.shard_mut(n)?.untag_entry(tag, entry))
+    }
+
+    pub fn remove_entry(&mut self, entry: EntryId) -> Result<Option<EntryData>> {
+        let n = self.shard_of(entry);
+        Ok(self.shard_mut(n)?.remove_entry(entry))
+    }
+
+    /// Clears all tags of the `entry`.
+    pub fn clear_entry(&mut self, entry: EntryId) -> Result<()> {
+        let n = self.shard_of(entry);
+        self.shard_mut(n)?.clear_entry(entry);
+        Ok(())
+    }
+
+    /// Finds the entry by a `path`. Returns the id of the entry if found.
+    pub fn find_entry<P: AsRef<Path>>(&self, path: P) -> Result<Option<EntryId>> {
+        for shard in self.all_shards()? {
+            if let Some(id) = shard.find_entry(path.as_ref()) {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn get_entry(&self, entry: EntryId) -> Result<Option<&EntryData>> {
+        Ok(self.shard(self.shard_of(entry))?.get_entry(entry))
+    }
+
+    /// Lists tags of the `entry` if such entry exists.
+    pub fn list_entry_tags(&self, entry: EntryId) -> Result<Option<Vec<&Tag>>> {
+        Ok(self.shard(self.shard_of(entry))?.list_entry_tags(entry))
+    }
+
+    /// Lists data of all entries present in the registry.
+    pub fn list_entries(&self) -> Result<impl Iterator<Item = &EntryData>> {
+        Ok(self
+            .all_shards()?
+            .into_iter()
+            .flat_map(|shard| shard.list_entries()))
+    }
+
+    /// Lists all tags of the registry, tags present in multiple shards are returned once.
+    pub fn list_tags(&self) -> Result<Vec<&Tag>> {
+        let mut names = BTreeSet::new();
+        Ok(self
+            .all_shards()?
+            .into_iter()
+            .flat_map(|shard| shard.list_tags())
+            .filter(|tag| names.insert(tag.name()))
+            .collect())
+    }
+
+    /// Returns entries that have any tag of the `tags`.
+    pub fn list_entries_with_any_tags<S: AsRef<str>>(&self, tags: &[S]) -> Result<Vec<EntryId>> {
+        Ok(self
+            .all_shards()?
+            .into_iter()
+            .flat_map(|shard| shard.list_entries_with_any_tags(tags))
+            .collect())
+    }
+
+    /// Returns entries that have all of the `tags`.
+    pub fn list_entries_with_all_tags<S: AsRef<str>>(&self, tags: &[S]) -> Result<Vec<EntryId>> {
+        let mut entries = vec![];
+        for shard in self.all_shards()? {
+            for id in shard.list_entries_with_any_tags(tags) {
+                let entry_tags = shard.list_entry_tags(id).unwrap_or_default();
+                if tags
+                    .iter()
+                    .all(|name| entry_tags.iter().any(|tag| tag.name() == name.as_ref()))
+                {
+                    entries.push(id);
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn routes_entries_to_shards() {
+        let dir = tempdir::TempDir::new("wutag-sharded").unwrap();
+        let path = dir.path().join("wutag.db");
+        let work = Tag::new("work", Color::Red);
+        let urgent = Tag::new("urgent", Color::Blue);
+
+        let mut registry = ShardedTagRegistry::new(&path, 4);
+        let mut ids = vec![];
+        for i in 0..8 {
+            let (id, added) = registry
+                .add_or_update_entry(EntryData::new(format!("/tmp/{i}")))
+                .unwrap();
+            assert!(added);
+            registry.tag_entry(&work, id).unwrap();
+            if i % 2 == 0 {
+                registry.tag_entry(&urgent, id).unwrap();
+            }
+            ids.push(id);
+        }
+        let (id, added) = registry
+            .add_or_update_entry(EntryData::new("/tmp/0"))
+            .unwrap();
+        assert_eq!((id, added), (ids[0], false));
+        registry.save().unwrap();
+
+        let saved: Vec<_> = (0..4)
+            .filter(|n| registry.shard_path(*n).exists())
+            .collect();
+        let used: BTreeSet<_> = ids.iter().map(|id| id % 4).collect();
+        assert_eq!(saved, used.into_iter().collect::<Vec<_>>());
+
+        let registry = ShardedTagRegistry::load(&path, 4);
+        assert_eq!(registry.loaded_shards(), 0);
+        assert_eq!(
+            registry.get_entry(ids[3]).unwrap(),
+            Some(&EntryData::new("/tmp/3"))
+        );
+        assert_eq!(registry.loaded_shards(), 1);
+
+        assert_eq!(registry.find_entry("/tmp/5").unwrap(), Some(ids[5]));
+        assert_eq!(registry.loaded_shards(), 4);
+        assert_eq!(registry.list_entries().unwrap().count(), 8);
+        assert_eq!(registry.list_tags().unwrap().len(), 2);
+
+        let mut both = registry
+            .list_entries_with_all_tags(&["work", "urgent"])
+            .unwrap();
+        both.sort_unstable();
+        let mut expected: Vec<_> = ids.iter().step_by(2).copied().collect();
+        expected.sort_unstable();
+        assert_eq!(both, expected);
+    }
+
+    #[test]
+    fn saves_only_modified_shards() {
+        let dir = tempdir::TempDir::new("wutag-sharded-dirty").unwrap();
+        let path = dir.path().join("wutag.db");
+        let mut registry = ShardedTagRegistry::new(&path, 3);
+        let mut ids = vec![];
+        for i in 0..16 {
+            let (id, _) = registry
+                .add_or_update_entry(EntryData::new(format!("/tmp/{i}")))
+                .unwrap();
+            ids.push(id);
+        }
+        registry.save().unwrap();
+        let modified = ids[0];
+        let read = *ids
+            .iter()
+            .find(|id| *id % 3 != modified % 3)
+            .expect("entries in multiple shards");
+
+        let mut registry = ShardedTagRegistry::load(&path, 3);
+        registry
+            .tag_entry(&Tag::new("dirty", Color::Red), modified)
+            .unwrap();
+        assert!(registry.get_entry(read).unwrap().is_some());
+        assert_eq!(registry.loaded_shards(), 2);
+        for n in 0..3 {
+            let _ = std::fs::remove_file(registry.shard_path(n));
+        }
+        registry.save().unwrap();
+
+        let saved: Vec<_> = (0..3)
+            .filter(|n| registry.shard_path(*n).exists())
+            .collect();
+        assert_eq!(saved, vec![modified % 3]);
+    }
+}
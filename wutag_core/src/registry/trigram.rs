@@ -0,0 +1,95 @@
+//! Index of trigrams of entry paths speeding up searches for path fragments.
+use super::EntryId;
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// Maps every sequence of three consecutive characters of lowercase entry paths to the entries
+/// containing it.
+#[derive(Debug, Default)]
+pub(crate) struct TrigramIndex {
+    trigrams: HashMap<[char; 3], BTreeSet<EntryId>>,
+}
+
+/// Returns unique trigrams of the lowercase `text`.
+fn trigrams(text: &str) -> BTreeSet<[char; 3]> {
+    let chars: Vec<_> = text.to_lowercase().chars().collect();
+    chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+impl TrigramIndex {
+    pub fn insert(&mut self, id: EntryId, path: &Path) {
+        for trigram in trigrams(&path.to_string_lossy()) {
+            self.trigrams.entry(trigram).or_default().insert(id);
+        }
+    }
+
+    pub fn remove(&mut self, id: EntryId, path: &Path) {
+        for trigram in trigrams(&path.to_string_lossy()) {
+            if let Some(entries) = self.trigrams.get_mut(&trigram) {
+                entries.remove(&id);
+                if entries.is_empty() {
+                    self.trigrams.remove(&trigram);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.trigrams.clear();
+    }
+
+    /// Returns entries whose paths contain all trigrams of the `fragment`, a superset of entries
+    /// containing the fragment itself. Returns `None` if the fragment is shorter than a trigram
+    /// and every entry has to be checked.
+    pub fn candidates(&self, fragment: &str) -> Option<BTreeSet<EntryId>> {
+        let mut candidates: Option<BTreeSet<EntryId>> = None;
+        for trigram in trigrams(fragment) {
+            let Some(entries) = self.trigrams.get(&trigram) else {
+                return Some(BTreeSet::new());
+            };
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(entries).copied().collect(),
+                None => entries.clone(),
+            });
+        }
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> TrigramIndex {
+        let mut index = TrigramIndex::default();
+        index.insert(1, Path::new("/home/user/Reports/q1.pdf"));
+        index.insert(2, Path::new("/home/user/notes.txt"));
+        index.insert(3, Path::new("/srv/report"));
+        index
+    }
+
+    #[test]
+    fn finds_candidates_of_fragments() {
+        let index = index();
+        assert_eq!(index.candidates("report"), Some(BTreeSet::from([1, 3])));
+        assert_eq!(index.candidates("REPORT"), Some(BTreeSet::from([1, 3])));
+        assert_eq!(index.candidates("/home/user"), Some(BTreeSet::from([1, 2])));
+        assert_eq!(index.candidates("missing"), Some(BTreeSet::new()));
+        // too short to have a trigram
+        assert_eq!(index.candidates("q1"), None);
+    }
+
+    #[test]
+    fn removes_entries() {
+        let mut index = index();
+        index.remove(3, Path::new("/srv/report"));
+        assert_eq!(index.candidates("report"), Some(BTreeSet::from([1])));
+        // trigrams only the removed entry had are dropped
+        assert!(!index.trigrams.contains_key(&['s', 'r', 'v']));
+
+        index.clear();
+        assert!(index.trigrams.is_empty());
+        assert_eq!(index.candidates("notes"), Some(BTreeSet::new()));
+    }
+}
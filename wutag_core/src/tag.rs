@@ -1,23 +1,120 @@
 //! Functions for manipulating tags on files.
+use chrono::{DateTime, Utc};
+use lru::LruCache;
 use rand::prelude::*;
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
+use std::fs;
 use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use unicode_normalization::UnicodeNormalization;
 
 use crate::color::Color;
-use crate::xattr::{list_xattrs, remove_xattr, set_xattr, Xattr};
+use crate::registry::{EntryId, TagRegistry};
+use crate::xattr::{get_xattr, list_xattrs, remove_xattr, replace_xattr, set_xattr, Xattr};
 use crate::{Error, Result, WUTAG_NAMESPACE};
 
 pub const DEFAULT_COLOR: Color = Color::BrightWhite;
+/// Number of times reading attributes of a file is retried when they change while being read.
+const ATTRS_CHANGED_RETRIES: usize = 3;
+pub const DEFAULT_WEIGHT: f32 = 1.0;
+/// Range of valid tag weights.
+pub const WEIGHT_RANGE: RangeInclusive<f32> = 0.0..=10.0;
 
-#[derive(Clone, Debug, Deserialize, Eq, Serialize)]
+fn default_weight() -> f32 {
+    DEFAULT_WEIGHT
+}
+
+/// Data stored in the value of the extended attribute of a tag. Empty fields are left out so
+/// that the values of tags without a description or metadata take up only a few bytes of the
+/// limited space for extended attributes of a file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct TagValue {
+    /// Color of the tag, only set in values written to or read from xattrs while the color of a
+    /// [Tag](Tag) is kept by the tag itself. Values saved by older versions have no color.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<crate::color::ColorSchema>")]
+    pub color: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+}
+
+impl TagValue {
+    /// Encodes this value as base64 of its CBOR representation.
+    pub fn encode(&self) -> Result<String> {
+        serde_cbor::to_vec(self)
+            .map(base64::encode)
+            .map_err(Error::from)
+    }
+
+    /// Decodes a value previously encoded with [TagValue::encode](TagValue::encode).
+    pub fn decode<S: AsRef<str>>(value: S) -> Result<Self> {
+        let bytes = base64::decode(value.as_ref().as_bytes())?;
+        serde_cbor::from_slice(&bytes).map_err(Error::from)
+    }
+
+    /// Checks whether the xattr `value` was saved by an older version, either empty, without a
+    /// color or with empty fields encoded, and should be rewritten in the current format.
+    fn is_outdated<S: AsRef<str>>(value: S) -> bool {
+        use serde_cbor::Value;
+
+        let value = value.as_ref();
+        if value.is_empty() {
+            return true;
+        }
+        let Ok(bytes) = base64::decode(value.as_bytes()) else {
+            return false;
+        };
+        match serde_cbor::from_slice(&bytes) {
+            Ok(Value::Map(fields)) => {
+                !fields.contains_key(&Value::Text("color".into()))
+                    || fields.values().any(|field| match field {
+                        Value::Null => true,
+                        Value::Map(map) => map.is_empty(),
+                        _ => false,
+                    })
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Tag {
+    #[serde(deserialize_with = "deserialize_name")]
     name: String,
+    #[schemars(with = "crate::color::ColorSchema")]
     color: Color,
+    /// Importance of the tag used to rank search results. Only stored in the registry as tags
+    /// are identified by their name on disk.
+    #[serde(default = "default_weight")]
+    weight: f32,
+    /// System tags are protected from being removed from files unless forced. Only stored in
+    /// the registry like the weight.
+    #[serde(default)]
+    system: bool,
+    #[serde(default)]
+    value: TagValue,
+}
+
+/// Deserializes a tag name normalizing it like [Tag::new](Tag::new).
+fn deserialize_name<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    String::deserialize(deserializer).map(normalize_name)
 }
 
 impl Hash for Tag {
@@ -27,16 +124,29 @@ impl Hash for Tag {
 }
 
 impl Tag {
+    /// Creates a new tag. The name is normalized to Unicode NFC form so that the same name
+    /// entered on different platforms results in the same tag.
     pub fn new<S>(name: S, color: Color) -> Self
     where
         S: Into<String>,
     {
         Tag {
-            name: name.into(),
+            name: normalize_name(name.into()),
             color,
+            weight: DEFAULT_WEIGHT,
+            system: false,
+            value: TagValue::default(),
         }
     }
 
+    /// Creates a new tag with a name normalized to Unicode NFC form and converted to lowercase.
+    pub fn new_folded<S>(name: S, color: Color) -> Self
+    where
+        S: Into<String>,
+    {
+        Tag::new(fold_name(name.into()), color)
+    }
+
     pub fn random<S>(name: S, colors: &[Color]) -> Self
     where
         S: Into<String>,
@@ -52,6 +162,10 @@ impl Tag {
         &self.name
     }
 
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = normalize_name(name.into());
+    }
+
     pub fn color(&self) -> &Color {
         &self.color
     }
@@ -60,36 +174,206 @@ impl Tag {
         self.color = *color;
     }
 
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    /// Sets the weight of this tag clamping it to [WEIGHT_RANGE](WEIGHT_RANGE).
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight.clamp(*WEIGHT_RANGE.start(), *WEIGHT_RANGE.end());
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.system
+    }
+
+    pub fn set_system(&mut self, system: bool) {
+        self.system = system;
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.value.description.as_deref()
+    }
+
+    pub fn set_description<S: Into<String>>(&mut self, description: Option<S>) {
+        self.value.description = description.map(Into::into);
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.value.metadata
+    }
+
+    pub fn set_metadata<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.value.metadata.insert(key.into(), value.into());
+    }
+
+    pub fn value(&self) -> &TagValue {
+        &self.value
+    }
+
     fn hash(&self) -> String {
         format!("{}.{}", WUTAG_NAMESPACE, base64::encode(&self.name))
     }
 
-    /// Tags the file at the given `path` with this tag. If the tag exists returns an error.
-    pub fn save_to<P>(&self, path: P) -> Result<()>
+    /// Encodes the value of the xattr of this tag, the key only holds the name of the tag.
+    fn encode_value(&self) -> Result<String> {
+        TagValue {
+            color: Some(self.color),
+            ..self.value.clone()
+        }
+        .encode()
+    }
+
+    /// Tags the file at the given `path` with this tag. If the tag exists returns an error. If
+    /// `path` is a symlink and `follow` is `false` the link itself is tagged instead of its target.
+    pub fn save_to<P>(&self, path: P, follow: bool) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        for tag in list_tags(path.as_ref())? {
-            if &tag == self {
-                return Err(Error::TagExists);
+        let path = path.as_ref();
+        let tags = retry_attrs_changed(path, || {
+            if follow {
+                list_tags_cached(path)
+            } else {
+                list_tags(path, false)
             }
+        })?;
+        if tags.contains(self) {
+            return Err(Error::TagExists);
         }
-        set_xattr(path, self.hash().as_str(), "")
+        let result = set_xattr(path, self.hash(), self.encode_value()?, follow);
+        invalidate_cached(path);
+        result
+    }
+
+    /// Saves the `color` as the color of this tag in the file at the given `path` keeping the
+    /// rest of its saved value. If the file doesn't have this tag returns
+    /// [Error::TagNotFound](wutag::Error::TagNotFound).
+    pub fn recolor_in<P>(&self, path: P, color: Color, follow: bool) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut saved = retry_attrs_changed(path, || list_tags(path, follow))?
+            .into_iter()
+            .find(|tag| tag == self)
+            .ok_or_else(|| Error::TagNotFound(self.name.clone()))?;
+        saved.color = color;
+        let value = saved.encode_value()?;
+        invalidate_cached(path);
+        replace_xattr(path, saved.hash(), value, follow)
+    }
+
+    /// Rewrites tags of the file at the given `path` saved by older versions with an empty or
+    /// verbose xattr value or under a key that isn't derived from the normalized name of the tag
+    /// to the current format. Returns the number of migrated tags.
+    ///
+    /// A tag is never lost when its new value doesn't fit. A value under the same key is
+    /// replaced in place and a tag moving to a new key is only removed from the old key once the
+    /// new one is written.
+    pub fn migrate_xattr<P>(path: P, follow: bool) -> Result<usize>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut migrated = 0;
+        for xattr in list_xattrs(path, follow)? {
+            if !xattr.key().starts_with(WUTAG_NAMESPACE) {
+                continue;
+            }
+            let key = xattr.key().to_string();
+            let outdated = TagValue::is_outdated(xattr.val());
+            let tag = match Tag::try_from(xattr) {
+                Ok(tag) => tag,
+                Err(_) => continue,
+            };
+            let new_key = tag.hash();
+            if !outdated && new_key == key {
+                continue;
+            }
+            let value = tag.encode_value()?;
+            invalidate_cached(path);
+            if new_key == key {
+                replace_xattr(path, key, value, follow)?;
+            } else {
+                match set_xattr(path, new_key, value, follow) {
+                    // the tag is already saved under the new key, only the old copy is left
+                    Ok(()) | Err(Error::TagExists) => {}
+                    Err(e) => return Err(e),
+                }
+                remove_xattr(path, key, follow)?;
+            }
+            migrated += 1;
+        }
+        Ok(migrated)
+    }
+
+    /// Moves the tag with the `name` of the file at the given `path` from the `old_namespace` like
+    /// `user.oldwutag` to the `new_namespace`. The value is written under the new key before the
+    /// old key is removed so the tag is never lost. If the tag already exists in the new namespace
+    /// returns [Error::TagExists](wutag::Error::TagExists) and leaves the old key in place.
+    pub fn move_tag<P: AsRef<Path>>(
+        path: P,
+        old_namespace: &str,
+        new_namespace: &str,
+        name: &str,
+    ) -> Result<()> {
+        let key = base64::encode(name);
+        move_xattr(
+            path.as_ref(),
+            &format!("{old_namespace}.{key}"),
+            &format!("{new_namespace}.{key}"),
+        )
+        .map_err(|e| match e {
+            Error::TagNotFound(_) => Error::TagNotFound(name.to_string()),
+            e => e,
+        })
+    }
+
+    /// Moves all tags of the file at the given `path` from the `old_namespace` to the
+    /// `new_namespace` keeping their keys. Returns the number of moved tags.
+    pub fn move_namespace<P: AsRef<Path>>(
+        path: P,
+        old_namespace: &str,
+        new_namespace: &str,
+    ) -> Result<usize> {
+        let path = path.as_ref();
+        let prefix = format!("{old_namespace}.");
+        let mut moved = 0;
+        for xattr in list_xattrs(path, true)? {
+            if let Some(key) = xattr.key().strip_prefix(&prefix) {
+                move_xattr(path, xattr.key(), &format!("{new_namespace}.{key}"))?;
+                moved += 1;
+            }
+        }
+        Ok(moved)
     }
 
     /// Removes this tag from the file at the given `path`. If the tag doesn't exists returns
-    /// [Error::TagNotFound](wutag::Error::TagNotFound)
-    pub fn remove_from<P>(&self, path: P) -> Result<()>
+    /// [Error::TagNotFound](wutag::Error::TagNotFound). Symlinks are handled like in
+    /// [save_to](Tag::save_to).
+    pub fn remove_from<P>(&self, path: P, follow: bool) -> Result<()>
     where
         P: AsRef<Path>,
     {
+        let path = path.as_ref();
         let hash = self.hash();
 
-        for xattr in list_xattrs(path.as_ref())? {
-            let key = xattr.key();
-            // make sure to only remove attributes corresponding to this namespace
-            if key == hash {
-                return remove_xattr(path, key);
+        for xattr in retry_attrs_changed(path, || list_xattrs(path, follow))? {
+            let key = xattr.key().to_string();
+            // make sure to only remove attributes corresponding to this namespace, tags saved
+            // under a key of a name that isn't normalized are matched by their decoded name
+            let matches = key == hash
+                || (key.starts_with(WUTAG_NAMESPACE)
+                    && Tag::try_from(xattr).is_ok_and(|tag| tag.name == self.name));
+            if matches {
+                let result = remove_xattr(path, key, follow);
+                invalidate_cached(path);
+                return result;
             }
         }
 
@@ -120,9 +404,11 @@ impl PartialEq for Tag {
     }
 }
 
+impl Eq for Tag {}
+
 impl PartialOrd for Tag {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.name.partial_cmp(&other.name)
+        Some(self.cmp(other))
     }
 }
 
@@ -133,6 +419,101 @@ macro_rules! next_or_else {
     };
 }
 
+/// Copies the value of the xattr `old_key` to a new xattr `new_key` and only then removes the old
+/// one. Fails without changes if `new_key` already exists.
+fn move_xattr(path: &Path, old_key: &str, new_key: &str) -> Result<()> {
+    invalidate_cached(path);
+    let value = get_xattr(path, old_key, true)?;
+    set_xattr(path, new_key, value.as_str(), true)?;
+    remove_xattr(path, old_key, true)
+}
+
+/// Normalizes the tag `name` to Unicode NFC form.
+pub fn normalize_name<S: AsRef<str>>(name: S) -> String {
+    name.as_ref().nfc().collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PatternToken {
+    Char(char),
+    AnyChar,
+    AnyChars,
+}
+
+/// Pattern matching tag names where `*` matches any number of characters and `?` a single one.
+/// A `\` escapes the following character so that `tmp-\*` only matches the tag `tmp-*` itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagPattern {
+    tokens: Vec<PatternToken>,
+}
+
+impl TagPattern {
+    pub fn new<S: AsRef<str>>(pattern: S) -> Self {
+        let mut tokens = vec![];
+        let mut chars = pattern.as_ref().chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '\\' => PatternToken::Char(chars.next().unwrap_or('\\')),
+                '*' => PatternToken::AnyChars,
+                '?' => PatternToken::AnyChar,
+                c => PatternToken::Char(c),
+            });
+        }
+        Self { tokens }
+    }
+
+    /// Returns the name matched by this pattern with escapes removed if it has no wildcards.
+    pub fn literal(&self) -> Option<String> {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                PatternToken::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the tag `name` matches this pattern.
+    pub fn matches<S: AsRef<str>>(&self, name: S) -> bool {
+        let name: Vec<_> = name.as_ref().chars().collect();
+        // position after the last `*` in the pattern and in the name to backtrack to
+        let mut backtrack = None;
+        let (mut t, mut n) = (0, 0);
+        while n < name.len() {
+            match self.tokens.get(t) {
+                Some(PatternToken::AnyChars) => {
+                    t += 1;
+                    backtrack = Some((t, n));
+                }
+                Some(PatternToken::AnyChar) => {
+                    t += 1;
+                    n += 1;
+                }
+                Some(PatternToken::Char(c)) if *c == name[n] => {
+                    t += 1;
+                    n += 1;
+                }
+                _ => match backtrack {
+                    Some((bt, bn)) => {
+                        backtrack = Some((bt, bn + 1));
+                        t = bt;
+                        n = bn + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        self.tokens[t..]
+            .iter()
+            .all(|token| *token == PatternToken::AnyChars)
+    }
+}
+
+/// Normalizes the tag `name` to Unicode NFC form and converts it to lowercase.
+pub fn fold_name<S: AsRef<str>>(name: S) -> String {
+    normalize_name(name).to_lowercase()
+}
+
 impl TryFrom<Xattr> for Tag {
     type Error = Error;
     fn try_from(xattr: Xattr) -> Result<Self> {
@@ -148,9 +529,26 @@ impl TryFrom<Xattr> for Tag {
             )));
         }
 
-        let tag_bytes = next_or_else!(elems, "missing tag")?;
-        let tag = serde_cbor::from_slice(&base64::decode(tag_bytes.as_bytes())?)?;
+        let tag_bytes = base64::decode(next_or_else!(elems, "missing tag")?.as_bytes())?;
+        let mut tag = match String::from_utf8(tag_bytes) {
+            Ok(name) => Tag::new(name, DEFAULT_COLOR),
+            // tags saved by older versions contain the whole tag serialized as CBOR
+            Err(e) => serde_cbor::from_slice::<Tag>(e.as_bytes())?,
+        };
 
+        // tags saved by older versions have an empty value or a value without the color, the
+        // color is then taken from the key if it holds the whole tag
+        if !xattr.val().is_empty() {
+            match TagValue::decode(xattr.val()) {
+                Ok(mut value) => {
+                    if let Some(color) = value.color.take() {
+                        tag.color = color;
+                    }
+                    tag.value = value;
+                }
+                Err(e) => log::debug!("invalid value of tag `{}` - {}", tag.name, e),
+            }
+        }
         Ok(tag)
     }
 }
@@ -162,7 +560,7 @@ where
 {
     let path = path.as_ref();
     let tag = tag.as_ref();
-    for _tag in list_xattrs(path)?.into_iter().flat_map(Tag::try_from) {
+    for _tag in list_xattrs(path, true)?.into_iter().flat_map(Tag::try_from) {
         if _tag.name == tag {
             return Ok(_tag);
         }
@@ -171,23 +569,347 @@ where
     Err(Error::TagNotFound(tag.to_string()))
 }
 
-/// Lists tags of the file at the given `path`.
-pub fn list_tags<P>(path: P) -> Result<Vec<Tag>>
+/// Lists tags of the file at the given `path`, or of the symlink itself if `follow` is `false`.
+pub fn list_tags<P>(path: P, follow: bool) -> Result<Vec<Tag>>
 where
     P: AsRef<Path>,
 {
-    list_xattrs(path).map(|attrs| {
-        let mut tags = Vec::new();
-        let it = attrs
-            .into_iter()
-            .filter(|xattr| xattr.key().starts_with(WUTAG_NAMESPACE))
-            .map(Tag::try_from);
+    let path = path.as_ref();
+    let mut outdated = false;
+    let tags = list_xattrs(path, follow)?
+        .into_iter()
+        .filter(|xattr| xattr.key().starts_with(WUTAG_NAMESPACE))
+        .flat_map(|xattr| {
+            let (key, empty) = (xattr.key().to_string(), xattr.val().is_empty());
+            let tag = Tag::try_from(xattr)?;
+            outdated |= empty || tag.hash() != key;
+            Ok::<_, Error>(tag)
+        })
+        .collect();
 
-        for tag in it.flatten() {
-            tags.push(tag);
+    // other outdated values are only rewritten by an explicit migration as checking them
+    // requires decoding every value
+    if outdated {
+        if let Err(e) = Tag::migrate_xattr(path, follow) {
+            log::debug!("failed to migrate tags of {} - {}", path.display(), e);
         }
-        tags
-    })
+    }
+
+    Ok(tags)
+}
+
+/// Calls `read` reading attributes of the file at `path` again when it fails with
+/// `Error::AttrsChanged` because another process modified them at the same time, at most
+/// [ATTRS_CHANGED_RETRIES](ATTRS_CHANGED_RETRIES) times.
+fn retry_attrs_changed<T>(path: &Path, mut read: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut retries = 0;
+    loop {
+        match read() {
+            Err(Error::AttrsChanged) if retries < ATTRS_CHANGED_RETRIES => {
+                retries += 1;
+                log::warn!(
+                    "attributes of `{}` changed while reading them, retrying ({retries}/{ATTRS_CHANGED_RETRIES})",
+                    path.display()
+                );
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Default number of files for which tags are kept in a [TagCache](TagCache).
+pub const DEFAULT_TAG_CACHE_CAPACITY: usize = 1024;
+
+/// Cache of tags of recently read files. Entries are invalidated once the file changes.
+pub struct TagCache {
+    cache: LruCache<PathBuf, (SystemTime, Vec<Tag>)>,
+}
+
+impl TagCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            cache: LruCache::new(capacity),
+        }
+    }
+
+    /// Returns the number of files with cached tags.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Removes the cached tags of the file at `path`.
+    pub fn invalidate<P: AsRef<Path>>(&mut self, path: P) {
+        self.cache.pop(path.as_ref());
+    }
+
+    /// Lists tags of the file at the given `path` like [list_tags](list_tags) but returns tags
+    /// stored in this cache if the file didn't change since they were read.
+    pub fn list_tags<P>(&mut self, path: P) -> Result<Vec<Tag>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let changed = change_time(&fs::metadata(path)?)?;
+
+        if let Some((cached_at, tags)) = self.cache.get(path) {
+            if *cached_at == changed {
+                return Ok(tags.clone());
+            }
+        }
+
+        let tags = list_tags(path, true)?;
+        self.cache.put(path.to_path_buf(), (changed, tags.clone()));
+        Ok(tags)
+    }
+}
+
+impl Default for TagCache {
+    fn default() -> Self {
+        Self::new(NonZeroUsize::new(DEFAULT_TAG_CACHE_CAPACITY).expect("non zero capacity"))
+    }
+}
+
+/// Returns the time of the last change of the file. Setting extended attributes doesn't update
+/// the modification time of a file so on unix the status change time is used instead.
+pub(crate) fn change_time(metadata: &fs::Metadata) -> Result<SystemTime> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let ctime = std::time::Duration::new(metadata.ctime() as u64, metadata.ctime_nsec() as u32);
+        Ok(SystemTime::UNIX_EPOCH + ctime)
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.modified().map_err(Error::from)
+    }
+}
+
+thread_local! {
+    /// Tags of files read by this thread, saves reading them again when a request handler
+    /// checks tags of the same files repeatedly.
+    static TAG_CACHE: RefCell<TagCache> = RefCell::new(TagCache::default());
+}
+
+/// Lists tags of the file at the given `path`, following symlinks, using a cache local to the
+/// current thread. Functions of this module modifying tags invalidate the cached tags, changes
+/// made by others are detected by the change time of the file.
+pub fn list_tags_cached<P>(path: P) -> Result<Vec<Tag>>
+where
+    P: AsRef<Path>,
+{
+    TAG_CACHE.with(|cache| cache.borrow_mut().list_tags(path))
+}
+
+/// Removes the tags of the file at `path` from the cache of the current thread used by
+/// [list_tags_cached](list_tags_cached).
+pub fn invalidate_cached<P: AsRef<Path>>(path: P) {
+    TAG_CACHE.with(|cache| cache.borrow_mut().invalidate(path));
+}
+
+/// Number of bytes from the beginning of a file that [TagRule](TagRule)s are matched against.
+pub const AUTO_TAG_READ_SIZE: u64 = 4096;
+
+/// Rule that applies its `tags` to files matching all of the specified criteria.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TagRule {
+    /// Prefix of the MIME type of the file like `image/`.
+    pub mime_prefix: Option<String>,
+    /// Pattern that has to match the content of the file.
+    #[serde(with = "serde_regex")]
+    pub content_pattern: Option<Regex>,
+    pub tags: Vec<Tag>,
+}
+
+impl TagRule {
+    fn matches(&self, mime: Option<&str>, content: &str) -> bool {
+        let mime_matches = self.mime_prefix.as_ref().map_or(true, |prefix| {
+            mime.is_some_and(|mime| mime.starts_with(prefix))
+        });
+        let content_matches = self
+            .content_pattern
+            .as_ref()
+            .map_or(true, |pattern| pattern.is_match(content));
+        mime_matches && content_matches
+    }
+}
+
+mod serde_regex {
+    use regex::Regex;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        regex: &Option<Regex>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        regex.as_ref().map(Regex::as_str).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Regex>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|pattern| Regex::new(&pattern).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// Rule that adds the tag `then_tag` to files tagged with `if_tag`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct ImplicationRule {
+    pub if_tag: String,
+    pub then_tag: String,
+}
+
+/// Returns names of tags implied by `tags` according to `rules`, including tags implied by other
+/// implied tags. Names present in `tags` are not returned and cycles in `rules` are ignored.
+pub fn implied_tags<S: AsRef<str>>(tags: &[S], rules: &[ImplicationRule]) -> Vec<String> {
+    let mut seen: BTreeSet<&str> = tags.iter().map(AsRef::as_ref).collect();
+    let mut pending: Vec<&str> = seen.iter().copied().collect();
+    let mut implied = vec![];
+    while let Some(name) = pending.pop() {
+        for rule in rules.iter().filter(|rule| rule.if_tag == name) {
+            if seen.insert(&rule.then_tag) {
+                implied.push(rule.then_tag.clone());
+                pending.push(&rule.then_tag);
+            }
+        }
+    }
+    implied
+}
+
+/// Predicate selecting entries of a registry by their tags, paths and creation times, created
+/// with [TagFilter::new](TagFilter::new). Entries have to match all of the conditions.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TagFilter {
+    all_tags: Vec<String>,
+    any_tags: Vec<String>,
+    not_tags: Vec<String>,
+    path_prefix: Option<PathBuf>,
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+}
+
+/// Builder of a [TagFilter](TagFilter).
+#[derive(Clone, Debug, Default)]
+pub struct TagFilterBuilder {
+    filter: TagFilter,
+}
+
+impl TagFilterBuilder {
+    /// Matches entries tagged with all of the `tags`.
+    pub fn has_all_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.filter
+            .all_tags
+            .extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Matches entries tagged with at least one of the `tags`.
+    pub fn has_any_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.filter
+            .any_tags
+            .extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Matches entries not tagged with the `tag`.
+    pub fn not_has_tag(mut self, tag: impl Into<String>) -> Self {
+        self.filter.not_tags.push(tag.into());
+        self
+    }
+
+    /// Matches entries whose paths start with the `prefix` compared by whole components.
+    pub fn path_starts_with(mut self, prefix: impl AsRef<Path>) -> Self {
+        self.filter.path_prefix = Some(prefix.as_ref().to_path_buf());
+        self
+    }
+
+    /// Matches entries added to the registry after `time`.
+    pub fn created_after(mut self, time: DateTime<Utc>) -> Self {
+        self.filter.created_after = Some(time);
+        self
+    }
+
+    /// Matches entries added to the registry before `time`.
+    pub fn created_before(mut self, time: DateTime<Utc>) -> Self {
+        self.filter.created_before = Some(time);
+        self
+    }
+
+    pub fn build(self) -> TagFilter {
+        self.filter
+    }
+}
+
+impl TagFilter {
+    /// Returns a builder of a filter matching all entries until conditions are added.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> TagFilterBuilder {
+        TagFilterBuilder::default()
+    }
+
+    /// Returns ids of entries of the `registry` matching this filter sorted by their paths.
+    pub fn apply(&self, registry: &TagRegistry) -> Vec<EntryId> {
+        let mut found: Vec<_> = registry
+            .list_entries_and_ids()
+            .filter(|(_, entry)| {
+                self.path_prefix
+                    .as_ref()
+                    .map_or(true, |prefix| entry.path().starts_with(prefix))
+                    && self.created_after.map_or(true, |t| entry.created_at() > t)
+                    && self.created_before.map_or(true, |t| entry.created_at() < t)
+            })
+            .filter(|(id, _)| {
+                let tags = registry.list_entry_tags(**id).unwrap_or_default();
+                let has = |name: &String| tags.iter().any(|tag| tag.name() == name);
+                self.all_tags.iter().all(has)
+                    && (self.any_tags.is_empty() || self.any_tags.iter().any(has))
+                    && !self.not_tags.iter().any(has)
+            })
+            .map(|(id, entry)| (*id, entry))
+            .collect();
+        found.sort_unstable_by(|(_, a), (_, b)| a.path().cmp(b.path()));
+        found.into_iter().map(|(id, _)| id).collect()
+    }
+}
+
+/// Returns tags of all `rules` matching the file at the given `path`. The MIME type of the file
+/// is inferred from its content and only the first [AUTO_TAG_READ_SIZE](AUTO_TAG_READ_SIZE)
+/// bytes are matched against content patterns.
+pub fn auto_tag_file<P: AsRef<Path>>(path: P, rules: &[TagRule]) -> Result<Vec<Tag>> {
+    let mut content = vec![];
+    fs::File::open(path.as_ref())?
+        .take(AUTO_TAG_READ_SIZE)
+        .read_to_end(&mut content)?;
+    let mime = infer::get(&content).map(|kind| kind.mime_type());
+    let text = String::from_utf8_lossy(&content);
+
+    let mut tags: Vec<Tag> = vec![];
+    for rule in rules.iter().filter(|rule| rule.matches(mime, &text)) {
+        for tag in &rule.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+    }
+    Ok(tags)
 }
 
 /// Lists tags of the file at the given `path` as a [BTreeSet](BTreeSet).
@@ -195,7 +917,7 @@ pub fn list_tags_btree<P>(path: P) -> Result<BTreeSet<Tag>>
 where
     P: AsRef<Path>,
 {
-    list_xattrs(path).map(|attrs| {
+    list_xattrs(path, true).map(|attrs| {
         let mut tags = BTreeSet::new();
         let it = attrs
             .into_iter()
@@ -209,16 +931,18 @@ where
     })
 }
 
-/// Clears all tags of the file at the given `path`.
-pub fn clear_tags<P>(path: P) -> Result<()>
+/// Clears all tags of the file at the given `path`, or of the symlink itself if `follow` is
+/// `false`.
+pub fn clear_tags<P>(path: P, follow: bool) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    for xattr in list_xattrs(path.as_ref())?
+    invalidate_cached(path.as_ref());
+    for xattr in list_xattrs(path.as_ref(), follow)?
         .iter()
         .filter(|xattr| xattr.key().starts_with(WUTAG_NAMESPACE))
     {
-        remove_xattr(path.as_ref(), xattr.key())?;
+        remove_xattr(path.as_ref(), xattr.key(), follow)?;
     }
 
     Ok(())
@@ -231,5 +955,708 @@ pub fn has_tags<P>(path: P) -> Result<bool>
 where
     P: AsRef<Path>,
 {
-    list_tags(path).map(|tags| !tags.is_empty())
+    list_tags(path, true).map(|tags| !tags.is_empty())
+}
+
+/// Decides how [visit_tagged_files](visit_tagged_files) continues after visiting a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VisitorAction {
+    Continue,
+    /// Don't descend into the visited directory. Same as `Continue` for other files.
+    Skip,
+    /// End the walk.
+    Stop,
+}
+
+/// Visitor of tagged files called by [visit_tagged_files](visit_tagged_files).
+pub trait TagVisitor {
+    fn visit(&mut self, path: &Path, tags: &[Tag]) -> VisitorAction;
+}
+
+impl<F> TagVisitor for F
+where
+    F: FnMut(&Path, &[Tag]) -> VisitorAction,
+{
+    fn visit(&mut self, path: &Path, tags: &[Tag]) -> VisitorAction {
+        self(path, tags)
+    }
+}
+
+/// Walks files under `base_dir` up to `max_depth` levels deep calling the `visitor` for each file
+/// or directory that has tags. Files are visited in order of their names and symlinks to
+/// directories are not descended into. Subdirectories that can't be read are skipped.
+pub fn visit_tagged_files<P, V>(base_dir: P, max_depth: usize, visitor: &mut V) -> Result<()>
+where
+    P: AsRef<Path>,
+    V: TagVisitor,
+{
+    visit_dir(base_dir.as_ref(), 1, max_depth, visitor).map(|_| ())
+}
+
+/// Visits tagged files in `dir` at `depth`. Returns `false` if the visitor stopped the walk.
+fn visit_dir<V: TagVisitor>(
+    dir: &Path,
+    depth: usize,
+    max_depth: usize,
+    visitor: &mut V,
+) -> Result<bool> {
+    if depth > max_depth {
+        return Ok(true);
+    }
+    let mut entries: Vec<_> = fs::read_dir(dir)?.flatten().collect();
+    entries.sort_unstable_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let action = match list_tags(&path, true) {
+            Ok(tags) if tags.is_empty() => VisitorAction::Continue,
+            Ok(tags) => visitor.visit(&path, &tags),
+            Err(e) => {
+                log::debug!("failed to list tags of `{}` - {e}", path.display());
+                VisitorAction::Continue
+            }
+        };
+        match action {
+            VisitorAction::Stop => return Ok(false),
+            VisitorAction::Skip => {}
+            VisitorAction::Continue if entry.file_type().is_ok_and(|t| t.is_dir()) => {
+                match visit_dir(&path, depth + 1, max_depth, visitor) {
+                    Ok(true) => {}
+                    Ok(false) => return Ok(false),
+                    Err(e) => log::debug!("failed to read directory `{}` - {e}", path.display()),
+                }
+            }
+            VisitorAction::Continue => {}
+        }
+    }
+    Ok(true)
+}
+
+/// Finds files under `base_dir` up to `max_depth` levels deep tagged with all of the `tags` or
+/// with any of them if `any` is set.
+pub fn search_files_with_tags<P, S>(
+    base_dir: P,
+    max_depth: usize,
+    tags: &[S],
+    any: bool,
+) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let mut found = vec![];
+    visit_tagged_files(
+        base_dir,
+        max_depth,
+        &mut |path: &Path, file_tags: &[Tag]| {
+            let tagged = |name: &S| file_tags.iter().any(|tag| tag.name() == name.as_ref());
+            let matches = if any {
+                tags.iter().any(tagged)
+            } else {
+                tags.iter().all(tagged)
+            };
+            if matches {
+                found.push(path.to_path_buf());
+            }
+            VisitorAction::Continue
+        },
+    )?;
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn matches_tag_patterns() {
+        let pattern = TagPattern::new("tmp-*");
+        assert!(pattern.matches("tmp-a"));
+        assert!(pattern.matches("tmp-"));
+        assert!(!pattern.matches("keep"));
+        assert!(!pattern.matches("old-tmp-a"));
+        assert_eq!(pattern.literal(), None);
+
+        let pattern = TagPattern::new("*-?-*x");
+        assert!(pattern.matches("a-b-x"));
+        assert!(pattern.matches("a-b-c-xx"));
+        assert!(!pattern.matches("a-bc-y"));
+
+        let literal = TagPattern::new(r"tmp-\*");
+        assert_eq!(literal.literal().as_deref(), Some("tmp-*"));
+        assert!(literal.matches("tmp-*"));
+        assert!(!literal.matches("tmp-a"));
+    }
+
+    #[test]
+    fn normalizes_tag_names() {
+        let nfc = Tag::new("caf\u{e9}", Color::Red);
+        let nfd = Tag::new("cafe\u{301}", Color::Red);
+        assert_eq!(nfc, nfd);
+        assert_eq!(nfc.name(), "caf\u{e9}");
+
+        let parsed = Tag::try_from(Xattr::new(
+            format!("{WUTAG_NAMESPACE}.{}", base64::encode("cafe\u{301}")),
+            "",
+        ))
+        .unwrap();
+        assert_eq!(parsed, nfc);
+        assert_eq!(parsed.name(), "caf\u{e9}");
+
+        let mut unnormalized = nfc.clone();
+        unnormalized.name = "cafe\u{301}".into();
+        let deserialized: Tag =
+            serde_cbor::from_slice(&serde_cbor::to_vec(&unnormalized).unwrap()).unwrap();
+        assert_eq!(deserialized.name(), "caf\u{e9}");
+
+        assert_eq!(Tag::new_folded("CAFE\u{301}", Color::Red), nfc);
+    }
+
+    #[test]
+    fn rekeys_tags_with_unnormalized_names() {
+        let dir = tempdir::TempDir::new("wutag-tag-nfd").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let tag = Tag::new("caf\u{e9}", Color::Red);
+        let nfd_key = format!("{WUTAG_NAMESPACE}.{}", base64::encode("cafe\u{301}"));
+
+        set_xattr(
+            &file,
+            nfd_key.as_str(),
+            tag.encode_value().unwrap().as_str(),
+            true,
+        )
+        .unwrap();
+        tag.remove_from(&file, true).unwrap();
+        assert!(list_tags(&file, true).unwrap().is_empty());
+
+        set_xattr(
+            &file,
+            nfd_key.as_str(),
+            tag.encode_value().unwrap().as_str(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(list_tags(&file, true).unwrap(), vec![tag.clone()]);
+        let keys: Vec<_> = list_xattrs(&file, true)
+            .unwrap()
+            .into_iter()
+            .map(|xattr| xattr.key().to_string())
+            .collect();
+        assert_eq!(keys, vec![tag.hash()]);
+        assert!(matches!(tag.save_to(&file, true), Err(Error::TagExists)));
+        tag.remove_from(&file, true).unwrap();
+    }
+
+    #[test]
+    fn parses_tag_from_xattr() {
+        let tag = Tag::new("code", Color::Red);
+        let parsed = Tag::try_from(Xattr::new(tag.hash(), "")).unwrap();
+        assert_eq!(parsed.name(), "code");
+
+        let old = format!(
+            "{WUTAG_NAMESPACE}.{}",
+            base64::encode(serde_cbor::to_vec(&tag).unwrap())
+        );
+        let parsed = Tag::try_from(Xattr::new(old, "")).unwrap();
+        assert_eq!(parsed.name(), "code");
+        assert_eq!(parsed.color(), &Color::Red);
+
+        let mut weighted = tag.clone();
+        weighted.set_weight(3.0);
+        let old = format!(
+            "{WUTAG_NAMESPACE}.{}",
+            base64::encode(serde_cbor::to_vec(&weighted).unwrap())
+        );
+        let parsed = Tag::try_from(Xattr::new(old, "")).unwrap();
+        assert_eq!(parsed.weight(), 3.0);
+
+        assert!(Tag::try_from(Xattr::new("security.wutag.Zm9v", "")).is_err());
+    }
+
+    #[test]
+    fn caches_tags_until_file_changes() {
+        let dir = tempdir::TempDir::new("wutag-tag-cache").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        Tag::new("code", Color::Red).save_to(&file, true).unwrap();
+
+        let mut cache = TagCache::default();
+        assert!(cache.is_empty());
+        let tags = cache.list_tags(&file).unwrap();
+        assert_eq!(tags, vec![Tag::new("code", Color::Red)]);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.list_tags(&file).unwrap(), tags);
+
+        // make sure the change time differs on filesystems with coarse timestamps
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        Tag::new("rust", Color::Red).save_to(&file, true).unwrap();
+        let mut tags = cache.list_tags(&file).unwrap();
+        tags.sort_unstable();
+        assert_eq!(
+            tags,
+            vec![Tag::new("code", Color::Red), Tag::new("rust", Color::Red)]
+        );
+        assert_eq!(cache.len(), 1);
+        cache.invalidate(&file);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn caches_tags_per_thread() {
+        let dir = tempdir::TempDir::new("wutag-tag-thread-cache").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let cached = || TAG_CACHE.with(|cache| cache.borrow().len());
+        let code = Tag::new("code", Color::Red);
+
+        assert!(list_tags_cached(&file).unwrap().is_empty());
+        assert_eq!(cached(), 1);
+        code.save_to(&file, true).unwrap();
+        assert_eq!(cached(), 0);
+        assert_eq!(list_tags_cached(&file).unwrap(), vec![code.clone()]);
+        assert!(matches!(code.save_to(&file, true), Err(Error::TagExists)));
+
+        // other threads have their own cache
+        let other = file.clone();
+        std::thread::spawn(move || {
+            assert_eq!(list_tags_cached(&other).unwrap().len(), 1);
+            assert_eq!(TAG_CACHE.with(|cache| cache.borrow().len()), 1);
+        })
+        .join()
+        .unwrap();
+
+        code.remove_from(&file, true).unwrap();
+        assert_eq!(cached(), 0);
+        assert!(list_tags_cached(&file).unwrap().is_empty());
+        invalidate_cached(&file);
+        assert_eq!(cached(), 0);
+    }
+
+    #[test]
+    fn auto_tags_files_matching_rules() {
+        let dir = tempdir::TempDir::new("wutag-auto-tag").unwrap();
+        let image = dir.path().join("image");
+        fs::write(&image, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let notes = dir.path().join("notes");
+        fs::write(&notes, "TODO: write tests").unwrap();
+
+        let rules = vec![
+            TagRule {
+                mime_prefix: Some("image/".into()),
+                content_pattern: None,
+                tags: vec![Tag::new("image", Color::Red)],
+            },
+            TagRule {
+                mime_prefix: None,
+                content_pattern: Some(Regex::new("TODO|FIXME").unwrap()),
+                tags: vec![Tag::new("todo", Color::Red), Tag::new("image", Color::Red)],
+            },
+        ];
+
+        assert_eq!(
+            auto_tag_file(&image, &rules).unwrap(),
+            vec![Tag::new("image", Color::Red)]
+        );
+        assert_eq!(
+            auto_tag_file(&notes, &rules).unwrap(),
+            vec![Tag::new("todo", Color::Red), Tag::new("image", Color::Red)]
+        );
+
+        let serialized = serde_cbor::to_vec(&rules[1]).unwrap();
+        let rule: TagRule = serde_cbor::from_slice(&serialized).unwrap();
+        assert_eq!(rule.content_pattern.unwrap().as_str(), "TODO|FIXME");
+    }
+
+    #[test]
+    fn stores_tag_value_in_xattr() {
+        let dir = tempdir::TempDir::new("wutag-tag-value").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+
+        let mut tag = Tag::new("code", Color::Red);
+        tag.set_description(Some("source files"));
+        tag.set_metadata("lang", "rust");
+        tag.save_to(&file, true).unwrap();
+
+        let tags = list_tags(&file, true).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].description(), Some("source files"));
+        assert_eq!(
+            tags[0].metadata().get("lang").map(String::as_str),
+            Some("rust")
+        );
+    }
+
+    #[test]
+    fn migrates_empty_xattr_values() {
+        let dir = tempdir::TempDir::new("wutag-tag-migrate").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let tag = Tag::new("code", Color::Red);
+        set_xattr(&file, tag.hash(), String::new(), true).unwrap();
+
+        assert_eq!(list_tags(&file, true).unwrap(), vec![tag.clone()]);
+        let value = crate::xattr::get_xattr(&file, tag.hash(), true).unwrap();
+        assert_eq!(TagValue::decode(value).unwrap().description, None);
+        assert_eq!(Tag::migrate_xattr(&file, true).unwrap(), 0);
+    }
+
+    #[test]
+    fn keeps_tags_that_fail_to_migrate() {
+        let dir = tempdir::TempDir::new("wutag-tag-migrate-full").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let tag = Tag::new("code", Color::Red);
+        set_xattr(&file, tag.hash(), String::new(), true).unwrap();
+
+        // fills the space for attributes of the file so that the migrated value doesn't fit
+        let mut filled = false;
+        for size in [256, 16, 1] {
+            for i in 0..1000 {
+                let key = format!("user.filler-{size}-{i}");
+                match set_xattr(&file, key, "0".repeat(size), true) {
+                    Ok(()) => {}
+                    Err(_) => {
+                        filled = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if !filled {
+            // the filesystem has no practical limit of the size of attributes
+            return;
+        }
+
+        assert!(Tag::migrate_xattr(&file, true).is_err());
+        assert_eq!(get_xattr(&file, tag.hash(), true).unwrap(), "");
+        assert_eq!(list_tags(&file, true).unwrap(), vec![tag]);
+    }
+
+    #[test]
+    fn stores_color_in_xattr_value() {
+        let dir = tempdir::TempDir::new("wutag-tag-color").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let color = Color::TrueColor { r: 1, g: 2, b: 3 };
+        let tag = Tag::new("code", color);
+        tag.save_to(&file, true).unwrap();
+
+        let value = TagValue::decode(get_xattr(&file, tag.hash(), true).unwrap()).unwrap();
+        assert_eq!(value.color, Some(color));
+        let tags = list_tags(&file, true).unwrap();
+        assert_eq!(tags[0].color(), &color);
+        assert_eq!(tags[0].value().color, None);
+        tag.remove_from(&file, true).unwrap();
+
+        // older versions stored the whole tag in the key and nothing in the value
+        let old_key = format!(
+            "{WUTAG_NAMESPACE}.{}",
+            base64::encode(serde_cbor::to_vec(&tag).unwrap())
+        );
+        set_xattr(&file, old_key.as_str(), "", true).unwrap();
+        let tags = list_tags(&file, true).unwrap();
+        assert_eq!(tags[0].color(), &color);
+        // the tag is migrated to a key with the name and a value with the color
+        let value = TagValue::decode(get_xattr(&file, tag.hash(), true).unwrap()).unwrap();
+        assert_eq!(value.color, Some(color));
+        tag.remove_from(&file, true).unwrap();
+
+        // or only the name in the key and a value without the color
+        let mut described = tag.clone();
+        described.set_description(Some("source files"));
+        set_xattr(&file, tag.hash(), described.value().encode().unwrap(), true).unwrap();
+        let tags = list_tags(&file, true).unwrap();
+        assert_eq!(tags[0].color(), &DEFAULT_COLOR);
+        assert_eq!(tags[0].description(), Some("source files"));
+        // which is rewritten with the color by a migration
+        assert_eq!(Tag::migrate_xattr(&file, true).unwrap(), 1);
+        let value = TagValue::decode(get_xattr(&file, tag.hash(), true).unwrap()).unwrap();
+        assert_eq!(value.color, Some(DEFAULT_COLOR));
+        assert_eq!(value.description.as_deref(), Some("source files"));
+
+        // a new color replaces only the color of the saved value
+        tag.recolor_in(&file, Color::Blue, true).unwrap();
+        let tags = list_tags(&file, true).unwrap();
+        assert_eq!(tags[0].color(), &Color::Blue);
+        assert_eq!(tags[0].description(), Some("source files"));
+        assert!(matches!(
+            Tag::new("missing", Color::Red).recolor_in(&file, Color::Blue, true),
+            Err(Error::TagNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn saves_compact_xattr_values() {
+        let dir = tempdir::TempDir::new("wutag-tag-compact").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let tag = Tag::new("code", Color::Red);
+        tag.save_to(&file, true).unwrap();
+        let value = get_xattr(&file, tag.hash(), true).unwrap();
+        let color_only = TagValue {
+            color: Some(Color::Red),
+            ..Default::default()
+        };
+        assert_eq!(value, color_only.encode().unwrap());
+
+        // values of older versions encoded empty fields
+        #[derive(Serialize)]
+        struct VerboseValue {
+            description: Option<String>,
+            metadata: HashMap<String, String>,
+        }
+        let verbose = VerboseValue {
+            description: None,
+            metadata: HashMap::from([("lang".to_string(), "rust".to_string())]),
+        };
+        let verbose = base64::encode(serde_cbor::to_vec(&verbose).unwrap());
+        let other = Tag::new("other", Color::Red);
+        set_xattr(&file, other.hash(), verbose.clone(), true).unwrap();
+
+        let tags = list_tags(&file, true).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(get_xattr(&file, other.hash(), true).unwrap(), verbose);
+        assert_eq!(Tag::migrate_xattr(&file, true).unwrap(), 1);
+        let value = get_xattr(&file, other.hash(), true).unwrap();
+        assert_ne!(value, verbose);
+        assert!(!TagValue::is_outdated(&value));
+        assert_eq!(
+            TagValue::decode(value).unwrap().metadata.get("lang"),
+            Some(&"rust".to_string())
+        );
+        assert_eq!(Tag::migrate_xattr(&file, true).unwrap(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tags_symlink_or_its_target() {
+        let dir = tempdir::TempDir::new("wutag-symlink").unwrap();
+        let target = dir.path().join("target");
+        let link = dir.path().join("link");
+        fs::write(&target, b"").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let followed = Tag::new("followed", Color::Red);
+        let linked = Tag::new("linked", Color::Blue);
+
+        followed.save_to(&link, true).unwrap();
+        assert_eq!(list_tags(&target, true).unwrap(), vec![followed.clone()]);
+        assert_eq!(list_tags(&link, true).unwrap(), vec![followed.clone()]);
+        assert!(list_tags(&link, false).unwrap().is_empty());
+        assert!(followed.remove_from(&link, false).is_err());
+
+        // Linux only allows `user.*` attributes on regular files and directories
+        match linked.save_to(&link, false) {
+            Ok(()) => assert_eq!(list_tags(&link, false).unwrap(), vec![linked.clone()]),
+            Err(_) if cfg!(target_os = "linux") => {}
+            Err(e) => panic!("{e}"),
+        }
+        assert_eq!(list_tags(&target, true).unwrap(), vec![followed.clone()]);
+
+        followed.remove_from(&link, true).unwrap();
+        assert!(list_tags(&target, true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn visits_tagged_files() {
+        let dir = tempdir::TempDir::new("wutag-visit").unwrap();
+        let code = Tag::new("code", Color::Red);
+        let rust = Tag::new("rust", Color::Red);
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        code.save_to(&sub, true).unwrap();
+        for (path, tags) in [
+            (dir.path().join("a.rs"), vec![&code, &rust]),
+            (dir.path().join("b.txt"), vec![]),
+            (dir.path().join("c.md"), vec![&code]),
+            (sub.join("d.rs"), vec![&rust]),
+        ] {
+            fs::write(&path, b"").unwrap();
+            for tag in tags {
+                tag.save_to(&path, true).unwrap();
+            }
+        }
+        let visit = |max_depth, action: &dyn Fn(&Path) -> VisitorAction| {
+            let mut visited = vec![];
+            visit_tagged_files(dir.path(), max_depth, &mut |path: &Path, _: &[Tag]| {
+                visited.push(path.strip_prefix(dir.path()).unwrap().to_path_buf());
+                action(path)
+            })
+            .unwrap();
+            visited
+        };
+
+        let all = visit(2, &|_| VisitorAction::Continue);
+        assert_eq!(all, ["a.rs", "c.md", "sub", "sub/d.rs"].map(PathBuf::from));
+        assert_eq!(visit(1, &|_| VisitorAction::Continue).len(), 3);
+        let skipped = visit(2, &|_| VisitorAction::Skip);
+        assert_eq!(skipped, ["a.rs", "c.md", "sub"].map(PathBuf::from));
+        let stopped = visit(2, &|path| {
+            if path.ends_with("c.md") {
+                VisitorAction::Stop
+            } else {
+                VisitorAction::Continue
+            }
+        });
+        assert_eq!(stopped, ["a.rs", "c.md"].map(PathBuf::from));
+
+        assert_eq!(
+            search_files_with_tags(dir.path(), 2, &["code", "rust"], false).unwrap(),
+            vec![dir.path().join("a.rs")]
+        );
+        assert_eq!(
+            search_files_with_tags(dir.path(), 2, &["rust"], true).unwrap(),
+            vec![dir.path().join("a.rs"), sub.join("d.rs")]
+        );
+    }
+
+    #[test]
+    fn tags_file_from_multiple_threads() {
+        let dir = tempdir::TempDir::new("wutag-concurrent-tags").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+
+        let tags: Vec<_> = (0..2)
+            .map(|thread| {
+                (0..25)
+                    .map(|i| Tag::new(format!("thread{thread}-{i}"), Color::Red))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        std::thread::scope(|s| {
+            for thread_tags in &tags {
+                let file = &file;
+                s.spawn(move || {
+                    for tag in thread_tags {
+                        tag.save_to(file, true).unwrap();
+                    }
+                    for tag in thread_tags.iter().step_by(2) {
+                        tag.remove_from(file, true).unwrap();
+                    }
+                });
+            }
+        });
+
+        let saved = list_tags(&file, true).unwrap();
+        for tag in tags.iter().flatten() {
+            let removed = tag
+                .name()
+                .rsplit('-')
+                .next()
+                .unwrap()
+                .parse::<usize>()
+                .unwrap()
+                % 2
+                == 0;
+            assert_eq!(saved.contains(tag), !removed, "{}", tag.name());
+        }
+    }
+
+    #[test]
+    fn resolves_implied_tags() {
+        let rule = |if_tag: &str, then_tag: &str| ImplicationRule {
+            if_tag: if_tag.into(),
+            then_tag: then_tag.into(),
+        };
+        let rules = vec![
+            rule("rust", "code"),
+            rule("code", "work"),
+            rule("work", "rust"),
+            rule("photo", "image"),
+        ];
+
+        let mut implied = implied_tags(&["rust"], &rules);
+        implied.sort();
+        assert_eq!(implied, vec!["code", "work"]);
+        assert_eq!(implied_tags(&["code", "photo"], &rules).len(), 3);
+        assert!(implied_tags(&["image"], &rules).is_empty());
+    }
+
+    #[test]
+    fn filters_entries() {
+        use crate::registry::EntryData;
+
+        let mut registry = TagRegistry::default();
+        let mut add = |path: &str, tags: &[&str]| {
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            for tag in tags {
+                registry.tag_entry(&Tag::new(*tag, Color::Red), id);
+            }
+            id
+        };
+        let lib = add("/home/code/lib.rs", &["rust", "code"]);
+        let old = add("/home/code/old.rs", &["rust", "code", "archived"]);
+        let script = add("/srv/deploy.rs", &["rust", "code"]);
+        let notes = add("/home/notes.md", &["docs"]);
+
+        let filter = TagFilter::new()
+            .has_all_tags(["rust", "code"])
+            .path_starts_with("/home")
+            .not_has_tag("archived")
+            .build();
+        assert_eq!(filter.apply(&registry), vec![lib]);
+        let filter = TagFilter::new().has_any_tags(["docs", "archived"]).build();
+        assert_eq!(filter.apply(&registry), vec![old, notes]);
+        assert!(TagFilter::new()
+            .path_starts_with("/srv/deploy")
+            .build()
+            .apply(&registry)
+            .is_empty());
+
+        let hour_ago = Utc::now() - chrono::Duration::hours(1);
+        let filter = TagFilter::new().created_after(hour_ago).build();
+        assert_eq!(filter.apply(&registry), vec![lib, old, notes, script]);
+        let filter = TagFilter::new().created_before(hour_ago).build();
+        assert!(filter.apply(&registry).is_empty());
+
+        let serialized = serde_cbor::to_vec(&filter).unwrap();
+        assert_eq!(
+            serde_cbor::from_slice::<TagFilter>(&serialized).unwrap(),
+            filter
+        );
+    }
+
+    #[test]
+    fn moves_tag_between_namespaces() {
+        let dir = tempdir::TempDir::new("wutag-move-tag").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        let mut tag = Tag::new("code", Color::Red);
+        tag.set_description(Some("source files"));
+        let value = tag.value().encode().unwrap();
+        let old_key = format!("user.oldwutag.{}", base64::encode("code"));
+        let new_key = tag.hash();
+        set_xattr(&file, old_key.as_str(), value.as_str(), true).unwrap();
+        assert!(list_tags(&file, true).unwrap().is_empty());
+
+        Tag::move_tag(&file, "user.oldwutag", WUTAG_NAMESPACE, "code").unwrap();
+        assert!(get_xattr(&file, &old_key, true).is_err());
+        assert_eq!(get_xattr(&file, &new_key, true).unwrap(), value);
+        let tags = list_tags(&file, true).unwrap();
+        assert_eq!(tags, vec![tag]);
+        assert_eq!(tags[0].description(), Some("source files"));
+
+        assert!(matches!(
+            Tag::move_tag(&file, "user.oldwutag", WUTAG_NAMESPACE, "code"),
+            Err(Error::TagNotFound(name)) if name == "code"
+        ));
+
+        // an existing tag in the new namespace is not overwritten
+        set_xattr(&file, old_key.as_str(), "", true).unwrap();
+        assert!(matches!(
+            Tag::move_tag(&file, "user.oldwutag", WUTAG_NAMESPACE, "code"),
+            Err(Error::TagExists)
+        ));
+        assert_eq!(get_xattr(&file, &new_key, true).unwrap(), value);
+
+        Tag::new("rust", Color::Red).save_to(&file, true).unwrap();
+        assert_eq!(
+            Tag::move_namespace(&file, WUTAG_NAMESPACE, "user.newwutag").unwrap(),
+            2
+        );
+        assert!(list_tags(&file, true).unwrap().is_empty());
+        assert!(get_xattr(&file, &old_key, true).is_ok());
+    }
 }
@@ -6,18 +6,34 @@ mod windows;
 
 #[cfg(unix)]
 use unix::{
-    get_xattr as _get_xattr, list_xattrs as _list_xattrs, remove_xattr as _remove_xattr,
-    set_xattr as _set_xattr,
+    copy_xattrs as _copy_xattrs, get_xattr as _get_xattr, list_xattrs as _list_xattrs,
+    remove_xattr as _remove_xattr, replace_xattr as _replace_xattr, set_xattr as _set_xattr,
 };
 #[cfg(windows)]
 pub use windows::{
-    get_xattr as _get_xattr, list_xattrs as _list_xattrs, remove_xattr as _remove_xattr,
-    set_xattr as _set_xattr,
+    copy_xattrs as _copy_xattrs, get_xattr as _get_xattr, list_xattrs as _list_xattrs,
+    remove_xattr as _remove_xattr, replace_xattr as _replace_xattr, set_xattr as _set_xattr,
 };
 
-use crate::Result;
-use std::path::Path;
+use crate::{Error, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+/// Bytes taken by every extended attribute on top of its name and value, the size of the header
+/// of an attribute on ext4.
+pub const XATTR_ENTRY_OVERHEAD: usize = 16;
+/// Largest value of an extended attribute allowed by Linux.
+const XATTR_SIZE_MAX: usize = 64 * 1024;
+/// Name of the attribute written while probing for free space.
+const PROBE_KEY: &str = "user.wutag-capacity-probe";
+
+/// Extended attribute of a file. Attributes are equal if their keys are equal regardless of
+/// their values, the same way as tags are compared by their names.
+#[derive(Clone, Debug)]
 pub struct Xattr {
     key: String,
     val: String,
@@ -44,39 +60,255 @@ impl Xattr {
     }
 }
 
+impl PartialEq for Xattr {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Xattr {}
+
+impl Hash for Xattr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
 impl From<(String, String)> for Xattr {
     fn from(xattr: (String, String)) -> Self {
         Self::new(xattr.0, xattr.1)
     }
 }
 
-pub fn set_xattr<P, S>(path: P, name: S, value: S) -> Result<()>
+impl From<Xattr> for (String, String) {
+    fn from(xattr: Xattr) -> Self {
+        (xattr.key, xattr.val)
+    }
+}
+
+/// Sets the extended attribute `name` of the file at `path`. With `follow` set to `false` a
+/// symlink at `path` gets the attribute itself instead of its target.
+pub fn set_xattr<P, S>(path: P, name: S, value: S, follow: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    _set_xattr(path, name, value, follow)
+}
+
+/// Replaces the value of the existing extended attribute `name` of the file at `path`. Unlike
+/// removing the attribute and setting it again the old value is kept if the new value doesn't
+/// fit.
+pub fn replace_xattr<P, S>(path: P, name: S, value: S, follow: bool) -> Result<()>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    _set_xattr(path, name, value)
+    _replace_xattr(path, name, value, follow)
 }
 
-pub fn get_xattr<P, S>(path: P, name: S) -> Result<String>
+pub fn get_xattr<P, S>(path: P, name: S, follow: bool) -> Result<String>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    _get_xattr(path, name)
+    _get_xattr(path, name, follow)
 }
 
-pub fn list_xattrs<P>(path: P) -> Result<Vec<Xattr>>
+pub fn list_xattrs<P>(path: P, follow: bool) -> Result<Vec<Xattr>>
 where
     P: AsRef<Path>,
 {
-    _list_xattrs(path).map(|attrs| attrs.into_iter().map(From::from).collect())
+    _list_xattrs(path, follow).map(|attrs| attrs.into_iter().map(From::from).collect())
 }
 
-pub fn remove_xattr<P, S>(path: P, name: S) -> Result<()>
+pub fn remove_xattr<P, S>(path: P, name: S, follow: bool) -> Result<()>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    _remove_xattr(path, name)
+    _remove_xattr(path, name, follow)
+}
+
+/// Space taken by extended attributes of a file and the space left for new ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct XattrCapacity {
+    /// Number of attributes of the file.
+    pub attributes: usize,
+    /// Bytes taken by names and values of the attributes.
+    pub used: usize,
+    /// Bytes of the name and value of the largest attribute that can still be added.
+    pub free: usize,
+}
+
+impl XattrCapacity {
+    /// Estimates how many more attributes with names and values of `size` bytes in total fit in
+    /// the free space, each of them taking up [XATTR_ENTRY_OVERHEAD](XATTR_ENTRY_OVERHEAD) bytes
+    /// more.
+    pub fn fits(&self, size: usize) -> usize {
+        if self.free == 0 {
+            return 0;
+        }
+        (self.free + XATTR_ENTRY_OVERHEAD) / (size + XATTR_ENTRY_OVERHEAD)
+    }
+}
+
+/// Temporary file created next to the file whose capacity is probed, removed when dropped.
+struct ProbeFile(PathBuf);
+
+impl ProbeFile {
+    fn create(next_to: &Path) -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = next_to.parent().unwrap_or_else(|| Path::new("."));
+        let path = dir.join(format!(
+            ".wutag-capacity-probe-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for ProbeFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Measures the space taken by extended attributes of the file at `path` and probes how much is
+/// left by writing the largest attribute that fits. The probe runs on a temporary file in the
+/// same directory holding attributes of the same size, so the file itself is never modified.
+/// Filesystems like ext4 limit all attributes of a file to a single block while others only
+/// limit the size of a single value to 64 KiB, so the free space is never reported as larger than
+/// that.
+pub fn xattr_capacity<P>(path: P, follow: bool) -> Result<XattrCapacity>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let attrs = list_xattrs(path, follow)?;
+    let used = attrs.iter().map(|x| x.key.len() + x.val.len()).sum();
+
+    let target = if follow {
+        fs::canonicalize(path)?
+    } else {
+        path.to_path_buf()
+    };
+    let probe = ProbeFile::create(&target)?;
+    // attributes like security labels are given to new files as well
+    let existing = list_xattrs(&probe.0, true)?;
+    for (i, attr) in attrs.iter().enumerate() {
+        if existing.contains(attr) {
+            continue;
+        }
+        match set_xattr(&probe.0, attr.key(), attr.val(), true) {
+            Ok(()) => continue,
+            Err(Error::TagListFull(_)) => break,
+            // attributes outside of the user namespace are replaced with ones of the same size
+            Err(_) => {}
+        }
+        let key = format!("user.wutag-capacity-fill{i}");
+        let len = (attr.key.len() + attr.val.len()).saturating_sub(key.len());
+        match set_xattr(&probe.0, key.as_str(), &"0".repeat(len), true) {
+            Ok(()) => {}
+            Err(Error::TagListFull(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let fits = |size: usize| -> Result<bool> {
+        match set_xattr(&probe.0, PROBE_KEY, &"0".repeat(size), true) {
+            Ok(()) => remove_xattr(&probe.0, PROBE_KEY, true).map(|_| true),
+            Err(Error::TagListFull(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    };
+    let free = if !fits(0)? {
+        0
+    } else if fits(XATTR_SIZE_MAX)? {
+        PROBE_KEY.len() + XATTR_SIZE_MAX
+    } else {
+        // the largest value that fits is within `fitting..too_large`
+        let (mut fitting, mut too_large) = (0, XATTR_SIZE_MAX);
+        while too_large - fitting > 1 {
+            let size = fitting + (too_large - fitting) / 2;
+            if fits(size)? {
+                fitting = size;
+            } else {
+                too_large = size;
+            }
+        }
+        PROBE_KEY.len() + fitting
+    };
+
+    Ok(XattrCapacity {
+        attributes: attrs.len(),
+        used,
+        free,
+    })
+}
+
+/// Copies extended attributes with names starting with `prefix`, or all of them if `None`, from
+/// `src` to `dst` skipping the ones that already exist on `dst`. Returns the number of copied
+/// attributes.
+pub fn copy_xattrs<P, Q>(src: P, dst: Q, prefix: Option<&str>) -> Result<usize>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    _copy_xattrs(src.as_ref(), dst.as_ref(), prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn compares_xattrs_by_key() {
+        let xattr = Xattr::new("user.wutag.a", "1");
+        assert_eq!(xattr, Xattr::new("user.wutag.a", "2"));
+        assert_ne!(xattr, Xattr::new("user.wutag.b", "1"));
+
+        let set: HashSet<_> = [
+            xattr.clone(),
+            Xattr::new("user.wutag.a", "2"),
+            Xattr::new("user.wutag.b", "1"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.get(&xattr).unwrap().val(), "1");
+
+        let pair: (String, String) = xattr.into();
+        assert_eq!(pair, ("user.wutag.a".to_string(), "1".to_string()));
+        assert_eq!(Xattr::from(pair).key(), "user.wutag.a");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probes_free_xattr_space() {
+        let dir = tempdir::TempDir::new("wutag-xattr-capacity").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let empty = xattr_capacity(&file, true).unwrap();
+        assert_eq!((empty.attributes, empty.used), (0, 0));
+        assert!(empty.free > 0);
+
+        set_xattr(&file, "user.wutag.a", &"1".repeat(100), true).unwrap();
+        let capacity = xattr_capacity(&file, true).unwrap();
+        assert_eq!((capacity.attributes, capacity.used), (1, 112));
+        assert!(capacity.free <= empty.free);
+        assert!(capacity.fits(100) <= capacity.free / 100);
+        // the probe doesn't leave anything behind
+        assert_eq!(list_xattrs(&file, true).unwrap().len(), 1);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let full = XattrCapacity::default();
+        assert_eq!(full.fits(1), 0);
+    }
 }
@@ -1,11 +1,11 @@
 #![cfg(unix)]
 #[cfg(target_os = "macos")]
 use libc::XATTR_NOFOLLOW;
-use libc::{getxattr, listxattr, removexattr, setxattr, XATTR_CREATE};
+use libc::{c_int, getxattr, listxattr, removexattr, setxattr, XATTR_CREATE, XATTR_REPLACE};
 #[cfg(target_os = "linux")]
 use libc::{lgetxattr, llistxattr, lremovexattr, lsetxattr};
+use std::collections::HashSet;
 use std::ffi::{CStr, CString, OsStr};
-use std::fs;
 use std::io;
 use std::mem;
 use std::os::raw::{c_char, c_void};
@@ -13,59 +13,101 @@ use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 use std::ptr;
 
+use super::Xattr;
 use crate::{Error, Result};
 
-fn is_symlink(path: &Path) -> bool {
-    let mut is_symlink = false;
-    if let Ok(metadata) = fs::metadata(path) {
-        is_symlink = metadata.file_type().is_symlink();
-    }
-    is_symlink
-}
-
 /// Sets the value of the extended attribute identified by `name` and associated with the given `path` in the
-/// filesystem.
-pub fn set_xattr<P, S>(path: P, name: S, value: S) -> Result<()>
+/// filesystem. If `path` is a symlink and `follow` is `false` the attribute is set on the link itself
+/// instead of its target.
+pub fn set_xattr<P, S>(path: P, name: S, value: S, follow: bool) -> Result<()>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    let size = value.as_ref().as_bytes().len();
-    let path = path.as_ref();
+    let size = value.as_ref().len();
+
+    _set_xattr(
+        path.as_ref(),
+        name.as_ref(),
+        value.as_ref(),
+        size,
+        !follow,
+        XATTR_CREATE,
+    )
+}
 
-    _set_xattr(path, name.as_ref(), value.as_ref(), size, is_symlink(path))
+/// Replaces the value of the existing extended attribute identified by `name` and associated with
+/// the given `path` in the filesystem. The old value is kept if the new one can't be written.
+pub fn replace_xattr<P, S>(path: P, name: S, value: S, follow: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let size = value.as_ref().len();
+
+    _set_xattr(
+        path.as_ref(),
+        name.as_ref(),
+        value.as_ref(),
+        size,
+        !follow,
+        XATTR_REPLACE,
+    )
 }
 
 /// Retrieves the value of the extended attribute identified by `name` and associated with the given
 /// `path` in the filesystem.
-pub fn get_xattr<P, S>(path: P, name: S) -> Result<String>
+pub fn get_xattr<P, S>(path: P, name: S, follow: bool) -> Result<String>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    let path = path.as_ref();
-    _get_xattr(path, name.as_ref(), is_symlink(path))
+    _get_xattr(path.as_ref(), name.as_ref(), !follow)
 }
 
 /// Retrieves a list of all extended attributes with their values associated with the given `path`
 /// in the filesystem.
-pub fn list_xattrs<P>(path: P) -> Result<Vec<(String, String)>>
+pub fn list_xattrs<P>(path: P, follow: bool) -> Result<Vec<(String, String)>>
 where
     P: AsRef<Path>,
 {
-    let path = path.as_ref();
-    _list_xattrs(path, is_symlink(path))
+    _list_xattrs(path.as_ref(), !follow)
 }
 
 /// Removes the extended attribute identified by `name` and associated with the given `path` in the
 /// filesystem.
-pub fn remove_xattr<P, S>(path: P, name: S) -> Result<()>
+pub fn remove_xattr<P, S>(path: P, name: S, follow: bool) -> Result<()>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
 {
-    let path = path.as_ref();
-    _remove_xattr(path, name.as_ref(), is_symlink(path))
+    _remove_xattr(path.as_ref(), name.as_ref(), !follow)
+}
+
+/// Copies extended attributes with names starting with `prefix`, or all of them if `None`, from
+/// `src` to `dst` skipping the ones that already exist on `dst`. Returns the number of copied
+/// attributes. Symlinks are always followed.
+pub fn copy_xattrs(src: &Path, dst: &Path, prefix: Option<&str>) -> Result<usize> {
+    let csrc = CString::new(src.to_string_lossy().as_bytes())?;
+    let cdst = CString::new(dst.to_string_lossy().as_bytes())?;
+    let existing: HashSet<_> = parse_xattrs(&list_xattrs_raw(cdst.as_c_str(), false)?)
+        .into_iter()
+        .collect();
+
+    let mut copied = 0;
+    for name in parse_xattrs(&list_xattrs_raw(csrc.as_c_str(), false)?) {
+        if existing.contains(&name) || !prefix.map_or(true, |prefix| name.starts_with(prefix)) {
+            continue;
+        }
+        let value = _get_xattr(src, &name, false)?;
+        match _set_xattr(dst, &name, &value, value.len(), false, XATTR_CREATE) {
+            Ok(()) => copied += 1,
+            // created in the meantime
+            Err(Error::TagExists) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(copied)
 }
 
 //################################################################################
@@ -105,10 +147,11 @@ unsafe fn __setxattr(
     value: *const c_void,
     size: usize,
     symlink: bool,
+    flags: c_int,
 ) -> isize {
     let func = if symlink { lsetxattr } else { setxattr };
 
-    func(path, name, value, size, XATTR_CREATE) as isize
+    func(path, name, value, size, flags) as isize
 }
 
 #[cfg(target_os = "macos")]
@@ -118,10 +161,11 @@ unsafe fn __setxattr(
     value: *const c_void,
     size: usize,
     symlink: bool,
+    flags: c_int,
 ) -> isize {
     let opts = if symlink { XATTR_NOFOLLOW } else { 0 };
 
-    setxattr(path, name, value, size, 0, opts | XATTR_CREATE) as isize
+    setxattr(path, name, value, size, 0, opts | flags) as isize
 }
 
 #[cfg(target_os = "linux")]
@@ -176,6 +220,7 @@ fn _set_xattr(
     value: &str,
     size: usize,
     symlink: bool, // if provided path is a symlink set the attribute on the symlink not the file/directory it points to
+    flags: c_int,  // whether the attribute is created or replaced
 ) -> Result<()> {
     let path = CString::new(path.to_string_lossy().as_bytes())?;
     let name = CString::new(name.as_bytes())?;
@@ -188,10 +233,16 @@ fn _set_xattr(
             value.as_ptr() as *const c_void,
             size,
             symlink,
+            flags,
         );
 
         if ret != 0 {
-            return Err(Error::from(io::Error::last_os_error()));
+            let err = io::Error::last_os_error();
+            // the value is too large for the filesystem
+            if err.raw_os_error() == Some(libc::E2BIG) {
+                return Err(Error::TagListFull(err));
+            }
+            return Err(Error::from(err));
         }
     }
 
@@ -218,7 +269,7 @@ fn _get_xattr(path: &Path, name: &str, symlink: bool) -> Result<String> {
     };
 
     if ret == -1 {
-        return Err(Error::from(io::Error::last_os_error()));
+        return Err(read_error());
     }
 
     let ret = ret as usize;
@@ -240,9 +291,19 @@ fn _list_xattrs(path: &Path, symlink: bool) -> Result<Vec<(String, String)>> {
     let keys = parse_xattrs(&raw);
 
     let mut attrs = Vec::new();
+    let mut seen = HashSet::new();
 
     for key in keys {
-        attrs.push((key.clone(), _get_xattr(path, key.as_str(), symlink)?));
+        let value = match _get_xattr(path, key.as_str(), symlink) {
+            // the attribute was removed after the attributes were listed
+            Err(Error::TagNotFound(name)) if name.is_empty() => return Err(Error::AttrsChanged),
+            result => result?,
+        };
+        // some filesystems list the same key more than once
+        let xattr = Xattr::new(key, value);
+        if seen.insert(xattr.clone()) {
+            attrs.push(xattr.into());
+        }
     }
 
     Ok(attrs)
@@ -252,6 +313,18 @@ fn _list_xattrs(path: &Path, symlink: bool) -> Result<Vec<(String, String)>> {
 // Other
 //################################################################################
 
+/// Error of a call reading into a buffer sized by a preceding size query. Attributes growing in
+/// between make the buffer too small which is reported as `Error::AttrsChanged` so that callers
+/// can retry.
+fn read_error() -> Error {
+    let e = io::Error::last_os_error();
+    if e.raw_os_error() == Some(libc::ERANGE) {
+        Error::AttrsChanged
+    } else {
+        Error::from(e)
+    }
+}
+
 fn get_xattr_size(path: &CStr, name: &CStr, symlink: bool) -> Result<usize> {
     let ret = unsafe { __getxattr(path.as_ptr(), name.as_ptr(), ptr::null_mut(), 0, symlink) };
 
@@ -282,7 +355,7 @@ fn list_xattrs_raw(path: &CStr, symlink: bool) -> Result<Vec<u8>> {
     let ret = unsafe { __listxattr(path.as_ptr(), buf_ptr as *mut c_char, size, symlink) };
 
     if ret == -1 {
-        return Err(Error::from(io::Error::last_os_error()));
+        return Err(read_error());
     }
 
     let ret = ret as usize;
@@ -330,3 +403,60 @@ fn parses_xattrs_from_raw() {
     assert_eq!(it.next(), Some(&"user.key3".to_string()));
     assert_eq!(it.next(), Some(&"security.testing".to_string()));
 }
+
+#[test]
+fn copies_xattrs_with_prefix() {
+    use std::fs;
+    let dir = tempdir::TempDir::new("wutag-copy-xattrs").unwrap();
+    let (src, dst) = (dir.path().join("src"), dir.path().join("dst"));
+    fs::write(&src, b"").unwrap();
+    fs::write(&dst, b"").unwrap();
+    set_xattr(&src, "user.wutag.Y29kZQ==", "code", true).unwrap();
+    set_xattr(&src, "user.wutag.cnVzdA==", "rust", true).unwrap();
+    set_xattr(&src, "user.other", "other", true).unwrap();
+    set_xattr(&dst, "user.wutag.cnVzdA==", "existing", true).unwrap();
+
+    assert_eq!(copy_xattrs(&src, &dst, Some("user.wutag")).unwrap(), 1);
+    let mut copied = list_xattrs(&dst, true).unwrap();
+    copied.sort_unstable();
+    assert_eq!(
+        copied,
+        vec![
+            ("user.wutag.Y29kZQ==".to_string(), "code".to_string()),
+            ("user.wutag.cnVzdA==".to_string(), "existing".to_string()),
+        ]
+    );
+
+    assert_eq!(copy_xattrs(&src, &dst, None).unwrap(), 1);
+    assert_eq!(get_xattr(&dst, "user.other", true).unwrap(), "other");
+    assert_eq!(copy_xattrs(&src, &dst, None).unwrap(), 0);
+}
+
+#[test]
+fn follows_symlinks_unless_told_otherwise() {
+    use std::fs;
+    let dir = tempdir::TempDir::new("wutag-symlink-xattrs").unwrap();
+    let (target, link) = (dir.path().join("target"), dir.path().join("link"));
+    fs::write(&target, b"").unwrap();
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    set_xattr(&link, "user.followed", "target", true).unwrap();
+    assert_eq!(get_xattr(&target, "user.followed", true).unwrap(), "target");
+    assert_eq!(get_xattr(&link, "user.followed", true).unwrap(), "target");
+    assert!(get_xattr(&link, "user.followed", false).is_err());
+    assert!(list_xattrs(&link, false).unwrap().is_empty());
+
+    // Linux only allows `user.*` attributes on regular files and directories
+    match set_xattr(&link, "user.linked", "link", false) {
+        Ok(()) => {
+            assert_eq!(get_xattr(&link, "user.linked", false).unwrap(), "link");
+            remove_xattr(&link, "user.linked", false).unwrap();
+        }
+        Err(_) if cfg!(target_os = "linux") => {}
+        Err(e) => panic!("{e}"),
+    }
+    assert!(get_xattr(&target, "user.linked", true).is_err());
+
+    remove_xattr(&link, "user.followed", true).unwrap();
+    assert!(list_xattrs(&target, true).unwrap().is_empty());
+}
@@ -4,7 +4,7 @@ use std::path::Path;
 
 use crate::Result;
 
-pub fn set_xattr<P, S>(path: P, name: S, value: S) -> Result<()>
+pub fn set_xattr<P, S>(path: P, name: S, value: S, follow: bool) -> Result<()>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
@@ -12,7 +12,15 @@ where
     Ok(())
 }
 
-pub fn get_xattr<P, S>(path: P, name: S) -> Result<String>
+pub fn replace_xattr<P, S>(path: P, name: S, value: S, follow: bool) -> Result<()>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    Ok(())
+}
+
+pub fn get_xattr<P, S>(path: P, name: S, follow: bool) -> Result<String>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
@@ -20,14 +28,18 @@ where
     Ok(String::new())
 }
 
-pub fn list_xattrs<P>(path: P) -> Result<Vec<(String, String)>>
+pub fn list_xattrs<P>(path: P, follow: bool) -> Result<Vec<(String, String)>>
 where
     P: AsRef<Path>,
 {
     Ok(Vec::new())
 }
 
-pub fn remove_xattr<P, S>(path: P, name: S) -> Result<()>
+pub fn copy_xattrs(src: &Path, dst: &Path, prefix: Option<&str>) -> Result<usize> {
+    Ok(0)
+}
+
+pub fn remove_xattr<P, S>(path: P, name: S, follow: bool) -> Result<()>
 where
     P: AsRef<Path>,
     S: AsRef<str>,
@@ -0,0 +1,309 @@
+//! Append-only log of requests modifying tags, recording who made them and when.
+use crate::registry::data_dir;
+use chrono::{DateTime, Utc};
+use once_cell::sync::OnceCell;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error as ThisError;
+use wutag_core::glob::Glob;
+use wutag_ipc::{AuditLogEntry, Request};
+
+#[derive(Debug, ThisError)]
+pub enum AuditLogError {
+    #[error("failed to open audit log `{0}` - {1}")]
+    Open(PathBuf, io::Error),
+    #[error("failed to serialize audit log entry - {0}")]
+    Serialize(serde_cbor::Error),
+    #[error("failed to write audit log `{0}` - {1}")]
+    Write(PathBuf, io::Error),
+    #[error("failed to read audit log `{0}` - {1}")]
+    Read(PathBuf, io::Error),
+    #[error("failed to rotate audit log `{0}` - {1}")]
+    Rotate(PathBuf, io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, AuditLogError>;
+
+/// Size of the audit log after which it is rotated. Only the last rotated log is kept so the
+/// entries take up at most twice this size.
+const MAX_AUDIT_LOG_SIZE: u64 = 4 * 1024 * 1024;
+
+static AUDIT_LOG_FILE: OnceCell<PathBuf> = OnceCell::new();
+
+/// Returns the path of the audit log file.
+pub fn audit_log_file() -> PathBuf {
+    AUDIT_LOG_FILE
+        .get_or_init(|| data_dir().join("wutag-audit.cbor"))
+        .clone()
+}
+
+/// Overrides the path of the audit log file. Has to be called before anything is logged,
+/// returns `false` if the path was already set.
+#[cfg(test)]
+pub fn set_audit_log_file(path: PathBuf) -> bool {
+    AUDIT_LOG_FILE.set(path).is_ok()
+}
+
+/// Path of the previous audit log that the log at `path` is rotated to.
+fn rotated_file(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".1");
+    path.with_file_name(name)
+}
+
+/// Returns the name of the user with the id `uid`. Falls back to the id itself if the user has
+/// no name and to `unknown` if the id isn't known.
+pub fn user_name(uid: Option<u32>) -> String {
+    let Some(uid) = uid else {
+        return "unknown".into();
+    };
+    #[cfg(target_os = "linux")]
+    {
+        let mut passwd = std::mem::MaybeUninit::<libc::passwd>::uninit();
+        let mut buf = vec![0 as libc::c_char; 4096];
+        let mut result = std::ptr::null_mut();
+        // SAFETY: `passwd`, `buf` and `result` outlive the call and `buf.len()` is the size of
+        // `buf`, the name is only read if an entry was found and it points into `buf`
+        let ret = unsafe {
+            libc::getpwuid_r(
+                uid,
+                passwd.as_mut_ptr(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        if ret == 0 && !result.is_null() {
+            // SAFETY: see above
+            let name = unsafe { std::ffi::CStr::from_ptr((*result).pw_name) };
+            return name.to_string_lossy().into_owned();
+        }
+    }
+    uid.to_string()
+}
+
+/// Path matched by the `glob`, logged in place of the files of pattern requests.
+fn glob_path(glob: &Glob) -> Vec<PathBuf> {
+    vec![glob.base_dir.join(&glob.pattern)]
+}
+
+/// Describes the `request` made by the `user` as an entry of the audit log or returns `None` if it
+/// doesn't modify anything. The entry is marked as succeeded until the outcome is known.
+pub fn audit_entry(request: &Request, user: String) -> Option<AuditLogEntry> {
+    let names = |tags: &[wutag_core::tag::Tag]| tags.iter().map(|t| t.name().to_string()).collect();
+    let (operation, paths, tags) = match request {
+        Request::TagFiles { files, tags, .. } => ("tag", files.clone(), names(tags)),
+        Request::TagFilesPattern { glob, tags, .. } => ("tag", glob_path(glob), names(tags)),
+        Request::UntagFiles { files, tags, .. } => ("untag", files.clone(), names(tags)),
+        Request::SetExactTags { files, tags } => ("set-exact", files.clone(), names(tags)),
+        Request::UntagFilesPattern { glob, tags, .. } => ("untag", glob_path(glob), names(tags)),
+        Request::EditTag { tag, .. } => ("edit-tag", vec![], vec![tag.clone()]),
+        Request::SetTagMeta { tag, .. } => ("set-tag-meta", vec![], vec![tag.clone()]),
+        Request::CreateTag { name, .. } => ("create-tag", vec![], vec![name.clone()]),
+        Request::AbsolutizePaths { root } => ("absolutize-paths", vec![root.clone()], vec![]),
+        Request::ClearFiles { files, .. } => ("clear-files", files.clone(), vec![]),
+        Request::ClearFilesPattern { glob, .. } => ("clear-files", glob_path(glob), vec![]),
+        Request::ClearTags { tags, .. } => ("clear-tags", vec![], tags.clone()),
+        Request::ClearAllTags { .. } => ("clear-all-tags", vec![], vec![]),
+        Request::CopyTags { source, target } => {
+            let mut paths = vec![source.clone()];
+            paths.extend(target.iter().cloned());
+            ("copy-tags", paths, vec![])
+        }
+        Request::CopyTagsPattern { source, glob } => {
+            let mut paths = vec![source.clone()];
+            paths.extend(glob_path(glob));
+            ("copy-tags", paths, vec![])
+        }
+        Request::RenameTagsMatching {
+            from_pattern,
+            replacement,
+        } => (
+            "rename-tags",
+            vec![],
+            vec![from_pattern.clone(), replacement.clone()],
+        ),
+        Request::AddImplicationRule { if_tag, then_tag } => (
+            "add-implication-rule",
+            vec![],
+            vec![if_tag.clone(), then_tag.clone()],
+        ),
+        Request::ClearCache => ("clear-cache", vec![], vec![]),
+        Request::ClearRecentTags => ("clear-recent-tags", vec![], vec![]),
+        Request::MigrateNamespace { glob, .. } => ("migrate-namespace", glob_path(glob), vec![]),
+        Request::DiffWithFilesystem {
+            base_dir,
+            fix: true,
+            ..
+        } => ("diff-fs-fix", vec![base_dir.clone()], vec![]),
+        _ => return None,
+    };
+    Some(AuditLogEntry {
+        timestamp: Utc::now(),
+        user,
+        operation: operation.to_string(),
+        paths,
+        tags,
+        succeeded: true,
+    })
+}
+
+/// Appends the `entry` to the audit log at `path` creating the file if it doesn't exist. The log
+/// is rotated once it would grow above [MAX_AUDIT_LOG_SIZE](MAX_AUDIT_LOG_SIZE).
+pub fn append(path: &Path, entry: &AuditLogEntry) -> Result<()> {
+    append_capped(path, entry, MAX_AUDIT_LOG_SIZE)
+}
+
+fn append_capped(path: &Path, entry: &AuditLogEntry, max_size: u64) -> Result<()> {
+    let data = serde_cbor::to_vec(entry).map_err(AuditLogError::Serialize)?;
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+    if size > 0 && size + data.len() as u64 > max_size {
+        fs::rename(path, rotated_file(path)).map_err(|e| AuditLogError::Rotate(path.into(), e))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| AuditLogError::Open(path.into(), e))?;
+    // the whole entry is written at once so that a partially written entry can only be the last
+    file.write_all(&data)
+        .map_err(|e| AuditLogError::Write(path.into(), e))
+}
+
+/// Reads entries of the audit log at `path` logged at or after `since`, oldest first, including
+/// the entries of the rotated log. If `limit` is set only that many of the most recent entries
+/// are returned.
+pub fn read(
+    path: &Path,
+    since: Option<DateTime<Utc>>,
+    limit: Option<usize>,
+) -> Result<Vec<AuditLogEntry>> {
+    let mut entries = VecDeque::new();
+    for path in [rotated_file(path), path.to_path_buf()] {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(AuditLogError::Read(path, e)),
+        };
+        let reader = BufReader::new(file);
+        for entry in serde_cbor::Deserializer::from_reader(reader).into_iter::<AuditLogEntry>() {
+            match entry {
+                Ok(entry) if since.map_or(true, |since| entry.timestamp >= since) => {
+                    // only the most recent entries are kept in memory
+                    if limit.is_some_and(|limit| entries.len() >= limit) {
+                        entries.pop_front();
+                    }
+                    if limit != Some(0) {
+                        entries.push_back(entry);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) if e.is_io() => {
+                    return Err(AuditLogError::Read(path, io::Error::other(e.to_string())))
+                }
+                Err(e) => {
+                    // most likely the daemon was stopped while writing the last entry
+                    log::warn!("skipping the rest of audit log `{}` - {e}", path.display());
+                    break;
+                }
+            }
+        }
+    }
+    Ok(entries.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use wutag_core::color::Color;
+    use wutag_core::tag::Tag;
+
+    #[test]
+    fn appends_and_reads_entries() {
+        let dir = TempDir::new("wutag-audit").unwrap();
+        let path = dir.path().join("audit.cbor");
+        assert!(read(&path, None, None).unwrap().is_empty());
+
+        let tag = |file: &str| {
+            audit_entry(
+                &Request::TagFiles {
+                    files: vec![file.into()],
+                    tags: vec![Tag::new("audited", Color::Red)],
+                    auto_tag: false,
+                    rules: vec![],
+                    expires_in: None,
+                    no_follow: false,
+                    palette: vec![],
+                },
+                "tester".into(),
+            )
+            .unwrap()
+        };
+        let mut first = tag("/a");
+        first.timestamp -= chrono::Duration::hours(2);
+        append(&path, &first).unwrap();
+        append(&path, &tag("/b")).unwrap();
+        append(&path, &tag("/c")).unwrap();
+        assert!(audit_entry(&Request::Stats, "tester".into()).is_none());
+
+        let entries = read(&path, None, None).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].operation, "tag");
+        assert_eq!(entries[0].tags, vec!["audited".to_string()]);
+        assert_eq!(entries[0].user, "tester");
+
+        let paths = |entries: Vec<AuditLogEntry>| {
+            entries
+                .into_iter()
+                .flat_map(|e| e.paths)
+                .collect::<Vec<_>>()
+        };
+        let since = Utc::now() - chrono::Duration::hours(1);
+        assert_eq!(
+            paths(read(&path, Some(since), None).unwrap()),
+            vec![PathBuf::from("/b"), PathBuf::from("/c")]
+        );
+        assert_eq!(
+            paths(read(&path, None, Some(1)).unwrap()),
+            vec![PathBuf::from("/c")]
+        );
+    }
+
+    #[test]
+    fn rotates_log() {
+        let dir = TempDir::new("wutag-audit-rotate").unwrap();
+        let path = dir.path().join("audit.cbor");
+        let entry = |tag: &str| AuditLogEntry {
+            timestamp: Utc::now(),
+            user: "tester".into(),
+            operation: "create-tag".into(),
+            paths: vec![],
+            tags: vec![tag.into()],
+            succeeded: true,
+        };
+        let size = serde_cbor::to_vec(&entry("0")).unwrap().len() as u64;
+
+        // the log fits two entries so every second entry rotates it
+        for tag in ["0", "1", "2", "3", "4"] {
+            append_capped(&path, &entry(tag), size * 2).unwrap();
+        }
+        assert!(rotated_file(&path).exists());
+        let tags = |entries: Vec<AuditLogEntry>| {
+            entries.into_iter().flat_map(|e| e.tags).collect::<Vec<_>>()
+        };
+        assert_eq!(tags(read(&path, None, None).unwrap()), vec!["2", "3", "4"]);
+        assert_eq!(tags(read(&path, None, Some(2)).unwrap()), vec!["3", "4"]);
+    }
+
+    #[test]
+    fn resolves_user_names() {
+        assert_eq!(user_name(None), "unknown");
+        assert_eq!(user_name(Some(u32::MAX - 1)), (u32::MAX - 1).to_string());
+        #[cfg(target_os = "linux")]
+        assert_eq!(user_name(Some(0)), "root");
+    }
+}
@@ -0,0 +1,190 @@
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io};
+use thiserror::Error as ThisError;
+use wutag_core::registry::{EvictionPolicy, RegistryMount};
+use wutag_core::tag::ImplicationRule;
+
+const CONFIG_FILE: &str = "wutagd.yml";
+/// Environment variable overriding the path of the configuration file.
+pub const CONFIG_ENV: &str = "WUTAGD_CONFIG";
+
+#[derive(Debug, ThisError)]
+pub enum ConfigError {
+    #[error("failed to load configuration `{0}` - {1}")]
+    Load(PathBuf, io::Error),
+    #[error("failed to deserialize configuration `{0}` - {1}")]
+    Deserialize(PathBuf, serde_yaml::Error),
+    #[error("failed to save configuration `{0}` - {1}")]
+    Save(PathBuf, io::Error),
+    #[error("failed to serialize configuration - {0}")]
+    Serialize(serde_yaml::Error),
+    #[error("failed to determine the config directory")]
+    NoConfigDir,
+    #[error("invalid maintenance schedule `{0}` - {1}")]
+    InvalidSchedule(String, cron::error::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ConfigError>;
+
+/// Decides how the daemon watches tagged files for changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// Watch each tagged file separately.
+    #[default]
+    Files,
+    /// Watch each parent directory of tagged files non-recursively. Uses one watch per
+    /// directory containing tagged files instead of one per file at the cost of receiving events
+    /// of untagged files in those directories. Directories are never watched recursively as
+    /// recursive inotify watches need a watch for every subdirectory. Also accepted as
+    /// `directories`.
+    #[serde(alias = "directories")]
+    Parents,
+}
+
+/// Default interval of checking files for changes with [NotifyBackend::Poll](NotifyBackend::Poll).
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+
+/// Mechanism used to receive notifications about changes of tagged files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyBackend {
+    /// Use the native backend of the system and fall back to polling if it is not available.
+    #[default]
+    Auto,
+    /// Use the native backend of the system like inotify on Linux.
+    Native,
+    /// Periodically check metadata of watched files. Works on network filesystems like NFS or
+    /// SMB where native notifications are not delivered but doesn't detect changes of tags.
+    Poll { interval_ms: u64 },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DaemonConfig {
+    /// Whether to watch tagged files for changes at all. When disabled the daemon only serves
+    /// requests and removed files stay in the registry until they are cleared.
+    #[serde(default = "default_watch")]
+    pub watch: bool,
+    #[serde(default)]
+    pub watch_mode: WatchMode,
+    /// Interval in minutes at which entries of files that no longer exist are removed from the
+    /// registries, `0` disables the sweep.
+    #[serde(default)]
+    pub sweep_interval_mins: u64,
+    /// Whether the sweep also updates tags of entries to match the tags saved in their files.
+    #[serde(default)]
+    pub sweep_verify_tags: bool,
+    #[serde(default)]
+    pub notify_backend: NotifyBackend,
+    /// Rules adding tags to files tagged with other tags.
+    #[serde(default)]
+    pub implication_rules: Vec<ImplicationRule>,
+    /// Directories whose files are tracked in separate registries instead of the default one.
+    #[serde(default)]
+    pub registry_mounts: Vec<RegistryMount>,
+    /// Whether connections of clients running as other users than the daemon are rejected.
+    /// Only enforced on Linux.
+    #[serde(default = "default_require_same_user")]
+    pub require_same_user: bool,
+    /// Maximum number of entries of each registry. Entries over the limit are evicted along
+    /// with the tags saved in their files, the number of entries is not limited if unset.
+    #[serde(default)]
+    pub max_registry_entries: Option<usize>,
+    /// Decides which entries are evicted first when there are more than `max_registry_entries`.
+    #[serde(default)]
+    pub eviction_policy: EvictionPolicy,
+    /// Cron expression with seconds, like `0 0 3 * * *` for every night at 3:00 UTC, at which
+    /// stale entries are removed and the registries are backed up. Disabled if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance_cron: Option<String>,
+    /// Whether maintenance also removes expired tags, they are otherwise removed by the notify
+    /// daemon which only runs when watching files.
+    #[serde(default)]
+    pub maintenance_prune_expired_tags: bool,
+}
+
+fn default_watch() -> bool {
+    true
+}
+
+fn default_require_same_user() -> bool {
+    true
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            watch: default_watch(),
+            watch_mode: WatchMode::default(),
+            sweep_interval_mins: 0,
+            sweep_verify_tags: false,
+            notify_backend: NotifyBackend::default(),
+            implication_rules: vec![],
+            registry_mounts: vec![],
+            require_same_user: default_require_same_user(),
+            max_registry_entries: None,
+            eviction_policy: EvictionPolicy::default(),
+            maintenance_cron: None,
+            maintenance_prune_expired_tags: false,
+        }
+    }
+}
+
+impl DaemonConfig {
+    /// Loads the config from the file at `path`.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|e| ConfigError::Load(path.into(), e))?;
+        let config: Self =
+            serde_yaml::from_slice(&data).map_err(|e| ConfigError::Deserialize(path.into(), e))?;
+        config.maintenance_schedule()?;
+        Ok(config)
+    }
+
+    /// Parses [maintenance_cron](DaemonConfig::maintenance_cron), `None` if it is not set.
+    pub fn maintenance_schedule(&self) -> Result<Option<Schedule>> {
+        self.maintenance_cron
+            .as_deref()
+            .map(|cron| {
+                Schedule::from_str(cron).map_err(|e| ConfigError::InvalidSchedule(cron.into(), e))
+            })
+            .transpose()
+    }
+
+    /// Saves the config to the file at `path` creating missing parent directories.
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let data = serde_yaml::to_string(self).map_err(ConfigError::Serialize)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| ConfigError::Save(path.into(), e))?;
+        }
+        fs::write(path, data).map_err(|e| ConfigError::Save(path.into(), e))
+    }
+
+    /// Returns the path specified by [CONFIG_ENV](CONFIG_ENV) or the path of the config file in
+    /// the config directory of the user.
+    pub fn default_path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return Ok(path.into());
+        }
+        dirs::config_dir()
+            .map(|dir| dir.join(CONFIG_FILE))
+            .ok_or(ConfigError::NoConfigDir)
+    }
+
+    /// Loads the config from the file specified by [CONFIG_ENV](CONFIG_ENV) or from the config
+    /// directory of the user. Returns the default config if the file in the config directory
+    /// doesn't exist.
+    pub fn load_default_location() -> Result<Self> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return Self::load_file(path);
+        }
+        match dirs::config_dir().map(|dir| dir.join(CONFIG_FILE)) {
+            Some(path) if path.exists() => Self::load_file(path),
+            _ => Ok(Self::default()),
+        }
+    }
+}
@@ -1,11 +1,32 @@
-use crate::registry::{get_registry_read, get_registry_write};
-use crate::{EntryEvent, Result, ENTRIES_EVENTS};
-use std::path::PathBuf;
+use crate::audit;
+use crate::config::DaemonConfig;
+use crate::maintenance;
+use crate::registry::{self, get_registry_read, get_registry_write, Scope};
+use crate::{
+    add_subscriber, has_subscribers, notify_subscribers, EntryEvent, Result, ENTRIES_EVENTS,
+    LOG_LEVEL_LIMIT, WATCH_FAILURES,
+};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use thiserror::Error as ThisError;
-use wutag_core::color::{Color, DEFAULT_COLORS};
-use wutag_core::registry::EntryData;
-use wutag_core::tag::{clear_tags, list_tags, Tag};
-use wutag_ipc::{IpcError, IpcServer, PayloadResult, Request, Response};
+use wutag_core::color::{color_to_hex, Color, DEFAULT_COLORS};
+use wutag_core::glob::Glob;
+use wutag_core::registry::{
+    EntryData, EntryId, EvictionPolicy, RecordedChanges, TagRegistry, DESCRIPTION_META_KEY,
+};
+use wutag_core::tag::{
+    auto_tag_file, clear_tags, implied_tags, list_tags, ImplicationRule, Tag, TagFilter,
+    TagPattern, TagRule, DEFAULT_COLOR, WEIGHT_RANGE,
+};
+use wutag_core::xattr::{copy_xattrs, list_xattrs, xattr_capacity};
+use wutag_core::{Error as CoreError, WUTAG_NAMESPACE};
+use wutag_ipc::{
+    BatchSummary, ErrorCode, FileTagChanges, InspectFilesResult, IpcError, IpcServer, Page, Paged,
+    PayloadResult, RegistryStats, Request, Response, ResponseError, SetExactTagsResult,
+    TagCapacity,
+};
 
 #[derive(Debug, ThisError)]
 pub enum DaemonError {
@@ -15,16 +36,285 @@ pub enum DaemonError {
     SendResponse(IpcError),
 }
 
+/// Time window in which identical mutating requests are only processed once.
+const DEDUP_WINDOW: Duration = Duration::from_secs(1);
+
+/// Whether the `request` modifies the registry or files. Reads are idempotent so they are never
+/// deduplicated.
+fn is_mutating(request: &Request) -> bool {
+    matches!(
+        request,
+        Request::TagFiles { .. }
+            | Request::TagFilesPattern { .. }
+            | Request::UntagFiles { .. }
+            | Request::UntagFilesPattern { .. }
+            | Request::SetExactTags { .. }
+            | Request::EditTag { .. }
+            | Request::SetTagMeta { .. }
+            | Request::CreateTag { .. }
+            | Request::AbsolutizePaths { .. }
+            | Request::ClearFiles { .. }
+            | Request::ClearFilesPattern { .. }
+            | Request::ClearTags { .. }
+            | Request::ClearAllTags { .. }
+            | Request::CopyTags { .. }
+            | Request::CopyTagsPattern { .. }
+            | Request::RenameTagsMatching { .. }
+            | Request::AddImplicationRule { .. }
+            | Request::ClearCache
+            | Request::ClearRecentTags
+            | Request::MigrateNamespace { .. }
+            | Request::DiffWithFilesystem { fix: true, .. }
+    )
+}
+
+/// Hash identifying repeated `request`s. Tags built by the CLI get random colors and the same
+/// files may be listed in a different order, so the hash only covers the kind of the request,
+/// its options, sorted paths and names of tags.
+fn dedup_key(request: &Request) -> serde_cbor::Result<blake3::Hash> {
+    fn canonical_tags(tags: &mut [Tag]) {
+        for tag in tags.iter_mut() {
+            tag.set_color(&DEFAULT_COLOR);
+        }
+        tags.sort_by(|a, b| a.name().cmp(b.name()));
+    }
+
+    let mut request = request.clone();
+    match &mut request {
+        Request::TagFiles { files, tags, .. }
+        | Request::UntagFiles { files, tags, .. }
+        | Request::SetExactTags { files, tags } => {
+            files.sort();
+            canonical_tags(tags);
+        }
+        Request::TagFilesPattern { tags, .. } | Request::UntagFilesPattern { tags, .. } => {
+            canonical_tags(tags)
+        }
+        Request::ClearFiles { files, .. } => files.sort(),
+        Request::ClearTags { tags, .. } => tags.sort(),
+        Request::CopyTags { target, .. } => target.sort(),
+        _ => {}
+    }
+    serde_cbor::to_vec(&request).map(|bytes| blake3::hash(&bytes))
+}
+
+/// Paths of the files of the `request` used to select the registry it is processed with.
+fn request_paths(request: &Request) -> &[PathBuf] {
+    match request {
+        Request::TagFiles { files, .. }
+        | Request::UntagFiles { files, .. }
+        | Request::SetExactTags { files, .. }
+        | Request::InspectFiles { files, .. }
+        | Request::ClearFiles { files, .. }
+        | Request::CopyTags { target: files, .. } => files,
+        Request::TagFilesPattern { glob, .. }
+        | Request::UntagFilesPattern { glob, .. }
+        | Request::InspectFilesPattern { glob, .. }
+        | Request::ClearFilesPattern { glob, .. }
+        | Request::CopyTagsPattern { glob, .. }
+        | Request::MigrateNamespace { glob, .. } => std::slice::from_ref(&glob.base_dir),
+        Request::DiffWithFilesystem { base_dir, .. } => std::slice::from_ref(base_dir),
+        _ => &[],
+    }
+}
+
+/// Scope of the registry of the mount containing the files of the `request`. Requests without
+/// files use the default registry. If the files are inside of multiple mounts returns the first
+/// file of each of them as a request is only processed with a single registry.
+fn request_scope(request: &Request) -> std::result::Result<Scope, Vec<PathBuf>> {
+    let mut scopes: Vec<(Scope, &PathBuf)> = vec![];
+    for path in request_paths(request) {
+        let scope = registry::path_scope(path);
+        if !scopes.iter().any(|(s, _)| *s == scope) {
+            scopes.push((scope, path));
+        }
+    }
+    let mut paths: Vec<_> = scopes.iter().map(|(_, path)| (*path).clone()).collect();
+    if let Request::DiffWithFilesystem { base_dir, .. } = request {
+        // files of mounts inside of the directory are missing from its registry
+        paths.extend(registry::mounts_below(base_dir));
+    }
+    if paths.len() > 1 {
+        return Err(paths);
+    }
+    Ok(scopes.first().and_then(|(scope, _)| *scope))
+}
+
+/// Size of the name and value of the xattr of a tag with a short name and no description, used
+/// to estimate the capacity of files without tags.
+const TYPICAL_TAG_SIZE: usize = 40;
+
+/// Maximum number of tags suggested in place of a tag that doesn't exist.
+const MAX_SUGGESTED_TAGS: usize = 5;
+
+/// Number of the most used tags reported in registry stats.
+const MOST_USED_TAGS: usize = 5;
+
+/// Describes how symlinks of an entry with `no_follow` are tagged.
+fn symlink_handling(no_follow: bool) -> &'static str {
+    if no_follow {
+        "as a symlink"
+    } else {
+        "following the symlink"
+    }
+}
+
+/// Error of a request referring to the tag `name` that doesn't exist. Tags with the same name
+/// in a different case or with names containing one another are suggested instead.
+fn tag_not_found(registry: &TagRegistry, name: &str) -> ResponseError {
+    let lowercase = name.to_lowercase();
+    let mut suggested: Vec<_> = registry
+        .list_tags()
+        .map(Tag::name)
+        .filter(|tag| {
+            let tag = tag.to_lowercase();
+            tag.contains(&lowercase) || lowercase.contains(&tag)
+        })
+        .collect();
+    suggested.sort_unstable();
+    suggested.truncate(MAX_SUGGESTED_TAGS);
+    ResponseError::new(
+        ErrorCode::TagNotFound,
+        format!("tag `{name}` doesn't exist"),
+    )
+    .with_details(serde_json::json!({
+        "tag": name,
+        "suggested_tags": suggested,
+    }))
+}
+
+/// Error of a request with an invalid argument like an empty list of files.
+fn invalid_argument(message: impl Into<String>) -> ResponseError {
+    ResponseError::new(ErrorCode::InvalidArgument, message)
+}
+
+/// Error of a request referring to the file at `path` that isn't tracked in the registry.
+fn entry_not_found(path: &Path) -> ResponseError {
+    ResponseError::new(
+        ErrorCode::EntryNotFound,
+        format!("`{}` isn't tagged", path.display()),
+    )
+    .with_details(serde_json::json!({ "path": path }))
+}
+
+/// Error of loading, locking or saving the registry.
+fn registry_error(e: impl std::fmt::Display) -> ResponseError {
+    ResponseError::new(ErrorCode::Registry, e.to_string())
+}
+
+/// Error of reading or writing extended attributes of the file at `path`.
+fn filesystem_error(path: &Path, e: impl std::fmt::Display) -> ResponseError {
+    ResponseError::new(ErrorCode::Filesystem, format!("`{}` - {e}", path.display()))
+        .with_details(serde_json::json!({ "path": path }))
+}
+
+/// Error of a request with the `glob` pattern that couldn't be expanded.
+fn pattern_error(glob: &Glob, e: CoreError) -> ResponseError {
+    ResponseError::new(ErrorCode::Pattern, e.to_string())
+        .with_details(serde_json::json!({ "pattern": glob.pattern }))
+}
+
+/// Whether the tag named `name` is a system tag protected from removal without `force`.
+fn is_system_tag(registry: &TagRegistry, name: &str) -> bool {
+    registry
+        .get_tag(name)
+        .map(|t| t.is_system())
+        .unwrap_or(false)
+}
+
+/// Response to a request modifying files matched by the `glob` when it matched no files.
+fn no_matches(glob: &Glob) -> Response {
+    log::warn!(
+        "pattern `{}` matched no files in `{}`",
+        glob.pattern,
+        glob.base_dir.display()
+    );
+    Response::NoMatches(glob.pattern.clone())
+}
+
+/// Evicts entries over the limit of `max_entries` in the order of the `policy` removing their
+/// tags from the files. Entries in `keep` are never evicted. Returns paths of the evicted entries.
+fn evict_excess_entries(
+    registry: &mut TagRegistry,
+    max_entries: usize,
+    policy: EvictionPolicy,
+    keep: &HashSet<EntryId>,
+) -> Vec<PathBuf> {
+    let mut evicted = vec![];
+    for id in registry.entries_to_evict(max_entries, policy, keep) {
+        let Some(entry) = registry.get_entry(id).cloned() else {
+            continue;
+        };
+        for tag in registry.list_entry_tags(id).unwrap_or_default() {
+            if let Err(e) = tag.remove_from(entry.path(), !entry.no_follow()) {
+                log::debug!(
+                    "failed to untag evicted entry `{}` - {e}",
+                    entry.path().display()
+                );
+            }
+        }
+        registry.clear_entry(id);
+        log::warn!(
+            "evicted entry `{}`, the registry is limited to {max_entries} entries",
+            entry.path().display()
+        );
+        evicted.push(entry.into_path_buf());
+    }
+    evicted
+}
+
+/// Whether the `response` to a mutating request reports success.
+fn is_success(response: &Response) -> bool {
+    match response {
+        Response::TagFiles(PayloadResult::Ok(summary))
+        | Response::UntagFiles(PayloadResult::Ok(summary))
+        | Response::ClearFiles(PayloadResult::Ok(summary))
+        | Response::CopyTags(PayloadResult::Ok(summary)) => summary.failed == 0,
+        Response::SetExactTags(PayloadResult::Ok(result)) => result.summary.failed == 0,
+        response => matches!(
+            response,
+            Response::EditTag(PayloadResult::Ok(_))
+                | Response::SetTagMeta(PayloadResult::Ok(_))
+                | Response::CreateTag(PayloadResult::Ok(_))
+                | Response::AbsolutizePaths(PayloadResult::Ok(_))
+                | Response::ClearTags(PayloadResult::Ok(_))
+                | Response::RenameTagsMatching(PayloadResult::Ok(_))
+                | Response::AddImplicationRule(PayloadResult::Ok(_))
+                | Response::ClearCache(PayloadResult::Ok(_))
+                | Response::ClearRecentTags(PayloadResult::Ok(_))
+                | Response::DiffWithFilesystem(PayloadResult::Ok(_))
+        ),
+    }
+}
+
 pub struct WutagDaemon {
     listener: IpcServer,
     unprocessed_events: Vec<EntryEvent>,
+    implication_rules: Vec<ImplicationRule>,
+    /// Hashes of recently processed mutating requests along with the time they were processed
+    /// and their serialized response.
+    recent_requests: HashMap<blake3::Hash, (Instant, Vec<u8>)>,
+    /// Whether the notify daemon is running and consuming entries events.
+    watch: bool,
+    max_registry_entries: Option<usize>,
+    eviction_policy: EvictionPolicy,
+    maintenance_prune_expired_tags: bool,
+    /// Id of the user running the client of the request being processed.
+    client_uid: Option<u32>,
 }
 
 impl WutagDaemon {
-    pub fn new(listener: IpcServer) -> Result<Self> {
+    pub fn new(listener: IpcServer, config: &DaemonConfig) -> Result<Self> {
         Ok(Self {
             listener,
             unprocessed_events: vec![],
+            implication_rules: config.implication_rules.clone(),
+            recent_requests: HashMap::new(),
+            watch: config.watch,
+            max_registry_entries: config.max_registry_entries,
+            eviction_policy: config.eviction_policy,
+            maintenance_prune_expired_tags: config.maintenance_prune_expired_tags,
+            client_uid: None,
         })
     }
 
@@ -44,8 +334,32 @@ impl WutagDaemon {
             .listener
             .accept_request()
             .map_err(DaemonError::AcceptRequest)?;
+        self.client_uid = self.listener.peer_uid();
+        let (scope, request) = match request {
+            Request::Scoped { registry, request } => match registry::mount_scope(&registry) {
+                Some(scope) => (scope, *request),
+                None => {
+                    return self
+                        .listener
+                        .send_response(Response::UnknownMount(registry))
+                        .map_err(|e| DaemonError::SendResponse(e).into())
+                }
+            },
+            request => match request_scope(&request) {
+                Ok(scope) => (scope, request),
+                Err(paths) => {
+                    return self
+                        .listener
+                        .send_response(Response::MixedMounts(paths))
+                        .map_err(|e| DaemonError::SendResponse(e).into())
+                }
+            },
+        };
+        if let Request::Subscribe = request {
+            return self.subscribe();
+        }
         let timestamp = std::time::Instant::now();
-        let response = self.process_request(request);
+        let response = registry::with_scope(scope, || self.handle_request(request));
         self.listener
             .send_response(response)
             .map_err(DaemonError::SendResponse)?;
@@ -54,6 +368,70 @@ impl WutagDaemon {
         Ok(())
     }
 
+    /// Keeps the connection of the subscribe request open to push changes of tags to it.
+    fn subscribe(&mut self) -> Result<()> {
+        let mut subscriber = self
+            .listener
+            .detach_connection()
+            .map_err(DaemonError::SendResponse)?;
+        subscriber
+            .send(&Response::Subscribe(PayloadResult::Ok(())))
+            .map_err(DaemonError::SendResponse)?;
+        add_subscriber(subscriber);
+        log::debug!("client subscribed to tag changes");
+        Ok(())
+    }
+
+    /// Pushes events with changes of tags of entries recorded in the current registry.
+    fn push_tag_changes(&mut self) {
+        let RecordedChanges { before, after } = match get_registry_write() {
+            Ok(mut registry) => registry.take_changes(),
+            Err(e) => {
+                log::error!("{e}");
+                return;
+            }
+        };
+        let mut paths: Vec<_> = before.keys().chain(after.keys()).collect();
+        paths.sort_unstable();
+        paths.dedup();
+        for path in paths {
+            let event = EntryEvent::tags_changed(
+                path,
+                before.get(path).map(Vec::as_slice).unwrap_or_default(),
+                after.get(path).map(Vec::as_slice).unwrap_or_default(),
+            );
+            if let Some(event) = event {
+                self.push_event(event);
+            }
+        }
+    }
+
+    /// Evicts entries of the current registry over the `max_registry_entries` limit. Entries
+    /// changed by the current request are kept, the changes have to be recorded.
+    fn enforce_entry_limit(&mut self) {
+        let Some(max_entries) = self.max_registry_entries else {
+            return;
+        };
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => {
+                log::error!("{e}");
+                return;
+            }
+        };
+        let touched = registry.changed_entries();
+        let evicted =
+            evict_excess_entries(&mut registry, max_entries, self.eviction_policy, &touched);
+        if evicted.is_empty() {
+            return;
+        }
+        if let Err(e) = registry.save() {
+            log::error!("{e}");
+        }
+        drop(registry);
+        self.push_event(EntryEvent::Remove(evicted));
+    }
+
     fn flush_events(&mut self) {
         match ENTRIES_EVENTS.try_write() {
             Ok(mut events) => events.append(&mut self.unprocessed_events),
@@ -64,6 +442,22 @@ impl WutagDaemon {
     }
 
     fn push_event(&mut self, event: EntryEvent) {
+        if !self.watch {
+            // nothing would consume the events, changes of tags are forwarded right away instead
+            if let EntryEvent::TagsChanged {
+                path,
+                added,
+                removed,
+            } = event
+            {
+                notify_subscribers(Response::TagChanged {
+                    path,
+                    added,
+                    removed,
+                });
+            }
+            return;
+        }
         match ENTRIES_EVENTS.try_write() {
             Ok(mut events) => {
                 events.push(event);
@@ -75,60 +469,340 @@ impl WutagDaemon {
         }
     }
 
+    /// Processes the `request` unless it repeats the last mutating request which was
+    /// successfully processed within [DEDUP_WINDOW](DEDUP_WINDOW), in which case the response of
+    /// the first request is returned again. This protects against scripts accidentally repeating
+    /// requests in a retry loop.
+    fn handle_request(&mut self, request: Request) -> Response {
+        let now = Instant::now();
+        self.recent_requests
+            .retain(|_, (processed_at, _)| now.duration_since(*processed_at) < DEDUP_WINDOW);
+
+        let mut hash = None;
+        let mut audit_entry = None;
+        let mut mutating = false;
+        if is_mutating(&request) {
+            mutating = true;
+            match dedup_key(&request) {
+                Ok(digest) => {
+                    if let Some((_, response)) = self.recent_requests.get(&digest) {
+                        match serde_cbor::from_slice(response) {
+                            Ok(response) => {
+                                log::debug!(
+                                    "skipping duplicate request received within {DEDUP_WINDOW:?}"
+                                );
+                                return response;
+                            }
+                            Err(e) => log::debug!("failed to deserialize cached response - {e}"),
+                        }
+                    }
+                    hash = Some(digest);
+                }
+                Err(e) => log::debug!("failed to serialize request for deduplication - {e}"),
+            }
+            audit_entry = audit::audit_entry(&request, audit::user_name(self.client_uid));
+        }
+
+        let push_changes = hash.is_some() && has_subscribers();
+        // entries changed by the request are never evicted right away
+        let limit_entries = mutating && self.max_registry_entries.is_some();
+        if push_changes || limit_entries {
+            match get_registry_write() {
+                Ok(mut registry) => registry.record_changes(),
+                Err(e) => log::error!("{e}"),
+            }
+        }
+        let response = self.process_request(request);
+        if limit_entries {
+            self.enforce_entry_limit();
+        }
+        if let Some(mut entry) = audit_entry {
+            entry.succeeded = is_success(&response);
+            if let Err(e) = audit::append(&audit::audit_log_file(), &entry) {
+                log::error!("{e}");
+            }
+        }
+        if push_changes {
+            self.push_tag_changes();
+        } else if limit_entries {
+            match get_registry_write() {
+                Ok(mut registry) => {
+                    registry.take_changes();
+                }
+                Err(e) => log::error!("{e}"),
+            }
+        }
+        if let Some(hash) = hash {
+            // any other modification may change the outcome of repeating earlier requests so
+            // only consecutive identical requests are deduplicated
+            self.recent_requests.clear();
+            if is_success(&response) {
+                match serde_cbor::to_vec(&response) {
+                    Ok(bytes) => {
+                        self.recent_requests.insert(hash, (Instant::now(), bytes));
+                    }
+                    Err(e) => log::debug!("failed to serialize response for deduplication - {e}"),
+                }
+            }
+        }
+        response
+    }
+
     fn process_request(&mut self, request: Request) -> Response {
         match request {
-            Request::TagFiles { files, tags } => self.tag_files(files, tags),
-            Request::TagFilesPattern { glob, tags } => match glob.glob_paths() {
-                Ok(files) => self.tag_files(files, tags),
-                Err(e) => Response::TagFiles(PayloadResult::Error(vec![e.to_string()])),
+            Request::TagFiles {
+                files,
+                tags,
+                auto_tag,
+                rules,
+                expires_in,
+                no_follow,
+                palette,
+            } => self.tag_files(
+                files,
+                tags,
+                auto_tag.then_some(rules),
+                expires_in,
+                no_follow,
+                palette,
+            ),
+            Request::TagFilesPattern {
+                glob,
+                tags,
+                auto_tag,
+                rules,
+                expires_in,
+                no_follow,
+                palette,
+            } => match glob.glob_paths() {
+                Ok(files) if files.is_empty() => no_matches(&glob),
+                Ok(files) => self.tag_files(
+                    files,
+                    tags,
+                    auto_tag.then_some(rules),
+                    expires_in,
+                    no_follow,
+                    palette,
+                ),
+                Err(e) => Response::TagFiles(PayloadResult::Detailed(pattern_error(&glob, e))),
             },
-            Request::UntagFiles { files, tags } => self.untag_files(files, tags),
-            Request::UntagFilesPattern { glob, tags } => match glob.glob_paths() {
-                Ok(files) => self.untag_files(files, tags),
-                Err(e) => Response::UntagFiles(PayloadResult::Error(vec![e.to_string()])),
+            Request::SetExactTags { files, tags } => self.set_exact_tags(files, tags),
+            Request::UntagFiles {
+                files,
+                tags,
+                no_follow,
+                force,
+            } => self.untag_files(files, tags, no_follow, force),
+            Request::UntagFilesPattern {
+                glob,
+                tags,
+                no_follow,
+                force,
+            } => match glob.glob_paths() {
+                Ok(files) if files.is_empty() => no_matches(&glob),
+                Ok(files) => self.untag_files(files, tags, no_follow, force),
+                Err(e) => Response::UntagFiles(PayloadResult::Detailed(pattern_error(&glob, e))),
             },
-            Request::ListTags { with_files } => self.list_tags(with_files),
-            Request::ListFiles { with_tags } => self.list_files(with_tags),
-            Request::InspectFiles { files } => self.inspect_files(files),
-            Request::InspectFilesPattern { glob } => match glob.glob_paths() {
-                Ok(files) => self.inspect_files(files),
-                Err(e) => Response::InspectFiles(PayloadResult::Error(e.to_string())),
+            Request::ListTags {
+                with_files,
+                page,
+                include_unused,
+            } => self.list_tags(with_files, page, include_unused),
+            Request::ListFiles { with_tags, page } => self.list_files(with_tags, page),
+            Request::InspectFiles { files, no_follow } => self.inspect_files(files, no_follow),
+            Request::InspectFilesRaw { files } => self.inspect_files_raw(files),
+            Request::XattrCapacity { file } => self.xattr_capacity(file),
+            Request::InspectFilesRawPattern { glob } => match glob.glob_paths() {
+                Ok(files) if files.is_empty() => no_matches(&glob),
+                Ok(files) => self.inspect_files_raw(files),
+                Err(e) => {
+                    Response::InspectFilesRaw(PayloadResult::Detailed(pattern_error(&glob, e)))
+                }
+            },
+            Request::InspectFilesPattern { glob, no_follow } => match glob.glob_paths() {
+                Ok(files) if files.is_empty() => {
+                    log::warn!(
+                        "pattern `{}` matched no files in `{}`",
+                        glob.pattern,
+                        glob.base_dir.display()
+                    );
+                    Response::InspectFiles(PayloadResult::Ok(InspectFilesResult {
+                        entries: vec![],
+                        match_count: 0,
+                        expiries: HashMap::new(),
+                    }))
+                }
+                Ok(files) => self.inspect_files(files, no_follow),
+                Err(e) => Response::InspectFiles(PayloadResult::Detailed(pattern_error(&glob, e))),
             },
-            Request::ClearFiles { files } => self.clear_files(files),
-            Request::ClearFilesPattern { glob } => match glob.glob_paths() {
-                Ok(files) => self.clear_files(files),
-                Err(e) => Response::ClearFiles(PayloadResult::Error(vec![e.to_string()])),
+            Request::ClearFiles { files, force } => self.clear_files(files, force),
+            Request::ClearFilesPattern { glob, force } => match glob.glob_paths() {
+                Ok(files) if files.is_empty() => no_matches(&glob),
+                Ok(files) => self.clear_files(files, force),
+                Err(e) => Response::ClearFiles(PayloadResult::Detailed(pattern_error(&glob, e))),
             },
-            Request::ClearTags { tags } => self.clear_tags(tags),
+            Request::ClearTags {
+                tags,
+                patterns,
+                force,
+            } => self.clear_tags(tags, patterns, force),
+            Request::ClearAllTags { force } => self.clear_all_tags(force),
             Request::Search { tags, any } => self.search(tags, any),
+            Request::SearchWeighted { tags, any } => self.search_weighted(tags, any),
+            Request::SearchWithTags { tags, any } => self.search_with_tags(tags, any),
+            Request::SearchByTagPrefix { prefix } => self.search_by_tag_prefix(prefix),
+            Request::SearchByOriginalName { name } => self.search_by_original_name(name),
+            Request::SearchByPathFragment { fragment } => self.search_by_path_fragment(fragment),
+            Request::SearchFiltered { filter } => self.search_filtered(filter),
             Request::CopyTags { source, target } => self.copy_tags(source, target),
             Request::CopyTagsPattern { source, glob } => match glob.glob_paths() {
+                Ok(target) if target.is_empty() => no_matches(&glob),
                 Ok(target) => self.copy_tags(source, target),
-                Err(e) => Response::CopyTags(PayloadResult::Error(vec![e.to_string()])),
+                Err(e) => Response::CopyTags(PayloadResult::Detailed(pattern_error(&glob, e))),
             },
             Request::Ping => self.ping(),
-            Request::EditTag { tag, color } => self.edit_tag(tag, color),
+            Request::EditTag {
+                tag,
+                color,
+                weight,
+                system,
+            } => self.edit_tag(tag, color, weight, system),
             Request::ClearCache => self.clean_cache(),
+            Request::ExportGraph => self.export_graph(),
+            Request::RecentTags => self.recent_tags(),
+            Request::ClearRecentTags => self.clear_recent_tags(),
+            Request::RenameTagsMatching {
+                from_pattern,
+                replacement,
+            } => self.rename_tags_matching(from_pattern, replacement),
+            Request::Stats => self.stats(),
+            Request::RunMaintenance => self.run_maintenance(),
+            Request::Verify => self.verify(),
+            Request::VerifyTags { full } => self.verify_tags(full),
+            Request::TagOverlap { a, b } => self.tag_overlap(a, b),
+            Request::TagExists { name } => self.tag_exists(name),
+            Request::AddImplicationRule { if_tag, then_tag } => {
+                self.add_implication_rule(if_tag, then_tag)
+            }
+            Request::ListImplicationRules => {
+                Response::ListImplicationRules(PayloadResult::Ok(self.implication_rules.clone()))
+            }
+            Request::SetLogLevel { level } => self.set_log_level(level),
+            Request::ListMounts => Response::ListMounts(PayloadResult::Ok(registry::mounts())),
+            Request::ListColors => self.list_colors(),
+            Request::RecentEntries { since, limit } => self.recent_entries(since, limit),
+            Request::SetTagMeta { tag, key, value } => self.set_tag_meta(tag, key, value),
+            Request::GetTagMeta { tag } => self.get_tag_meta(tag),
+            Request::CreateTag {
+                name,
+                color,
+                description,
+            } => self.create_tag(name, color, description),
+            Request::AbsolutizePaths { root } => self.absolutize_paths(root),
+            Request::GetAuditLog { since, limit } => self.get_audit_log(since, limit),
+            Request::DiffWithFilesystem {
+                base_dir,
+                max_depth,
+                fix,
+            } => self.diff_with_filesystem(base_dir, max_depth, fix),
+            Request::Scoped { registry, .. } => Response::UnknownMount(registry),
+            // subscriptions are handled before processing requests as they need the connection
+            Request::Subscribe => Response::Subscribe(PayloadResult::Detailed(invalid_argument(
+                "subscription requires a dedicated connection",
+            ))),
+            Request::MigrateNamespace { glob, from, to } => match glob.glob_paths() {
+                Ok(files) if files.is_empty() => no_matches(&glob),
+                Ok(files) => self.migrate_namespace(files, from, to),
+                Err(e) => {
+                    Response::MigrateNamespace(PayloadResult::Detailed(pattern_error(&glob, e)))
+                }
+            },
         }
     }
 
-    fn tag_files(&mut self, files: Vec<PathBuf>, tags: Vec<Tag>) -> Response {
+    /// Tags `files` with `tags` and with tags of matching `rules` if auto tagging is enabled.
+    /// Tags implied by the added tags according to implication rules are added as well. If
+    /// `expires_in` is specified `tags` are removed from the files once it passes, otherwise
+    /// they are kept permanently. With `no_follow` symlinks are tagged themselves instead of their
+    /// targets.
+    fn tag_files(
+        &mut self,
+        files: Vec<PathBuf>,
+        tags: Vec<Tag>,
+        rules: Option<Vec<TagRule>>,
+        expires_in: Option<Duration>,
+        no_follow: bool,
+        palette: Vec<Color>,
+    ) -> Response {
         if files.is_empty() {
-            return Response::TagFiles(PayloadResult::Error(vec!["no files to tag".into()]));
+            return Response::TagFiles(PayloadResult::Detailed(invalid_argument(
+                "no files to tag",
+            )));
         }
-        if tags.is_empty() {
-            return Response::TagFiles(PayloadResult::Error(vec!["no tags provided".into()]));
+        if tags.is_empty() && rules.is_none() {
+            return Response::TagFiles(PayloadResult::Detailed(invalid_argument(
+                "no tags provided",
+            )));
         }
-        let mut errors = vec![];
+        let mut summary = BatchSummary::default();
         let mut new_entries = vec![];
-        let mut registry = get_registry_write();
+        let expires_at = match expires_in.map(chrono::Duration::from_std) {
+            Some(Ok(expires_in)) => Some(Utc::now() + expires_in),
+            Some(Err(e)) => {
+                return Response::TagFiles(PayloadResult::Detailed(invalid_argument(format!(
+                    "invalid expiry time - {e}"
+                ))))
+            }
+            None => None,
+        };
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::TagFiles(PayloadResult::Detailed(registry_error(e))),
+        };
+        // new tags get the least used colors so that they are easier to tell apart
+        let pick_colors = !palette.is_empty();
+        let palette = if pick_colors {
+            palette
+        } else {
+            DEFAULT_COLORS.to_vec()
+        };
+        let mut colors = registry.next_colors(&palette);
+        let mut tags = tags;
+        for tag in tags.iter_mut() {
+            if registry.get_tag(tag.name()).is_some() {
+                continue;
+            }
+            // tags created up front keep their color, weight and protection
+            if let Some(defined) = registry.get_tag_or_defined(tag.name()) {
+                tag.set_color(defined.color());
+                tag.set_weight(defined.weight());
+                tag.set_system(defined.is_system());
+            } else if pick_colors {
+                tag.set_color(&colors.next().unwrap_or(DEFAULT_COLOR));
+            }
+        }
 
         for file in &files {
             log::trace!("processing file {}", file.display());
-            let entry = EntryData::new(file);
+            // tags of a symlink and of its target can't be tracked under the same path
+            if let Some(existing) = registry
+                .find_entry(file)
+                .and_then(|id| registry.get_entry(id))
+            {
+                if existing.no_follow() != no_follow {
+                    summary.errors.push(format!(
+                        "`{}` is already tagged {}, untag it first",
+                        file.display(),
+                        symlink_handling(existing.no_follow())
+                    ));
+                    summary.failed += 1;
+                    continue;
+                }
+            }
+            let entry = EntryData::new(file).with_no_follow(no_follow);
             let (id, added) = registry.add_or_update_entry(entry);
             if added {
-                if let Err(e) = clear_tags(file) {
+                if let Err(e) = clear_tags(file, !no_follow) {
                     log::error!(
                         "failed to clear tags of file `{}`, reason: {e}",
                         file.display()
@@ -136,20 +810,78 @@ impl WutagDaemon {
                 }
                 new_entries.push(file.to_path_buf());
             }
-            for tag in &tags {
-                log::trace!("tagging file {}, tag {tag}", file.display());
-                if let Err(e) = tag.save_to(file) {
-                    errors.push(format!(
-                        "Error for `{}` tag: `{tag}`, reason: {e}",
+            let errors = summary.errors.len();
+            let mut saved = 0;
+            let mut file_tags = tags.clone();
+            if let Some(rules) = &rules {
+                match auto_tag_file(file, rules) {
+                    Ok(auto_tags) => {
+                        file_tags.extend(auto_tags.into_iter().filter(|tag| !tags.contains(tag)))
+                    }
+                    Err(e) => summary.errors.push(format!(
+                        "Failed to auto tag `{}`, reason: {e}",
                         file.display()
-                    ));
-                } else {
+                    )),
+                }
+            }
+            let names: Vec<_> = file_tags.iter().map(Tag::name).collect();
+            let implied: Vec<_> = implied_tags(&names, &self.implication_rules)
+                .into_iter()
+                .map(|name| {
+                    registry
+                        .get_tag_or_defined(&name)
+                        .cloned()
+                        .unwrap_or_else(|| Tag::new(name, colors.next().unwrap_or(DEFAULT_COLOR)))
+                })
+                .collect();
+            file_tags.extend(implied);
+            for tag in &file_tags {
+                log::trace!("tagging file {}, tag {tag}", file.display());
+                let tagged = match tag.save_to(file, !no_follow) {
+                    Ok(_) => {
+                        saved += 1;
+                        true
+                    }
+                    Err(CoreError::TagExists) => {
+                        log::trace!("file {} already tagged with {tag}", file.display());
+                        summary.already_tagged += 1;
+                        true
+                    }
+                    Err(e @ CoreError::TagListFull(_)) => {
+                        summary.errors.push(format!(
+                            "Error for `{}` tag: `{tag}`, reason: {e}, {saved} of {} tags were \
+                             saved before reaching the limit",
+                            file.display(),
+                            file_tags.len()
+                        ));
+                        break;
+                    }
+                    Err(e) => {
+                        summary.errors.push(format!(
+                            "Error for `{}` tag: `{tag}`, reason: {e}",
+                            file.display()
+                        ));
+                        false
+                    }
+                };
+                if tagged {
                     registry.tag_entry(tag, id);
+                    registry.record_recent_tag(tag);
+                    if tags.contains(tag) {
+                        registry.set_tag_expiry(tag, id, expires_at);
+                    }
                 }
             }
-            if registry.list_entry_tags(id).unwrap_or_default().is_empty() {
+            if registry.tag_count_for_entry(id) == 0 {
                 registry.remove_entry(id);
             }
+            if summary.errors.len() > errors {
+                summary.failed += 1;
+            } else if saved > 0 {
+                summary.succeeded += 1;
+            } else {
+                summary.skipped += 1;
+            }
         }
 
         if let Err(e) = registry.save() {
@@ -160,33 +892,85 @@ impl WutagDaemon {
             self.push_event(EntryEvent::Add(new_entries));
         }
 
-        if errors.is_empty() {
-            Response::TagFiles(PayloadResult::Ok(()))
-        } else {
-            Response::TagFiles(PayloadResult::Error(errors))
-        }
+        Response::TagFiles(PayloadResult::Ok(summary))
     }
 
-    fn untag_files(&mut self, files: Vec<PathBuf>, tags: Vec<Tag>) -> Response {
+    fn untag_files(
+        &mut self,
+        files: Vec<PathBuf>,
+        tags: Vec<Tag>,
+        no_follow: bool,
+        force: bool,
+    ) -> Response {
         if files.is_empty() {
-            return Response::UntagFiles(PayloadResult::Error(vec!["no files to untag".into()]));
+            return Response::UntagFiles(PayloadResult::Detailed(invalid_argument(
+                "no files to untag",
+            )));
         }
         if tags.is_empty() {
-            return Response::UntagFiles(PayloadResult::Error(vec!["no tags provided".into()]));
+            return Response::UntagFiles(PayloadResult::Detailed(invalid_argument(
+                "no tags provided",
+            )));
         }
-        let mut registry = get_registry_write();
-        let mut errors = vec![];
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::UntagFiles(PayloadResult::Detailed(registry_error(e))),
+        };
+        let mut summary = BatchSummary::default();
         let mut removed = vec![];
 
+        let (protected, tags): (Vec<_>, Vec<_>) = tags
+            .into_iter()
+            .partition(|tag| !force && is_system_tag(&registry, tag.name()));
+        for tag in &protected {
+            log::warn!("skipping removal of system tag {tag}");
+            summary
+                .errors
+                .push(format!("{tag} is a system tag, use --force to remove it"));
+        }
+
         for file in &files {
-            if let Some(id) = registry.find_entry(file) {
-                for tag in &tags {
-                    if let Err(e) = tag.remove_from(file) {
-                        errors.push(format!("{} tag: {tag}, error: {e}", file.display()));
-                    } else if let Some(entry) = registry.untag_entry(tag, id) {
-                        removed.push(entry.into_path_buf());
+            let Some(id) = registry.find_entry(file).filter(|_| !tags.is_empty()) else {
+                summary.skipped += 1;
+                continue;
+            };
+            // the tags are saved either on the symlink or on its target, not on both
+            if let Some(entry) = registry
+                .get_entry(id)
+                .filter(|e| e.no_follow() != no_follow)
+            {
+                summary.errors.push(format!(
+                    "`{}` is tagged {}, untag it the same way",
+                    file.display(),
+                    symlink_handling(entry.no_follow())
+                ));
+                summary.failed += 1;
+                continue;
+            }
+            let errors = summary.errors.len();
+            let mut untagged = false;
+            for tag in &tags {
+                match tag.remove_from(file, !no_follow) {
+                    Ok(()) => untagged = true,
+                    // the file doesn't have this tag, only drop it from the registry
+                    Err(CoreError::TagNotFound(_)) => {}
+                    Err(e) => {
+                        summary
+                            .errors
+                            .push(format!("{} tag: {tag}, error: {e}", file.display()));
+                        continue;
                     }
                 }
+                if let Some(entry) = registry.untag_entry(tag, id) {
+                    removed.push(entry.into_path_buf());
+                }
+            }
+            if summary.errors.len() > errors {
+                summary.failed += 1;
+            } else if untagged {
+                summary.succeeded += 1;
+            } else {
+                summary.skipped += 1;
             }
         }
 
@@ -198,46 +982,274 @@ impl WutagDaemon {
             self.push_event(EntryEvent::Remove(removed));
         }
 
-        if errors.is_empty() {
-            Response::UntagFiles(PayloadResult::Ok(()))
-        } else {
-            Response::UntagFiles(PayloadResult::Error(errors))
+        Response::UntagFiles(PayloadResult::Ok(summary))
+    }
+
+    /// Adds the missing `tags` to every file and removes all of its other tags. Tags saved in the
+    /// files are compared with the `tags` so that entries out of sync with their files are fixed
+    /// too.
+    fn set_exact_tags(&mut self, files: Vec<PathBuf>, tags: Vec<Tag>) -> Response {
+        if files.is_empty() {
+            return Response::SetExactTags(PayloadResult::Detailed(invalid_argument(
+                "no files to tag",
+            )));
+        }
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::SetExactTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        let mut result = SetExactTagsResult::default();
+        let mut new_entries = vec![];
+        let mut removed_entries = vec![];
+
+        for file in &files {
+            let current = match list_tags(file, true) {
+                Ok(current) => current,
+                Err(e) => {
+                    result.summary.failed += 1;
+                    result
+                        .summary
+                        .errors
+                        .push(format!("{} - {e}", file.display()));
+                    continue;
+                }
+            };
+            let errors = result.summary.errors.len();
+            let mut changes = FileTagChanges {
+                path: file.clone(),
+                ..Default::default()
+            };
+            for tag in current.iter().filter(|tag| !tags.contains(tag)) {
+                match tag.remove_from(file, true) {
+                    Ok(()) => changes.removed.push(tag.name().to_string()),
+                    Err(e) => result
+                        .summary
+                        .errors
+                        .push(format!("{} tag: {tag}, error: {e}", file.display())),
+                }
+            }
+            for tag in tags.iter().filter(|tag| !current.contains(tag)) {
+                match tag.save_to(file, true) {
+                    Ok(()) => {
+                        changes.added.push(tag.name().to_string());
+                        registry.record_recent_tag(tag);
+                    }
+                    Err(e) => result.summary.errors.push(format!(
+                        "Error for `{}` tag: `{tag}`, reason: {e}",
+                        file.display()
+                    )),
+                }
+            }
+
+            // the registry follows the tags actually saved in the file
+            let saved: Vec<_> = current
+                .iter()
+                .filter(|tag| !changes.removed.iter().any(|name| name == tag.name()))
+                .chain(
+                    tags.iter()
+                        .filter(|tag| changes.added.iter().any(|name| name == tag.name())),
+                )
+                .cloned()
+                .collect();
+            match registry.find_entry(file) {
+                Some(id) => {
+                    if let Some(entry) = registry.sync_entry_tags(id, &saved) {
+                        removed_entries.push(entry.into_path_buf());
+                    }
+                    if registry.tag_count_for_entry(id) == 0 {
+                        registry.remove_entry(id);
+                    }
+                }
+                None if !saved.is_empty() => {
+                    let (id, _) = registry.add_or_update_entry(EntryData::new(file));
+                    registry.sync_entry_tags(id, &saved);
+                    new_entries.push(file.clone());
+                }
+                None => {}
+            }
+
+            if result.summary.errors.len() > errors {
+                result.summary.failed += 1;
+            } else if changes.added.is_empty() && changes.removed.is_empty() {
+                result.summary.skipped += 1;
+            } else {
+                result.summary.succeeded += 1;
+            }
+            if !changes.added.is_empty() || !changes.removed.is_empty() {
+                result.changes.push(changes);
+            }
+        }
+
+        if let Err(e) = registry.save() {
+            log::error!("{e}")
+        }
+        if !new_entries.is_empty() {
+            self.push_event(EntryEvent::Add(new_entries));
         }
+        if !removed_entries.is_empty() {
+            self.push_event(EntryEvent::Remove(removed_entries));
+        }
+
+        Response::SetExactTags(PayloadResult::Ok(result))
     }
 
-    fn edit_tag(&mut self, tag: String, color: Color) -> Response {
-        let mut registry = get_registry_write();
-        if registry.get_tag(&tag).is_none() {
-            return Response::EditTag(PayloadResult::Error(format!("tag {tag} doesn't exist")));
+    fn edit_tag(
+        &mut self,
+        tag: String,
+        color: Option<Color>,
+        weight: Option<f32>,
+        system: Option<bool>,
+    ) -> Response {
+        if let Some(weight) = weight.filter(|w| !WEIGHT_RANGE.contains(w)) {
+            return Response::EditTag(PayloadResult::Detailed(invalid_argument(format!(
+                "weight {weight} is out of range {}-{}",
+                WEIGHT_RANGE.start(),
+                WEIGHT_RANGE.end()
+            ))));
+        }
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::EditTag(PayloadResult::Detailed(registry_error(e))),
+        };
+        if registry.get_tag_or_defined(&tag).is_none() {
+            return Response::EditTag(PayloadResult::Detailed(tag_not_found(&registry, &tag)));
+        }
+        let mut failed = vec![];
+        if let Some(color) = color {
+            registry.update_tag_color(&tag, color);
+            // the color is also saved in the xattr value of every tagged file
+            let saved = Tag::new(&tag, color);
+            for id in registry.list_entries_with_all_tags([&tag]) {
+                let Some(entry) = registry.get_entry(id) else {
+                    continue;
+                };
+                if let Err(e) = saved.recolor_in(entry.path(), color, !entry.no_follow()) {
+                    failed.push((entry.path().to_path_buf(), e.to_string()));
+                }
+            }
+        }
+        if let Some(weight) = weight {
+            registry.update_tag_weight(&tag, weight);
+        }
+        if let Some(system) = system {
+            registry.update_tag_system(&tag, system);
         }
-        registry.update_tag_color(tag, color);
         if let Err(e) = registry.save() {
             log::error!("{e}")
         }
+        if !failed.is_empty() {
+            let message = failed
+                .iter()
+                .map(|(path, e)| format!("`{}` - {e}", path.display()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let paths: Vec<_> = failed.into_iter().map(|(path, _)| path).collect();
+            return Response::EditTag(PayloadResult::Detailed(
+                ResponseError::new(
+                    ErrorCode::Filesystem,
+                    format!("failed to update the color of `{tag}` on files: {message}"),
+                )
+                .with_details(serde_json::json!({ "paths": paths })),
+            ));
+        }
         Response::EditTag(PayloadResult::Ok(()))
     }
 
+    fn set_tag_meta(&mut self, tag: String, key: String, value: Option<String>) -> Response {
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::SetTagMeta(PayloadResult::Detailed(registry_error(e))),
+        };
+        if !registry.set_tag_meta(&tag, &key, value) {
+            return Response::SetTagMeta(PayloadResult::Detailed(tag_not_found(&registry, &tag)));
+        }
+        if let Err(e) = registry.save() {
+            log::error!("{e}")
+        }
+        Response::SetTagMeta(PayloadResult::Ok(()))
+    }
+
+    fn create_tag(&mut self, name: String, color: Color, description: Option<String>) -> Response {
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::CreateTag(PayloadResult::Detailed(registry_error(e))),
+        };
+        let tag = Tag::new(name, color);
+        if tag.name().is_empty() {
+            return Response::CreateTag(PayloadResult::Detailed(invalid_argument(
+                "tag name is empty",
+            )));
+        }
+        if !registry.define_tag(tag.clone()) {
+            return Response::CreateTag(PayloadResult::Detailed(invalid_argument(format!(
+                "tag `{tag}` already exists"
+            ))));
+        }
+        if let Some(description) = description {
+            registry.set_tag_meta(tag.name(), DESCRIPTION_META_KEY, Some(description));
+        }
+        if let Err(e) = registry.save() {
+            log::error!("{e}")
+        }
+        Response::CreateTag(PayloadResult::Ok(()))
+    }
+
+    fn get_tag_meta(&mut self, tag: Option<String>) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::GetTagMeta(PayloadResult::Detailed(registry_error(e))),
+        };
+        let meta = match tag {
+            Some(tag) => {
+                if registry.get_tag_or_defined(&tag).is_none() {
+                    return Response::GetTagMeta(PayloadResult::Detailed(tag_not_found(
+                        &registry, &tag,
+                    )));
+                }
+                let meta = registry.tag_meta(&tag).cloned().unwrap_or_default();
+                BTreeMap::from([(tag, meta)])
+            }
+            None => registry
+                .list_tag_meta()
+                .map(|(name, meta)| (name.clone(), meta.clone()))
+                .collect(),
+        };
+        Response::GetTagMeta(PayloadResult::Ok(meta))
+    }
+
     fn copy_tags(&mut self, source: PathBuf, target: Vec<PathBuf>) -> Response {
-        let tags = match list_tags(&source) {
+        let tags = match list_tags(&source, true) {
             Ok(tags) => tags,
             Err(e) => {
-                return Response::CopyTags(PayloadResult::Error(vec![format!(
-                    "faile to copy tags - {e}"
-                )]))
+                return Response::CopyTags(PayloadResult::Detailed(filesystem_error(&source, e)))
             }
         };
         if tags.is_empty() {
-            return Response::CopyTags(PayloadResult::Ok(()));
+            // tags of tracked files can be missing on disk, untracked files were never tagged
+            match get_registry_read() {
+                Ok(registry) if registry.find_entry(&source).is_none() => {
+                    return Response::CopyTags(PayloadResult::Detailed(entry_not_found(&source)))
+                }
+                Ok(_) => {}
+                Err(e) => return Response::CopyTags(PayloadResult::Detailed(registry_error(e))),
+            }
+            return Response::CopyTags(PayloadResult::Ok(BatchSummary {
+                skipped: target.len(),
+                ..Default::default()
+            }));
         }
 
-        let mut errors = vec![];
+        let mut summary = BatchSummary::default();
         let mut new_entries = vec![];
-        let mut registry = get_registry_write();
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::CopyTags(PayloadResult::Detailed(registry_error(e))),
+        };
 
         for path in target {
             let (id, added) = registry.add_or_update_entry(EntryData::new(&path));
             if added {
-                if let Err(e) = clear_tags(&path) {
+                if let Err(e) = clear_tags(&path, true) {
                     log::error!(
                         "failed to clear tags of file `{}`, reason: {e}",
                         path.display()
@@ -245,14 +1257,27 @@ impl WutagDaemon {
                 }
                 new_entries.push(path.to_path_buf());
             }
-            for tag in &tags {
-                if let Err(e) = tag.save_to(&path) {
-                    errors.push(e.to_string());
-                } else {
-                    registry.tag_entry(tag, id);
+            // tags the target already has are kept as they are
+            match copy_xattrs(&source, &path, Some(WUTAG_NAMESPACE)) {
+                Ok(copied) => {
+                    for tag in &tags {
+                        registry.tag_entry(tag, id);
+                    }
+                    if copied > 0 {
+                        summary.succeeded += 1;
+                    } else {
+                        summary.skipped += 1;
+                    }
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    summary.errors.push(format!(
+                        "failed to copy tags to `{}`, reason: {e}",
+                        path.display()
+                    ));
                 }
             }
-            if registry.list_entry_tags(id).unwrap_or_default().is_empty() {
+            if registry.tag_count_for_entry(id) == 0 {
                 registry.remove_entry(id);
             }
         }
@@ -265,32 +1290,74 @@ impl WutagDaemon {
             self.push_event(EntryEvent::Add(new_entries));
         }
 
-        if errors.is_empty() {
-            Response::CopyTags(PayloadResult::Ok(()))
-        } else {
-            Response::CopyTags(PayloadResult::Error(errors))
-        }
+        Response::CopyTags(PayloadResult::Ok(summary))
     }
 
-    fn clear_files(&mut self, files: Vec<PathBuf>) -> Response {
+    /// Removes all tags from the `files`. System tags are kept unless `force` is set, files
+    /// with system tags only lose their other tags.
+    fn clear_files(&mut self, files: Vec<PathBuf>, force: bool) -> Response {
         if files.is_empty() {
-            return Response::ClearFiles(PayloadResult::Error(vec!["no files to clear".into()]));
+            return Response::ClearFiles(PayloadResult::Detailed(invalid_argument(
+                "no files to clear",
+            )));
         }
 
-        let mut errors = vec![];
-        let mut registry = get_registry_write();
+        let mut summary = BatchSummary::default();
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::ClearFiles(PayloadResult::Detailed(registry_error(e))),
+        };
 
         for file in &files {
             if let Some(id) = registry.find_entry(file) {
-                let entry = registry.get_entry(id).unwrap();
-                if let Err(e) = clear_tags(entry.path()) {
-                    errors.push(format!(
+                let entry = registry.get_entry(id).unwrap().clone();
+                let (protected, other): (Vec<_>, Vec<_>) = registry
+                    .list_entry_tags(id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .cloned()
+                    .partition(|tag| !force && tag.is_system());
+                if !protected.is_empty() {
+                    let names: Vec<_> = protected.iter().map(Tag::name).collect();
+                    log::warn!(
+                        "keeping system tags of `{}`: {}",
+                        entry.path().display(),
+                        names.join(", ")
+                    );
+                    summary.errors.push(format!(
+                        "kept system tags of `{}`: {}, use --force to remove them",
+                        entry.path().display(),
+                        names.join(", ")
+                    ));
+                    let errors = summary.errors.len();
+                    for tag in &other {
+                        if let Err(e) = tag.remove_from(entry.path(), !entry.no_follow()) {
+                            summary
+                                .errors
+                                .push(format!("{} tag: {tag}, error: {e}", entry.path().display()));
+                        } else {
+                            registry.untag_entry(tag, id);
+                        }
+                    }
+                    if summary.errors.len() > errors {
+                        summary.failed += 1;
+                    } else if other.is_empty() {
+                        summary.skipped += 1;
+                    } else {
+                        summary.succeeded += 1;
+                    }
+                } else if let Err(e) = clear_tags(entry.path(), !entry.no_follow()) {
+                    summary.failed += 1;
+                    summary.errors.push(format!(
                         "failed to clear tags from `{}`, reason: {e}",
                         entry.path().display()
                     ));
                 } else {
+                    summary.succeeded += 1;
                     registry.clear_entry(id);
                 }
+            } else {
+                summary.skipped += 1;
             }
         }
 
@@ -300,33 +1367,166 @@ impl WutagDaemon {
 
         self.push_event(EntryEvent::Remove(files));
 
-        if errors.is_empty() {
-            Response::ClearFiles(PayloadResult::Ok(()))
-        } else {
-            Response::ClearFiles(PayloadResult::Error(errors))
-        }
+        Response::ClearFiles(PayloadResult::Ok(summary))
     }
 
-    fn clear_tags(&mut self, tags: Vec<String>) -> Response {
-        if tags.is_empty() {
-            return Response::ClearTags(PayloadResult::Error(vec!["no tags to clear".into()]));
+    /// Moves tags of the `files` from the `from` namespace to `to` and updates their entries with
+    /// the tags they have afterwards. Outdated values of tags in the current namespace are
+    /// rewritten too, this is the only place where they are looked for.
+    fn migrate_namespace(
+        &mut self,
+        files: Vec<PathBuf>,
+        from: Option<String>,
+        to: String,
+    ) -> Response {
+        if let Some(from) = from.as_ref().filter(|from| **from == to) {
+            return Response::MigrateNamespace(PayloadResult::Detailed(invalid_argument(format!(
+                "source and target namespace are both `{from}`"
+            ))));
         }
 
+        let mut errors = vec![];
+        let mut moved = 0;
+        let mut added = vec![];
         let mut removed = vec![];
-        let mut registry = get_registry_write();
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => {
+                return Response::MigrateNamespace(PayloadResult::Detailed(registry_error(e)))
+            }
+        };
 
-        for tag in &tags {
-            let tag = Tag::random(tag, DEFAULT_COLORS);
-            let cleared = registry.clear_tag(&tag);
-            if let Some(cleared) = cleared {
-                for entry in &cleared {
-                    if let Err(e) = tag.remove_from(entry.path()) {
-                        log::error!(
-                            "failed to untag {tag} entry `{}`, reason: {e}",
-                            entry.path().display()
-                        );
+        for path in files {
+            if to == WUTAG_NAMESPACE {
+                match Tag::migrate_xattr(&path, true) {
+                    Ok(n) => moved += n,
+                    Err(e) => errors.push(format!(
+                        "failed to rewrite tags of `{}`, reason: {e}",
+                        path.display()
+                    )),
+                }
+            }
+            let Some(from) = &from else {
+                continue;
+            };
+            match Tag::move_namespace(&path, from, &to) {
+                Ok(0) => continue,
+                Ok(n) => moved += n,
+                Err(e) => errors.push(format!(
+                    "failed to migrate tags of `{}`, reason: {e}",
+                    path.display()
+                )),
+            }
+            let tags = match list_tags(&path, true) {
+                Ok(tags) => tags,
+                Err(e) => {
+                    errors.push(format!(
+                        "failed to list tags of `{}`, reason: {e}",
+                        path.display()
+                    ));
+                    continue;
+                }
+            };
+            if let Some(id) = registry.find_entry(&path) {
+                if registry.sync_entry_tags(id, &tags).is_some() {
+                    removed.push(path);
+                }
+            } else if !tags.is_empty() {
+                let (id, _) = registry.add_or_update_entry(EntryData::new(&path));
+                for tag in &tags {
+                    registry.tag_entry(tag, id);
+                }
+                added.push(path);
+            }
+        }
+
+        if let Err(e) = registry.save() {
+            log::error!("{e}")
+        }
+
+        if !added.is_empty() {
+            self.push_event(EntryEvent::Add(added));
+        }
+        if !removed.is_empty() {
+            self.push_event(EntryEvent::Remove(removed));
+        }
+
+        if errors.is_empty() {
+            Response::MigrateNamespace(PayloadResult::Ok(moved))
+        } else {
+            Response::MigrateNamespace(PayloadResult::Error(errors))
+        }
+    }
+
+    /// Removes the `tags` from all files and the registry. With `patterns` the names are
+    /// expanded to the names of existing tags matching them. System tags are skipped unless
+    /// `force` is set.
+    fn clear_tags(&mut self, tags: Vec<String>, patterns: bool, force: bool) -> Response {
+        if tags.is_empty() {
+            return Response::ClearTags(PayloadResult::Detailed(invalid_argument(
+                "no tags to clear",
+            )));
+        }
+        if !patterns {
+            return self.clear_tag_names(tags, force);
+        }
+
+        let names = match get_registry_read() {
+            Ok(registry) => tags
+                .iter()
+                .flat_map(|tag| {
+                    let pattern = TagPattern::new(tag);
+                    match pattern.literal() {
+                        Some(name) => vec![name],
+                        None => registry
+                            .list_tags()
+                            .chain(registry.list_unused_tags())
+                            .filter(|tag| pattern.matches(tag.name()))
+                            .map(|tag| tag.name().to_string())
+                            .collect(),
                     }
+                })
+                .collect(),
+            Err(e) => return Response::ClearTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        self.clear_tag_names(names, force)
+    }
+
+    /// Removes the tags named `tags` from all files and the registry. System tags are skipped
+    /// unless `force` is set.
+    fn clear_tag_names(&mut self, mut tags: Vec<String>, force: bool) -> Response {
+        let mut removed = vec![];
+        let mut errors = vec![];
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::ClearTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        tags.sort_unstable();
+        tags.dedup();
+
+        for tag in &tags {
+            if !force && is_system_tag(&registry, tag) {
+                log::warn!("skipping system tag {tag}");
+                errors.push(format!("{tag} is a system tag, use --force to clear it"));
+                continue;
+            }
+            let tag = Tag::random(tag, DEFAULT_COLORS);
+            // entries left with other tags aren't returned by `clear_tag` but still have to be
+            // untagged on disk
+            let tagged: Vec<_> = registry
+                .list_entries_with_any_tags([tag.name()])
+                .into_iter()
+                .filter_map(|id| registry.get_entry(id).cloned())
+                .collect();
+            for entry in &tagged {
+                if let Err(e) = tag.remove_from(entry.path(), !entry.no_follow()) {
+                    log::error!(
+                        "failed to untag {tag} entry `{}`, reason: {e}",
+                        entry.path().display()
+                    );
                 }
+            }
+            if let Some(cleared) = registry.clear_tag(&tag) {
                 cleared
                     .into_iter()
                     .map(|e| e.into_path_buf())
@@ -342,44 +1542,238 @@ impl WutagDaemon {
             self.push_event(EntryEvent::Remove(removed));
         }
 
-        Response::ClearFiles(PayloadResult::Ok(()))
+        if errors.is_empty() {
+            Response::ClearTags(PayloadResult::Ok(()))
+        } else {
+            Response::ClearTags(PayloadResult::Error(errors))
+        }
     }
 
-    fn list_tags(&mut self, with_files: bool) -> Response {
-        let registry = get_registry_read();
-        if with_files {
-            Response::ListTags(PayloadResult::Ok(
-                registry.list_tags_and_entries().collect(),
-            ))
-        } else {
-            Response::ListTags(PayloadResult::Ok(
-                registry.list_tags().map(|t| (t.clone(), vec![])).collect(),
-            ))
+    fn clear_all_tags(&mut self, force: bool) -> Response {
+        let tags: Vec<_> = match get_registry_read() {
+            Ok(registry) => registry.list_tags().map(|t| t.name().to_string()).collect(),
+            Err(e) => return Response::ClearTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        if tags.is_empty() {
+            return Response::ClearTags(PayloadResult::Ok(()));
+        }
+        self.clear_tag_names(tags, force)
+    }
+
+    fn list_tags(&mut self, with_files: bool, page: Page, include_unused: bool) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::ListTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        let (items, total) =
+            registry.list_tags_page(page.offset, page.limit, with_files, include_unused);
+        Response::ListTags(PayloadResult::Ok(Paged { items, total }))
+    }
+
+    fn list_colors(&mut self) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::ListColors(PayloadResult::Detailed(registry_error(e))),
+        };
+        let mut colors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for tag in registry.list_tags() {
+            colors
+                .entry(color_to_hex(*tag.color()))
+                .or_default()
+                .push(tag.name().to_string());
+        }
+        for names in colors.values_mut() {
+            names.sort_unstable();
+        }
+        Response::ListColors(PayloadResult::Ok(colors))
+    }
+
+    fn recent_entries(&mut self, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::RecentEntries(PayloadResult::Detailed(registry_error(e))),
+        };
+        let entries = registry
+            .list_recent_entries(since, limit)
+            .into_iter()
+            .cloned()
+            .collect();
+        Response::RecentEntries(PayloadResult::Ok(entries))
+    }
+
+    fn get_audit_log(&mut self, since: Option<DateTime<Utc>>, limit: Option<usize>) -> Response {
+        let path = audit::audit_log_file();
+        match audit::read(&path, since, limit) {
+            Ok(entries) => Response::GetAuditLog(PayloadResult::Ok(entries)),
+            Err(e) => Response::GetAuditLog(PayloadResult::Detailed(filesystem_error(&path, e))),
+        }
+    }
+
+    /// Compares the registry with tags saved in the files. With `fix` the registry is updated to
+    /// match the files, entries without tags on disk are removed and untracked tagged files are
+    /// added.
+    fn diff_with_filesystem(
+        &mut self,
+        base_dir: PathBuf,
+        max_depth: Option<usize>,
+        fix: bool,
+    ) -> Response {
+        let diff = match get_registry_read() {
+            Ok(registry) => registry.diff_with_filesystem(&base_dir, max_depth),
+            Err(e) => {
+                return Response::DiffWithFilesystem(PayloadResult::Detailed(registry_error(e)))
+            }
+        };
+        let diff = match diff {
+            Ok(diff) => diff,
+            Err(e) => {
+                return Response::DiffWithFilesystem(PayloadResult::Detailed(filesystem_error(
+                    &base_dir, e,
+                )))
+            }
+        };
+        if !fix || diff.is_empty() {
+            return Response::DiffWithFilesystem(PayloadResult::Ok(diff));
+        }
+
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => {
+                return Response::DiffWithFilesystem(PayloadResult::Detailed(registry_error(e)))
+            }
+        };
+        let mut added = vec![];
+        let mut removed = vec![];
+        for path in &diff.entries_in_registry_only {
+            if let Some(id) = registry.find_entry(path) {
+                registry.clear_entry(id);
+                removed.push(path.clone());
+            }
+        }
+        for path in &diff.entries_in_xattrs_only {
+            let tags = match list_tags(path, true) {
+                Ok(tags) => tags,
+                Err(e) => {
+                    log::error!("failed to list tags of `{}`, reason: {e}", path.display());
+                    continue;
+                }
+            };
+            let (id, _) = registry.add_or_update_entry(EntryData::new(path));
+            for tag in &tags {
+                registry.tag_entry(tag, id);
+            }
+            added.push(path.clone());
+        }
+        for (path, _, tags) in &diff.tag_mismatches {
+            if let Some(id) = registry.find_entry(path) {
+                if let Some(entry) = registry.sync_entry_tags(id, tags) {
+                    removed.push(entry.into_path_buf());
+                }
+            }
+        }
+
+        if let Err(e) = registry.save() {
+            log::error!("{e}")
+        }
+        if !added.is_empty() {
+            self.push_event(EntryEvent::Add(added));
+        }
+        if !removed.is_empty() {
+            self.push_event(EntryEvent::Remove(removed));
+        }
+        Response::DiffWithFilesystem(PayloadResult::Ok(diff))
+    }
+
+    fn list_files(&mut self, with_tags: bool, page: Page) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::ListFiles(PayloadResult::Detailed(registry_error(e))),
+        };
+        let (items, total) = registry.list_entries_page(page.offset, page.limit, with_tags);
+        Response::ListFiles(PayloadResult::Ok(Paged { items, total }))
+    }
+
+    fn inspect_files_raw(&mut self, files: Vec<PathBuf>) -> Response {
+        let mut inspected = vec![];
+        for file in files {
+            let xattrs = match list_xattrs(&file, true) {
+                Ok(xattrs) => xattrs,
+                Err(e) => {
+                    return Response::InspectFilesRaw(PayloadResult::Detailed(filesystem_error(
+                        &file, e,
+                    )))
+                }
+            };
+            let xattrs = xattrs
+                .into_iter()
+                .filter(|xattr| xattr.key().starts_with(WUTAG_NAMESPACE))
+                .map(|xattr| (xattr.key().to_string(), xattr.val().to_string()))
+                .collect();
+            inspected.push((file, xattrs));
         }
+        Response::InspectFilesRaw(PayloadResult::Ok(inspected))
     }
 
-    fn list_files(&mut self, with_tags: bool) -> Response {
-        let registry = get_registry_read();
-        let entries = if with_tags {
-            registry.list_entries_and_tags().collect()
+    /// Estimates how many more tags fit on the `file` assuming they are as large as its current
+    /// tags on average.
+    fn xattr_capacity(&mut self, file: PathBuf) -> Response {
+        let (capacity, xattrs) = match xattr_capacity(&file, true)
+            .and_then(|c| Ok((c, list_xattrs(&file, true)?)))
+        {
+            Ok(result) => result,
+            Err(e) => {
+                return Response::XattrCapacity(PayloadResult::Detailed(filesystem_error(&file, e)))
+            }
+        };
+        let sizes: Vec<_> = xattrs
+            .iter()
+            .filter(|xattr| xattr.key().starts_with(WUTAG_NAMESPACE))
+            .map(|xattr| xattr.key().len() + xattr.val().len())
+            .collect();
+        let tag_size = if sizes.is_empty() {
+            TYPICAL_TAG_SIZE
         } else {
-            registry
-                .list_entries()
-                .map(|e| (e.clone(), vec![]))
-                .collect()
+            sizes.iter().sum::<usize>() / sizes.len()
         };
-        Response::ListFiles(PayloadResult::Ok(entries))
+        Response::XattrCapacity(PayloadResult::Ok(TagCapacity {
+            capacity,
+            tags: sizes.len(),
+            tag_size,
+            tags_left: capacity.fits(tag_size),
+        }))
     }
 
-    fn inspect_files(&mut self, files: Vec<PathBuf>) -> Response {
+    /// Returns tags of the `files` tracked in the registry. With `no_follow` tags of symlinks are
+    /// read from the links themselves since the registry doesn't tell them apart from the tags of
+    /// their targets.
+    fn inspect_files(&mut self, files: Vec<PathBuf>, no_follow: bool) -> Response {
         if files.is_empty() {
-            return Response::InspectFiles(PayloadResult::Error("no files to inspect".into()));
+            return Response::InspectFiles(PayloadResult::Detailed(invalid_argument(
+                "no files to inspect",
+            )));
         }
         let mut entries = vec![];
+        let mut expiries = HashMap::new();
+        let match_count = files.len();
 
-        let registry = get_registry_read();
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::InspectFiles(PayloadResult::Detailed(registry_error(e))),
+        };
         for file in files {
-            if let Some(id) = registry.find_entry(&file) {
+            if no_follow {
+                match list_tags(&file, false) {
+                    Ok(tags) if tags.is_empty() => {}
+                    Ok(tags) => {
+                        let entry = registry
+                            .find_entry(&file)
+                            .and_then(|id| registry.get_entry(id).cloned())
+                            .unwrap_or_else(|| EntryData::new(&file).with_no_follow(true));
+                        entries.push((entry, tags));
+                    }
+                    Err(e) => log::debug!("failed to list tags of `{}` - {e}", file.display()),
+                }
+            } else if let Some(id) = registry.find_entry(&file) {
                 let tags = registry
                     .list_entry_tags(id)
                     .unwrap_or_default()
@@ -387,18 +1781,30 @@ impl WutagDaemon {
                     .cloned()
                     .collect();
                 let entry = registry.get_entry(id).unwrap().clone();
+                if let Some(entry_expiries) = registry.entry_expiries(id) {
+                    expiries.insert(entry.path().to_path_buf(), entry_expiries.clone());
+                }
                 entries.push((entry, tags));
             }
         }
 
-        Response::InspectFiles(PayloadResult::Ok(entries))
+        Response::InspectFiles(PayloadResult::Ok(InspectFilesResult {
+            entries,
+            match_count,
+            expiries,
+        }))
     }
 
     fn search(&mut self, tags: Vec<String>, any: bool) -> Response {
         if tags.is_empty() {
-            return Response::Search(PayloadResult::Error("no tags to search for".into()));
+            return Response::Search(PayloadResult::Detailed(invalid_argument(
+                "no tags to search for",
+            )));
         }
-        let registry = get_registry_read();
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::Search(PayloadResult::Detailed(registry_error(e))),
+        };
         let entries = if any {
             registry.list_entries_with_any_tags(tags)
         } else {
@@ -413,16 +1819,1593 @@ impl WutagDaemon {
         Response::Search(PayloadResult::Ok(found))
     }
 
+    fn search_by_tag_prefix(&mut self, prefix: String) -> Response {
+        if prefix.is_empty() {
+            return Response::SearchByTagPrefix(PayloadResult::Detailed(invalid_argument(
+                "no prefix to search for",
+            )));
+        }
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => {
+                return Response::SearchByTagPrefix(PayloadResult::Detailed(registry_error(e)))
+            }
+        };
+        let found = registry
+            .list_tags_with_prefix(&prefix)
+            .into_iter()
+            .map(|tag| {
+                let mut entries: Vec<_> = registry
+                    .list_entries_with_any_tags([tag.name()])
+                    .into_iter()
+                    .filter_map(|id| registry.get_entry(id).cloned())
+                    .collect();
+                entries.sort_unstable_by(|a, b| a.path().cmp(b.path()));
+                (tag.clone(), entries)
+            })
+            .collect();
+        Response::SearchByTagPrefix(PayloadResult::Ok(found))
+    }
+
+    fn search_by_original_name(&mut self, name: String) -> Response {
+        if name.is_empty() {
+            return Response::SearchByOriginalName(PayloadResult::Detailed(invalid_argument(
+                "no name to search for",
+            )));
+        }
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => {
+                return Response::SearchByOriginalName(PayloadResult::Detailed(registry_error(e)))
+            }
+        };
+        let found = registry
+            .find_entries_by_original_name(&name)
+            .into_iter()
+            .map(|(_, entry)| entry.clone())
+            .collect();
+        Response::SearchByOriginalName(PayloadResult::Ok(found))
+    }
+
+    fn search_by_path_fragment(&mut self, fragment: String) -> Response {
+        if fragment.is_empty() {
+            return Response::SearchByPathFragment(PayloadResult::Detailed(invalid_argument(
+                "no path fragment to search for",
+            )));
+        }
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => {
+                return Response::SearchByPathFragment(PayloadResult::Detailed(registry_error(e)))
+            }
+        };
+        let found = registry
+            .search_entries_by_path_fragment(&fragment)
+            .into_iter()
+            .cloned()
+            .collect();
+        Response::SearchByPathFragment(PayloadResult::Ok(found))
+    }
+
+    fn search_filtered(&mut self, filter: TagFilter) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::SearchFiltered(PayloadResult::Detailed(registry_error(e))),
+        };
+        let found = filter
+            .apply(&registry)
+            .into_iter()
+            .filter_map(|id| registry.get_entry(id).cloned())
+            .collect();
+        Response::SearchFiltered(PayloadResult::Ok(found))
+    }
+
+    fn search_weighted(&mut self, tags: Vec<String>, any: bool) -> Response {
+        if tags.is_empty() {
+            return Response::SearchWeighted(PayloadResult::Detailed(invalid_argument(
+                "no tags to search for",
+            )));
+        }
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::SearchWeighted(PayloadResult::Detailed(registry_error(e))),
+        };
+        let entries = if any {
+            registry.list_entries_with_any_tags(&tags)
+        } else {
+            registry.list_entries_with_all_tags(&tags)
+        };
+        let mut found: Vec<_> = entries
+            .into_iter()
+            .filter_map(|id| {
+                registry
+                    .get_entry(id)
+                    .map(|entry| (entry.clone(), registry.entry_score(id, &tags)))
+            })
+            .collect();
+        found.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Response::SearchWeighted(PayloadResult::Ok(found))
+    }
+
+    /// Finds entries like [search_weighted](WutagDaemon::search_weighted) returning them with
+    /// their tags so that clients don't have to inspect every found file.
+    fn search_with_tags(&mut self, tags: Vec<String>, any: bool) -> Response {
+        if tags.is_empty() {
+            return Response::SearchWithTags(PayloadResult::Detailed(invalid_argument(
+                "no tags to search for",
+            )));
+        }
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::SearchWithTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        let entries = if any {
+            registry.list_entries_with_any_tags(&tags)
+        } else {
+            registry.list_entries_with_all_tags(&tags)
+        };
+        let mut found: Vec<_> = entries
+            .into_iter()
+            .filter_map(|id| {
+                registry
+                    .get_entry(id)
+                    .map(|entry| (id, entry, registry.entry_score(id, &tags)))
+            })
+            .collect();
+        found.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+        let found = found
+            .into_iter()
+            .map(|(id, entry, _)| {
+                let mut entry_tags: Vec<_> = registry
+                    .list_entry_tags(id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                entry_tags.sort_unstable();
+                (entry.clone(), entry_tags)
+            })
+            .collect();
+        Response::SearchWithTags(PayloadResult::Ok(found))
+    }
+
     fn ping(&mut self) -> Response {
         Response::Ping(PayloadResult::Ok(()))
     }
 
+    /// Removes all entries and tags from the registry leaving the files untouched. Entries of
+    /// deleted files are logged separately and all of the removed entries stop being watched.
     fn clean_cache(&mut self) -> Response {
-        let mut registry = get_registry_write();
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::ClearCache(PayloadResult::Detailed(registry_error(e))),
+        };
+        let mut removed = vec![];
+        for entry in registry.evict_stale_entries() {
+            log::info!(
+                "entry `{}` not found, removing from registry",
+                entry.path().display()
+            );
+            removed.push(entry.into_path_buf());
+        }
+        removed.extend(
+            registry
+                .list_entries()
+                .map(|entry| entry.path().to_path_buf()),
+        );
         registry.clear();
         if let Err(e) = registry.save() {
             log::error!("{e}")
         }
+        if !removed.is_empty() {
+            self.push_event(EntryEvent::Remove(removed));
+        }
         Response::ClearCache(PayloadResult::Ok(()))
     }
+
+    fn export_graph(&mut self) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::ExportGraph(PayloadResult::Detailed(registry_error(e))),
+        };
+        let mut dot = vec![];
+        if let Err(e) = registry.export_dot(&mut dot) {
+            return Response::ExportGraph(PayloadResult::Error(e.to_string()));
+        }
+        Response::ExportGraph(PayloadResult::Ok(
+            String::from_utf8_lossy(&dot).into_owned(),
+        ))
+    }
+
+    fn recent_tags(&mut self) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::RecentTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        let tags = registry
+            .list_recent_tags()
+            .filter_map(|name| registry.get_tag(name).cloned())
+            .collect();
+        Response::RecentTags(PayloadResult::Ok(tags))
+    }
+
+    fn clear_recent_tags(&mut self) -> Response {
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::ClearRecentTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        registry.clear_recent_tags();
+        if let Err(e) = registry.save() {
+            log::error!("{e}")
+        }
+        Response::ClearRecentTags(PayloadResult::Ok(()))
+    }
+
+    /// Renames all tags starting with `from_pattern` by replacing the prefix with `replacement`.
+    fn rename_tags_matching(&mut self, from_pattern: String, replacement: String) -> Response {
+        if from_pattern.is_empty() {
+            return Response::RenameTagsMatching(PayloadResult::Detailed(invalid_argument(
+                "no prefix to rename",
+            )));
+        }
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => {
+                return Response::RenameTagsMatching(PayloadResult::Detailed(registry_error(e)))
+            }
+        };
+
+        let mut mapping: Vec<_> = registry
+            .list_tags()
+            .filter_map(|tag| {
+                tag.name()
+                    .strip_prefix(&from_pattern)
+                    .map(|rest| (tag.name().to_string(), format!("{replacement}{rest}")))
+            })
+            .filter(|(from, to)| from != to)
+            .collect();
+        mapping.sort_unstable();
+
+        let mut files: BTreeMap<(PathBuf, bool), Vec<&(String, String)>> = BTreeMap::new();
+        for rename in &mapping {
+            for id in registry.list_entries_with_any_tags([&rename.0]) {
+                if let Some(entry) = registry.get_entry(id) {
+                    files
+                        .entry((entry.path().to_path_buf(), !entry.no_follow()))
+                        .or_default()
+                        .push(rename);
+                }
+            }
+            if registry.get_tag(&rename.1).is_some() {
+                log::info!("merging tag {} into existing tag {}", rename.0, rename.1);
+            }
+        }
+
+        let mut errors = vec![];
+        for ((path, follow), renames) in files {
+            for (from, _) in &renames {
+                if let Err(e) = Tag::new(from, DEFAULT_COLOR).remove_from(&path, follow) {
+                    errors.push(format!("{} tag: {from}, error: {e}", path.display()));
+                }
+            }
+            for (_, to) in &renames {
+                match Tag::new(to, DEFAULT_COLOR).save_to(&path, follow) {
+                    Ok(_) | Err(CoreError::TagExists) => {}
+                    Err(e) => errors.push(format!("{} tag: {to}, error: {e}", path.display())),
+                }
+            }
+        }
+
+        registry.rename_tags(&mapping);
+        if let Err(e) = registry.save() {
+            log::error!("{e}")
+        }
+
+        if errors.is_empty() {
+            Response::RenameTagsMatching(PayloadResult::Ok(mapping))
+        } else {
+            Response::RenameTagsMatching(PayloadResult::Error(errors))
+        }
+    }
+
+    fn stats(&mut self) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::Stats(PayloadResult::Detailed(registry_error(e))),
+        };
+        let unsaved = match registry.unsaved_entries() {
+            Ok(unsaved) => Some(unsaved),
+            Err(e) => {
+                log::error!("failed to compare the registry with its file - {e}");
+                None
+            }
+        };
+        Response::Stats(PayloadResult::Ok(RegistryStats {
+            path: registry.path().to_path_buf(),
+            entries: registry.list_entries().count(),
+            tags: registry.list_tags().count(),
+            most_used_tags: registry
+                .list_most_used_tags(MOST_USED_TAGS)
+                .into_iter()
+                .map(|(tag, count)| (tag.name().to_string(), count))
+                .collect(),
+            watch_failures: WATCH_FAILURES
+                .read()
+                .map(|failures| failures.clone())
+                .unwrap_or_else(|e| e.into_inner().clone()),
+            last_maintenance: maintenance::last_maintenance(),
+            unsaved_added: unsaved.map(|unsaved| unsaved.added),
+            unsaved_removed: unsaved.map(|unsaved| unsaved.removed),
+        }))
+    }
+
+    /// Runs the scheduled maintenance of all registries right away.
+    fn run_maintenance(&mut self) -> Response {
+        let summary = maintenance::run_maintenance(self.maintenance_prune_expired_tags, self.watch);
+        Response::RunMaintenance(PayloadResult::Ok(summary))
+    }
+
+    /// Returns entries that no longer exist on the filesystem.
+    fn verify(&mut self) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::Verify(PayloadResult::Detailed(registry_error(e))),
+        };
+        Response::Verify(PayloadResult::Ok(
+            registry
+                .list_stale_entries()
+                .map(|(_, entry)| entry.clone())
+                .collect(),
+        ))
+    }
+
+    /// Compares tags of entries with tags of their files, skipping files unchanged since they
+    /// were last verified unless `full` is set.
+    fn verify_tags(&mut self, full: bool) -> Response {
+        // files are read under the read lock so that other requests aren't blocked meanwhile
+        let mut report = match get_registry_read() {
+            Ok(registry) => {
+                let report = registry.verify_entries(full);
+                if report.count_drift {
+                    log::error!(
+                        "tag counts of entries in registry `{}` don't match its tags",
+                        registry.path().display()
+                    );
+                }
+                report
+            }
+            Err(e) => return Response::VerifyTags(PayloadResult::Detailed(registry_error(e))),
+        };
+        for (path, e) in &report.errors {
+            log::debug!("failed to list tags of `{}` - {e}", path.display());
+        }
+        if report.has_verified_changes() {
+            match get_registry_write() {
+                Ok(mut registry) => {
+                    if registry.record_verified(&mut report) {
+                        if let Err(e) = registry.save() {
+                            log::error!("{e}");
+                        }
+                    }
+                }
+                Err(e) => log::error!("failed to store verified entries - {e}"),
+            }
+        }
+        Response::VerifyTags(PayloadResult::Ok(report))
+    }
+
+    fn absolutize_paths(&mut self, root: PathBuf) -> Response {
+        if root.is_relative() {
+            return Response::AbsolutizePaths(PayloadResult::Detailed(invalid_argument(format!(
+                "root `{}` is not an absolute path",
+                root.display()
+            ))));
+        }
+        let mut registry = match get_registry_write() {
+            Ok(registry) => registry,
+            Err(e) => return Response::AbsolutizePaths(PayloadResult::Detailed(registry_error(e))),
+        };
+        let relative: Vec<_> = registry
+            .list_entries()
+            .map(EntryData::path)
+            .filter(|path| path.is_relative())
+            .map(Path::to_path_buf)
+            .collect();
+        let migration = registry.absolutize_paths(&root);
+        if let Err(e) = registry.save() {
+            log::error!("{e}");
+        }
+        drop(registry);
+
+        let added: Vec<_> = relative
+            .into_iter()
+            .filter(|path| !migration.unresolved.contains(path))
+            .map(|path| root.join(path))
+            .collect();
+        if !added.is_empty() {
+            self.push_event(EntryEvent::Add(added));
+        }
+        Response::AbsolutizePaths(PayloadResult::Ok(migration))
+    }
+
+    /// Returns entries tagged with both tags `a` and `b` and with only one of them.
+    fn tag_overlap(&mut self, a: String, b: String) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::TagOverlap(PayloadResult::Detailed(registry_error(e))),
+        };
+        for name in [&a, &b] {
+            if registry.get_tag(name).is_none() {
+                return Response::TagOverlap(PayloadResult::Detailed(tag_not_found(
+                    &registry, name,
+                )));
+            }
+        }
+        Response::TagOverlap(PayloadResult::Ok(
+            registry.tag_overlap(&a, &b).unwrap_or_default(),
+        ))
+    }
+
+    fn add_implication_rule(&mut self, if_tag: String, then_tag: String) -> Response {
+        if if_tag.is_empty() || then_tag.is_empty() {
+            return Response::AddImplicationRule(PayloadResult::Detailed(invalid_argument(
+                "tag names can't be empty",
+            )));
+        }
+        if if_tag == then_tag {
+            return Response::AddImplicationRule(PayloadResult::Detailed(invalid_argument(
+                format!("tag `{if_tag}` can't imply itself"),
+            )));
+        }
+        let rule = ImplicationRule { if_tag, then_tag };
+        if self.implication_rules.contains(&rule) {
+            return Response::AddImplicationRule(PayloadResult::Ok(()));
+        }
+
+        // the config is reloaded so that options overridden on the command line aren't saved
+        let result = DaemonConfig::default_path().and_then(|path| {
+            let mut config = if path.exists() {
+                DaemonConfig::load_file(&path)?
+            } else {
+                DaemonConfig::default()
+            };
+            if !config.implication_rules.contains(&rule) {
+                config.implication_rules.push(rule.clone());
+            }
+            config.save_file(&path)
+        });
+        if let Err(e) = result {
+            return Response::AddImplicationRule(PayloadResult::Error(e.to_string()));
+        }
+        self.implication_rules.push(rule);
+        Response::AddImplicationRule(PayloadResult::Ok(()))
+    }
+
+    fn set_log_level(&mut self, level: String) -> Response {
+        match level.parse::<log::LevelFilter>() {
+            Ok(level) if LOG_LEVEL_LIMIT.get().is_some_and(|limit| level > *limit) => {
+                Response::SetLogLevel(PayloadResult::Detailed(invalid_argument(format!(
+                    "log level `{level}` is above `{}` enabled by module filters of RUST_LOG, \
+                     restart the daemon with a different RUST_LOG to raise it",
+                    LOG_LEVEL_LIMIT.get().unwrap()
+                ))))
+            }
+            Ok(level) => {
+                log::set_max_level(level);
+                log::info!("log level set to {level}");
+                Response::SetLogLevel(PayloadResult::Ok(()))
+            }
+            Err(_) => Response::SetLogLevel(PayloadResult::Detailed(invalid_argument(format!(
+                "invalid log level `{level}`, valid levels are off, error, warn, info, debug and trace"
+            )))),
+        }
+    }
+
+    fn tag_exists(&mut self, name: String) -> Response {
+        let registry = match get_registry_read() {
+            Ok(registry) => registry,
+            Err(e) => return Response::TagExists(PayloadResult::Detailed(registry_error(e))),
+        };
+        Response::TagExists(PayloadResult::Ok(registry.get_tag(name).is_some()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DaemonConfig;
+    use crate::notifyd::NotifyDaemon;
+    use crate::TEST_DIR;
+    use std::collections::BTreeSet;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tempdir::TempDir;
+    use wutag_core::registry::RegistryMount;
+    use wutag_core::tag::Tag;
+    use wutag_ipc::{socket_name, IpcClient};
+
+    fn new_daemon(name: &str) -> (WutagDaemon, String) {
+        let socket = socket_name(
+            TEST_DIR.path(),
+            format!("wutag-{name}-{}.sock", std::process::id()),
+        );
+        let daemon = WutagDaemon::new(
+            IpcServer::new(socket.as_str()).unwrap(),
+            &DaemonConfig::default(),
+        )
+        .unwrap();
+        (daemon, socket)
+    }
+
+    const CLIENTS: usize = 8;
+    const ITERATIONS: usize = 25;
+
+    fn request(client: &IpcClient, request: Request) -> Response {
+        client.request(request).expect("response from daemon")
+    }
+
+    fn tag_names(tags: impl IntoIterator<Item = Tag>) -> BTreeSet<String> {
+        tags.into_iter().map(Tag::into_name).collect()
+    }
+
+    /// Runs the daemon along with the notify daemon against a temporary registry and socket and
+    /// sends concurrent requests from multiple clients checking that every request is handled and
+    /// that the final state of the registry matches the tags of the files.
+    #[test]
+    fn handles_concurrent_requests() {
+        let dir = TempDir::new("wutag-daemon-stress").unwrap();
+        let (daemon, socket) = new_daemon("stress");
+        let notify_daemon = NotifyDaemon::new(&DaemonConfig::default()).unwrap();
+        std::thread::spawn(|| daemon.work_loop());
+        std::thread::spawn(|| notify_daemon.work_loop());
+
+        let files: Vec<_> = (0..CLIENTS)
+            .map(|i| dir.path().join(format!("file{i}")))
+            .collect();
+        let (tx, rx) = mpsc::channel();
+        for (i, file) in files.iter().cloned().enumerate() {
+            std::fs::write(&file, b"").unwrap();
+            let tx = tx.clone();
+            let client = IpcClient::new(socket.as_str());
+            std::thread::spawn(move || {
+                let shared = Tag::new("shared", Color::Red);
+                let own = Tag::new(format!("own{i}"), Color::Red);
+                for _ in 0..ITERATIONS {
+                    let response = request(
+                        &client,
+                        Request::TagFiles {
+                            files: vec![file.clone()],
+                            tags: vec![shared.clone(), own.clone()],
+                            auto_tag: false,
+                            rules: vec![],
+                            expires_in: None,
+                            no_follow: false,
+                            palette: vec![],
+                        },
+                    );
+                    assert!(
+                        matches!(response, Response::TagFiles(PayloadResult::Ok(_))),
+                        "{response:?}"
+                    );
+                    let response = request(
+                        &client,
+                        Request::Search {
+                            tags: vec![own.name().to_string()],
+                            any: false,
+                        },
+                    );
+                    assert!(
+                        matches!(&response, Response::Search(PayloadResult::Ok(found)) if found.len() == 1),
+                        "{response:?}"
+                    );
+                    let response = request(
+                        &client,
+                        Request::UntagFiles {
+                            files: vec![file.clone()],
+                            tags: vec![own.clone()],
+                            no_follow: false,
+                            force: false,
+                        },
+                    );
+                    assert!(
+                        matches!(response, Response::UntagFiles(PayloadResult::Ok(_))),
+                        "{response:?}"
+                    );
+                }
+                request(
+                    &client,
+                    Request::TagFiles {
+                        files: vec![file],
+                        tags: vec![own],
+                        auto_tag: false,
+                        rules: vec![],
+                        expires_in: None,
+                        no_follow: false,
+                        palette: vec![],
+                    },
+                );
+                tx.send(i).unwrap();
+            });
+        }
+        drop(tx);
+        for _ in 0..CLIENTS {
+            rx.recv_timeout(Duration::from_secs(60))
+                .expect("all clients should finish without a deadlock or a panic");
+        }
+
+        // give the notify daemon time to process the remaining events
+        std::thread::sleep(Duration::from_millis(500));
+
+        let client = IpcClient::new(socket.as_str());
+        match request(
+            &client,
+            Request::Search {
+                tags: vec!["shared".into()],
+                any: false,
+            },
+        ) {
+            Response::Search(PayloadResult::Ok(found)) => assert_eq!(found.len(), CLIENTS),
+            response => panic!("unexpected response {response:?}"),
+        }
+        let inspected = match request(
+            &client,
+            Request::InspectFiles {
+                files: files.clone(),
+                no_follow: false,
+            },
+        ) {
+            Response::InspectFiles(PayloadResult::Ok(result)) => result.entries,
+            response => panic!("unexpected response {response:?}"),
+        };
+        assert_eq!(inspected.len(), CLIENTS);
+        for (entry, tags) in inspected {
+            let i = files.iter().position(|f| f == entry.path()).unwrap();
+            let expected: BTreeSet<_> = ["shared".to_string(), format!("own{i}")].into();
+            assert_eq!(tag_names(tags), expected);
+            assert_eq!(tag_names(list_tags(entry.path(), true).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn deduplicates_identical_requests() {
+        let dir = TempDir::new("wutag-daemon-dedup").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("dedup");
+        let tag = Tag::new("dedup", Color::Red);
+        let tag_file = || Request::TagFiles {
+            files: vec![file.clone()],
+            tags: vec![tag.clone()],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        };
+
+        for _ in 0..3 {
+            let response = daemon.handle_request(tag_file());
+            // duplicates get the response of the first request
+            assert!(matches!(
+                response,
+                Response::TagFiles(PayloadResult::Ok(BatchSummary { succeeded: 1, .. }))
+            ));
+            // removing the tag behind the back of the daemon reveals whether it was processed
+            // again as it would be saved to the file once more
+            let _ = tag.remove_from(&file, true);
+        }
+        assert!(list_tags(&file, true).unwrap().is_empty());
+
+        std::thread::sleep(DEDUP_WINDOW);
+        daemon.handle_request(tag_file());
+        assert_eq!(list_tags(&file, true).unwrap(), vec![tag.clone()]);
+
+        // a different modification in between makes the request be processed again
+        let _ = tag.remove_from(&file, true);
+        daemon.handle_request(tag_file());
+        daemon.handle_request(Request::ClearRecentTags);
+        daemon.handle_request(tag_file());
+        assert_eq!(list_tags(&file, true).unwrap(), vec![tag.clone()]);
+
+        // reads are never deduplicated
+        for _ in 0..2 {
+            let response = daemon.handle_request(Request::TagExists {
+                name: "dedup".into(),
+            });
+            assert!(matches!(
+                response,
+                Response::TagExists(PayloadResult::Ok(true))
+            ));
+        }
+    }
+
+    #[test]
+    fn deduplicates_requests_with_random_tag_colors() {
+        let dir = TempDir::new("wutag-daemon-dedup-colors").unwrap();
+        let files = ["a", "b"].map(|name| dir.path().join(name));
+        for file in &files {
+            std::fs::write(file, b"").unwrap();
+        }
+        let (mut daemon, _) = new_daemon("dedup-colors");
+        // built the way `wutag set` builds them, every tag gets a random color
+        let tag_files = |files: Vec<PathBuf>| Request::TagFiles {
+            files,
+            tags: vec![
+                Tag::random("dedup-colors-a", DEFAULT_COLORS),
+                Tag::random("dedup-colors-b", DEFAULT_COLORS),
+            ],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        };
+
+        daemon.handle_request(tag_files(files.to_vec()));
+        for file in &files {
+            assert_eq!(list_tags(file, true).unwrap().len(), 2);
+            clear_tags(file, true).unwrap();
+        }
+        let mut reversed = files.to_vec();
+        reversed.reverse();
+        let response = daemon.handle_request(tag_files(reversed));
+        assert!(matches!(
+            response,
+            Response::TagFiles(PayloadResult::Ok(BatchSummary { succeeded: 2, .. }))
+        ));
+        // the repeated request wasn't processed again
+        for file in &files {
+            assert!(list_tags(file, true).unwrap().is_empty());
+        }
+
+        let first = dedup_key(&tag_files(files.to_vec())).unwrap();
+        let mut auto_tagged = tag_files(files.to_vec());
+        if let Request::TagFiles { auto_tag, .. } = &mut auto_tagged {
+            *auto_tag = true;
+        }
+        assert_ne!(first, dedup_key(&auto_tagged).unwrap());
+    }
+
+    #[test]
+    fn records_outcome_in_audit_log() {
+        let (mut daemon, _) = new_daemon("audit");
+        let create_tag = || Request::CreateTag {
+            name: "audit-outcome".into(),
+            color: Color::Red,
+            description: None,
+        };
+        daemon.handle_request(create_tag());
+        // a tag with the same name already exists
+        std::thread::sleep(DEDUP_WINDOW);
+        daemon.handle_request(create_tag());
+
+        let outcomes = audit::read(&audit::audit_log_file(), None, None)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.tags == ["audit-outcome"])
+            .map(|entry| (entry.user, entry.succeeded))
+            .collect::<Vec<_>>();
+        // the requests were handled without a connection so the user isn't known
+        assert_eq!(
+            outcomes,
+            vec![
+                ("unknown".to_string(), true),
+                ("unknown".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn summarizes_batch_operations() {
+        let dir = TempDir::new("wutag-daemon-batch").unwrap();
+        let file = dir.path().join("file");
+        let missing = dir.path().join("missing");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("batch");
+        let tag = Tag::new("batch", Color::Red);
+        let summary = |response| match response {
+            Response::TagFiles(PayloadResult::Ok(summary))
+            | Response::UntagFiles(PayloadResult::Ok(summary)) => {
+                (summary.succeeded, summary.failed, summary.skipped)
+            }
+            response => panic!("unexpected response {response:?}"),
+        };
+
+        let response = daemon.handle_request(Request::TagFiles {
+            files: vec![file.clone(), missing.clone()],
+            tags: vec![tag.clone()],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        });
+        assert_eq!(summary(response), (1, 1, 0));
+
+        // the same tags again but with the file listed twice to avoid deduplication
+        let response = daemon.handle_request(Request::TagFiles {
+            files: vec![file.clone(), file.clone()],
+            tags: vec![tag.clone()],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        });
+        let Response::TagFiles(PayloadResult::Ok(batch)) = &response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(batch.already_tagged, 2);
+        assert_eq!(summary(response), (0, 0, 2));
+
+        let response = daemon.handle_request(Request::UntagFiles {
+            files: vec![file.clone(), missing],
+            tags: vec![tag.clone()],
+            no_follow: false,
+            force: false,
+        });
+        assert_eq!(summary(response), (1, 0, 1));
+
+        // a tag missing from the file is skipped and only removed from the registry
+        daemon.handle_request(Request::TagFiles {
+            files: vec![file.clone()],
+            tags: vec![tag.clone()],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        });
+        tag.remove_from(&file, true).unwrap();
+        let response = daemon.handle_request(Request::UntagFiles {
+            files: vec![file.clone()],
+            tags: vec![tag.clone()],
+            no_follow: false,
+            force: false,
+        });
+        assert_eq!(summary(response), (0, 0, 1));
+        let registry = get_registry_read().unwrap();
+        assert!(registry
+            .find_entry(&file)
+            .and_then(|id| registry.list_entry_tags(id))
+            .map_or(true, |tags| !tags.contains(&&tag)));
+    }
+
+    #[test]
+    fn reports_capacity_of_files() {
+        let dir = TempDir::new("wutag-daemon-capacity").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("capacity");
+        let capacity = |daemon: &mut WutagDaemon| match daemon
+            .handle_request(Request::XattrCapacity { file: file.clone() })
+        {
+            Response::XattrCapacity(PayloadResult::Ok(capacity)) => capacity,
+            response => panic!("unexpected response {response:?}"),
+        };
+
+        let empty = capacity(&mut daemon);
+        assert_eq!((empty.tags, empty.tag_size), (0, TYPICAL_TAG_SIZE));
+        assert!(empty.tags_left > 0);
+
+        // no filesystem takes values larger than 64 KiB so the last tag never fits
+        let mut tags: Vec<_> = (0..3)
+            .map(|i| Tag::new(format!("capacity-{i}"), Color::Red))
+            .collect();
+        let mut large = Tag::new("capacity-large", Color::Red);
+        large.set_description(Some("x".repeat(70_000)));
+        tags.push(large);
+        let response = daemon.handle_request(Request::TagFiles {
+            files: vec![file.clone()],
+            tags,
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        });
+        let Response::TagFiles(PayloadResult::Ok(summary)) = response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(summary.failed, 1);
+        assert!(summary.errors[0].contains("3 of 4 tags were saved"));
+
+        let tagged = capacity(&mut daemon);
+        assert_eq!(tagged.tags, 3);
+        assert_eq!(tagged.tags_left, tagged.capacity.fits(tagged.tag_size));
+        assert!(tagged.capacity.free <= empty.capacity.free);
+    }
+
+    #[test]
+    fn suggests_similar_tags() {
+        let mut registry = TagRegistry::default();
+        let (id, _) = registry.add_or_update_entry(EntryData::new("/tmp"));
+        for name in ["Rust", "rust-lang", "python"] {
+            registry.tag_entry(&Tag::new(name, Color::Red), id);
+        }
+
+        let error = tag_not_found(&registry, "rust");
+        assert_eq!(error.code, ErrorCode::TagNotFound);
+        let details = error.details.unwrap();
+        assert_eq!(details["tag"], "rust");
+        assert_eq!(
+            details["suggested_tags"],
+            serde_json::json!(["Rust", "rust-lang"])
+        );
+    }
+
+    #[test]
+    fn reports_error_codes() {
+        let dir = TempDir::new("wutag-daemon-codes").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("codes");
+        let detailed = |response| match response {
+            Response::TagFiles(PayloadResult::Detailed(e))
+            | Response::CopyTags(PayloadResult::Detailed(e))
+            | Response::XattrCapacity(PayloadResult::Detailed(e)) => e,
+            response => panic!("unexpected response {response:?}"),
+        };
+
+        let error = detailed(daemon.handle_request(Request::TagFiles {
+            files: vec![],
+            tags: vec![Tag::new("codes", Color::Red)],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        }));
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+
+        let error = detailed(daemon.handle_request(Request::CopyTags {
+            source: file.clone(),
+            target: vec![dir.path().join("other")],
+        }));
+        assert_eq!(error.code, ErrorCode::EntryNotFound);
+        assert_eq!(error.details.unwrap()["path"], file.display().to_string());
+
+        let missing = dir.path().join("missing");
+        let error = detailed(daemon.handle_request(Request::XattrCapacity {
+            file: missing.clone(),
+        }));
+        assert_eq!(error.code, ErrorCode::Filesystem);
+        assert_eq!(
+            error.details.unwrap()["path"],
+            missing.display().to_string()
+        );
+
+        let error = detailed(daemon.handle_request(Request::TagFilesPattern {
+            glob: Glob {
+                pattern: "file[".into(),
+                base_dir: dir.path().to_path_buf(),
+                max_depth: 1,
+            },
+            tags: vec![Tag::new("codes", Color::Red)],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        }));
+        assert_eq!(error.code, ErrorCode::Pattern);
+        assert_eq!(error.details.unwrap()["pattern"], "file[");
+
+        let error = registry_error(registry::RegistryError::Lock);
+        assert_eq!(error.code, ErrorCode::Registry);
+    }
+
+    #[test]
+    fn protects_system_tags() {
+        let dir = TempDir::new("wutag-daemon-system").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("system");
+        let system = Tag::new("system-protected", Color::Red);
+        let other = Tag::new("system-other", Color::Blue);
+        let tag_file = |daemon: &mut WutagDaemon| {
+            daemon.handle_request(Request::TagFiles {
+                files: vec![file.clone()],
+                tags: vec![system.clone(), other.clone()],
+                auto_tag: false,
+                rules: vec![],
+                expires_in: None,
+                no_follow: false,
+                palette: vec![],
+            })
+        };
+        let saved = || {
+            let mut tags = list_tags(&file, true).unwrap();
+            tags.sort_unstable();
+            tags
+        };
+
+        tag_file(&mut daemon);
+        assert!(matches!(
+            daemon.handle_request(Request::EditTag {
+                tag: system.name().to_string(),
+                color: None,
+                weight: None,
+                system: Some(true),
+            }),
+            Response::EditTag(PayloadResult::Ok(()))
+        ));
+        assert!(is_system_tag(&get_registry_read().unwrap(), system.name()));
+
+        let Response::UntagFiles(PayloadResult::Ok(summary)) =
+            daemon.handle_request(Request::UntagFiles {
+                files: vec![file.clone()],
+                tags: vec![system.clone()],
+                no_follow: false,
+                force: false,
+            })
+        else {
+            panic!("unexpected response");
+        };
+        assert_eq!((summary.succeeded, summary.skipped), (0, 1));
+        assert!(summary.errors[0].contains("system tag"));
+        assert_eq!(saved(), vec![other.clone(), system.clone()]);
+
+        let Response::ClearFiles(PayloadResult::Ok(summary)) =
+            daemon.handle_request(Request::ClearFiles {
+                files: vec![file.clone()],
+                force: false,
+            })
+        else {
+            panic!("unexpected response");
+        };
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(saved(), vec![system.clone()]);
+
+        let response = daemon.handle_request(Request::ClearTags {
+            tags: vec![system.name().to_string()],
+            patterns: false,
+            force: false,
+        });
+        assert!(matches!(
+            response,
+            Response::ClearTags(PayloadResult::Error(errors)) if errors.len() == 1
+        ));
+        assert_eq!(saved(), vec![system.clone()]);
+
+        let response = daemon.handle_request(Request::UntagFiles {
+            files: vec![file.clone()],
+            tags: vec![system.clone()],
+            no_follow: false,
+            force: true,
+        });
+        assert!(matches!(
+            response,
+            Response::UntagFiles(PayloadResult::Ok(_))
+        ));
+        assert!(saved().is_empty());
+        assert!(get_registry_read().unwrap().find_entry(&file).is_none());
+    }
+
+    #[test]
+    fn tags_files_with_predefined_tags() {
+        let dir = TempDir::new("wutag-daemon-predefined").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("predefined");
+        let name = "predefined-tag";
+        let response = daemon.handle_request(Request::CreateTag {
+            name: name.into(),
+            color: Color::Magenta,
+            description: None,
+        });
+        assert!(matches!(
+            response,
+            Response::CreateTag(PayloadResult::Ok(()))
+        ));
+        let response = daemon.handle_request(Request::EditTag {
+            tag: name.into(),
+            color: None,
+            weight: Some(3.0),
+            system: Some(true),
+        });
+        assert!(matches!(response, Response::EditTag(PayloadResult::Ok(()))));
+
+        daemon.handle_request(Request::TagFiles {
+            files: vec![file.clone()],
+            tags: vec![Tag::new(name, Color::Red)],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        });
+        let saved = list_tags(&file, true).unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].color(), &Color::Magenta);
+        let registry = get_registry_read().unwrap();
+        let tag = registry.get_tag(name).unwrap();
+        assert_eq!(tag.color(), &Color::Magenta);
+        assert_eq!((tag.weight(), tag.is_system()), (3.0, true));
+    }
+
+    #[test]
+    fn rewrites_saved_colors_when_recoloring() {
+        let dir = TempDir::new("wutag-daemon-recolor").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("recolor");
+        let tag = Tag::new("recolor-me", Color::Red);
+        daemon.handle_request(Request::TagFiles {
+            files: vec![file.clone()],
+            tags: vec![tag.clone()],
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        });
+
+        let response = daemon.handle_request(Request::EditTag {
+            tag: tag.name().to_string(),
+            color: Some(Color::Blue),
+            weight: None,
+            system: None,
+        });
+        assert!(matches!(response, Response::EditTag(PayloadResult::Ok(()))));
+        let saved = list_tags(&file, true).unwrap();
+        assert_eq!(saved, vec![tag]);
+        assert_eq!(saved[0].color(), &Color::Blue);
+    }
+
+    #[test]
+    fn clears_tags_literally() {
+        let dir = TempDir::new("wutag-daemon-literal").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("literal");
+        let tags: Vec<_> = ["literal-*", "literal-a"]
+            .into_iter()
+            .map(|name| Tag::new(name, Color::Red))
+            .collect();
+        daemon.handle_request(Request::TagFiles {
+            files: vec![file.clone()],
+            tags: tags.clone(),
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        });
+
+        let response = daemon.handle_request(Request::ClearTags {
+            tags: vec!["literal-*".into()],
+            patterns: false,
+            force: false,
+        });
+        assert!(matches!(
+            response,
+            Response::ClearTags(PayloadResult::Ok(()))
+        ));
+        assert_eq!(list_tags(&file, true).unwrap(), vec![tags[1].clone()]);
+    }
+
+    #[test]
+    fn clears_all_tags_with_special_characters() {
+        // every tag of the registry is cleared so the test uses the registry of a mount
+        let dir = test_mount().join("clear-all");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("clear-all");
+        let tags: Vec<_> = ["back\\slash", "star*"]
+            .into_iter()
+            .map(|name| Tag::new(name, Color::Red))
+            .collect();
+
+        registry::with_scope(Some(0), || {
+            daemon.handle_request(Request::TagFiles {
+                files: vec![file.clone()],
+                tags: tags.clone(),
+                auto_tag: false,
+                rules: vec![],
+                expires_in: None,
+                no_follow: false,
+                palette: vec![],
+            });
+            assert_eq!(list_tags(&file, true).unwrap().len(), 2);
+            let response = daemon.handle_request(Request::ClearAllTags { force: false });
+            assert!(matches!(
+                response,
+                Response::ClearTags(PayloadResult::Ok(()))
+            ));
+            assert!(list_tags(&file, true).unwrap().is_empty());
+            let registry = get_registry_read().unwrap();
+            assert!(tags
+                .iter()
+                .all(|tag| registry.get_tag(tag.name()).is_none()));
+        });
+    }
+
+    #[test]
+    fn absolutizes_relative_paths() {
+        let dir = TempDir::new("wutag-daemon-absolutize").unwrap();
+        std::fs::write(dir.path().join("absolutize-moved"), b"").unwrap();
+        let (mut daemon, _) = new_daemon("absolutize");
+
+        let response = daemon.handle_request(Request::AbsolutizePaths {
+            root: "relative".into(),
+        });
+        assert!(matches!(
+            response,
+            Response::AbsolutizePaths(PayloadResult::Detailed(e)) if e.code == ErrorCode::InvalidArgument
+        ));
+
+        {
+            let mut registry = get_registry_write().unwrap();
+            for name in ["absolutize-moved", "absolutize-missing"] {
+                let (id, _) = registry.add_or_update_entry(EntryData::new(name));
+                registry.tag_entry(&Tag::new("absolutize", Color::Red), id);
+            }
+        }
+        // holding the lock of the events makes the daemon keep them, otherwise notify daemons of
+        // other tests could consume them first
+        let events = ENTRIES_EVENTS.write().unwrap();
+        let response = daemon.handle_request(Request::AbsolutizePaths {
+            root: dir.path().to_path_buf(),
+        });
+        drop(events);
+        let Response::AbsolutizePaths(PayloadResult::Ok(migration)) = response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(migration.rewritten, 1);
+        assert_eq!(
+            migration.unresolved,
+            vec![PathBuf::from("absolutize-missing")]
+        );
+
+        let moved = dir.path().join("absolutize-moved");
+        let saved = TagRegistry::load(registry::registry_file()).unwrap();
+        assert!(saved.find_entry(&moved).is_some());
+        assert!(saved.find_entry("absolutize-moved").is_none());
+        assert!(daemon.unprocessed_events.iter().any(
+            |event| matches!(event, EntryEvent::Add(paths) if paths == std::slice::from_ref(&moved))
+        ));
+
+        let mut registry = get_registry_write().unwrap();
+        if let Some(id) = registry.find_entry("absolutize-missing") {
+            registry.clear_entry(id);
+        }
+    }
+
+    #[test]
+    fn clears_tags_matching_patterns() {
+        let dir = TempDir::new("wutag-daemon-wildcard").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("wildcard");
+        let tags: Vec<_> = ["wildcard-tmp-a", "wildcard-tmp-b", "wildcard-keep"]
+            .into_iter()
+            .map(|name| Tag::new(name, Color::Red))
+            .collect();
+        daemon.handle_request(Request::TagFiles {
+            files: vec![file.clone()],
+            tags: tags.clone(),
+            auto_tag: false,
+            rules: vec![],
+            expires_in: None,
+            no_follow: false,
+            palette: vec![],
+        });
+
+        let response = daemon.handle_request(Request::ClearTags {
+            tags: vec!["wildcard-tmp-*".into()],
+            patterns: true,
+            force: false,
+        });
+        assert!(matches!(
+            response,
+            Response::ClearTags(PayloadResult::Ok(()))
+        ));
+        assert_eq!(list_tags(&file, true).unwrap(), vec![tags[2].clone()]);
+        let registry = get_registry_read().unwrap();
+        assert!(registry.get_tag("wildcard-tmp-a").is_none());
+        assert!(registry.get_tag("wildcard-tmp-b").is_none());
+        assert!(registry.get_tag("wildcard-keep").is_some());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn tags_symlink_targets_unless_no_follow() {
+        let dir = TempDir::new("wutag-daemon-symlink").unwrap();
+        let target = dir.path().join("target");
+        let link = dir.path().join("link");
+        std::fs::write(&target, b"").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let (mut daemon, _) = new_daemon("symlink");
+        let tag = Tag::new("linked", Color::Red);
+        let tag_link =
+            |daemon: &mut WutagDaemon, no_follow| match daemon.handle_request(Request::TagFiles {
+                files: vec![link.clone(), link.clone()],
+                tags: vec![tag.clone()],
+                auto_tag: false,
+                rules: vec![],
+                expires_in: None,
+                no_follow,
+                palette: vec![],
+            }) {
+                Response::TagFiles(PayloadResult::Ok(summary)) => summary,
+                response => panic!("unexpected response {response:?}"),
+            };
+        let inspect_link = |daemon: &mut WutagDaemon, no_follow| match daemon.handle_request(
+            Request::InspectFiles {
+                files: vec![link.clone()],
+                no_follow,
+            },
+        ) {
+            Response::InspectFiles(PayloadResult::Ok(result)) => result.entries,
+            response => panic!("unexpected response {response:?}"),
+        };
+
+        assert_eq!(tag_link(&mut daemon, false).succeeded, 1);
+        assert_eq!(list_tags(&target, true).unwrap(), vec![tag.clone()]);
+        assert_eq!(inspect_link(&mut daemon, false)[0].1, vec![tag.clone()]);
+        assert!(inspect_link(&mut daemon, true).is_empty());
+
+        // the link is already tracked with the tags of its target
+        let summary = tag_link(&mut daemon, true);
+        assert_eq!(summary.failed, 2);
+        assert!(summary.errors[0].contains("following the symlink"));
+        assert!(list_tags(&link, false).unwrap().is_empty());
+
+        // untagging has to handle the link the same way
+        let untag_link =
+            |daemon: &mut WutagDaemon, no_follow| match daemon.handle_request(Request::UntagFiles {
+                files: vec![link.clone()],
+                tags: vec![tag.clone()],
+                no_follow,
+                force: false,
+            }) {
+                Response::UntagFiles(PayloadResult::Ok(summary)) => summary,
+                response => panic!("unexpected response {response:?}"),
+            };
+        let summary = untag_link(&mut daemon, true);
+        assert_eq!(summary.failed, 1);
+        assert!(summary.errors[0].contains("following the symlink"));
+        assert_eq!(list_tags(&target, true).unwrap(), vec![tag.clone()]);
+        assert_eq!(untag_link(&mut daemon, false).succeeded, 1);
+        assert!(list_tags(&target, true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn evicts_entries_over_the_limit() {
+        let dir = TempDir::new("wutag-daemon-evict").unwrap();
+        let mut registry = TagRegistry::new(dir.path().join("wutag.db"));
+        let tag = Tag::new("evicted", Color::Red);
+        let files: Vec<_> = (0..5)
+            .map(|i| {
+                let file = dir.path().join(format!("file-{i}"));
+                std::fs::write(&file, b"").unwrap();
+                tag.save_to(&file, true).unwrap();
+                let (id, _) = registry.add_or_update_entry(EntryData::new(&file));
+                registry.tag_entry(&tag, id);
+                std::thread::sleep(Duration::from_millis(5));
+                file
+            })
+            .collect();
+
+        let none = HashSet::new();
+        let evicted = evict_excess_entries(&mut registry, 3, EvictionPolicy::Oldest, &none);
+        assert_eq!(evicted, files[..2]);
+        assert_eq!(registry.list_entries().count(), 3);
+        for file in &files[..2] {
+            assert!(registry.find_entry(file).is_none());
+            assert!(list_tags(file, true).unwrap().is_empty());
+        }
+        for file in &files[2..] {
+            assert!(registry.find_entry(file).is_some());
+            assert_eq!(list_tags(file, true).unwrap(), vec![tag.clone()]);
+        }
+        assert!(evict_excess_entries(&mut registry, 3, EvictionPolicy::Oldest, &none).is_empty());
+
+        // entries changed by the current request are kept even if they would be evicted first
+        registry.record_changes();
+        let id = registry.find_entry(&files[2]).unwrap();
+        registry.tag_entry(&Tag::new("touched", Color::Red), id);
+        let touched = registry.changed_entries();
+        let evicted = evict_excess_entries(&mut registry, 2, EvictionPolicy::Oldest, &touched);
+        assert_eq!(evicted, files[3..4]);
+        assert!(registry.find_entry(&files[2]).is_some());
+    }
+
+    #[test]
+    fn sets_exact_tags_of_files() {
+        let dir = TempDir::new("wutag-daemon-set-exact").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (mut daemon, _) = new_daemon("set-exact");
+        let tag = |name: &str| Tag::new(name, Color::Red);
+        tag("old").save_to(&file, true).unwrap();
+        tag("kept").save_to(&file, true).unwrap();
+        let mut set_exact = |tags: Vec<Tag>| match daemon.handle_request(Request::SetExactTags {
+            files: vec![file.clone()],
+            tags,
+        }) {
+            Response::SetExactTags(PayloadResult::Ok(result)) => result,
+            response => panic!("unexpected response {response:?}"),
+        };
+
+        let result = set_exact(vec![tag("kept"), tag("new")]);
+        assert_eq!(result.summary.succeeded, 1);
+        assert_eq!(result.changes[0].added, vec!["new"]);
+        assert_eq!(result.changes[0].removed, vec!["old"]);
+        let mut saved = list_tags(&file, true).unwrap();
+        saved.sort_unstable();
+        assert_eq!(saved, vec![tag("kept"), tag("new")]);
+        {
+            let registry = get_registry_read().unwrap();
+            let id = registry.find_entry(&file).unwrap();
+            let mut tags = registry.list_entry_tags(id).unwrap();
+            tags.sort_unstable();
+            assert_eq!(tags, vec![&tag("kept"), &tag("new")]);
+        }
+
+        // files that already have exactly the tags are left as they are, the same tags in
+        // a different order would be a duplicate of the last request
+        std::thread::sleep(DEDUP_WINDOW);
+        let result = set_exact(vec![tag("new"), tag("kept")]);
+        assert!(result.changes.is_empty());
+        assert_eq!(result.summary.skipped, 1);
+
+        let result = set_exact(vec![]);
+        assert_eq!(result.changes[0].removed.len(), 2);
+        assert!(list_tags(&file, true).unwrap().is_empty());
+        assert!(get_registry_read().unwrap().find_entry(&file).is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_log_level() {
+        let (mut daemon, _) = new_daemon("log-level");
+        let max_level = log::max_level();
+        let response = daemon.handle_request(Request::SetLogLevel {
+            level: "verbose".into(),
+        });
+        assert!(matches!(
+            response,
+            Response::SetLogLevel(PayloadResult::Detailed(e)) if e.code == ErrorCode::InvalidArgument
+        ));
+        assert_eq!(log::max_level(), max_level);
+    }
+
+    #[test]
+    fn rejects_log_level_above_module_filters() {
+        let (mut daemon, _) = new_daemon("log-level-limit");
+        LOG_LEVEL_LIMIT.set(log::LevelFilter::Info).unwrap();
+        let max_level = log::max_level();
+        let set_level = |daemon: &mut WutagDaemon, level: &str| {
+            daemon.handle_request(Request::SetLogLevel {
+                level: level.into(),
+            })
+        };
+
+        let response = set_level(&mut daemon, "debug");
+        assert!(matches!(
+            response,
+            Response::SetLogLevel(PayloadResult::Detailed(e))
+                if e.code == ErrorCode::InvalidArgument && e.message.contains("RUST_LOG")
+        ));
+        assert_eq!(log::max_level(), max_level);
+
+        let response = set_level(&mut daemon, "info");
+        assert!(matches!(
+            response,
+            Response::SetLogLevel(PayloadResult::Ok(()))
+        ));
+        assert_eq!(log::max_level(), log::LevelFilter::Info);
+        log::set_max_level(max_level);
+    }
+
+    /// Returns the root of a mount whose registry is separate from the default registry shared
+    /// by the tests.
+    fn test_mount() -> PathBuf {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        let mount = TEST_DIR.path().join("mount");
+        INIT.call_once(|| {
+            assert!(registry::init_mounts(&[RegistryMount {
+                path: mount.clone(),
+                registry_file: TEST_DIR.path().join("mount.db"),
+            }]))
+        });
+        mount
+    }
+
+    #[test]
+    fn rejects_files_of_multiple_mounts() {
+        let mount = test_mount();
+        let other = TEST_DIR.path().join("other");
+        let inspect = |files: Vec<PathBuf>| Request::InspectFiles {
+            files,
+            no_follow: false,
+        };
+        let diff = |base_dir: PathBuf| Request::DiffWithFilesystem {
+            base_dir,
+            max_depth: None,
+            fix: false,
+        };
+
+        assert_eq!(
+            request_scope(&inspect(vec![mount.join("a"), mount.join("b")])),
+            Ok(Some(0))
+        );
+        assert_eq!(
+            request_scope(&inspect(vec![
+                mount.join("a"),
+                other.join("b"),
+                other.join("c")
+            ])),
+            Err(vec![mount.join("a"), other.join("b")])
+        );
+        assert_eq!(request_scope(&diff(mount.join("nested"))), Ok(Some(0)));
+        assert_eq!(
+            request_scope(&diff(TEST_DIR.path().to_path_buf())),
+            Err(vec![TEST_DIR.path().to_path_buf(), mount])
+        );
+    }
+
+    #[test]
+    fn pushes_tag_changes_to_subscribers() {
+        let dir = TempDir::new("wutag-daemon-subscribe").unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        let (daemon, socket) = new_daemon("subscribe");
+        let notify_daemon = NotifyDaemon::new(&DaemonConfig::default()).unwrap();
+        std::thread::spawn(|| daemon.work_loop());
+        std::thread::spawn(|| notify_daemon.work_loop());
+
+        let client = IpcClient::new(socket.as_str());
+        let mut subscription = client.subscribe::<_, Response>(Request::Subscribe).unwrap();
+        assert!(matches!(
+            subscription.next(),
+            Some(Ok(Response::Subscribe(PayloadResult::Ok(()))))
+        ));
+
+        let tag = Tag::new("subscribed", Color::Red);
+        request(
+            &client,
+            Request::TagFiles {
+                files: vec![file.clone()],
+                tags: vec![tag.clone()],
+                auto_tag: false,
+                rules: vec![],
+                expires_in: None,
+                no_follow: false,
+                palette: vec![],
+            },
+        );
+        request(
+            &client,
+            Request::UntagFiles {
+                files: vec![file.clone()],
+                tags: vec![tag.clone()],
+                no_follow: false,
+                force: false,
+            },
+        );
+
+        // reads changes in a separate thread so that a missing change fails the test instead of
+        // blocking it, other tests running at the same time may modify other files
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for change in subscription {
+                if let Response::TagChanged {
+                    path,
+                    added,
+                    removed,
+                } = change.unwrap()
+                {
+                    if path == file && tx.send((added, removed)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        let timeout = Duration::from_secs(5);
+        assert_eq!(rx.recv_timeout(timeout), Ok((vec![tag.clone()], vec![])));
+        assert_eq!(rx.recv_timeout(timeout), Ok((vec![], vec![tag])));
+    }
 }
@@ -1,17 +1,53 @@
+mod audit;
+mod config;
 mod daemon;
+mod maintenance;
 mod notifyd;
 mod registry;
+mod sweep;
 
+use clap::{Parser, ValueEnum};
+use config::{DaemonConfig, NotifyBackend, DEFAULT_POLL_INTERVAL_MS};
 use daemon::WutagDaemon;
+use maintenance::MaintenanceScheduler;
 use notifyd::NotifyDaemon;
-use once_cell::sync::Lazy;
-use std::path::PathBuf;
-use std::sync::RwLock;
+use once_cell::sync::{Lazy, OnceCell};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, RwLock};
+use sweep::ConsistencySweep;
 use thiserror::Error as ThisError;
-use wutag_ipc::{default_socket, IpcServer};
+use wutag_core::tag::Tag;
+use wutag_core::xattr::set_xattr;
+use wutag_ipc::{default_socket, IpcServer, Response, Subscriber, WatchFailures};
 
 pub static ENTRIES_EVENTS: Lazy<RwLock<Vec<EntryEvent>>> = Lazy::new(|| RwLock::new(Vec::new()));
 pub static NOTIFY_EVENTS: Lazy<RwLock<Vec<notify::Event>>> = Lazy::new(|| RwLock::new(Vec::new()));
+/// Files that the notify daemon failed to watch, reported in registry stats.
+pub static WATCH_FAILURES: Lazy<RwLock<WatchFailures>> =
+    Lazy::new(|| RwLock::new(WatchFailures::default()));
+/// Queues of changes of tags of clients subscribed with `Request::Subscribe`, see
+/// [add_subscriber](add_subscriber).
+/// Highest log level enabled by module filters of `RUST_LOG`, set if the level can't be raised
+/// above it at runtime.
+pub static LOG_LEVEL_LIMIT: OnceCell<log::LevelFilter> = OnceCell::new();
+pub static SUBSCRIBERS: Lazy<RwLock<Vec<SyncSender<Arc<Response>>>>> =
+    Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Number of changes queued for a subscriber that doesn't read them before it is dropped.
+const SUBSCRIBER_QUEUE_SIZE: usize = 256;
+
+/// Directory with the registry shared by all tests as the registry is global.
+#[cfg(test)]
+pub static TEST_DIR: Lazy<tempdir::TempDir> = Lazy::new(|| {
+    let dir = tempdir::TempDir::new("wutag-daemon-test").unwrap();
+    assert!(registry::set_registry_file(dir.path().join("wutag.db")));
+    assert!(audit::set_audit_log_file(
+        dir.path().join("wutag-audit.cbor")
+    ));
+    dir
+});
 
 #[derive(Debug, ThisError)]
 pub enum Error {
@@ -29,6 +65,12 @@ pub enum Error {
     EntriesEventsLock(String),
     #[error(transparent)]
     IpcServerInit(wutag_ipc::IpcError),
+    #[error(transparent)]
+    XattrUnsupported(wutag_core::Error),
+    #[error("failed to check support of extended attributes - {0}")]
+    XattrCheck(wutag_core::Error),
+    #[error("failed to create a file to check support of extended attributes - {0}")]
+    XattrCheckFile(std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -37,22 +79,240 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum EntryEvent {
     Add(Vec<PathBuf>),
     Remove(Vec<PathBuf>),
+    /// Tags of the entry at `path` changed, forwarded to subscribers.
+    TagsChanged {
+        path: PathBuf,
+        added: Vec<Tag>,
+        removed: Vec<Tag>,
+    },
+}
+
+impl EntryEvent {
+    /// Returns the change between tags of the entry at `path` `before` and `after` it was
+    /// modified or `None` if they are the same.
+    pub fn tags_changed(path: &Path, before: &[Tag], after: &[Tag]) -> Option<EntryEvent> {
+        let added: Vec<_> = after
+            .iter()
+            .filter(|t| !before.contains(t))
+            .cloned()
+            .collect();
+        let removed: Vec<_> = before
+            .iter()
+            .filter(|t| !after.contains(t))
+            .cloned()
+            .collect();
+        if added.is_empty() && removed.is_empty() {
+            return None;
+        }
+        Some(EntryEvent::TagsChanged {
+            path: path.to_path_buf(),
+            added,
+            removed,
+        })
+    }
+}
+
+pub fn has_subscribers() -> bool {
+    SUBSCRIBERS
+        .read()
+        .map(|subscribers| !subscribers.is_empty())
+        .unwrap_or_default()
+}
+
+/// Starts pushing changes of tags to the `subscriber`. Changes are written to the connection by a
+/// separate thread so that a slow client never blocks the daemon.
+pub fn add_subscriber(mut subscriber: Subscriber) {
+    let (tx, rx) = mpsc::sync_channel::<Arc<Response>>(SUBSCRIBER_QUEUE_SIZE);
+    std::thread::spawn(move || {
+        for change in rx {
+            if let Err(e) = subscriber.send(&*change) {
+                log::debug!("dropping subscriber, reason: {e}");
+                return;
+            }
+        }
+    });
+    match SUBSCRIBERS.write() {
+        Ok(mut subscribers) => subscribers.push(tx),
+        Err(e) => log::error!("failed to lock subscribers - {e}"),
+    }
+}
+
+/// Queues the `change` for all subscribers without waiting for them to receive it. Subscribers
+/// that disconnected or whose queue is full are dropped.
+pub fn notify_subscribers(change: Response) {
+    let mut subscribers = match SUBSCRIBERS.write() {
+        Ok(subscribers) => subscribers,
+        Err(e) => {
+            log::error!("failed to lock subscribers - {e}");
+            return;
+        }
+    };
+    let change = Arc::new(change);
+    subscribers.retain(
+        |subscriber| match subscriber.try_send(Arc::clone(&change)) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                log::warn!("dropping subscriber that doesn't keep up with changes of tags");
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        },
+    );
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum BackendArg {
+    Auto,
+    Inotify,
+    Poll,
+}
+
+#[derive(Parser)]
+#[clap(version, about = "Daemon watching files tagged with wutag")]
+struct Opts {
+    #[arg(long, value_enum)]
+    /// Mechanism used to watch tagged files for changes, overrides `notify_backend` from the
+    /// configuration. `poll` works on network filesystems.
+    notify_backend: Option<BackendArg>,
+    #[arg(long)]
+    /// Maximum number of entries of each registry, overrides `max_registry_entries` from the
+    /// configuration.
+    max_entries: Option<usize>,
+}
+
+/// Initializes the logger configured with `RUST_LOG`. If it contains just a level, like
+/// `RUST_LOG=info`, messages of all levels are passed to the logger and filtered by the global
+/// maximum level instead so that the level can be changed at runtime with `Request::SetLogLevel`.
+/// Otherwise the level can only be lowered at runtime and the highest level of the filters is
+/// saved in [LOG_LEVEL_LIMIT](LOG_LEVEL_LIMIT).
+fn init_logger() {
+    let mut builder = pretty_env_logger::formatted_builder();
+    let filters = std::env::var("RUST_LOG").ok();
+    let level = match filters.as_deref().map(str::parse::<log::LevelFilter>) {
+        Some(Ok(level)) => level,
+        Some(Err(_)) => {
+            builder.parse_filters(filters.as_deref().unwrap_or_default());
+            builder.init();
+            let _ = LOG_LEVEL_LIMIT.set(log::max_level());
+            return;
+        }
+        None => log::LevelFilter::Error,
+    };
+    builder.filter_level(log::LevelFilter::Trace);
+    builder.init();
+    log::set_max_level(level);
+}
+
+/// Verifies that extended attributes can be set on files in `dir` by tagging a temporary file.
+/// Only the filesystem rejecting extended attributes is reported as unsupported, other failures
+/// are returned as they are.
+fn check_xattr_support(dir: &Path) -> Result<()> {
+    let test_file = dir.join(format!(".wutag-xattr-test-{}", std::process::id()));
+    fs::write(&test_file, []).map_err(Error::XattrCheckFile)?;
+    let result = set_xattr(&test_file, "user.wutag.test", "", true);
+    if let Err(e) = fs::remove_file(&test_file) {
+        log::warn!("failed to remove `{}`, reason: {e}", test_file.display());
+    }
+    match result {
+        Ok(()) => Ok(()),
+        Err(e @ wutag_core::Error::XattrNotSupported(_)) => {
+            log::debug!("failed to set test xattr, reason: {e}");
+            Err(Error::XattrUnsupported(
+                wutag_core::Error::XattrUnsupported(dir.into()),
+            ))
+        }
+        Err(e) => Err(Error::XattrCheck(e)),
+    }
 }
 
 pub fn main() -> Result<()> {
-    pretty_env_logger::init();
+    init_logger();
+    let opts = Opts::parse();
+
+    let mut config = match DaemonConfig::load_default_location() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    };
+    if let Some(backend) = opts.notify_backend {
+        config.notify_backend = match (backend, config.notify_backend) {
+            (BackendArg::Auto, _) => NotifyBackend::Auto,
+            (BackendArg::Inotify, _) => NotifyBackend::Native,
+            (BackendArg::Poll, poll @ NotifyBackend::Poll { .. }) => poll,
+            (BackendArg::Poll, _) => NotifyBackend::Poll {
+                interval_ms: DEFAULT_POLL_INTERVAL_MS,
+            },
+        };
+    }
+
+    if let Some(max_entries) = opts.max_entries {
+        config.max_registry_entries = Some(max_entries);
+    }
 
-    let listener = IpcServer::new(default_socket()).map_err(Error::IpcServerInit)?;
-    let daemon = WutagDaemon::new(listener)?;
-    let notify_daemon = NotifyDaemon::new()?;
+    if let Err(e) = check_xattr_support(&registry::data_dir()) {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+
+    registry::init_mounts(&config.registry_mounts);
+
+    let listener = IpcServer::new(default_socket())
+        .map_err(Error::IpcServerInit)?
+        .with_require_same_user(config.require_same_user);
+    let daemon = WutagDaemon::new(listener, &config)?;
+    let notify_daemon = if config.watch {
+        Some(NotifyDaemon::new(&config)?)
+    } else {
+        log::info!("watching files is disabled");
+        None
+    };
+    let sweep = ConsistencySweep::new(&config);
+    let maintenance = MaintenanceScheduler::new(&config);
 
     std::thread::scope(|s| {
         let h1 = s.spawn(|| daemon.work_loop());
-        let h2 = s.spawn(|| notify_daemon.work_loop());
+        let h2 = notify_daemon.map(|notify_daemon| s.spawn(|| notify_daemon.work_loop()));
+        let h3 = sweep.map(|sweep| s.spawn(|| sweep.work_loop()));
+        let h4 = maintenance.map(|maintenance| s.spawn(|| maintenance.work_loop()));
 
         h1.join().unwrap();
-        h2.join().unwrap();
+        if let Some(h2) = h2 {
+            h2.join().unwrap();
+        }
+        if let Some(h3) = h3 {
+            h3.join().unwrap();
+        }
+        if let Some(h4) = h4 {
+            h4.join().unwrap();
+        }
     });
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_xattr_support() {
+        let dir = tempdir::TempDir::new("wutag-daemon-xattr-check").unwrap();
+        check_xattr_support(dir.path()).unwrap();
+        assert!(fs::read_dir(dir.path()).unwrap().next().is_none());
+
+        // failing to create the test file isn't mistaken for missing support of xattrs
+        let missing = dir.path().join("missing");
+        match check_xattr_support(&missing) {
+            Err(Error::XattrCheckFile(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            result => panic!("unexpected result {result:?}"),
+        }
+
+        #[cfg(unix)]
+        assert!(matches!(
+            wutag_core::Error::from(std::io::Error::from_raw_os_error(libc::ENOTSUP)),
+            wutag_core::Error::XattrNotSupported(_)
+        ));
+    }
+}
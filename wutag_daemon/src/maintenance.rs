@@ -0,0 +1,277 @@
+//! Scheduled maintenance removing stale entries and expired tags and backing up the registries.
+use crate::config::DaemonConfig;
+use crate::registry::{self, get_registry_read, get_registry_write};
+use crate::{notify_subscribers, EntryEvent, ENTRIES_EVENTS};
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use wutag_core::registry::{EntryData, TagRegistry};
+use wutag_core::tag::Tag;
+use wutag_core::Error as CoreError;
+use wutag_ipc::{MaintenanceSummary, Response};
+
+/// Name of the backups of the registries saved by maintenance.
+pub const BACKUP_NAME: &str = "nightly";
+/// Number of backups of each registry kept by maintenance.
+pub const KEPT_BACKUPS: usize = 7;
+
+static LAST_MAINTENANCE: RwLock<Option<DateTime<Utc>>> = RwLock::new(None);
+
+/// Returns the time of the last maintenance run since the daemon started.
+pub fn last_maintenance() -> Option<DateTime<Utc>> {
+    match LAST_MAINTENANCE.read() {
+        Ok(last) => *last,
+        Err(e) => *e.into_inner(),
+    }
+}
+
+/// Runs maintenance of all registries. With `prune_expired_tags` expired tags are removed from
+/// the registries and the files, removed entries are unwatched if `watch` is set.
+pub fn run_maintenance(prune_expired_tags: bool, watch: bool) -> MaintenanceSummary {
+    let mut summary = MaintenanceSummary {
+        ran_at: Utc::now(),
+        ..Default::default()
+    };
+    for scope in registry::scopes() {
+        registry::with_scope(scope, || {
+            if let Err(e) = maintain_registry(prune_expired_tags, watch, &mut summary) {
+                log::error!("maintenance failed - {e}");
+                summary.errors.push(e);
+            }
+        });
+    }
+    match LAST_MAINTENANCE.write() {
+        Ok(mut last) => *last = Some(summary.ran_at),
+        Err(e) => *e.into_inner() = Some(summary.ran_at),
+    }
+    log::info!(
+        "maintenance evicted {} stale entries, removed {} expired tags, saved {} backups",
+        summary.evicted,
+        summary.expired_tags,
+        summary.backups.len()
+    );
+    summary
+}
+
+/// Runs maintenance of the registry of the current scope adding its results to `summary`. The
+/// registry is only locked for writing while it is updated, files are untagged and the backup is
+/// saved afterwards.
+fn maintain_registry(
+    prune_expired_tags: bool,
+    watch: bool,
+    summary: &mut MaintenanceSummary,
+) -> Result<(), String> {
+    let mut registry = get_registry_write().map_err(|e| e.to_string())?;
+    let maintenance = maintain(&mut registry, prune_expired_tags, summary)?;
+    drop(registry);
+
+    maintenance.remove_expired_tags();
+    let registry = get_registry_read().map_err(|e| e.to_string())?;
+    back_up(&registry, summary)?;
+    drop(registry);
+
+    if watch && !maintenance.removed.is_empty() {
+        match ENTRIES_EVENTS.write() {
+            Ok(mut events) => events.push(EntryEvent::Remove(maintenance.removed)),
+            Err(e) => log::warn!("failed to lock entries events, reason: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Changes made to a registry by [maintain] that still have to be applied to the files.
+struct Maintenance {
+    /// Paths of the removed entries.
+    removed: Vec<PathBuf>,
+    /// Expired tags removed from entries of the registry.
+    expired: Vec<(EntryData, Tag)>,
+}
+
+impl Maintenance {
+    /// Removes the expired tags from the files and tells subscribers about it.
+    fn remove_expired_tags(&self) {
+        for (entry, tag) in &self.expired {
+            match tag.remove_from(entry.path(), !entry.no_follow()) {
+                Ok(_) | Err(CoreError::TagNotFound(_)) => {}
+                Err(e) => log::error!(
+                    "failed to remove expired tag {tag} from `{}`, reason: {e}",
+                    entry.path().display()
+                ),
+            }
+            notify_subscribers(Response::TagChanged {
+                path: entry.path().to_path_buf(),
+                added: vec![],
+                removed: vec![tag.clone()],
+            });
+        }
+    }
+}
+
+/// Whether the missing file of the `entry` is on a filesystem that is currently present. Files
+/// whose directory is gone as well may be on removable media or a network share that isn't
+/// mounted right now, so their entries are kept.
+fn on_present_mount(entry: &EntryData) -> bool {
+    entry.path().parent().map_or(true, Path::is_dir)
+}
+
+/// Removes stale entries of files on present filesystems and optionally expired tags from the
+/// `registry` and saves it.
+fn maintain(
+    registry: &mut TagRegistry,
+    prune_expired_tags: bool,
+    summary: &mut MaintenanceSummary,
+) -> Result<Maintenance, String> {
+    let mut stale: Vec<_> = registry
+        .list_stale_entries()
+        .filter(|(_, entry)| on_present_mount(entry))
+        .map(|(id, entry)| (*id, entry.path().to_path_buf()))
+        .collect();
+    stale.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+    let mut removed = Vec::with_capacity(stale.len());
+    for (id, path) in stale {
+        registry.clear_entry(id);
+        removed.push(path);
+    }
+    summary.evicted += removed.len();
+    let expired = if prune_expired_tags {
+        registry.prune_expired_tags(Utc::now())
+    } else {
+        vec![]
+    };
+    for (entry, _) in &expired {
+        if registry.find_entry(entry.path()).is_none() {
+            removed.push(entry.path().to_path_buf());
+        }
+    }
+    summary.expired_tags += expired.len();
+
+    registry.save().map_err(|e| e.to_string())?;
+    Ok(Maintenance { removed, expired })
+}
+
+/// Saves a backup of the `registry` adding its path to the `summary`.
+fn back_up(registry: &TagRegistry, summary: &mut MaintenanceSummary) -> Result<(), String> {
+    let backup = registry
+        .save_backup(BACKUP_NAME, KEPT_BACKUPS)
+        .map_err(|e| e.to_string())?;
+    summary.backups.push(backup);
+    Ok(())
+}
+
+/// Runs maintenance at the times given by the `maintenance_cron` of the configuration.
+pub struct MaintenanceScheduler {
+    schedule: Schedule,
+    prune_expired_tags: bool,
+    watch: bool,
+}
+
+impl MaintenanceScheduler {
+    /// Returns `None` if maintenance is not scheduled or the schedule is invalid.
+    pub fn new(config: &DaemonConfig) -> Option<Self> {
+        let schedule = match config.maintenance_schedule() {
+            Ok(schedule) => schedule?,
+            Err(e) => {
+                log::error!("{e}");
+                return None;
+            }
+        };
+        Some(Self {
+            schedule,
+            prune_expired_tags: config.maintenance_prune_expired_tags,
+            watch: config.watch,
+        })
+    }
+
+    pub fn work_loop(self) {
+        run_scheduled(&self.schedule, || {
+            run_maintenance(self.prune_expired_tags, self.watch);
+        });
+    }
+}
+
+/// Calls `run` at every upcoming time of the `schedule` until there are no more.
+fn run_scheduled(schedule: &Schedule, mut run: impl FnMut()) {
+    while let Some(next) = schedule.upcoming(Utc).next() {
+        log::debug!("next maintenance at {next}");
+        if let Ok(wait) = (next - Utc::now()).to_std() {
+            std::thread::sleep(wait);
+        }
+        run();
+    }
+    log::info!("maintenance schedule has no upcoming runs");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Duration, Timelike};
+    use std::fs;
+    use std::str::FromStr;
+    use tempdir::TempDir;
+    use wutag_core::color::Color;
+    use wutag_core::tag::list_tags;
+
+    #[test]
+    fn evicts_prunes_and_backs_up() {
+        let dir = TempDir::new("wutag-maintenance").unwrap();
+        let mut registry = TagRegistry::new(dir.path().join("wutag.db"));
+        let kept = dir.path().join("kept");
+        let gone = dir.path().join("gone");
+        fs::write(&kept, b"").unwrap();
+        let expired = Tag::new("expired", Color::Red);
+        let permanent = Tag::new("permanent", Color::Red);
+        for tag in [&expired, &permanent] {
+            tag.save_to(&kept, true).unwrap();
+        }
+        let (id, _) = registry.add_or_update_entry(EntryData::new(&kept));
+        registry.tag_entry(&expired, id);
+        registry.tag_entry(&permanent, id);
+        registry.set_tag_expiry(&expired, id, Some(Utc::now()));
+        let (id, _) = registry.add_or_update_entry(EntryData::new(&gone));
+        registry.tag_entry(&permanent, id);
+        // the directory is missing as well, like on unmounted media
+        let unmounted = dir.path().join("media").join("file");
+        let (id, _) = registry.add_or_update_entry(EntryData::new(&unmounted));
+        registry.tag_entry(&permanent, id);
+
+        let mut summary = MaintenanceSummary::default();
+        let maintenance = maintain(&mut registry, true, &mut summary).unwrap();
+        assert_eq!(maintenance.removed, vec![gone]);
+        assert_eq!((summary.evicted, summary.expired_tags), (1, 1));
+        assert!(registry.find_entry(&unmounted).is_some());
+        let id = registry.find_entry(&kept).unwrap();
+        assert_eq!(registry.list_entry_tags(id).unwrap(), vec![&permanent]);
+        // files are untagged once the registry is no longer locked
+        assert_eq!(list_tags(&kept, true).unwrap().len(), 2);
+        maintenance.remove_expired_tags();
+        assert_eq!(list_tags(&kept, true).unwrap(), vec![permanent.clone()]);
+
+        back_up(&registry, &mut summary).unwrap();
+        assert_eq!(summary.backups, vec![dir.path().join("wutag.db.nightly.1")]);
+        assert!(TagRegistry::load(&summary.backups[0]).is_ok());
+    }
+
+    #[test]
+    fn runs_at_scheduled_times() {
+        let at = Utc::now() + Duration::seconds(2);
+        let once = Schedule::from_str(&format!(
+            "{} {} {} {} {} * {}",
+            at.second(),
+            at.minute(),
+            at.hour(),
+            at.day(),
+            at.month(),
+            at.year()
+        ))
+        .unwrap();
+        let mut runs = 0;
+        run_scheduled(&once, || runs += 1);
+        assert_eq!(runs, 1);
+        assert!(Utc::now() >= at.with_nanosecond(0).unwrap());
+
+        let past = Schedule::from_str("0 0 0 1 1 * 2000").unwrap();
+        run_scheduled(&past, || runs += 1);
+        assert_eq!(runs, 1);
+    }
+}
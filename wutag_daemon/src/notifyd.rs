@@ -1,12 +1,27 @@
-use crate::registry::try_get_registry_write_loop;
-use crate::{EntryEvent, Error, Result, ENTRIES_EVENTS, NOTIFY_EVENTS};
+use crate::config::{DaemonConfig, NotifyBackend, WatchMode, DEFAULT_POLL_INTERVAL_MS};
+use crate::registry::{self, try_get_registry_write_loop};
+use crate::{
+    notify_subscribers, EntryEvent, Error, Result, ENTRIES_EVENTS, NOTIFY_EVENTS, WATCH_FAILURES,
+};
+use chrono::Utc;
 use notify::{
-    self, event::RemoveKind, Event, EventHandler, EventKind, RecommendedWatcher, RecursiveMode,
-    Watcher,
+    self,
+    event::{ModifyKind, RemoveKind, RenameMode},
+    Event, EventHandler, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
 };
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
 use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use thiserror::Error as ThisError;
+use wutag_core::registry::TagRegistry;
+use wutag_core::tag::list_tags;
+use wutag_core::Error as CoreError;
+use wutag_ipc::{Response, WatchFailures};
+
+/// Interval at which expired tags are removed from files.
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
 
 #[derive(Debug, ThisError)]
 pub enum NotifyDaemonError {
@@ -19,9 +34,74 @@ pub enum NotifyDaemonError {
 }
 
 pub struct NotifyDaemon {
-    notify: RecommendedWatcher,
+    notify: Box<dyn Watcher + Send>,
+    mode: WatchMode,
+    dirs: DirWatches,
+    /// Entries that failed to be watched.
+    failed: BTreeSet<PathBuf>,
+    limit_reached: bool,
+    last_error: Option<String>,
+    /// Whether the warning about current failures was already logged.
+    warned: bool,
+}
+
+/// Checks whether the `error` was caused by reaching the limit of watches.
+fn is_watch_limit(error: &notify::Error) -> bool {
+    match &error.kind {
+        notify::ErrorKind::MaxFilesWatch => true,
+        // ENOSPC, returned by inotify once the limit of watches is reached
+        notify::ErrorKind::Io(e) => e.raw_os_error() == Some(28),
+        _ => false,
+    }
 }
 
+fn is_not_found(error: &notify::Error) -> bool {
+    matches!(&error.kind, notify::ErrorKind::Io(e) if e.kind() == io::ErrorKind::NotFound)
+}
+
+/// Tracks directories watched in [WatchMode::Parents](WatchMode::Parents) along with the entries
+/// in each of them.
+#[derive(Debug, Default)]
+struct DirWatches {
+    dirs: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+}
+
+impl DirWatches {
+    /// Returns the parent directory of the `entry` if it isn't watched yet.
+    fn plan(&self, entry: &Path) -> Option<PathBuf> {
+        let parent = entry.parent().unwrap_or(entry);
+        (!self.dirs.contains_key(parent)).then(|| parent.to_path_buf())
+    }
+
+    /// Adds the `entry` returning the same result as [plan](DirWatches::plan).
+    fn add(&mut self, entry: &Path) -> Option<PathBuf> {
+        let plan = self.plan(entry);
+        let parent = entry.parent().unwrap_or(entry);
+        self.dirs
+            .entry(parent.to_path_buf())
+            .or_default()
+            .insert(entry.to_path_buf());
+        plan
+    }
+
+    /// Removes the `entry` returning the directory that no longer contains any entries.
+    fn remove(&mut self, entry: &Path) -> Option<PathBuf> {
+        let (dir, entries) = self
+            .dirs
+            .iter_mut()
+            .find(|(_, entries)| entries.contains(entry))?;
+        entries.remove(entry);
+        if entries.is_empty() {
+            let dir = dir.clone();
+            self.dirs.remove(&dir);
+            Some(dir)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone)]
 struct Handler;
 
 impl EventHandler for Handler {
@@ -31,10 +111,14 @@ impl EventHandler for Handler {
                 EventKind::Remove(RemoveKind::File)
                 | EventKind::Remove(RemoveKind::Any)
                 | EventKind::Remove(RemoveKind::Folder)
-                | EventKind::Remove(RemoveKind::Other) => match NOTIFY_EVENTS.try_write() {
-                    Ok(mut events) => events.push(event),
-                    Err(e) => log::error!("failed to lock notify events, reason: {e}"),
-                },
+                | EventKind::Remove(RemoveKind::Other)
+                | EventKind::Modify(ModifyKind::Metadata(_))
+                | EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                    match NOTIFY_EVENTS.try_write() {
+                        Ok(mut events) => events.push(event),
+                        Err(e) => log::error!("failed to lock notify events, reason: {e}"),
+                    }
+                }
                 _ => {}
             },
             Err(e) => {
@@ -44,17 +128,59 @@ impl EventHandler for Handler {
     }
 }
 
+/// Creates a watcher using the `backend` that passes events to the `handler`. With
+/// [NotifyBackend::Auto](NotifyBackend::Auto) falls back to polling if the native backend is not
+/// available.
+fn new_watcher<H>(backend: NotifyBackend, handler: H) -> notify::Result<Box<dyn Watcher + Send>>
+where
+    H: EventHandler + Clone,
+{
+    let poll = |handler, interval_ms| -> notify::Result<Box<dyn Watcher + Send>> {
+        let config =
+            notify::Config::default().with_poll_interval(Duration::from_millis(interval_ms));
+        Ok(Box::new(PollWatcher::new(handler, config)?))
+    };
+    match backend {
+        NotifyBackend::Native => Ok(Box::new(RecommendedWatcher::new(
+            handler,
+            Default::default(),
+        )?)),
+        NotifyBackend::Poll { interval_ms } => poll(handler, interval_ms),
+        NotifyBackend::Auto => {
+            match RecommendedWatcher::new(handler.clone(), Default::default()) {
+                Ok(watcher) => Ok(Box::new(watcher)),
+                Err(e) if is_not_found(&e) => {
+                    log::warn!("native file notifications are not available ({e}), falling back to polling");
+                    poll(handler, DEFAULT_POLL_INTERVAL_MS)
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+}
+
 impl NotifyDaemon {
-    pub fn new() -> Result<Self> {
+    pub fn new(config: &DaemonConfig) -> Result<Self> {
+        let mode = config.watch_mode;
         let mut d = Self {
-            notify: RecommendedWatcher::new(Handler, Default::default())
+            notify: new_watcher(config.notify_backend, Handler)
                 .map_err(NotifyDaemonError::NotifyWatcherInit)?,
+            mode,
+            dirs: DirWatches::default(),
+            failed: BTreeSet::new(),
+            limit_reached: false,
+            last_error: None,
+            warned: false,
         };
 
-        d.rebuild_watch_entries().map(|_| d)
+        for scope in registry::scopes() {
+            registry::with_scope(scope, || d.rebuild_watch_entries())?;
+        }
+        Ok(d)
     }
 
     pub fn work_loop(mut self) {
+        let mut last_sweep: Option<Instant> = None;
         loop {
             if let Err(e) = self.handle_entries_events() {
                 log::error!("{e}");
@@ -62,45 +188,163 @@ impl NotifyDaemon {
             if let Err(e) = self.handle_notify_events() {
                 log::error!("{e}");
             }
+            if last_sweep.map_or(true, |t| t.elapsed() >= EXPIRY_SWEEP_INTERVAL) {
+                for scope in registry::scopes() {
+                    if let Err(e) = registry::with_scope(scope, || self.remove_expired_tags()) {
+                        log::error!("{e}");
+                    }
+                }
+                last_sweep = Some(Instant::now());
+            }
             std::thread::sleep(std::time::Duration::from_millis(200));
         }
     }
 
-    fn rebuild_watch_entries(&mut self) -> Result<()> {
+    /// Removes tags that expired from the registry and from the files.
+    fn remove_expired_tags(&mut self) -> Result<()> {
         let mut registry = try_get_registry_write_loop()?;
-        let mut to_remove = vec![];
-        for entry in registry.list_entries().cloned() {
-            if let Err(e) = self.add_watch_entry(entry.path()) {
-                log::error!("{e}");
-
-                if let crate::Error::NotifyDaemon(NotifyDaemonError::AddWatchEntry(e)) = e {
-                    if let notify::ErrorKind::Io(err) = &e.kind {
-                        if let std::io::ErrorKind::NotFound = err.kind() {
-                            to_remove.push(entry);
-                        }
-                    }
+        let expired = registry.list_expired_tags(Utc::now());
+        if expired.is_empty() {
+            return Ok(());
+        }
+        for (id, tag) in expired {
+            let (path, follow) = match registry.get_entry(id) {
+                Some(entry) => (entry.path().to_path_buf(), !entry.no_follow()),
+                None => continue,
+            };
+            log::info!("tag `{tag}` of `{}` expired", path.display());
+            match tag.remove_from(&path, follow) {
+                Ok(_) | Err(CoreError::TagNotFound(_)) => {}
+                Err(e) => log::error!(
+                    "failed to remove expired tag {tag} from `{}`, reason: {e}",
+                    path.display()
+                ),
+            }
+            notify_subscribers(Response::TagChanged {
+                path: path.clone(),
+                added: vec![],
+                removed: vec![tag.clone()],
+            });
+            if let Some(entry) = registry.untag_entry(&tag, id) {
+                if let Err(e) = self.unwatch_entry(entry.path()) {
+                    log::debug!("{e}");
                 }
-                continue;
             }
         }
-        for entry in to_remove {
+        registry.save().map_err(Error::RegistrySave)
+    }
+
+    fn rebuild_watch_entries(&mut self) -> Result<()> {
+        let mut registry = try_get_registry_write_loop()?;
+        let evicted = registry.evict_stale_entries();
+        for entry in &evicted {
             log::info!(
                 "entry `{}` not found, removing from registry",
                 entry.path().display()
             );
-            if let Some(id) = registry.find_entry(entry.path()) {
-                registry.remove_entry(id);
+        }
+        for entry in registry.list_entries() {
+            if let Err(e) = self.watch_entry(entry.path()) {
+                log::debug!("{e}");
             }
         }
         registry.save().map_err(Error::RegistrySave)?;
+        self.warn_watch_failures();
         Ok(())
     }
 
-    fn add_watch_entry(&mut self, entry: impl AsRef<Path>) -> Result<()> {
+    /// Logs a single warning about entries that are not watched until all failures are resolved.
+    fn warn_watch_failures(&mut self) {
+        if self.failed.is_empty() || self.warned {
+            return;
+        }
+        self.warned = true;
+        let count = self.failed.len();
+        if self.limit_reached {
+            log::warn!(
+                "watch limit reached; {count} files are not being monitored - increase fs.inotify.max_user_watches"
+            );
+        } else {
+            log::warn!(
+                "{count} files are not being monitored - {}",
+                self.last_error.as_deref().unwrap_or("unknown error")
+            );
+        }
+    }
+
+    /// Publishes the current watch failures so that they are reported in registry stats.
+    fn publish_watch_failures(&mut self) {
+        if self.failed.is_empty() {
+            self.limit_reached = false;
+            self.last_error = None;
+            self.warned = false;
+        }
+        let failures = WatchFailures {
+            unwatched: self.failed.len(),
+            limit_reached: self.limit_reached,
+            last_error: self.last_error.clone(),
+        };
+        match WATCH_FAILURES.write() {
+            Ok(mut current) => *current = failures,
+            Err(e) => *e.into_inner() = failures,
+        }
+    }
+
+    /// Starts watching the `entry` and records whether it failed.
+    fn watch_entry(&mut self, entry: impl AsRef<Path>) -> Result<()> {
+        let entry = entry.as_ref();
+        let result = self.try_watch_entry(entry);
+        match &result {
+            Ok(()) => {
+                self.failed.remove(entry);
+            }
+            Err(Error::NotifyDaemon(NotifyDaemonError::AddWatchEntry(e))) if !is_not_found(e) => {
+                self.failed.insert(entry.to_path_buf());
+                self.limit_reached |= is_watch_limit(e);
+                self.last_error = Some(e.to_string());
+            }
+            Err(_) => {}
+        }
+        self.publish_watch_failures();
+        result
+    }
+
+    /// Starts watching the `entry` either directly or through its parent directory depending on
+    /// the [WatchMode](WatchMode).
+    fn try_watch_entry(&mut self, entry: &Path) -> Result<()> {
+        match self.mode {
+            WatchMode::Files => self.add_watch_entry(entry, RecursiveMode::NonRecursive),
+            WatchMode::Parents => {
+                if let Some(dir) = self.dirs.plan(entry) {
+                    self.add_watch_entry(dir, RecursiveMode::NonRecursive)?;
+                }
+                self.dirs.add(entry);
+                Ok(())
+            }
+        }
+    }
+
+    /// Stops watching the `entry`. When watching directories the directory is only unwatched
+    /// once it doesn't contain any other entries.
+    fn unwatch_entry(&mut self, entry: impl AsRef<Path>) -> Result<()> {
+        let entry = entry.as_ref();
+        if self.failed.remove(entry) {
+            self.publish_watch_failures();
+        }
+        match self.mode {
+            WatchMode::Files => self.remove_watch_entry(entry),
+            WatchMode::Parents => match self.dirs.remove(entry) {
+                Some(dir) => self.remove_watch_entry(dir),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn add_watch_entry(&mut self, entry: impl AsRef<Path>, mode: RecursiveMode) -> Result<()> {
         let entry = entry.as_ref();
         log::trace!("adding watch entry {}", entry.display());
         self.notify
-            .watch(entry, RecursiveMode::NonRecursive)
+            .watch(entry, mode)
             .map_err(NotifyDaemonError::AddWatchEntry)
             .map_err(Error::from)
     }
@@ -126,19 +370,124 @@ impl NotifyDaemon {
         }
         let events = mem::take(&mut *events_handle);
         mem::drop(events_handle);
+        // each entry is only found in the registry of the mount containing it
+        for scope in registry::scopes() {
+            registry::with_scope(scope, || self.apply_notify_events(&events))?;
+        }
+        Ok(())
+    }
+
+    fn apply_notify_events(&mut self, events: &[notify::Event]) -> Result<()> {
         let mut registry = try_get_registry_write_loop()?;
+        let mut changed = false;
         for event in events {
-            for path in event.paths {
-                if let Some(id) = registry.find_entry(&path) {
-                    log::trace!("removing entry {}, id: {id}", path.display());
-                    registry.clear_entry(id);
+            // both paths of a rename are only known to directory watches
+            if let (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) =
+                (event.kind, &event.paths[..])
+            {
+                changed |= self.rename_entry(&mut registry, from, to);
+                continue;
+            }
+            // directory watches also report events of files that aren't tagged
+            for path in &event.paths {
+                if let Some(id) = registry.find_entry(path) {
+                    changed = true;
+                    if let EventKind::Modify(ModifyKind::Metadata(_)) = event.kind {
+                        self.sync_entry(&mut registry, path);
+                    } else {
+                        log::trace!("removing entry {}, id: {id}", path.display());
+                        let removed: Vec<_> = registry
+                            .list_entry_tags(id)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .cloned()
+                            .collect();
+                        notify_subscribers(Response::TagChanged {
+                            path: path.clone(),
+                            added: vec![],
+                            removed,
+                        });
+                        registry.clear_entry(id);
+                        if let Err(e) = self.unwatch_entry(path) {
+                            log::debug!("{e}");
+                        }
+                    }
                 }
             }
         }
-        registry.save().map_err(Error::RegistrySave)?;
+        if changed {
+            registry.save().map_err(Error::RegistrySave)?;
+        }
         Ok(())
     }
 
+    /// Moves the entry of the file renamed from `from` to `to` keeping its tags and its original
+    /// name, so that renamed files can still be found by it. An entry of a file replaced by the
+    /// rename is removed. Returns `false` if `from` is not tagged.
+    fn rename_entry(&mut self, registry: &mut TagRegistry, from: &Path, to: &Path) -> bool {
+        let Some(id) = registry.find_entry(from) else {
+            return false;
+        };
+        log::trace!(
+            "renaming entry {} to {}, id: {id}",
+            from.display(),
+            to.display()
+        );
+        if let Some(replaced) = registry.find_entry(to) {
+            registry.clear_entry(replaced);
+        }
+        registry.update_entry_path(id, to);
+        if let Err(e) = self.unwatch_entry(from) {
+            log::debug!("{e}");
+        }
+        if let Err(e) = self.watch_entry(to) {
+            log::debug!("{e}");
+        }
+        true
+    }
+
+    /// Updates tags of the entry at `path` in the registry to match tags saved in its xattrs.
+    fn sync_entry(&mut self, registry: &mut TagRegistry, path: &Path) {
+        let id = match registry.find_entry(path) {
+            Some(id) => id,
+            None => return,
+        };
+        let follow = registry
+            .get_entry(id)
+            .map_or(true, |entry| !entry.no_follow());
+        let tags = match list_tags(path, follow) {
+            Ok(tags) => tags,
+            Err(e) => {
+                log::error!("failed to list tags of `{}`, reason: {e}", path.display());
+                return;
+            }
+        };
+        log::trace!("syncing tags of entry {}, id: {id}", path.display());
+        let before: Vec<_> = registry
+            .list_entry_tags(id)
+            .unwrap_or_default()
+            .into_iter()
+            .cloned()
+            .collect();
+        if let Some(EntryEvent::TagsChanged {
+            path,
+            added,
+            removed,
+        }) = EntryEvent::tags_changed(path, &before, &tags)
+        {
+            notify_subscribers(Response::TagChanged {
+                path,
+                added,
+                removed,
+            });
+        }
+        if let Some(entry) = registry.sync_entry_tags(id, &tags) {
+            if let Err(e) = self.unwatch_entry(entry.path()) {
+                log::error!("{e}");
+            }
+        }
+    }
+
     fn handle_entries_events(&mut self) -> Result<()> {
         let mut events_handle = match ENTRIES_EVENTS.try_write() {
             Ok(events) => events,
@@ -157,22 +506,305 @@ impl NotifyDaemon {
             match event {
                 EntryEvent::Add(entries) => {
                     for entry in entries {
-                        if let Err(e) = self.add_watch_entry(entry) {
-                            log::error!("{e}");
+                        if let Err(e) = self.watch_entry(entry) {
+                            log::debug!("{e}");
                             continue;
                         }
                     }
+                    self.warn_watch_failures();
                 }
                 EntryEvent::Remove(entries) => {
                     for entry in entries {
-                        if let Err(e) = self.remove_watch_entry(&entry) {
+                        if let Err(e) = self.unwatch_entry(&entry) {
                             log::error!("{}: {e}", entry.display());
                             continue;
                         }
                     }
                 }
+                EntryEvent::TagsChanged {
+                    path,
+                    added,
+                    removed,
+                } => notify_subscribers(Response::TagChanged {
+                    path,
+                    added,
+                    removed,
+                }),
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{new_watcher, DirWatches, NotifyDaemon};
+    use crate::config::{DaemonConfig, NotifyBackend, WatchMode};
+    use crate::registry::{get_registry_read, get_registry_write};
+    use crate::TEST_DIR;
+    use notify::event::{ModifyKind, RenameMode};
+    use notify::{
+        Event, EventHandler, EventKind, RecommendedWatcher, RecursiveMode, Watcher, WatcherKind,
+    };
+    use once_cell::sync::Lazy;
+    use std::collections::BTreeSet;
+    use std::ffi::OsStr;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+    use wutag_core::color::Color;
+    use wutag_core::registry::EntryData;
+    use wutag_core::tag::Tag;
+
+    #[test]
+    fn reports_metadata_event_on_xattr_change() {
+        let tmp_dir = tempdir::TempDir::new("notifyd-sync").unwrap();
+        let file = tmp_dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        let tracked = Tag::new("notifyd-tracked", Color::Red);
+        let external = Tag::new("notifyd-external", Color::Blue);
+        tracked.save_to(&file, true).unwrap();
+
+        // makes sure the registry of the tests is used
+        Lazy::force(&TEST_DIR);
+        {
+            let mut registry = get_registry_write().unwrap();
+            let (id, _) = registry.add_or_update_entry(EntryData::new(&file));
+            registry.tag_entry(&tracked, id);
+        }
+        let mut daemon = NotifyDaemon::new(&DaemonConfig::default()).unwrap();
+
+        // modified outside of wutag, the daemon isn't asked to update the registry
+        external.save_to(&file, true).unwrap();
+
+        let start = Instant::now();
+        loop {
+            daemon.handle_notify_events().unwrap();
+            let registry = get_registry_read().unwrap();
+            let id = registry.find_entry(&file).expect("entry of the file");
+            let tags = registry.list_entry_tags(id).unwrap_or_default();
+            if tags.contains(&&external) {
+                assert!(tags.contains(&&tracked));
+                break;
+            }
+            drop(registry);
+            assert!(
+                start.elapsed() < Duration::from_millis(500),
+                "registry not synced with xattrs"
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn watches_nested_directories_separately() {
+        let tmp_dir = tempdir::TempDir::new("notifyd-nested").unwrap();
+        let nested = tmp_dir.path().join("nested");
+        std::fs::create_dir(&nested).unwrap();
+        let (nested_file, file) = (nested.join("file"), tmp_dir.path().join("file"));
+        std::fs::write(&nested_file, "").unwrap();
+        std::fs::write(&file, "").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let mut daemon = NotifyDaemon {
+            notify: Box::new(RecommendedWatcher::new(tx, Default::default()).unwrap()),
+            mode: WatchMode::Parents,
+            dirs: DirWatches::default(),
+            failed: BTreeSet::new(),
+            limit_reached: false,
+            last_error: None,
+            warned: false,
+        };
+        daemon.watch_entry(&nested_file).unwrap();
+        // the parent is watched next to the nested directory instead of replacing its watch
+        daemon.watch_entry(&file).unwrap();
+        assert_eq!(daemon.dirs.dirs.len(), 2);
+
+        Tag::new("test", Color::Red)
+            .save_to(&nested_file, true)
+            .unwrap();
+        loop {
+            let event = rx
+                .recv_timeout(Duration::from_millis(500))
+                .expect("event of the nested file")
+                .unwrap();
+            if event.paths == [nested_file.clone()] {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn moves_entries_of_renamed_files() {
+        let tmp_dir = tempdir::TempDir::new("notifyd-rename").unwrap();
+        let (from, to) = (tmp_dir.path().join("from"), tmp_dir.path().join("to"));
+        std::fs::write(&from, "").unwrap();
+        std::fs::write(&to, "").unwrap();
+        let (renamed, replaced) = (
+            Tag::new("notifyd-renamed", Color::Red),
+            Tag::new("notifyd-replaced", Color::Blue),
+        );
+
+        Lazy::force(&TEST_DIR);
+        let id = {
+            let mut registry = get_registry_write().unwrap();
+            let (id, _) = registry.add_or_update_entry(EntryData::new(&from));
+            registry.tag_entry(&renamed, id);
+            let (replaced_id, _) = registry.add_or_update_entry(EntryData::new(&to));
+            registry.tag_entry(&replaced, replaced_id);
+            id
+        };
+        let (tx, _rx) = mpsc::channel();
+        let mut daemon = NotifyDaemon {
+            notify: Box::new(RecommendedWatcher::new(tx, Default::default()).unwrap()),
+            mode: WatchMode::Parents,
+            dirs: DirWatches::default(),
+            failed: BTreeSet::new(),
+            limit_reached: false,
+            last_error: None,
+            warned: false,
+        };
+
+        std::fs::rename(&from, &to).unwrap();
+        let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(from.clone())
+            .add_path(to.clone());
+        daemon.apply_notify_events(&[event]).unwrap();
+
+        let registry = get_registry_read().unwrap();
+        assert!(registry.find_entry(&from).is_none());
+        assert_eq!(registry.find_entry(&to), Some(id));
+        assert_eq!(registry.list_entry_tags(id).unwrap(), vec![&renamed]);
+        assert_eq!(
+            registry.get_entry(id).unwrap().original_name(),
+            Some(OsStr::new("from"))
+        );
+        assert_eq!(registry.entry_count_for_tag(&replaced), 0);
+        assert!(daemon.dirs.dirs.contains_key(tmp_dir.path()));
+    }
+
+    /// Watcher that fails to watch any path once `limit` paths are watched.
+    struct LimitedWatcher {
+        limit: usize,
+        watched: BTreeSet<PathBuf>,
+    }
+
+    impl Watcher for LimitedWatcher {
+        fn new<F: EventHandler>(_: F, _: notify::Config) -> notify::Result<Self> {
+            Ok(Self {
+                limit: 0,
+                watched: BTreeSet::new(),
+            })
+        }
+
+        fn watch(&mut self, path: &Path, _: RecursiveMode) -> notify::Result<()> {
+            if self.watched.len() >= self.limit {
+                return Err(notify::Error::new(notify::ErrorKind::MaxFilesWatch));
+            }
+            self.watched.insert(path.to_path_buf());
+            Ok(())
+        }
+
+        fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+            self.watched.remove(path);
+            Ok(())
+        }
+
+        fn kind() -> WatcherKind {
+            WatcherKind::NullWatcher
+        }
+    }
+
+    #[test]
+    fn records_entries_that_failed_to_be_watched() {
+        let mut daemon = NotifyDaemon {
+            notify: Box::new(LimitedWatcher {
+                limit: 1,
+                watched: BTreeSet::new(),
+            }),
+            mode: WatchMode::Files,
+            dirs: DirWatches::default(),
+            failed: BTreeSet::new(),
+            limit_reached: false,
+            last_error: None,
+            warned: false,
+        };
+        daemon.watch_entry("/watched").unwrap();
+        assert!(daemon.watch_entry("/unwatched").is_err());
+        assert!(daemon.watch_entry("/other").is_err());
+        assert_eq!(daemon.failed.len(), 2);
+        assert!(daemon.limit_reached);
+        assert!(daemon.last_error.is_some());
+
+        daemon.warn_watch_failures();
+        assert!(daemon.warned);
+
+        // failures are resolved once the entries are watched or no longer tagged
+        daemon.unwatch_entry("/watched").unwrap();
+        daemon.watch_entry("/unwatched").unwrap();
+        daemon.unwatch_entry("/other").unwrap();
+        assert!(daemon.failed.is_empty());
+        assert!(!daemon.limit_reached);
+        assert!(daemon.last_error.is_none());
+        assert!(!daemon.warned);
+    }
+
+    #[test]
+    fn polls_watched_files() {
+        let tmp_dir = tempdir::TempDir::new("notifyd-poll").unwrap();
+        let file = tmp_dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+
+        let config: DaemonConfig =
+            serde_yaml::from_str("notify_backend:\n  poll:\n    interval_ms: 20").unwrap();
+        assert_eq!(
+            config.notify_backend,
+            NotifyBackend::Poll { interval_ms: 20 }
+        );
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = new_watcher(config.notify_backend, tx).unwrap();
+        watcher.watch(&file, RecursiveMode::NonRecursive).unwrap();
+
+        std::fs::remove_file(&file).unwrap();
+        loop {
+            let event = rx
+                .recv_timeout(Duration::from_millis(500))
+                .expect("event of the removed file")
+                .unwrap();
+            if event.kind.is_remove() && event.paths == [file.clone()] {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn groups_entries_by_parent_directory() {
+        let mut dirs = DirWatches::default();
+        assert_eq!(
+            dirs.add(Path::new("/home/user/docs/a")),
+            Some("/home/user/docs".into())
+        );
+        assert_eq!(dirs.add(Path::new("/home/user/docs/b")), None);
+        assert_eq!(
+            dirs.add(Path::new("/home/user/docs/nested/c")),
+            Some("/home/user/docs/nested".into())
+        );
+        // tagged directories are covered by a watch of their parent, never by a recursive one
+        assert_eq!(
+            dirs.add(Path::new("/home/user/e")),
+            Some("/home/user".into())
+        );
+        assert_eq!(dirs.add(Path::new("/a")), Some("/".into()));
+        assert_eq!(dirs.dirs.len(), 4);
+
+        // the former recursive mode now watches parents as well
+        let config: DaemonConfig = serde_yaml::from_str("watch_mode: directories").unwrap();
+        assert_eq!(config.watch_mode, WatchMode::Parents);
+
+        assert_eq!(dirs.remove(Path::new("/home/user/docs/a")), None);
+        assert_eq!(
+            dirs.remove(Path::new("/home/user/docs/b")),
+            Some("/home/user/docs".into())
+        );
+    }
+}
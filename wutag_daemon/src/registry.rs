@@ -1,63 +1,248 @@
 use crate::Result;
-use once_cell::sync::Lazy;
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+use once_cell::sync::{Lazy, OnceCell};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, TryLockResult,
+};
+use std::time::Duration;
 use thiserror::Error as ThisError;
-use wutag_core::registry::TagRegistry;
+use wutag_core::registry::{RegistryMount, TagRegistry};
 
 #[derive(Debug, ThisError)]
 pub enum RegistryError {
-    #[error("failed to acquire poisoned lock - {0}")]
-    LockPoisoned(String),
     #[error("failed to acquire lock for registry")]
     Lock,
 }
 
-static REGISTRY: Lazy<RwLock<TagRegistry>> = Lazy::new(|| {
+/// Returns the directory in which the registry is stored, creating it if it doesn't exist.
+pub fn data_dir() -> PathBuf {
     let data_dir = dirs::data_dir().expect("valid data directory");
     if !data_dir.exists() {
         std::fs::create_dir_all(&data_dir).expect("failed to initialize data directory");
     }
-    let registry_file = data_dir.join("wutag.db");
+    data_dir
+}
+
+static REGISTRY_FILE: OnceCell<PathBuf> = OnceCell::new();
+
+/// Returns the path of the registry file.
+pub fn registry_file() -> PathBuf {
+    REGISTRY_FILE
+        .get_or_init(|| data_dir().join("wutag.db"))
+        .clone()
+}
+
+/// Overrides the path of the registry file. Has to be called before the registry is first
+/// accessed, returns `false` if the path was already set.
+#[cfg(test)]
+pub fn set_registry_file(path: PathBuf) -> bool {
+    REGISTRY_FILE.set(path).is_ok()
+}
+
+fn load_registry(registry_file: PathBuf) -> RwLock<TagRegistry> {
     RwLock::new(
         TagRegistry::load(&registry_file).unwrap_or_else(|_| TagRegistry::new(registry_file)),
     )
-});
-
-pub fn get_registry_write() -> RwLockWriteGuard<'static, TagRegistry> {
-    match REGISTRY.try_write() {
-        Ok(registry) => registry,
-        Err(e) => {
-            eprintln!("failed to lock registry for writing, reason: {e}");
-            std::process::exit(1);
-        }
+}
+
+static REGISTRY: Lazy<RwLock<TagRegistry>> = Lazy::new(|| load_registry(registry_file()));
+
+/// Registry of files inside of a mount from the `registry_mounts` of the configuration.
+struct Mount {
+    mount: RegistryMount,
+    registry: RwLock<TagRegistry>,
+}
+
+static MOUNTS: OnceCell<Vec<Mount>> = OnceCell::new();
+
+/// Selects the registry used by the current thread, either the registry of the mount with the
+/// index or the default registry if `None`.
+pub type Scope = Option<usize>;
+
+thread_local! {
+    static SCOPE: Cell<Scope> = const { Cell::new(None) };
+}
+
+/// Loads registries of the `mounts` creating missing directories of their files. Returns `false`
+/// if the mounts were already loaded.
+pub fn init_mounts(mounts: &[RegistryMount]) -> bool {
+    let mounts = mounts
+        .iter()
+        .map(|mount| {
+            if let Some(dir) = mount.registry_file.parent() {
+                if let Err(e) = std::fs::create_dir_all(dir) {
+                    log::error!("failed to create directory `{}` - {e}", dir.display());
+                }
+            }
+            Mount {
+                mount: mount.clone(),
+                registry: load_registry(mount.registry_file.clone()),
+            }
+        })
+        .collect();
+    MOUNTS.set(mounts).is_ok()
+}
+
+fn loaded_mounts() -> &'static [Mount] {
+    MOUNTS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+pub fn mounts() -> Vec<RegistryMount> {
+    loaded_mounts().iter().map(|m| m.mount.clone()).collect()
+}
+
+/// Returns the scope of the mount with the root directory at `path`.
+pub fn mount_scope(path: &Path) -> Option<Scope> {
+    loaded_mounts()
+        .iter()
+        .position(|m| m.mount.path == path)
+        .map(Some)
+}
+
+/// Returns root directories of mounts inside of the directory at `path`, excluding a mount at
+/// `path` itself.
+pub fn mounts_below(path: &Path) -> Vec<PathBuf> {
+    loaded_mounts()
+        .iter()
+        .map(|m| &m.mount.path)
+        .filter(|mount| mount.starts_with(path) && *mount != path)
+        .cloned()
+        .collect()
+}
+
+/// Returns the scope of the innermost mount containing `path` or the default scope.
+pub fn path_scope(path: &Path) -> Scope {
+    innermost_mount(loaded_mounts().iter().map(|m| &m.mount), path)
+}
+
+fn innermost_mount<'a>(
+    mounts: impl Iterator<Item = &'a RegistryMount>,
+    path: &Path,
+) -> Option<usize> {
+    mounts
+        .enumerate()
+        .filter(|(_, mount)| mount.contains(path))
+        .max_by_key(|(_, mount)| mount.path.components().count())
+        .map(|(i, _)| i)
+}
+
+/// Returns scopes of all registries starting with the default one.
+pub fn scopes() -> impl Iterator<Item = Scope> {
+    std::iter::once(None).chain((0..loaded_mounts().len()).map(Some))
+}
+
+/// Runs `f` with the registry of the `scope` used by the registry getters on this thread.
+pub fn with_scope<T>(scope: Scope, f: impl FnOnce() -> T) -> T {
+    let previous = SCOPE.with(|s| s.replace(scope));
+    let result = f();
+    SCOPE.with(|s| s.set(previous));
+    result
+}
+
+/// Returns the registry of the scope of the current thread.
+pub fn current_registry() -> &'static RwLock<TagRegistry> {
+    match SCOPE.with(Cell::get).and_then(|i| loaded_mounts().get(i)) {
+        Some(mount) => &mount.registry,
+        None => &REGISTRY,
     }
 }
-pub fn get_registry_read() -> RwLockReadGuard<'static, TagRegistry> {
-    match REGISTRY.try_read() {
-        Ok(registry) => registry,
-        Err(e) => {
-            eprintln!("failed to lock registry for reading, reason: {e}");
-            std::process::exit(1);
+
+/// Number of attempts made to acquire the registry lock before giving up.
+const LOCK_ATTEMPTS: usize = 50;
+/// Delay between consecutive attempts to acquire the registry lock.
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// Returns the guard of a poisoned `lock` clearing the poison. The registry is plain data so a
+/// panic while holding the lock at worst loses the changes that were not saved yet.
+fn recover_poisoned<G>(lock: &RwLock<TagRegistry>, e: PoisonError<G>) -> G {
+    log::warn!("registry lock was poisoned by a panicking thread, recovering");
+    lock.clear_poison();
+    e.into_inner()
+}
+
+/// Retries `try_lock` on `lock` until it is acquired or all [LOCK_ATTEMPTS](LOCK_ATTEMPTS) fail.
+fn lock_with_retry<'a, G>(
+    lock: &'a RwLock<TagRegistry>,
+    try_lock: impl Fn(&'a RwLock<TagRegistry>) -> TryLockResult<G>,
+) -> Result<G> {
+    for _ in 0..LOCK_ATTEMPTS {
+        match try_lock(lock) {
+            Ok(guard) => return Ok(guard),
+            Err(TryLockError::Poisoned(e)) => return Ok(recover_poisoned(lock, e)),
+            Err(TryLockError::WouldBlock) => std::thread::sleep(LOCK_RETRY_DELAY),
         }
     }
+    Err(RegistryError::Lock.into())
+}
+
+/// Acquires `lock` with `try_lock` only if it is free right away. Used by background tasks so
+/// that they never make requests wait for the registry.
+pub fn try_lock_now<'a, G>(
+    lock: &'a RwLock<TagRegistry>,
+    try_lock: impl Fn(&'a RwLock<TagRegistry>) -> TryLockResult<G>,
+) -> Option<G> {
+    match try_lock(lock) {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(e)) => Some(recover_poisoned(lock, e)),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+pub fn get_registry_write() -> Result<RwLockWriteGuard<'static, TagRegistry>> {
+    lock_with_retry(current_registry(), RwLock::try_write)
+}
+
+pub fn get_registry_read() -> Result<RwLockReadGuard<'static, TagRegistry>> {
+    lock_with_retry(current_registry(), RwLock::try_read)
 }
 
 pub fn try_get_registry_write_loop() -> Result<RwLockWriteGuard<'static, TagRegistry>> {
-    let mut i = 0;
-    loop {
-        i += 1;
-        if i >= 5 {
-            return Err(RegistryError::Lock.into());
-        }
-        let registry = match REGISTRY.try_write() {
-            Ok(registry) => registry,
-            Err(e) => match e {
-                TryLockError::Poisoned(e) => {
-                    return Err(RegistryError::LockPoisoned(e.to_string()).into());
-                }
-                TryLockError::WouldBlock => continue,
-            },
+    lock_with_retry(current_registry(), RwLock::try_write)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wutag_core::registry::EntryData;
+
+    #[test]
+    fn recovers_from_poisoned_lock() {
+        let lock = RwLock::new(TagRegistry::default());
+        let _ = std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut registry = lock.write().unwrap();
+                registry.add_or_update_entry(EntryData::new("/tmp"));
+                panic!("poison the lock");
+            })
+            .join()
+        });
+        assert!(lock.is_poisoned());
+
+        let registry = lock_with_retry(&lock, RwLock::try_read).unwrap();
+        assert_eq!(registry.list_entries().count(), 1);
+        drop(registry);
+        assert!(!lock.is_poisoned());
+        assert!(lock_with_retry(&lock, RwLock::try_write).is_ok());
+    }
+
+    #[test]
+    fn selects_innermost_mount() {
+        let mount = |path: &str| RegistryMount {
+            path: path.into(),
+            registry_file: Path::new(path).join("wutag.db"),
         };
-        break Ok(registry);
+        let mounts = [
+            mount("/home/user/work"),
+            mount("/home/user/work/client"),
+            mount("/srv"),
+        ];
+        let find = |path: &str| innermost_mount(mounts.iter(), Path::new(path));
+
+        assert_eq!(find("/home/user/work/notes.txt"), Some(0));
+        assert_eq!(find("/home/user/work/client/a/b.txt"), Some(1));
+        assert_eq!(find("/home/user/workshop/a.txt"), None);
+        assert_eq!(find("/srv"), Some(2));
+        assert_eq!(find("/tmp/a.txt"), None);
     }
 }
@@ -0,0 +1,222 @@
+//! Periodic check of the registries against the filesystem catching changes missed by watching.
+use crate::config::DaemonConfig;
+use crate::registry::{self, try_lock_now};
+use crate::{notify_subscribers, EntryEvent, ENTRIES_EVENTS};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+use wutag_core::registry::{EntryId, TagRegistry};
+use wutag_core::tag::{list_tags, Tag};
+use wutag_ipc::Response;
+
+/// Outcome of a single sweep of a registry.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SweepSummary {
+    /// Number of entries that were checked.
+    pub checked: usize,
+    /// Number of entries removed because their files no longer exist.
+    pub pruned: usize,
+    /// Number of entries whose tags were updated to match the tags saved in their files.
+    pub reconciled: usize,
+}
+
+/// Removes entries of files that no longer exist from the registries every `interval` and
+/// optionally updates their tags to match the tags saved in the files.
+pub struct ConsistencySweep {
+    interval: Duration,
+    verify_tags: bool,
+    /// Whether the notify daemon is running and should stop watching pruned entries.
+    watch: bool,
+}
+
+impl ConsistencySweep {
+    /// Returns `None` if the sweep is disabled by a zero `sweep_interval_mins`.
+    pub fn new(config: &DaemonConfig) -> Option<Self> {
+        (config.sweep_interval_mins > 0).then(|| Self {
+            interval: Duration::from_secs(config.sweep_interval_mins * 60),
+            verify_tags: config.sweep_verify_tags,
+            watch: config.watch,
+        })
+    }
+
+    pub fn work_loop(self) {
+        loop {
+            std::thread::sleep(self.interval);
+            for scope in registry::scopes() {
+                let lock = registry::with_scope(scope, registry::current_registry);
+                if let Some(summary) = self.sweep(lock) {
+                    log::info!(
+                        "consistency sweep checked {} entries, pruned {}, reconciled {}",
+                        summary.checked,
+                        summary.pruned,
+                        summary.reconciled
+                    );
+                }
+            }
+        }
+    }
+
+    /// Checks entries of the registry behind `lock`. The files are checked without holding the
+    /// lock and the sweep is skipped if the registry is busy at the beginning or at the end so
+    /// that it never makes requests wait. Returns `None` if the sweep was skipped.
+    fn sweep(&self, lock: &RwLock<TagRegistry>) -> Option<SweepSummary> {
+        let entries: Vec<(EntryId, PathBuf, bool, BTreeSet<Tag>)> = {
+            let Some(registry) = try_lock_now(lock, RwLock::try_read) else {
+                log::debug!("registry is busy, skipping consistency sweep");
+                return None;
+            };
+            registry
+                .list_entries_and_ids()
+                .map(|(id, entry)| {
+                    let tags = registry
+                        .list_entry_tags_btree(*id)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .cloned()
+                        .collect();
+                    (*id, entry.path().to_path_buf(), !entry.no_follow(), tags)
+                })
+                .collect()
+        };
+
+        let mut stale = vec![];
+        let mut changed = vec![];
+        for (id, path, follow, tags) in &entries {
+            if fs::symlink_metadata(path).is_err() {
+                stale.push((*id, path));
+                continue;
+            }
+            if !self.verify_tags {
+                continue;
+            }
+            match list_tags(path, *follow) {
+                Ok(saved) if saved.iter().collect::<BTreeSet<_>>() != tags.iter().collect() => {
+                    changed.push((*id, path, tags, saved))
+                }
+                Ok(_) => {}
+                Err(e) => log::debug!("failed to list tags of `{}` - {e}", path.display()),
+            }
+        }
+
+        let mut summary = SweepSummary {
+            checked: entries.len(),
+            ..Default::default()
+        };
+        if stale.is_empty() && changed.is_empty() {
+            return Some(summary);
+        }
+        let Some(mut registry) = try_lock_now(lock, RwLock::try_write) else {
+            log::debug!("registry is busy, skipping consistency sweep");
+            return None;
+        };
+        // entries could have changed while the files were checked
+        let unchanged = |registry: &TagRegistry, id: EntryId, path: &PathBuf| {
+            registry.get_entry(id).is_some_and(|e| e.path() == path)
+        };
+
+        let mut pruned = vec![];
+        for (id, path) in stale {
+            if unchanged(&registry, id, path) && fs::symlink_metadata(path).is_err() {
+                registry.clear_entry(id);
+                pruned.push(path.clone());
+            }
+        }
+        for (id, path, before, after) in changed {
+            if !unchanged(&registry, id, path) {
+                continue;
+            }
+            let before: Vec<_> = before.iter().cloned().collect();
+            if let Some(EntryEvent::TagsChanged {
+                path,
+                added,
+                removed,
+            }) = EntryEvent::tags_changed(path, &before, &after)
+            {
+                notify_subscribers(Response::TagChanged {
+                    path,
+                    added,
+                    removed,
+                });
+            }
+            if let Some(entry) = registry.sync_entry_tags(id, &after) {
+                pruned.push(entry.path().to_path_buf());
+            }
+            summary.reconciled += 1;
+        }
+        summary.pruned = pruned.len();
+
+        if let Err(e) = registry.save() {
+            log::error!("{e}");
+        }
+        drop(registry);
+        if self.watch && !pruned.is_empty() {
+            match ENTRIES_EVENTS.write() {
+                Ok(mut events) => events.push(EntryEvent::Remove(pruned)),
+                Err(e) => log::warn!("failed to lock entries events, reason: {e}"),
+            }
+        }
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use wutag_core::color::Color;
+    use wutag_core::registry::EntryData;
+
+    #[test]
+    fn prunes_and_reconciles_entries() {
+        let dir = TempDir::new("wutag-sweep").unwrap();
+        let mut registry = TagRegistry::new(dir.path().join("wutag.db"));
+        let tag = Tag::new("swept", Color::Red);
+        let other = Tag::new("other", Color::Blue);
+        let mut add = |name: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, b"").unwrap();
+            tag.save_to(&path, true).unwrap();
+            let (id, _) = registry.add_or_update_entry(EntryData::new(&path));
+            registry.tag_entry(&tag, id);
+            (id, path)
+        };
+        let (_, kept) = add("kept");
+        let (_, removed) = add("removed");
+        let (changed_id, changed) = add("changed");
+        fs::remove_file(&removed).unwrap();
+        other.save_to(&changed, true).unwrap();
+
+        let lock = RwLock::new(registry);
+        let mut sweep = ConsistencySweep {
+            interval: Duration::ZERO,
+            verify_tags: false,
+            watch: false,
+        };
+        let summary = sweep.sweep(&lock).unwrap();
+        assert_eq!(
+            summary,
+            SweepSummary {
+                checked: 3,
+                pruned: 1,
+                reconciled: 0
+            }
+        );
+        assert!(lock.read().unwrap().find_entry(&removed).is_none());
+        assert!(lock.read().unwrap().find_entry(&kept).is_some());
+
+        sweep.verify_tags = true;
+        let summary = sweep.sweep(&lock).unwrap();
+        assert_eq!(summary.reconciled, 1);
+        let registry = lock.read().unwrap();
+        let mut tags = registry.list_entry_tags(changed_id).unwrap();
+        tags.sort_unstable();
+        assert_eq!(tags, vec![&other, &tag]);
+
+        // a busy registry is skipped instead of waiting for it
+        drop(registry);
+        let _guard = lock.write().unwrap();
+        assert!(sweep.sweep(&lock).is_none());
+    }
+}
@@ -1,8 +1,22 @@
-use crate::{payload::Payload, Result};
+use crate::{payload::Payload, IpcError, Result};
 use interprocess::local_socket::LocalSocketStream;
+use serde::{Deserialize, Serialize};
+use std::fs;
 use std::io::{self, BufReader};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 
+/// Number of consecutive failed connection attempts after which the circuit opens.
+pub const CIRCUIT_FAILURE_THRESHOLD: u32 = 3;
+/// Time the circuit stays open after it opens for the first time. Each following failed attempt
+/// in the half open state doubles it up to [MAX_CIRCUIT_BACKOFF](MAX_CIRCUIT_BACKOFF).
+pub const CIRCUIT_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest time the circuit stays open, the backoff stops doubling once it reaches it.
+pub const MAX_CIRCUIT_BACKOFF: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Error)]
 pub enum ClientError {
     #[error("failed to initialize connection - {0}")]
@@ -11,6 +25,8 @@ pub enum ClientError {
     NoActiveConnection,
     #[error("failed to bind local listener - {0}")]
     Bind(io::Error),
+    #[error("daemon is unreachable, next connection attempt in {:.1}s", .0.as_secs_f32())]
+    CircuitOpen(Duration),
 }
 
 pub struct IpcClient {
@@ -35,4 +51,322 @@ impl IpcClient {
 
         Ok(response)
     }
+
+    /// Sends the `request` and keeps the connection open to read payloads pushed by the server.
+    pub fn subscribe<REQUEST: Payload, EVENT: Payload>(
+        &self,
+        request: REQUEST,
+    ) -> Result<Subscription<EVENT>> {
+        let conn =
+            LocalSocketStream::connect(self.path.as_str()).map_err(ClientError::ConnectionInit)?;
+        let mut conn = BufReader::new(conn);
+
+        request.send(&mut conn)?;
+
+        Ok(Subscription {
+            conn,
+            _event: PhantomData,
+        })
+    }
+}
+
+/// Open connection yielding payloads pushed by the server until it closes the connection.
+pub struct Subscription<EVENT> {
+    conn: BufReader<LocalSocketStream>,
+    _event: PhantomData<EVENT>,
+}
+
+impl<EVENT: Payload> Iterator for Subscription<EVENT> {
+    type Item = Result<EVENT>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match EVENT::read(&mut self.conn) {
+            Err(IpcError::ConnectionRead(e)) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            result => Some(result),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent normally.
+    Closed,
+    /// Requests fail immediately until `retry_at`.
+    Open { retry_at: Instant },
+    /// A single request is let through to check whether the daemon is reachable again.
+    HalfOpen,
+}
+
+struct Breaker {
+    state: CircuitState,
+    failures: u32,
+    backoff: Duration,
+}
+
+/// State of a [Breaker](Breaker) saved to a file so that it is shared by short lived processes.
+/// Times are saved as wall clock time as an `Instant` only has a meaning within one process.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct SavedBreaker {
+    failures: u32,
+    backoff_ms: u64,
+    open_until: Option<SystemTime>,
+    half_open: bool,
+}
+
+impl Breaker {
+    fn to_saved(&self) -> SavedBreaker {
+        let now = Instant::now();
+        SavedBreaker {
+            failures: self.failures,
+            backoff_ms: self.backoff.as_millis() as u64,
+            open_until: match self.state {
+                CircuitState::Open { retry_at } => {
+                    Some(SystemTime::now() + retry_at.saturating_duration_since(now))
+                }
+                _ => None,
+            },
+            half_open: self.state == CircuitState::HalfOpen,
+        }
+    }
+
+    fn from_saved(saved: SavedBreaker, base_backoff: Duration) -> Self {
+        let state = match saved.open_until {
+            Some(open_until) => CircuitState::Open {
+                retry_at: Instant::now()
+                    + open_until
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default(),
+            },
+            None if saved.half_open => CircuitState::HalfOpen,
+            None => CircuitState::Closed,
+        };
+        Self {
+            state,
+            failures: saved.failures,
+            backoff: Duration::from_millis(saved.backoff_ms)
+                .clamp(base_backoff, MAX_CIRCUIT_BACKOFF.max(base_backoff)),
+        }
+    }
+}
+
+/// [IpcClient](IpcClient) that stops connecting to the daemon after
+/// [CIRCUIT_FAILURE_THRESHOLD](CIRCUIT_FAILURE_THRESHOLD) consecutive failed connection attempts
+/// and only tries again after an exponentially increasing backoff.
+///
+/// The state of the breaker is kept in memory unless a file is set with
+/// [with_state_file](CircuitBreakerClient::with_state_file). Processes sending a single request,
+/// like the CLI, have to share the state through the file for the circuit to ever open.
+pub struct CircuitBreakerClient {
+    client: IpcClient,
+    threshold: u32,
+    base_backoff: Duration,
+    breaker: Mutex<Breaker>,
+    state_file: Option<PathBuf>,
+}
+
+impl CircuitBreakerClient {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self::with_backoff(path, CIRCUIT_FAILURE_THRESHOLD, CIRCUIT_BACKOFF)
+    }
+
+    pub fn with_backoff(path: impl Into<String>, threshold: u32, backoff: Duration) -> Self {
+        Self {
+            client: IpcClient::new(path),
+            threshold,
+            base_backoff: backoff,
+            breaker: Mutex::new(Breaker {
+                state: CircuitState::Closed,
+                failures: 0,
+                backoff,
+            }),
+            state_file: None,
+        }
+    }
+
+    /// Loads the state of the breaker from the file at `path` before each request and saves it
+    /// there after the request. Failing to read or write the file only resets the state.
+    pub fn with_state_file(mut self, path: Option<PathBuf>) -> Self {
+        self.state_file = path;
+        self
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.breaker().state
+    }
+
+    /// Replaces the state of the `breaker` with the state saved by the last request of any
+    /// process.
+    fn load_state(&self, breaker: &mut Breaker) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let saved = match fs::read(path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                log::debug!("invalid circuit state in `{}` - {e}", path.display());
+                SavedBreaker::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => SavedBreaker::default(),
+            Err(e) => {
+                log::debug!("failed to read circuit state `{}` - {e}", path.display());
+                SavedBreaker::default()
+            }
+        };
+        *breaker = Breaker::from_saved(saved, self.base_backoff);
+    }
+
+    fn save_state(&self, breaker: &Breaker) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        if let Err(e) = write_state(path, &breaker.to_saved()) {
+            log::debug!("failed to save circuit state `{}` - {e}", path.display());
+        }
+    }
+
+    fn breaker(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.breaker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn request<REQUEST: Payload, RESPONSE: Payload>(
+        &self,
+        request: REQUEST,
+    ) -> Result<RESPONSE> {
+        let before = {
+            let mut breaker = self.breaker();
+            self.load_state(&mut breaker);
+            if let CircuitState::Open { retry_at } = breaker.state {
+                let now = Instant::now();
+                if now < retry_at {
+                    return Err(ClientError::CircuitOpen(retry_at - now).into());
+                }
+                breaker.state = CircuitState::HalfOpen;
+                self.save_state(&breaker);
+            }
+            (breaker.state, breaker.failures)
+        };
+
+        let result = self.client.request(request);
+
+        let mut breaker = self.breaker();
+        if let Err(IpcError::Client(ClientError::ConnectionInit(_))) = &result {
+            breaker.failures += 1;
+            if breaker.state == CircuitState::HalfOpen {
+                breaker.backoff = (breaker.backoff * 2).min(MAX_CIRCUIT_BACKOFF);
+            }
+            if breaker.state == CircuitState::HalfOpen || breaker.failures >= self.threshold {
+                log::debug!("opening circuit for {:?}", breaker.backoff);
+                breaker.state = CircuitState::Open {
+                    retry_at: Instant::now() + breaker.backoff,
+                };
+            }
+        } else {
+            breaker.state = CircuitState::Closed;
+            breaker.failures = 0;
+            breaker.backoff = self.base_backoff;
+        }
+        // requests to a reachable daemon don't have to write the file every time
+        if (breaker.state, breaker.failures) != before {
+            self.save_state(&breaker);
+        }
+
+        result
+    }
+
+    /// Subscribes without going through the circuit breaker as the connection is long lived.
+    pub fn subscribe<REQUEST: Payload, EVENT: Payload>(
+        &self,
+        request: REQUEST,
+    ) -> Result<Subscription<EVENT>> {
+        self.client.subscribe(request)
+    }
+}
+
+/// Writes the `state` to a temporary file first so that other processes never read a partially
+/// written state.
+fn write_state(path: &Path, state: &SavedBreaker) -> io::Result<()> {
+    let content = serde_json::to_vec(state).map_err(io::Error::other)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}", std::process::id()));
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{socket_name, IpcServer, PayloadResult, Request, Response};
+
+    #[test]
+    fn opens_circuit_after_failed_connections() {
+        let socket = socket_name(
+            std::env::temp_dir(),
+            format!("wutag-circuit-{}.sock", std::process::id()),
+        );
+        let client = CircuitBreakerClient::with_backoff(
+            &socket,
+            CIRCUIT_FAILURE_THRESHOLD,
+            Duration::from_millis(50),
+        );
+
+        for _ in 0..CIRCUIT_FAILURE_THRESHOLD {
+            assert!(matches!(
+                client.request::<_, Response>(Request::Ping),
+                Err(IpcError::Client(ClientError::ConnectionInit(_)))
+            ));
+        }
+        assert!(matches!(client.state(), CircuitState::Open { .. }));
+        assert!(matches!(
+            client.request::<_, Response>(Request::Ping),
+            Err(IpcError::Client(ClientError::CircuitOpen(_)))
+        ));
+
+        std::thread::sleep(Duration::from_millis(60));
+        let mut server = IpcServer::new(socket.as_str()).unwrap();
+        let handle = std::thread::spawn(move || {
+            server.accept_request::<Request>().unwrap();
+            server
+                .send_response(Response::Ping(PayloadResult::Ok(())))
+                .unwrap();
+        });
+
+        assert!(matches!(
+            client.request::<_, Response>(Request::Ping),
+            Ok(Response::Ping(PayloadResult::Ok(())))
+        ));
+        assert_eq!(client.state(), CircuitState::Closed);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn shares_circuit_state_between_clients() {
+        let socket = socket_name(
+            std::env::temp_dir(),
+            format!("wutag-circuit-shared-{}.sock", std::process::id()),
+        );
+        let state =
+            std::env::temp_dir().join(format!("wutag-circuit-{}.state", std::process::id()));
+        // every client sends a single request like an invocation of the CLI
+        let client = || {
+            CircuitBreakerClient::with_backoff(
+                &socket,
+                CIRCUIT_FAILURE_THRESHOLD,
+                Duration::from_secs(60),
+            )
+            .with_state_file(Some(state.clone()))
+        };
+
+        for _ in 0..CIRCUIT_FAILURE_THRESHOLD {
+            assert!(matches!(
+                client().request::<_, Response>(Request::Ping),
+                Err(IpcError::Client(ClientError::ConnectionInit(_)))
+            ));
+        }
+        let fresh = client();
+        assert!(matches!(
+            fresh.request::<_, Response>(Request::Ping),
+            Err(IpcError::Client(ClientError::CircuitOpen(_)))
+        ));
+        assert!(matches!(fresh.state(), CircuitState::Open { .. }));
+        fs::remove_file(&state).unwrap();
+    }
 }
@@ -1,22 +1,30 @@
 mod client;
+mod migration;
 mod payload;
 mod server;
 
-pub use client::{ClientError, IpcClient};
-pub use payload::{Payload, PayloadError, PayloadResult};
-pub use server::{IpcServer, ServerError};
+pub use client::{CircuitBreakerClient, CircuitState, ClientError, IpcClient, Subscription};
+pub use migration::Migration;
+pub use payload::{ErrorCode, Payload, PayloadError, PayloadResult, ResponseError};
+pub use server::{IpcServer, ServerError, Subscriber};
 
+use chrono::{DateTime, Utc};
 use interprocess::local_socket::NameTypeSupport;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 use wutag_core::color::Color;
 use wutag_core::glob::Glob;
-use wutag_core::registry::EntryData;
-use wutag_core::tag::Tag;
+use wutag_core::registry::{
+    EntryData, PathMigration, RegistryFsDiff, RegistryMount, TagOverlap, VerifyReport,
+};
+use wutag_core::tag::{ImplicationRule, Tag, TagFilter, TagRule};
+use wutag_core::xattr::XattrCapacity;
 
 pub type Result<T> = std::result::Result<T, IpcError>;
 
@@ -29,14 +37,23 @@ pub fn socket_name(base_path: impl AsRef<Path>, name: impl AsRef<str>) -> String
     }
 }
 
+/// Returns the directory of files used to communicate with the daemon.
+fn runtime_dir() -> PathBuf {
+    dirs::runtime_dir()
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_else(|| "/tmp".into())
+}
+
 pub fn default_socket() -> String {
     let username = whoami::username();
     let socketname = format!("wutag-{username}.sock");
-    let dir = dirs::runtime_dir()
-        .or_else(dirs::data_local_dir)
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_else(|| "/tmp".into());
-    socket_name(dir, socketname)
+    socket_name(runtime_dir(), socketname)
+}
+
+/// Returns the path of the file in which clients share the state of their
+/// [CircuitBreakerClient](CircuitBreakerClient), next to the socket of the daemon.
+pub fn default_circuit_file() -> PathBuf {
+    runtime_dir().join(format!("wutag-{}.circuit", whoami::username()))
 }
 
 #[derive(Debug, Error)]
@@ -51,40 +68,239 @@ pub enum IpcError {
     ConnectionRead(io::Error),
     #[error("failed to write to socket - {0}")]
     ConnectionWrite(io::Error),
+    #[error("{0}")]
+    Detailed(#[from] ResponseError),
     #[error("Error: {0}")]
     Other(String),
 }
 
+/// Result of inspecting files along with the number of paths that were matched for inspection.
+#[derive(Deserialize, Debug, Serialize)]
+pub struct InspectFilesResult {
+    pub entries: Vec<(EntryData, Vec<Tag>)>,
+    pub match_count: usize,
+    /// Expiry times of tags of inspected entries that expire.
+    #[serde(default)]
+    pub expiries: HashMap<PathBuf, BTreeMap<String, DateTime<Utc>>>,
+}
+
+/// Statistics of the registry used by the daemon.
 #[derive(Deserialize, Debug, Serialize)]
+pub struct RegistryStats {
+    pub path: PathBuf,
+    pub entries: usize,
+    pub tags: usize,
+    /// Names of the tags with the most entries along with the number of their entries.
+    #[serde(default)]
+    pub most_used_tags: Vec<(String, usize)>,
+    #[serde(default)]
+    pub watch_failures: WatchFailures,
+    /// Time of the last maintenance run since the daemon started.
+    #[serde(default)]
+    pub last_maintenance: Option<DateTime<Utc>>,
+    /// Number of entries added since the registry was last saved, `None` if its file couldn't
+    /// be read.
+    #[serde(default)]
+    pub unsaved_added: Option<usize>,
+    /// Number of entries removed since the registry was last saved.
+    #[serde(default)]
+    pub unsaved_removed: Option<usize>,
+}
+
+/// Outcome of a maintenance run of all registries of the daemon.
+#[derive(Clone, Default, Deserialize, Debug, Serialize, JsonSchema)]
+pub struct MaintenanceSummary {
+    pub ran_at: DateTime<Utc>,
+    /// Number of entries removed because their files no longer exist.
+    pub evicted: usize,
+    /// Number of expired tags removed from their files.
+    pub expired_tags: usize,
+    /// Paths of the backups of the registries saved by the run.
+    pub backups: Vec<PathBuf>,
+    /// Failures of the run, registries that failed are skipped.
+    pub errors: Vec<String>,
+}
+
+/// Summary of tagged files that the daemon failed to watch for changes.
+#[derive(Clone, Default, Deserialize, Debug, Serialize, JsonSchema)]
+pub struct WatchFailures {
+    /// Number of tagged files that are not monitored.
+    pub unwatched: usize,
+    /// Whether the failures were caused by reaching the limit of watches of the system.
+    pub limit_reached: bool,
+    /// Reason of the most recent failure.
+    pub last_error: Option<String>,
+}
+
+/// Outcome of a request modifying multiple files. Failures of single files are reported here
+/// while failures of the whole request are returned as errors.
+#[derive(Clone, Default, Deserialize, Debug, Serialize, JsonSchema)]
+pub struct BatchSummary {
+    /// Number of files that were modified.
+    pub succeeded: usize,
+    /// Number of files that failed to be modified.
+    pub failed: usize,
+    /// Number of files left as they were, for example because they already had the tags.
+    pub skipped: usize,
+    /// Number of tags that files already had when tagging them, such tags are neither saved
+    /// again nor counted as failures.
+    #[serde(default)]
+    pub already_tagged: usize,
+    /// Reasons of the failures.
+    pub errors: Vec<String>,
+}
+
+/// Names of tags added to and removed from a file.
+#[derive(Clone, Default, Deserialize, Debug, Serialize, JsonSchema)]
+pub struct FileTagChanges {
+    pub path: PathBuf,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Outcome of setting the exact tags of files along with the changes made to each file.
+#[derive(Clone, Default, Deserialize, Debug, Serialize, JsonSchema)]
+pub struct SetExactTagsResult {
+    pub summary: BatchSummary,
+    /// Changes of files whose tags were modified, files that already had the tags are left out.
+    pub changes: Vec<FileTagChanges>,
+}
+
+/// Space for extended attributes of a file along with an estimate of how many more tags fit.
+#[derive(Clone, Default, Deserialize, Debug, Serialize, JsonSchema)]
+pub struct TagCapacity {
+    pub capacity: XattrCapacity,
+    /// Number of tags of the file.
+    pub tags: usize,
+    /// Average size of the names and values of the xattrs of the tags of the file, or the size
+    /// of a typical tag if the file has none.
+    pub tag_size: usize,
+    /// Estimated number of tags of `tag_size` that still fit.
+    pub tags_left: usize,
+}
+
+/// Window of a listing, items are skipped up to `offset` and at most `limit` of them are listed.
+#[derive(Clone, Copy, Default, Deserialize, Debug, PartialEq, Eq, Serialize)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// Items of a page of a listing along with the total number of items of the whole listing.
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct Paged<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+}
+
+/// Keys and values of extended attributes of each file.
+pub type RawXattrs = Vec<(PathBuf, Vec<(String, String)>)>;
+
+/// Request modifying tags recorded in the audit log of the daemon.
+#[derive(Clone, Deserialize, Debug, Serialize, JsonSchema)]
+pub struct AuditLogEntry {
+    pub timestamp: DateTime<Utc>,
+    /// Name of the user running the client that made the request, `unknown` on platforms where
+    /// the daemon can't read credentials of clients.
+    pub user: String,
+    /// Kind of the request like `tag`, `untag` or `clear-files`.
+    pub operation: String,
+    /// Files of the request, requests with a pattern list the pattern instead.
+    pub paths: Vec<PathBuf>,
+    pub tags: Vec<String>,
+    /// Whether the request was processed successfully.
+    pub succeeded: bool,
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
 pub enum Request {
     TagFiles {
         files: Vec<PathBuf>,
         tags: Vec<Tag>,
+        /// Also tag the files with tags of matching `rules`.
+        #[serde(default)]
+        auto_tag: bool,
+        #[serde(default)]
+        rules: Vec<TagRule>,
+        /// Remove `tags` from the files once this time passes.
+        #[serde(default)]
+        expires_in: Option<Duration>,
+        /// Tag symlinks themselves instead of their targets.
+        #[serde(default)]
+        no_follow: bool,
+        /// Colors of `tags` that aren't in the registry yet are replaced with the least used
+        /// colors of this palette. Colors of `tags` are kept when it's empty.
+        #[serde(default)]
+        palette: Vec<Color>,
     },
     TagFilesPattern {
         glob: Glob,
         tags: Vec<Tag>,
+        #[serde(default)]
+        auto_tag: bool,
+        #[serde(default)]
+        rules: Vec<TagRule>,
+        #[serde(default)]
+        expires_in: Option<Duration>,
+        #[serde(default)]
+        no_follow: bool,
+        #[serde(default)]
+        palette: Vec<Color>,
     },
+    /// Makes `tags` the only tags of every file, adding the missing ones and removing others.
+    SetExactTags {
+        files: Vec<PathBuf>,
+        tags: Vec<Tag>,
+    },
+    /// Removes `tags` from files. System tags are only removed with `force`, same as with
+    /// the other requests removing tags.
     UntagFiles {
         files: Vec<PathBuf>,
         tags: Vec<Tag>,
+        #[serde(default)]
+        no_follow: bool,
+        #[serde(default)]
+        force: bool,
     },
     UntagFilesPattern {
         glob: Glob,
         tags: Vec<Tag>,
+        #[serde(default)]
+        no_follow: bool,
+        #[serde(default)]
+        force: bool,
     },
     EditTag {
         tag: String,
-        color: Color,
+        color: Option<Color>,
+        weight: Option<f32>,
+        /// Marks the tag as a system tag protected from removal or unmarks it.
+        #[serde(default)]
+        system: Option<bool>,
     },
     ClearFiles {
         files: Vec<PathBuf>,
+        #[serde(default)]
+        force: bool,
     },
     ClearFilesPattern {
         glob: Glob,
+        #[serde(default)]
+        force: bool,
     },
+    /// Removes the `tags` from all files. With `patterns` names with wildcards like `tmp-*` clear
+    /// every tag matching them, see `TagPattern`, otherwise the names are taken literally.
     ClearTags {
         tags: Vec<String>,
+        #[serde(default)]
+        patterns: bool,
+        #[serde(default)]
+        force: bool,
+    },
+    /// Removes every tag of the registry from all files, answered with `Response::ClearTags`.
+    ClearAllTags {
+        #[serde(default)]
+        force: bool,
     },
     CopyTags {
         source: PathBuf,
@@ -94,42 +310,242 @@ pub enum Request {
         source: PathBuf,
         glob: Glob,
     },
+    /// Tags are listed sorted by name. Tags created with `CreateTag` that no file has are only
+    /// listed with `include_unused`.
     ListTags {
         with_files: bool,
+        #[serde(default)]
+        page: Page,
+        #[serde(default)]
+        include_unused: bool,
     },
+    /// Files are listed sorted by path.
     ListFiles {
         with_tags: bool,
+        #[serde(default)]
+        page: Page,
     },
+    /// With `no_follow` tags of symlinks themselves are read from the files instead of the
+    /// registry.
     InspectFiles {
         files: Vec<PathBuf>,
+        #[serde(default)]
+        no_follow: bool,
     },
     InspectFilesPattern {
         glob: Glob,
+        #[serde(default)]
+        no_follow: bool,
+    },
+    /// Returns extended attributes of the `files` in the wutag namespace as they are saved,
+    /// regardless of whether the files are tracked in the registry.
+    InspectFilesRaw {
+        files: Vec<PathBuf>,
+    },
+    InspectFilesRawPattern {
+        glob: Glob,
+    },
+    /// Estimates how many more tags the `file` can hold before reaching the limit of the
+    /// filesystem for extended attributes.
+    XattrCapacity {
+        file: PathBuf,
     },
     Search {
         tags: Vec<String>,
         any: bool,
     },
+    SearchWeighted {
+        tags: Vec<String>,
+        any: bool,
+    },
+    /// Same as `SearchWeighted` but found entries are returned along with all of their tags
+    /// instead of their scores.
+    SearchWithTags {
+        tags: Vec<String>,
+        any: bool,
+    },
+    SearchByTagPrefix {
+        prefix: String,
+    },
+    /// Finds entries whose files were named `name` when they were added to the registry.
+    SearchByOriginalName {
+        name: String,
+    },
+    /// Finds entries whose paths contain `fragment` ignoring case.
+    SearchByPathFragment {
+        fragment: String,
+    },
+    /// Finds entries matching all conditions of the `filter`.
+    SearchFiltered {
+        filter: TagFilter,
+    },
     Ping,
     ClearCache,
+    ExportGraph,
+    RecentTags,
+    ClearRecentTags,
+    RenameTagsMatching {
+        from_pattern: String,
+        replacement: String,
+    },
+    Stats,
+    RunMaintenance,
+    Verify,
+    VerifyTags {
+        full: bool,
+    },
+    TagOverlap {
+        a: String,
+        b: String,
+    },
+    TagExists {
+        name: String,
+    },
+    AddImplicationRule {
+        if_tag: String,
+        then_tag: String,
+    },
+    ListImplicationRules,
+    /// Changes the maximum level of messages logged by the daemon, one of `off`, `error`,
+    /// `warn`, `info`, `debug` or `trace`.
+    SetLogLevel {
+        level: String,
+    },
+    /// Moves tags of files matched by the `glob` from the xattr namespace `from`, if set, to `to`.
+    /// Values of tags in the current namespace saved by older versions are rewritten as well.
+    MigrateNamespace {
+        glob: Glob,
+        from: Option<String>,
+        to: String,
+    },
+    /// Keeps the connection open and after `Response::Subscribe` pushes a
+    /// `Response::TagChanged` whenever tags of a file change.
+    Subscribe,
+    /// Processes the `request` with the registry of the mount with the root directory at
+    /// `registry` instead of selecting it by paths of files in the request.
+    Scoped {
+        registry: PathBuf,
+        request: Box<Request>,
+    },
+    ListMounts,
+    /// Lists colors of tags as hex strings along with names of tags using them.
+    ListColors,
+    /// Lists entries added at or after `since`, most recently added first, at most `limit` of
+    /// them.
+    RecentEntries {
+        since: Option<DateTime<Utc>>,
+        limit: Option<usize>,
+    },
+    /// Sets the metadata `key` of the `tag` to `value` or removes it if `value` is `None`.
+    SetTagMeta {
+        tag: String,
+        key: String,
+        value: Option<String>,
+    },
+    /// Returns metadata of the `tag` or of all tags if `tag` is `None` by tag name.
+    GetTagMeta {
+        tag: Option<String>,
+    },
+    /// Makes relative paths of entries saved by old versions of wutag absolute by joining them
+    /// with the absolute `root`.
+    AbsolutizePaths {
+        root: PathBuf,
+    },
+    /// Defines a tag without tagging any file so that it is kept along with its color until it
+    /// is used. The `description` is stored in the metadata of the tag.
+    CreateTag {
+        name: String,
+        color: Color,
+        description: Option<String>,
+    },
+    /// Returns entries of the audit log logged at or after `since`, oldest first. With `limit`
+    /// only that many of the most recent entries are returned.
+    GetAuditLog {
+        since: Option<DateTime<Utc>>,
+        limit: Option<usize>,
+    },
+    /// Compares the registry with tags saved in the files, files missing from the registry are
+    /// searched for under `base_dir`. With `fix` the registry is updated to match the files.
+    DiffWithFilesystem {
+        base_dir: PathBuf,
+        max_depth: Option<usize>,
+        fix: bool,
+    },
 }
 
-impl Payload for Request {}
+impl Payload for Request {
+    fn migrations() -> &'static [Migration] {
+        &[migration::tag_without_timestamp]
+    }
+}
 
 #[derive(Deserialize, Debug, Serialize)]
 pub enum Response {
-    TagFiles(PayloadResult<(), Vec<String>>),
-    UntagFiles(PayloadResult<(), Vec<String>>),
+    TagFiles(PayloadResult<BatchSummary, Vec<String>>),
+    UntagFiles(PayloadResult<BatchSummary, Vec<String>>),
+    SetExactTags(PayloadResult<SetExactTagsResult, Vec<String>>),
     EditTag(PayloadResult<(), String>),
-    CopyTags(PayloadResult<(), Vec<String>>),
-    ClearFiles(PayloadResult<(), Vec<String>>),
+    CopyTags(PayloadResult<BatchSummary, Vec<String>>),
+    ClearFiles(PayloadResult<BatchSummary, Vec<String>>),
     ClearTags(PayloadResult<(), Vec<String>>),
-    ListTags(PayloadResult<HashMap<Tag, Vec<EntryData>>, String>),
-    ListFiles(PayloadResult<Vec<(EntryData, Vec<Tag>)>, String>),
-    InspectFiles(PayloadResult<Vec<(EntryData, Vec<Tag>)>, String>),
+    ListTags(PayloadResult<Paged<(Tag, Vec<EntryData>)>, String>),
+    ListFiles(PayloadResult<Paged<(EntryData, Vec<Tag>)>, String>),
+    InspectFiles(PayloadResult<InspectFilesResult, String>),
+    InspectFilesRaw(PayloadResult<RawXattrs, String>),
+    XattrCapacity(PayloadResult<TagCapacity, String>),
     Search(PayloadResult<Vec<EntryData>, String>),
+    SearchWeighted(PayloadResult<Vec<(EntryData, f32)>, String>),
+    SearchWithTags(PayloadResult<Vec<(EntryData, Vec<Tag>)>, String>),
+    /// Tags with names starting with the prefix along with entries tagged with them.
+    SearchByTagPrefix(PayloadResult<Vec<(Tag, Vec<EntryData>)>, String>),
+    SearchByOriginalName(PayloadResult<Vec<EntryData>, String>),
+    SearchByPathFragment(PayloadResult<Vec<EntryData>, String>),
+    SearchFiltered(PayloadResult<Vec<EntryData>, String>),
     Ping(PayloadResult<(), String>),
     ClearCache(PayloadResult<(), String>),
+    ExportGraph(PayloadResult<String, String>),
+    RecentTags(PayloadResult<Vec<Tag>, String>),
+    ClearRecentTags(PayloadResult<(), String>),
+    RenameTagsMatching(PayloadResult<Vec<(String, String)>, Vec<String>>),
+    Stats(PayloadResult<RegistryStats, String>),
+    RunMaintenance(PayloadResult<MaintenanceSummary, String>),
+    Verify(PayloadResult<Vec<EntryData>, String>),
+    VerifyTags(PayloadResult<VerifyReport, String>),
+    TagOverlap(PayloadResult<TagOverlap, String>),
+    TagExists(PayloadResult<bool, String>),
+    AddImplicationRule(PayloadResult<(), String>),
+    ListImplicationRules(PayloadResult<Vec<ImplicationRule>, String>),
+    /// The glob pattern of a request modifying files matched no files so nothing was done.
+    NoMatches(String),
+    SetLogLevel(PayloadResult<(), String>),
+    /// Number of moved tags.
+    MigrateNamespace(PayloadResult<usize, Vec<String>>),
+    Subscribe(PayloadResult<(), String>),
+    /// Tags of the file at `path` changed, pushed to subscribers.
+    TagChanged {
+        path: PathBuf,
+        added: Vec<Tag>,
+        removed: Vec<Tag>,
+    },
+    ListMounts(PayloadResult<Vec<RegistryMount>, String>),
+    /// No registry is mounted at the path of a `Request::Scoped`.
+    UnknownMount(PathBuf),
+    /// Files of the request belong to registries of different mounts, contains a file of each
+    /// of them.
+    MixedMounts(Vec<PathBuf>),
+    ListColors(PayloadResult<BTreeMap<String, Vec<String>>, String>),
+    RecentEntries(PayloadResult<Vec<EntryData>, String>),
+    SetTagMeta(PayloadResult<(), String>),
+    GetTagMeta(PayloadResult<BTreeMap<String, BTreeMap<String, String>>, String>),
+    CreateTag(PayloadResult<(), String>),
+    AbsolutizePaths(PayloadResult<PathMigration, String>),
+    GetAuditLog(PayloadResult<Vec<AuditLogEntry>, String>),
+    /// Inconsistencies found before they were fixed.
+    DiffWithFilesystem(PayloadResult<RegistryFsDiff, String>),
 }
 
-impl Payload for Response {}
+impl Payload for Response {
+    fn migrations() -> &'static [Migration] {
+        &[migration::tag_without_timestamp]
+    }
+}
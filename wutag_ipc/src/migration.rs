@@ -0,0 +1,91 @@
+//! Migrations of payloads serialized by older versions of wutag
+use serde_cbor::Value;
+
+/// A function that transforms bytes of a payload in an old format to the current format. Returns
+/// `None` if the migration doesn't apply to the payload.
+pub type Migration = fn(&[u8]) -> Option<Vec<u8>>;
+
+/// Removes the `timestamp` field from every serialized tag in the payload. Older versions
+/// serialized tags as a `timestamp`, `name` and `color` triple.
+pub fn tag_without_timestamp(bytes: &[u8]) -> Option<Vec<u8>> {
+    fn strip(value: &mut Value) -> bool {
+        match value {
+            Value::Map(map) => {
+                let key = |k: &str| Value::Text(k.to_string());
+                let mut changed = false;
+                if map.contains_key(&key("name")) && map.contains_key(&key("color")) {
+                    changed |= map.remove(&key("timestamp")).is_some();
+                }
+                for v in map.values_mut() {
+                    changed |= strip(v);
+                }
+                changed
+            }
+            Value::Array(values) => {
+                let mut changed = false;
+                for v in values {
+                    changed |= strip(v);
+                }
+                changed
+            }
+            Value::Tag(_, value) => strip(value),
+            _ => false,
+        }
+    }
+
+    let mut value: Value = serde_cbor::from_slice(bytes).ok()?;
+    if strip(&mut value) {
+        serde_cbor::to_vec(&value).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Payload, Request};
+    use serde::Serialize;
+    use wutag_core::color::Color;
+
+    #[derive(Serialize)]
+    struct OldTag {
+        timestamp: i64,
+        name: String,
+        color: Color,
+    }
+
+    #[derive(Serialize)]
+    enum OldRequest {
+        TagFiles {
+            files: Vec<std::path::PathBuf>,
+            tags: Vec<OldTag>,
+        },
+    }
+
+    #[test]
+    fn migrates_tags_with_timestamp() {
+        let old = OldRequest::TagFiles {
+            files: vec!["/tmp".into()],
+            tags: vec![OldTag {
+                timestamp: 1234,
+                name: "test".into(),
+                color: Color::Red,
+            }],
+        };
+        let bytes = serde_cbor::to_vec(&old).unwrap();
+
+        let migrated = tag_without_timestamp(&bytes).unwrap();
+        assert!(tag_without_timestamp(&migrated).is_none());
+
+        match Request::from_payload_with_migration(&bytes).unwrap() {
+            Request::TagFiles { files, tags, .. } => {
+                assert_eq!(files, vec![std::path::PathBuf::from("/tmp")]);
+                assert_eq!(tags.len(), 1);
+                assert_eq!(tags[0].name(), "test");
+                assert_eq!(tags[0].color(), &Color::Red);
+            }
+            request => panic!("unexpected request {request:?}"),
+        }
+    }
+}
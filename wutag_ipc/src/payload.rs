@@ -1,3 +1,4 @@
+use crate::migration::Migration;
 use crate::{IpcError, Result};
 use interprocess::local_socket::LocalSocketStream;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -12,22 +13,74 @@ pub enum PayloadError {
     Deserialize(serde_cbor::Error),
 }
 
+/// Kind of an error returned by the daemon that clients can act upon.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// A tag given in the request doesn't exist, details contain the `tag` and
+    /// `suggested_tags` with similar names.
+    TagNotFound,
+    /// A file given in the request isn't tracked in the registry.
+    EntryNotFound,
+    /// An argument of the request is invalid, for example out of range.
+    InvalidArgument,
+    /// Loading, locking or saving the registry failed.
+    Registry,
+    /// Reading or writing tags of files failed.
+    Filesystem,
+    /// A glob pattern of the request is invalid.
+    Pattern,
+    /// No registry is mounted at the path given in the request.
+    MountNotFound,
+    Other,
+}
+
+/// Error returned by the daemon with a `code` telling its kind and details specific to the
+/// code, like `{ "tag": "rust", "suggested_tags": ["Rust", "rust-lang"] }` for
+/// [TagNotFound](ErrorCode::TagNotFound).
+#[derive(Clone, Debug, Deserialize, Serialize, Error)]
+#[error("{message}")]
+pub struct ResponseError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+}
+
+impl ResponseError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
 #[derive(Deserialize, Debug, Serialize)]
 pub enum PayloadResult<T, E> {
     Ok(T),
     Error(E),
+    /// Error with a code and structured details, returned in place of `Error` where clients
+    /// may handle the kind of the error.
+    Detailed(ResponseError),
 }
 
 impl<T, E> PayloadResult<T, E> {
     /// Converts this request result to std::result::Result by applying the `make_error_fn` to
-    /// the inner error
-    pub fn to_result<E2: std::error::Error>(
+    /// the inner error. Detailed errors are converted as they are.
+    pub fn to_result<E2: std::error::Error + From<ResponseError>>(
         self,
         make_error_fn: impl FnOnce(E) -> E2,
     ) -> std::result::Result<T, E2> {
         match self {
             PayloadResult::Ok(ok) => Ok(ok),
             PayloadResult::Error(e) => Err(make_error_fn(e)),
+            PayloadResult::Detailed(e) => Err(e.into()),
         }
     }
 }
@@ -45,6 +98,30 @@ pub trait Payload: Sized + DeserializeOwned + Serialize + std::fmt::Debug {
             .map_err(IpcError::Payload)
     }
 
+    /// Migrations applied in order to payloads that fail to deserialize.
+    fn migrations() -> &'static [Migration] {
+        &[]
+    }
+
+    /// Deserializes the payload and if that fails tries to deserialize the output of each
+    /// registered [migration](Payload::migrations) returning the first successful result.
+    fn from_payload_with_migration(bytes: &[u8]) -> Result<Self> {
+        match Self::from_payload(bytes) {
+            Err(IpcError::Payload(PayloadError::Deserialize(e))) => {
+                for migration in Self::migrations() {
+                    if let Some(Ok(payload)) =
+                        migration(bytes).map(|migrated| Self::from_payload(&migrated))
+                    {
+                        log::debug!("migrated payload from an old format");
+                        return Ok(payload);
+                    }
+                }
+                Err(IpcError::Payload(PayloadError::Deserialize(e)))
+            }
+            result => result,
+        }
+    }
+
     fn send(&self, conn: &mut BufReader<LocalSocketStream>) -> Result<()> {
         let payload = self.to_payload()?;
         send_payload(&payload, conn)
@@ -52,7 +129,7 @@ pub trait Payload: Sized + DeserializeOwned + Serialize + std::fmt::Debug {
 
     fn read(conn: &mut BufReader<LocalSocketStream>) -> Result<Self> {
         let payload = read_payload(conn)?;
-        Self::from_payload(&payload)
+        Self::from_payload_with_migration(&payload)
     }
 }
 
@@ -76,3 +153,37 @@ fn read_payload(conn: &mut BufReader<LocalSocketStream>) -> Result<Vec<u8>> {
         .map_err(IpcError::ConnectionRead)
         .map(|_| buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Response;
+
+    #[test]
+    fn sends_detailed_errors() {
+        let error = ResponseError::new(ErrorCode::TagNotFound, "tag `rust` doesn't exist")
+            .with_details(serde_json::json!({
+                "tag": "rust",
+                "suggested_tags": ["Rust", "rust-lang"],
+            }));
+        let payload = Response::EditTag(PayloadResult::Detailed(error))
+            .to_payload()
+            .unwrap();
+
+        let Response::EditTag(result) = Response::from_payload(&payload).unwrap() else {
+            panic!("unexpected response");
+        };
+        match result.to_result(IpcError::Other) {
+            Err(IpcError::Detailed(e)) => {
+                assert_eq!(e.code, ErrorCode::TagNotFound);
+                assert_eq!(e.to_string(), "tag `rust` doesn't exist");
+                assert_eq!(e.details.unwrap()["suggested_tags"][1], "rust-lang");
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+        assert_eq!(
+            serde_json::to_string(&ErrorCode::TagNotFound).unwrap(),
+            "\"TAG_NOT_FOUND\""
+        );
+    }
+}
@@ -16,6 +16,21 @@ pub enum ServerError {
     NoActiveConnection,
     #[error("failed to bind local listener - {0}")]
     Bind(io::Error),
+    #[error("failed to read credentials of the client - {0}")]
+    PeerCredentials(io::Error),
+    #[error("rejected connection of a client running as user {uid}")]
+    UnauthorizedClient { uid: u32 },
+}
+
+/// Connection of a client that subscribed to receive any number of payloads pushed by the server.
+pub struct Subscriber {
+    conn: BufReader<LocalSocketStream>,
+}
+
+impl Subscriber {
+    pub fn send<PAYLOAD: Payload>(&mut self, payload: &PAYLOAD) -> Result<()> {
+        payload.send(&mut self.conn)
+    }
 }
 
 pub struct IpcServer {
@@ -23,6 +38,9 @@ pub struct IpcServer {
     path: String,
     socket: LocalSocketListener,
     conns: VecDeque<BufReader<LocalSocketStream>>,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    require_same_user: bool,
+    peer_uid: Option<u32>,
 }
 
 impl IpcServer {
@@ -33,14 +51,37 @@ impl IpcServer {
             path,
             socket,
             conns: VecDeque::new(),
+            require_same_user: false,
+            peer_uid: None,
         })
     }
 
+    /// Rejects connections of clients running as a different user than the server. Only
+    /// supported on Linux, elsewhere all clients are accepted.
+    pub fn with_require_same_user(mut self, require_same_user: bool) -> Self {
+        self.require_same_user = require_same_user;
+        self
+    }
+
     pub fn accept_request<REQUEST: Payload>(&mut self) -> Result<REQUEST> {
         let conn = self
             .socket
             .accept()
             .map_err(ServerError::ConnectionAccept)?;
+        self.peer_uid = None;
+        #[cfg(target_os = "linux")]
+        match peer_uid(&conn) {
+            // SAFETY: getuid never fails
+            Ok(uid) if self.require_same_user && uid != unsafe { libc::getuid() } => {
+                log::warn!("rejected connection of a client running as user {uid}");
+                return Err(ServerError::UnauthorizedClient { uid }.into());
+            }
+            Ok(uid) => self.peer_uid = Some(uid),
+            Err(e) if self.require_same_user => {
+                return Err(ServerError::PeerCredentials(e).into());
+            }
+            Err(e) => log::debug!("failed to read credentials of the client - {e}"),
+        }
         let mut conn = BufReader::new(conn);
         let request = REQUEST::read(&mut conn)?;
         log::debug!("got request: {request:?}");
@@ -48,12 +89,85 @@ impl IpcServer {
         Ok(request)
     }
 
+    /// Returns the id of the user running the client of the last accepted request. Only known on
+    /// Linux, elsewhere always returns `None`.
+    pub fn peer_uid(&self) -> Option<u32> {
+        self.peer_uid
+    }
+
     pub fn send_response<RESPONSE: Payload>(&mut self, response: RESPONSE) -> Result<()> {
         if let Some(mut conn) = self.conns.pop_front() {
             log::debug!("sending response: {response:?}");
             return response.send(&mut conn);
         }
 
-        Err(ServerError::NoActiveConnection).map_err(IpcError::Server)
+        Err(IpcError::Server(ServerError::NoActiveConnection))
+    }
+
+    /// Keeps the connection of the oldest unanswered request open as a [Subscriber](Subscriber)
+    /// instead of sending a single response to it.
+    pub fn detach_connection(&mut self) -> Result<Subscriber> {
+        self.conns
+            .pop_front()
+            .map(|conn| Subscriber { conn })
+            .ok_or(IpcError::Server(ServerError::NoActiveConnection))
+    }
+}
+
+/// Returns the id of the user running the process on the other end of the `conn`.
+#[cfg(target_os = "linux")]
+fn peer_uid(conn: &LocalSocketStream) -> io::Result<u32> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` outlive the call and `len` is the size of `cred`
+    let ret = unsafe {
+        libc::getsockopt(
+            conn.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{socket_name, IpcClient, PayloadResult, Request, Response};
+
+    #[test]
+    fn accepts_clients_of_the_same_user() {
+        let socket = socket_name(
+            std::env::temp_dir(),
+            format!("wutag-peercred-{}.sock", std::process::id()),
+        );
+        let mut server = IpcServer::new(socket.as_str())
+            .unwrap()
+            .with_require_same_user(true);
+        let handle = std::thread::spawn(move || {
+            let request = server.accept_request::<Request>().unwrap();
+            assert!(matches!(request, Request::Ping));
+            server
+                .send_response(Response::Ping(PayloadResult::Ok(())))
+                .unwrap();
+        });
+
+        let client = IpcClient::new(socket);
+        assert!(matches!(
+            client.request::<_, Response>(Request::Ping),
+            Ok(Response::Ping(PayloadResult::Ok(())))
+        ));
+        handle.join().unwrap();
     }
 }